@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use clap::Parser;
 use tooling::{
     error::{Error, ErrorType},
-    model::Home,
+    model::{Home, HomeLockArgs},
 };
 
 mod ingest;
@@ -12,7 +12,7 @@ pub use ingest::*;
 /// The builder tool for AcaciaLinux
 #[derive(Parser)]
 pub struct Cli {
-    /// The log level to operate on (0 = info, 1 = debug, * = trace)
+    /// The log level to operate on (0 = warn, 1 = info, 2 = debug, * = trace)
     #[arg(long = "loglevel", short = 'v', default_value_t = 0, global = true)]
     pub loglevel: u8,
 
@@ -20,6 +20,9 @@ pub struct Cli {
     #[arg(long)]
     home: Option<PathBuf>,
 
+    #[command(flatten)]
+    pub lock: HomeLockArgs,
+
     #[command(subcommand)]
     command: BranchCommand,
 }
@@ -33,7 +36,7 @@ impl Cli {
     pub fn run(&self) -> Result<i32, Error> {
         if std::env::var("RUST_LOG").is_err() {
             match &self.loglevel {
-                0 => {}
+                0 => std::env::set_var("RUST_LOG", "warn"),
                 1 => std::env::set_var("RUST_LOG", "info"),
                 2 => std::env::set_var("RUST_LOG", "debug"),
                 _ => std::env::set_var("RUST_LOG", "trace"),
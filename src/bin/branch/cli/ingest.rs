@@ -1,12 +1,20 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 
 use clap::Parser;
+use indexmap::IndexMap;
 use log::info;
 use tooling::{
-    error::Error,
+    error::{Error, ErrorExt},
     files::formulafile::FormulaFile,
-    model::ObjectCompression,
-    util::{architecture::Architecture, fs::PathUtil},
+    model::{
+        odb_driver::FilesystemDriver, HomeLockScope, ObjectCompression, ObjectDB, ObjectID,
+        RepositoryIndex,
+    },
+    util::{
+        architecture::Architecture,
+        fs::{LockMode, PathUtil},
+        parse::parse_source_override,
+    },
 };
 
 use super::Cli;
@@ -19,9 +27,17 @@ pub struct IngestCommand {
     compression: ObjectCompression,
 
     /// The architecture to ingest the formula for
-    #[arg(long, short)]
+    #[arg(long, short, value_parser = Architecture::from_str)]
     pub architecture: Option<Architecture>,
 
+    /// Use a local directory instead of a source (`<DEST>=<DIR>`), marks the formula as tainted
+    #[arg(long = "source-override", value_parser = parse_source_override)]
+    source_overrides: Vec<(String, PathBuf)>,
+
+    /// The object id of the repository index to resolve the dependencies from
+    #[arg(long)]
+    index: Option<ObjectID>,
+
     /// The file to the formula to be ingested
     file: PathBuf,
 }
@@ -29,9 +45,34 @@ pub struct IngestCommand {
 impl IngestCommand {
     pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
         let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[
+                (HomeLockScope::ObjectDB, LockMode::Shared),
+                (HomeLockScope::Builder, LockMode::Shared),
+            ],
+            cli.lock.wait(),
+        )?;
+
+        let source_overrides: IndexMap<String, PathBuf> =
+            self.source_overrides.iter().cloned().collect();
+
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let index = match &self.index {
+            Some(oid) => RepositoryIndex::from_odb(&odb, oid).ctx(|| "Reading repository index")?,
+            None => RepositoryIndex::new(),
+        };
 
-        let (formula, object) =
-            FormulaFile::parse_and_resolve(&self.file, &home, self.get_arch()?, self.compression)?;
+        let (formula, object) = FormulaFile::parse_and_resolve(
+            &self.file,
+            &home,
+            &mut odb,
+            self.get_arch()?,
+            self.compression,
+            &source_overrides,
+            &index,
+        )?;
 
         info!(
             "Ingested {} -> {}:\n{:#?}",
@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorType},
+    model::{Home, HomeLockArgs},
+};
+
+mod audit;
+#[cfg(feature = "builder")]
+mod bootstrap;
+#[cfg(feature = "builder")]
+mod build;
+#[cfg(feature = "builder")]
+mod build_all;
+mod clean;
+mod diff_packages;
+mod explain_build;
+mod export_package;
+mod impact;
+mod import_package;
+mod install;
+mod publish;
+mod remove;
+mod rollback;
+mod serve;
+mod shell;
+mod status;
+mod upgrade;
+mod verify_image;
+mod which;
+
+#[derive(Parser)]
+pub struct Cli {
+    /// The log level to operate on (0 = warn, 1 = info, 2 = debug, * = trace)
+    #[arg(long = "loglevel", short = 'v', default_value_t = 0, global = true)]
+    pub loglevel: u8,
+
+    /// The home directory where all Acacia tooling works in [~/.acacia]
+    #[arg(long)]
+    home: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub lock: HomeLockArgs,
+
+    /// The command to execute
+    #[command(subcommand)]
+    command: TrunkCommand,
+}
+
+#[derive(Parser)]
+pub enum TrunkCommand {
+    /// Serve the object database over HTTP
+    Serve(serve::CommandServe),
+    /// List the packages that need to be rebuilt if a formula changes
+    Impact(impact::CommandImpact),
+    /// Spawn a shell in an ad-hoc environment containing a set of packages
+    Shell(shell::CommandShell),
+    /// Install packages to a root directory and run their scriptlets
+    Install(install::CommandInstall),
+    /// Remove installed packages from a root directory after running their scriptlets
+    Remove(remove::CommandRemove),
+    /// Deploy a minimal system built from a directory of formulae
+    #[cfg(feature = "builder")]
+    Bootstrap(bootstrap::CommandBootstrap),
+    /// Build a formula for multiple architectures
+    #[cfg(feature = "builder")]
+    Build(build::CommandBuild),
+    /// Build a directory tree of formulae in dependency order
+    #[cfg(feature = "builder")]
+    BuildAll(build_all::CommandBuildAll),
+    /// Print how a package has been built from its build receipts
+    ExplainBuild(explain_build::CommandExplainBuild),
+    /// Export a package to a standalone archive with embedded metadata
+    ExportPackage(export_package::CommandExportPackage),
+    /// Import a package archive into the object database
+    ImportPackage(import_package::CommandImportPackage),
+    /// Verify a deployed root directory against the tree it has been deployed from
+    VerifyImage(verify_image::CommandVerifyImage),
+    /// Upload a package to a remote object database and add it to its repository index
+    Publish(publish::CommandPublish),
+    /// Summarize the state of the home directory
+    Status(status::CommandStatus),
+    /// Remove temporary data from the home directory
+    Clean(clean::CommandClean),
+    /// Find the packages providing a file
+    Which(which::CommandWhich),
+    /// Compare the files, dependencies and size of two packages
+    DiffPackages(diff_packages::CommandDiffPackages),
+    /// Check packages against a dump of security advisories for known vulnerable versions
+    Audit(audit::CommandAudit),
+    /// Upgrade the packages installed to a root directory to the newest versions of a repository
+    Upgrade(upgrade::CommandUpgrade),
+    /// Roll a root directory back to a previous generation of its installed packages
+    Rollback(rollback::CommandRollback),
+}
+
+impl Cli {
+    pub fn run(&self) -> Result<i32, Error> {
+        if std::env::var("RUST_LOG").is_err() {
+            match &self.loglevel {
+                0 => std::env::set_var("RUST_LOG", "warn"),
+                1 => std::env::set_var("RUST_LOG", "info"),
+                2 => std::env::set_var("RUST_LOG", "debug"),
+                _ => std::env::set_var("RUST_LOG", "trace"),
+            }
+        }
+        pretty_env_logger::init();
+
+        self.command.run(self)
+    }
+
+    pub fn get_home(&self) -> Result<Home, Error> {
+        let home = match &self.home {
+            Some(root) => Home::new(root.clone()),
+            None => match home::home_dir() {
+                Some(home_dir) => Home::new(home_dir.join(tooling::HOME_DIR)),
+                None => {
+                    return Err(Error::new(ErrorType::Other(
+                        "Home cannot be determined, use '--home'".to_owned(),
+                    )))
+                }
+            },
+        }?;
+
+        Ok(home)
+    }
+}
+
+impl TrunkCommand {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        match self {
+            Self::Serve(cmd) => cmd.run(cli),
+            Self::Impact(cmd) => cmd.run(cli),
+            Self::Shell(cmd) => cmd.run(cli),
+            Self::Install(cmd) => cmd.run(cli),
+            Self::Remove(cmd) => cmd.run(cli),
+            #[cfg(feature = "builder")]
+            Self::Bootstrap(cmd) => cmd.run(cli),
+            #[cfg(feature = "builder")]
+            Self::Build(cmd) => cmd.run(cli),
+            #[cfg(feature = "builder")]
+            Self::BuildAll(cmd) => cmd.run(cli),
+            Self::ExplainBuild(cmd) => cmd.run(cli),
+            Self::ExportPackage(cmd) => cmd.run(cli),
+            Self::ImportPackage(cmd) => cmd.run(cli),
+            Self::VerifyImage(cmd) => cmd.run(cli),
+            Self::Publish(cmd) => cmd.run(cli),
+            Self::Status(cmd) => cmd.run(cli),
+            Self::Clean(cmd) => cmd.run(cli),
+            Self::Which(cmd) => cmd.run(cli),
+            Self::DiffPackages(cmd) => cmd.run(cli),
+            Self::Audit(cmd) => cmd.run(cli),
+            Self::Upgrade(cmd) => cmd.run(cli),
+            Self::Rollback(cmd) => cmd.run(cli),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{odb_driver::FilesystemDriver, FileIndex, ObjectDB, ObjectID, RepositoryIndex},
+    tools::auditor::Auditor,
+    util::fs::PathUtil,
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandAudit {
+    /// The advisory dump to audit against, a JSON file or a directory
+    /// of JSON files in the OSV format [<home>/advisories.json]
+    #[arg(long)]
+    db: Option<PathBuf>,
+
+    /// Download the advisory dump from this URL before auditing
+    #[arg(long)]
+    fetch: Option<String>,
+
+    /// The object id of a repository index to audit the packages of
+    /// instead of the packages installed to the root directory
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// The root directory to audit the installed packages of
+    #[arg(long, default_value = "/", conflicts_with = "index")]
+    root: PathBuf,
+
+    /// Audit all packages
+    #[arg(long, action, conflicts_with = "package")]
+    all: bool,
+
+    /// The name of the package to audit
+    #[arg(required_unless_present = "all")]
+    package: Option<String>,
+}
+
+impl CommandAudit {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let db = self.db.clone().unwrap_or(home.get_advisories_path());
+        if let Some(url) = &self.fetch {
+            Auditor::fetch(url, &db)?;
+        }
+
+        if !db.exists() {
+            eprintln!(
+                "There is no advisory dump at {}, fetch one using --fetch <URL>",
+                db.str_lossy()
+            );
+            return Ok(1);
+        }
+        let auditor = Auditor::load(&db)?;
+
+        // The names and versions of the packages to audit
+        let packages: Vec<(String, String)> = match &self.index {
+            Some(oid) => RepositoryIndex::from_odb(&odb, oid)
+                .ctx(|| "Reading repository index")?
+                .entries
+                .into_iter()
+                .map(|e| (e.name, e.version))
+                .collect(),
+            None => FileIndex::from_root(&odb, &self.root)?
+                .packages
+                .into_iter()
+                .map(|p| (p.name, p.version))
+                .collect(),
+        };
+
+        let packages: Vec<(String, String)> = match &self.package {
+            Some(name) => packages.into_iter().filter(|(n, _)| n == name).collect(),
+            None => packages,
+        };
+
+        if let (Some(name), true) = (&self.package, packages.is_empty()) {
+            eprintln!("No package named '{name}'");
+            return Ok(1);
+        }
+
+        let mut vulnerable = 0;
+        for (name, version) in &packages {
+            let advisories = auditor.audit(name, version);
+            if advisories.is_empty() {
+                continue;
+            }
+
+            vulnerable += 1;
+            for advisory in advisories {
+                println!("{name}@{version}: {advisory}");
+            }
+        }
+
+        eprintln!(
+            "Audited {} packages against {} advisories, {vulnerable} affected",
+            packages.len(),
+            auditor.len()
+        );
+
+        Ok(if vulnerable == 0 { 0 } else { 1 })
+    }
+}
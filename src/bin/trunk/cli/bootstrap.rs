@@ -0,0 +1,184 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use log::{info, warn};
+use tooling::{
+    error::{Error, ErrorExt},
+    files::formulaset::FormulaSet,
+    model::{
+        odb_driver::FilesystemDriver, HomeLockScope, MergeStrategy, ObjectCompression, ObjectDB,
+        ObjectID, RepositoryIndex, Tree,
+    },
+    tools::builder::Builder,
+    util::{
+        architecture::Architecture,
+        fs::{LockMode, PathUtil},
+        progress::ProgressBar,
+        userns::UserNamespace,
+        ODBUnpackable,
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandBootstrap {
+    /// The directory containing the formulae, one `<name>/formula.toml` per package
+    #[arg(long)]
+    formulas: PathBuf,
+
+    /// The root directory to deploy the system to
+    #[arg(long)]
+    root: PathBuf,
+
+    /// The object id of the repository index to resolve dependencies outside of the set from
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// The toolchain directory of the host to bind mount into the build roots
+    #[arg(long)]
+    toolchain: Option<PathBuf>,
+
+    /// Only warn about files claimed by multiple layouts of a formula instead of failing
+    #[arg(long, action)]
+    allow_overlap: bool,
+
+    /// Build in a user namespace without requiring `root` privileges
+    #[arg(long, action)]
+    unprivileged: bool,
+
+    /// The compression to use for inserting the objects
+    #[arg(long, short, default_value_t = ObjectCompression::Xz)]
+    compression: ObjectCompression,
+
+    /// How to resolve files provided by multiple packages
+    #[arg(long, default_value_t = MergeStrategy::Error)]
+    on_conflict: MergeStrategy,
+
+    /// Only print the build plan
+    #[arg(long, action)]
+    dry_run: bool,
+}
+
+impl CommandBootstrap {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let set = FormulaSet::load(&self.formulas)?;
+        let plan = set.bootstrap_plan().ctx(|| "Planning bootstrap")?;
+
+        for dependency in set.external_dependencies() {
+            warn!(
+                "'{dependency}' is not part of the formula set and has to be provided by the host"
+            );
+        }
+
+        println!("Pass 1 (cross tools):");
+        for entry in &plan.cross {
+            println!("  {entry}");
+        }
+        println!("Pass 2 (system):");
+        for entry in &plan.system {
+            println!("  {entry}");
+        }
+
+        if self.dry_run {
+            return Ok(0);
+        }
+
+        // Entering the user namespace has to happen before any threads get spawned
+        let user_namespace = if self.unprivileged {
+            Some(UserNamespace::enter()?)
+        } else {
+            None
+        };
+
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[
+                (HomeLockScope::ObjectDB, LockMode::Shared),
+                (HomeLockScope::Builder, LockMode::Shared),
+            ],
+            cli.lock.wait(),
+        )?;
+        let arch = Architecture::new_uname()?;
+
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        // The packages of pass 1 provide the host dependencies of pass 2,
+        // the packages of pass 2 replace them once built
+        let mut index = match &self.index {
+            Some(oid) => RepositoryIndex::from_odb(&odb, oid).ctx(|| "Reading repository index")?,
+            None => RepositoryIndex::new(),
+        };
+
+        let builder = Builder::new(&home, self.compression)?
+            .with_toolchain(self.toolchain.clone())
+            .with_allow_overlap(self.allow_overlap)
+            .with_user_namespace(user_namespace);
+
+        let mut built = 0;
+        let mut packages = Vec::new();
+        for (pass, entries) in [(1, &plan.cross), (2, &plan.system)] {
+            info!("Building pass {pass}");
+
+            for entry in entries {
+                let (package, is_built) = builder
+                    .resolve_and_build(&entry.path, &arch, &index, &mut odb)
+                    .ctx(|| format!("Building {entry} (pass {pass})"))?;
+
+                let oid = package
+                    .package
+                    .clone()
+                    .expect("Built entries have packages");
+                if is_built {
+                    println!("{entry} (pass {pass}): built ({oid})");
+                    built += 1;
+                } else {
+                    println!("{entry} (pass {pass}): up to date ({oid})");
+                }
+
+                // Only the packages of pass 2 end up in the system, the cross tools are build-time only
+                if pass == 2 {
+                    packages.push(package.clone());
+                }
+                index.insert(package);
+            }
+        }
+
+        if built > 0 {
+            let object = index
+                .insert_into_odb(&mut odb, self.compression)
+                .ctx(|| "Inserting repository index of the built packages")?;
+            println!("Built {built} packages, recorded in {}", object.oid);
+        }
+
+        let mut tree = Tree {
+            entries: Vec::new(),
+        };
+        for entry in packages {
+            let oid = entry
+                .package
+                .as_ref()
+                .expect("Resolved entries have packages");
+
+            let mut object = odb.read(oid).ctx(|| format!("Reading package {oid}"))?;
+            let package_tree = Tree::unpack_from_odb(&mut object, &odb)
+                .ctx(|| format!("Reading package tree {oid}"))?;
+
+            let conflicts = tree
+                .merge_with_strategy(package_tree, self.on_conflict)
+                .ctx(|| format!("Merging {entry} into the system"))?;
+            for conflict in conflicts {
+                warn!("{entry} conflicts with the system at {conflict}");
+            }
+        }
+
+        info!("Deploying system to {}", self.root.str_lossy());
+        let mut bar = ProgressBar::new("Deploying");
+        tree.deploy_with_progress(&self.root, &odb, &mut |p| bar.update_deploy(p))
+            .ctx(|| format!("Deploying system to {}", self.root.str_lossy()))?;
+        bar.finish();
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,351 @@
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+use clap::Parser;
+use log::{debug, info};
+use serde::Serialize;
+use tooling::{
+    env::FailureAction,
+    error::{Error, ErrorExt, ErrorType},
+    files::formulafile::FormulaFile,
+    model::{
+        odb_driver::FilesystemDriver, Home, HomeLockScope, ObjectCompression, ObjectDB, ObjectID,
+        RepositoryIndex, RepositoryIndexEntry,
+    },
+    tools::builder::{BuildStrategy, Builder},
+    util::{
+        architecture::Architecture, fs::LockMode, parse::parse_source_override,
+        userns::UserNamespace,
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandBuild {
+    /// The architectures to build for, can be given multiple times [host architecture]
+    #[arg(long, short, value_parser = Architecture::from_str)]
+    arch: Vec<Architecture>,
+
+    /// The directory containing the static `qemu-user` binaries (`qemu-<arch>-static`)
+    /// to emulate architectures that can't run on the host and can't be cross compiled
+    #[arg(long)]
+    emulate: Option<PathBuf>,
+
+    /// The object id of the repository index to resolve the dependencies from
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// The toolchain directory of the host to bind mount into the build root
+    #[arg(long)]
+    toolchain: Option<PathBuf>,
+
+    /// Only warn about files claimed by multiple layouts of a formula instead of failing
+    #[arg(long, action)]
+    allow_overlap: bool,
+
+    /// Build in a user namespace without requiring `root` privileges
+    #[arg(long, action)]
+    unprivileged: bool,
+
+    /// The action to take if a build step fails: `abort` the build or spawn
+    /// a `shell` in the build environment, aborting once it exits
+    #[arg(long, default_value_t = FailureAction::Abort, value_parser = FailureAction::from_str)]
+    on_failure: FailureAction,
+
+    /// Write the reports of all builds (steps, exit codes, validation findings)
+    /// as a JSON array to this path, whether they succeeded or not
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// A host environment variable to pass into the build environments, can be given
+    /// multiple times. Names ending in `*` match all variables with the prefix
+    #[arg(long = "pass-env")]
+    pass_env: Vec<String>,
+
+    /// Retain the files every build step changed as a tree object linked to
+    /// the step in the build report and receipt, to inspect failed builds
+    #[arg(long, action)]
+    step_snapshots: bool,
+
+    /// Drop the extra dependencies that provide nothing the built packages
+    /// need instead of recording them as validation findings
+    #[arg(long, action)]
+    prune_dependencies: bool,
+
+    /// Use a local directory instead of a source (`<DEST>=<DIR>`), marks the formula
+    /// and the receipts of the packages built from it as tainted
+    #[arg(long = "source-override", value_parser = parse_source_override)]
+    source_overrides: Vec<(String, PathBuf)>,
+
+    /// The compression to use for inserting the objects
+    #[arg(long, short, default_value_t = ObjectCompression::Xz)]
+    compression: ObjectCompression,
+
+    /// Print the combined report as JSON
+    #[arg(long, action)]
+    json: bool,
+
+    /// The formula file to build
+    formula: PathBuf,
+}
+
+/// The combined report of building a formula for multiple architectures
+#[derive(Serialize)]
+struct BuildMatrixReport {
+    /// The name of the package
+    name: String,
+    /// The version of the package
+    version: String,
+    /// The architecture of the host
+    host: String,
+    /// The result for each requested architecture, in the order they have been requested
+    targets: Vec<BuildTarget>,
+    /// The object id of the repository index recording the packages built by this invocation
+    index: Option<ObjectID>,
+}
+
+/// The result of building a formula for one architecture
+#[derive(Serialize)]
+struct BuildTarget {
+    /// The architecture
+    arch: String,
+    /// How the formula gets built, `None` if it can't be built on this host
+    strategy: Option<BuildStrategy>,
+    /// The object id of the formula resolved for the architecture
+    formula: Option<ObjectID>,
+    /// The object id of the package built from the formula
+    package: Option<ObjectID>,
+    /// Whether the package has been built by this invocation
+    built: bool,
+    /// Why the formula can't be built for the architecture
+    error: Option<String>,
+}
+
+impl CommandBuild {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        // Entering the user namespace has to happen before any threads get spawned
+        let user_namespace = if self.unprivileged {
+            Some(UserNamespace::enter()?)
+        } else {
+            None
+        };
+
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[
+                (HomeLockScope::ObjectDB, LockMode::Shared),
+                (HomeLockScope::Builder, LockMode::Shared),
+            ],
+            cli.lock.wait(),
+        )?;
+
+        let file = FormulaFile::parse(&self.formula)?;
+        let host = Architecture::new_uname()?;
+
+        let mut archs: Vec<Architecture> = Vec::new();
+        for arch in &self.arch {
+            if !archs.contains(arch) {
+                archs.push(arch.clone());
+            }
+        }
+        if archs.is_empty() {
+            archs.push(host.clone());
+        }
+
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let dependencies = match &self.index {
+            Some(oid) => RepositoryIndex::from_odb(&odb, oid).ctx(|| "Reading repository index")?,
+            None => RepositoryIndex::new(),
+        };
+
+        let builder = Builder::new(&home, self.compression)?
+            .with_toolchain(self.toolchain.clone())
+            .with_allow_overlap(self.allow_overlap)
+            .with_user_namespace(user_namespace)
+            .with_failure_action(self.on_failure)
+            .with_report(self.report.clone())
+            .with_passthrough_env(self.pass_env.clone())
+            .with_step_snapshots(self.step_snapshots)
+            .with_prune_dependencies(self.prune_dependencies)
+            .with_emulation(self.emulate.clone());
+
+        let mut report = BuildMatrixReport {
+            name: file.package.name.clone(),
+            version: file.package.version.clone(),
+            host: host.to_string(),
+            targets: Vec::new(),
+            index: None,
+        };
+
+        let mut built = RepositoryIndex::new();
+        for arch in archs {
+            let mut target = BuildTarget {
+                arch: arch.to_string(),
+                strategy: None,
+                formula: None,
+                package: None,
+                built: false,
+                error: None,
+            };
+
+            let res = self.build(
+                &home,
+                &builder,
+                &file,
+                &arch,
+                &dependencies,
+                &mut odb,
+                &mut target,
+            );
+            match res {
+                Ok(Some(entry)) => built.insert(entry),
+                Ok(None) => {}
+                Err(e) => {
+                    debug!("{e}");
+                    target.error = Some(e.oneline());
+                }
+            }
+
+            report.targets.push(target);
+        }
+
+        // Record the built packages, so they are found for the resolved formulae
+        if !built.entries.is_empty() {
+            let object = built
+                .insert_into_odb(&mut odb, self.compression)
+                .ctx(|| "Inserting repository index of the built packages")?;
+            info!(
+                "Recorded the built packages in repository index {}",
+                object.oid
+            );
+            report.index = Some(object.oid);
+        }
+
+        if self.json {
+            println!(
+                "{}",
+                serde_json::to_string(&report).expect("Serialize build report should never fail")
+            );
+        } else {
+            report.print();
+        }
+
+        let failed: Vec<&BuildTarget> = report
+            .targets
+            .iter()
+            .filter(|t| t.package.is_none())
+            .collect();
+
+        if !failed.is_empty() {
+            let mut msg = format!(
+                "{} of {} architectures have no built package:",
+                failed.len(),
+                report.targets.len()
+            );
+            for target in failed {
+                msg.push_str(&format!("\n  {target}"));
+            }
+
+            return Err(Error::new(ErrorType::Other(msg)));
+        }
+
+        Ok(0)
+    }
+
+    /// Resolves the formula for `arch` and builds it, unless a package
+    /// has been built from the resolved formula already
+    /// # Arguments
+    /// * `home` - The home to resolve the formula in
+    /// * `builder` - The builder to build the formula with
+    /// * `file` - The formula file to build
+    /// * `arch` - The architecture to build for
+    /// * `dependencies` - The repository index to resolve the dependencies from
+    /// * `odb` - The object database to insert the objects into
+    /// * `target` - The result to record the resolved formula and the package to
+    /// # Returns
+    /// The repository index entry of the package if it has been built
+    #[allow(clippy::too_many_arguments)]
+    fn build(
+        &self,
+        home: &Home,
+        builder: &Builder,
+        file: &FormulaFile,
+        arch: &Architecture,
+        dependencies: &RepositoryIndex,
+        odb: &mut ObjectDB,
+        target: &mut BuildTarget,
+    ) -> Result<Option<RepositoryIndexEntry>, Error> {
+        let (formula, object) = FormulaFile::parse_and_resolve(
+            &self.formula,
+            home,
+            odb,
+            arch.clone(),
+            self.compression,
+            &self.source_overrides.iter().cloned().collect(),
+            dependencies,
+        )
+        .ctx(|| format!("Resolving formula for {arch}"))?;
+        target.formula = Some(object.oid.clone());
+
+        let strategy = builder.get_strategy(&formula, arch).ok_or_else(|| {
+            Error::new(ErrorType::Other(format!(
+                "{arch} can't run on the host and the formula has no host dependencies \
+                 to cross compile with, emulate it using --emulate"
+            )))
+        })?;
+        target.strategy = Some(strategy);
+
+        if let Some(package) = RepositoryIndex::find_built_package(odb, &object.oid)? {
+            target.package = Some(package);
+            return Ok(None);
+        }
+
+        let output = builder.build(file.clone(), &formula, arch, odb)?;
+        target.package = Some(output.package.clone());
+        target.built = true;
+
+        Ok(Some(RepositoryIndexEntry {
+            name: formula.name,
+            version: formula.version,
+            arch: formula.arch,
+            provides: formula.provides,
+            license: formula.license,
+            formula: Some(object.oid),
+            package: Some(output.package),
+        }))
+    }
+}
+
+impl BuildMatrixReport {
+    /// Prints the report in a human-readable form
+    fn print(&self) {
+        println!("{}-{} (host {}):", self.name, self.version, self.host);
+        for target in &self.targets {
+            println!("  {target}");
+        }
+
+        if let Some(index) = &self.index {
+            println!("Built packages recorded in {index}");
+        }
+    }
+}
+
+impl Display for BuildTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.arch)?;
+
+        if let Some(strategy) = &self.strategy {
+            write!(f, " ({strategy})")?;
+        }
+
+        match (&self.error, &self.formula, &self.package) {
+            (Some(error), _, _) => write!(f, ": error: {error}"),
+            (None, _, Some(package)) if self.built => write!(f, ": built ({package})"),
+            (None, _, Some(package)) => write!(f, ": up to date ({package})"),
+            (None, Some(formula), None) => write!(f, ": needs build ({formula})"),
+            (None, None, None) => write!(f, ": not resolved"),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use clap::Parser;
+use log::{info, warn};
+use tooling::{
+    env::FailureAction,
+    error::{Error, ErrorExt},
+    files::formulaset::{FormulaSet, FormulaSetEntry},
+    model::{
+        odb_driver::FilesystemDriver, Home, HomeLockScope, ObjectCompression, ObjectDB, ObjectID,
+        RepositoryIndex, RepositoryIndexEntry,
+    },
+    tools::builder::Builder,
+    util::{architecture::Architecture, fs::LockMode, userns::UserNamespace},
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandBuildAll {
+    /// The architecture to build for [host architecture]
+    #[arg(long, short, value_parser = Architecture::from_str)]
+    arch: Option<Architecture>,
+
+    /// The object id of the repository index to resolve dependencies outside of the set from
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// The toolchain directory of the host to bind mount into the build roots
+    #[arg(long)]
+    toolchain: Option<PathBuf>,
+
+    /// Only warn about files claimed by multiple layouts of a formula instead of failing
+    #[arg(long, action)]
+    allow_overlap: bool,
+
+    /// Build in a user namespace without requiring `root` privileges
+    #[arg(long, action)]
+    unprivileged: bool,
+
+    /// The action to take if a build step fails: `abort` the build or spawn
+    /// a `shell` in the build environment, aborting once it exits
+    #[arg(long, default_value_t = FailureAction::Abort, value_parser = FailureAction::from_str)]
+    on_failure: FailureAction,
+
+    /// Write the reports of all builds (steps, exit codes, validation findings)
+    /// as a JSON array to this path, whether they succeeded or not
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// A host environment variable to pass into the build environments, can be given
+    /// multiple times. Names ending in `*` match all variables with the prefix
+    #[arg(long = "pass-env")]
+    pass_env: Vec<String>,
+
+    /// Retain the files every build step changed as a tree object linked to
+    /// the step in the build report and receipt, to inspect failed builds
+    #[arg(long, action)]
+    step_snapshots: bool,
+
+    /// Drop the extra dependencies that provide nothing the built packages
+    /// need instead of recording them as validation findings
+    #[arg(long, action)]
+    prune_dependencies: bool,
+
+    /// The compression to use for inserting the objects
+    #[arg(long, short, default_value_t = ObjectCompression::Xz)]
+    compression: ObjectCompression,
+
+    /// The number of independent formulae to build in parallel
+    #[arg(long, short, default_value_t = 1)]
+    jobs: usize,
+
+    /// Only print the build order
+    #[arg(long, action)]
+    dry_run: bool,
+
+    /// The directory to search for formulae (`formula.toml`) recursively
+    dir: PathBuf,
+}
+
+/// The package of a formula of the set, refer to [CommandBuildAll::build_wave()]
+struct WavePackage {
+    /// The repository index entry of the formula and its package
+    entry: RepositoryIndexEntry,
+    /// Whether the package has been built by this invocation
+    built: bool,
+}
+
+impl CommandBuildAll {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let set = FormulaSet::load_recursive(&self.dir)?;
+        let waves = set.build_waves().ctx(|| "Planning builds")?;
+
+        for dependency in set.external_dependencies() {
+            warn!(
+                "'{dependency}' is not part of the formula set and has to be provided by the host"
+            );
+        }
+
+        for (i, wave) in waves.iter().enumerate() {
+            println!("Wave {}:", i + 1);
+            for entry in wave {
+                println!("  {entry}");
+            }
+        }
+
+        if self.dry_run {
+            return Ok(0);
+        }
+
+        // Entering the user namespace has to happen before any threads get spawned
+        let user_namespace = if self.unprivileged {
+            Some(UserNamespace::enter()?)
+        } else {
+            None
+        };
+
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[
+                (HomeLockScope::ObjectDB, LockMode::Shared),
+                (HomeLockScope::Builder, LockMode::Shared),
+            ],
+            cli.lock.wait(),
+        )?;
+        let arch = match &self.arch {
+            Some(arch) => arch.clone(),
+            None => Architecture::new_uname()?,
+        };
+
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        // The packages of every wave are available to the following waves
+        let mut index = match &self.index {
+            Some(oid) => RepositoryIndex::from_odb(&odb, oid).ctx(|| "Reading repository index")?,
+            None => RepositoryIndex::new(),
+        };
+
+        let builder = Builder::new(&home, self.compression)?
+            .with_toolchain(self.toolchain.clone())
+            .with_allow_overlap(self.allow_overlap)
+            .with_user_namespace(user_namespace)
+            .with_failure_action(self.on_failure)
+            .with_report(self.report.clone())
+            .with_passthrough_env(self.pass_env.clone())
+            .with_step_snapshots(self.step_snapshots)
+            .with_prune_dependencies(self.prune_dependencies);
+
+        let mut built = 0;
+        for (i, wave) in waves.iter().enumerate() {
+            info!("Building wave {}", i + 1);
+
+            let packages = self.build_wave(wave, &home, &builder, &arch, &index)?;
+
+            for (entry, package) in wave.iter().zip(packages) {
+                let oid = package
+                    .entry
+                    .package
+                    .clone()
+                    .expect("Packages of waves are built");
+                if package.built {
+                    println!("{entry}: built ({oid})");
+                    built += 1;
+                } else {
+                    println!("{entry}: up to date ({oid})");
+                }
+
+                index.insert(package.entry);
+            }
+        }
+
+        if built > 0 {
+            let object = index
+                .insert_into_odb(&mut odb, self.compression)
+                .ctx(|| "Inserting repository index of the built packages")?;
+            println!("Built {built} packages, recorded in {}", object.oid);
+        }
+
+        Ok(0)
+    }
+
+    /// Builds the formulae of a wave that have not been built yet using up to `--jobs` threads
+    /// # Arguments
+    /// * `wave` - The formulae to build
+    /// * `home` - The home to build in
+    /// * `builder` - The builder to build the formulae with
+    /// * `arch` - The architecture to build the formulae for
+    /// * `index` - The repository index to resolve the dependencies from
+    /// # Returns
+    /// The packages of the formulae, in the order of `wave`
+    fn build_wave(
+        &self,
+        wave: &[&FormulaSetEntry],
+        home: &Home,
+        builder: &Builder,
+        arch: &Architecture,
+        index: &RepositoryIndex,
+    ) -> Result<Vec<WavePackage>, Error> {
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let packages = Mutex::new((0..wave.len()).map(|_| None).collect::<Vec<_>>());
+
+        let build = || -> Result<(), Error> {
+            let driver = FilesystemDriver::new(home.object_db_path())?;
+            let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+            while !failed.load(Ordering::Relaxed) {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(entry) = wave.get(i) else {
+                    break;
+                };
+
+                info!("Building {entry}");
+                let (entry, built) = builder
+                    .resolve_and_build(&entry.path, arch, index, &mut odb)
+                    .ctx(|| format!("Building {entry}"))
+                    .inspect_err(|_| {
+                        failed.store(true, Ordering::Relaxed);
+                    })?;
+
+                packages.lock().expect("Lock packages")[i] = Some(WavePackage { entry, built });
+            }
+
+            Ok(())
+        };
+
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..self.jobs.max(1)).map(|_| s.spawn(build)).collect();
+
+            handles
+                .into_iter()
+                .try_for_each(|h| h.join().expect("Join build thread"))
+        })?;
+
+        Ok(packages
+            .into_inner()
+            .expect("Unlock packages")
+            .into_iter()
+            .map(|package| package.expect("Every formula of the wave has been built"))
+            .collect())
+    }
+}
@@ -0,0 +1,85 @@
+use clap::{ArgGroup, Parser};
+use log::warn;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{Home, HomeLockScope},
+    util::fs::{self, FileLock, LockMode, PathUtil},
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+#[command(group(ArgGroup::new("what").required(true).multiple(true)))]
+pub struct CommandClean {
+    /// Remove the build directories that are not in use by a running build
+    #[arg(long, action, group = "what")]
+    builds: bool,
+}
+
+impl CommandClean {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[(HomeLockScope::Builder, LockMode::Exclusive)],
+            cli.lock.wait(),
+        )?;
+
+        if self.builds {
+            let removed = clean_builds(&home)?;
+            println!("Removed {removed} build directories");
+        }
+
+        Ok(0)
+    }
+}
+
+/// Removes the build directories of `home` that are not locked by a running build.
+///
+/// Directories with mounts below them are skipped with a warning, as
+/// removing them would remove the contents of the mounted filesystems
+/// # Arguments
+/// * `home` - The home to clean the build directories of
+/// # Returns
+/// The number of removed build directories
+fn clean_builds(home: &Home) -> Result<usize, Error> {
+    let builds_dir = home.get_builds_dir();
+    if !builds_dir.exists() {
+        return Ok(0);
+    }
+
+    let context = || format!("Reading build directories in {}", builds_dir.str_lossy());
+    let mut removed = 0;
+
+    for entry in std::fs::read_dir(&builds_dir).e_context(context)? {
+        let entry = entry.e_context(context)?;
+        if !entry.file_type().e_context(context)?.is_dir() {
+            continue;
+        }
+
+        let id = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+
+        // Hold the lock while removing, so no build can start using the directory
+        let Some(_lock) = FileLock::try_lock(&home.get_build_lock_path(&id))? else {
+            warn!("Skipping build {id}, it is in use");
+            continue;
+        };
+
+        let canonical = std::fs::canonicalize(&path)
+            .e_context(|| format!("Canonicalizing {}", path.str_lossy()))?;
+        let mounts = fs::mount_points_under(&canonical)?;
+        if !mounts.is_empty() {
+            let mounts: Vec<String> = mounts.iter().map(|m| m.str_lossy()).collect();
+            warn!(
+                "Skipping build {id}, unmount the mounts left below it first: {}",
+                mounts.join(", ")
+            );
+            continue;
+        }
+
+        fs::remove_dir_all(&path).ctx(|| format!("Removing build {id}"))?;
+        removed += 1;
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,233 @@
+use std::io;
+
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{
+        odb_driver::FilesystemDriver, Formula, ObjectDB, ObjectID, ObjectType, RepositoryIndex,
+        RepositoryIndexEntry, Tree, TreeDifference, TreeEntry,
+    },
+    util::{fs::PathUtil, ODBUnpackable},
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandDiffPackages {
+    /// The object id of the repository index listing the packages,
+    /// searched in the indices depending on each package if omitted
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// The object id of the old package
+    old: ObjectID,
+
+    /// The object id of the new package
+    new: ObjectID,
+}
+
+/// A package to compare, along with its repository index entry if there is one
+struct DiffPackage {
+    /// The object id of the package
+    oid: ObjectID,
+    /// The tree of the package's files
+    tree: Tree,
+    /// The repository index and the entry listing the package
+    entry: Option<(RepositoryIndex, RepositoryIndexEntry)>,
+    /// The formula the package has been built from
+    formula: Option<Formula>,
+}
+
+impl CommandDiffPackages {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let old = self.load(&odb, &self.old)?;
+        let new = self.load(&odb, &self.new)?;
+
+        println!("--- {}", old.describe());
+        println!("+++ {}", new.describe());
+
+        let mut size_delta: i64 = 0;
+        for difference in old.tree.diff(&new.tree) {
+            let path = difference.path();
+            let old_file = file_oid(old.tree.get_entry(path));
+            let new_file = file_oid(new.tree.get_entry(path));
+
+            match &difference {
+                TreeDifference::Added(_) => match &new_file {
+                    Some(oid) => println!("+ {} ({oid})", path.str_lossy()),
+                    None => println!("{difference}"),
+                },
+                TreeDifference::Removed(_) => match &old_file {
+                    Some(oid) => println!("- {} ({oid})", path.str_lossy()),
+                    None => println!("{difference}"),
+                },
+                TreeDifference::ContentChanged(_) => match (&old_file, &new_file) {
+                    (Some(old_oid), Some(new_oid)) => {
+                        println!("~ {} ({old_oid} => {new_oid})", path.str_lossy())
+                    }
+                    _ => println!("{difference}"),
+                },
+                TreeDifference::TypeChanged(_) | TreeDifference::InfoChanged { .. } => {
+                    println!("{difference}")
+                }
+            }
+
+            // Only the contents of differing files contribute to the size delta
+            if matches!(difference, TreeDifference::InfoChanged { .. }) {
+                continue;
+            }
+            size_delta -= files_size(&odb, old.tree.get_entry(path))? as i64;
+            size_delta += files_size(&odb, new.tree.get_entry(path))? as i64;
+        }
+
+        let (old_deps, new_deps) = (old.dependencies(), new.dependencies());
+        for dep in &old_deps {
+            if !new_deps.contains(dep) {
+                println!("- dependency {}", old.name_dependency(dep));
+            }
+        }
+        for dep in &new_deps {
+            if !old_deps.contains(dep) {
+                println!("+ dependency {}", new.name_dependency(dep));
+            }
+        }
+
+        println!("size: {size_delta:+} bytes");
+
+        Ok(0)
+    }
+
+    /// Reads a package and finds its repository index entry and formula
+    /// # Arguments
+    /// * `odb` - The object database to read from
+    /// * `oid` - The object id of the package
+    fn load(&self, odb: &ObjectDB, oid: &ObjectID) -> Result<DiffPackage, Error> {
+        let mut object = odb.read(oid).ctx(|| format!("Reading package {oid}"))?;
+        let tree = Tree::unpack_from_odb(&mut object, odb)
+            .ctx(|| format!("Reading package tree {oid}"))?;
+
+        let entry = self.find_entry(odb, oid)?;
+        let formula = match entry.as_ref().and_then(|(_, e)| e.formula.as_ref()) {
+            Some(formula) => Some(
+                Formula::from_odb(odb, formula)
+                    .ctx(|| format!("Reading formula of package {oid}"))?,
+            ),
+            None => None,
+        };
+
+        Ok(DiffPackage {
+            oid: oid.clone(),
+            tree,
+            entry,
+            formula,
+        })
+    }
+
+    /// Finds the repository index entry of a package in the index passed
+    /// using `--index` or in the repository indices depending on the package
+    /// # Arguments
+    /// * `odb` - The object database to read the indices from
+    /// * `package` - The object id of the package
+    fn find_entry(
+        &self,
+        odb: &ObjectDB,
+        package: &ObjectID,
+    ) -> Result<Option<(RepositoryIndex, RepositoryIndexEntry)>, Error> {
+        let indices = match &self.index {
+            Some(index) => vec![index.clone()],
+            None => {
+                let mut indices = Vec::new();
+                for dependent in odb.reverse_dependencies(package)? {
+                    if odb.get_object(&dependent)?.ty == ObjectType::AcaciaRepositoryIndex {
+                        indices.push(dependent);
+                    }
+                }
+                indices
+            }
+        };
+
+        for oid in indices {
+            let index = RepositoryIndex::from_odb(odb, &oid)
+                .ctx(|| format!("Reading repository index {oid}"))?;
+
+            if let Some(entry) = index
+                .entries
+                .iter()
+                .find(|e| e.package.as_ref() == Some(package))
+            {
+                let entry = entry.clone();
+                return Ok(Some((index, entry)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl DiffPackage {
+    /// Returns the object id of the package, along with its name and version if known
+    fn describe(&self) -> String {
+        match &self.entry {
+            Some((_, entry)) => format!("{} ({entry})", self.oid),
+            None => self.oid.to_string(),
+        }
+    }
+
+    /// Returns the runtime dependencies (target and extra dependencies) of the formula
+    fn dependencies(&self) -> Vec<ObjectID> {
+        self.formula
+            .iter()
+            .flat_map(|f| f.target_dependencies.iter().chain(&f.extra_dependencies))
+            .cloned()
+            .collect()
+    }
+
+    /// Names a dependency by the repository index entry providing it, if there is one
+    /// # Arguments
+    /// * `oid` - The object id of the dependency
+    fn name_dependency(&self, oid: &ObjectID) -> String {
+        self.entry
+            .as_ref()
+            .and_then(|(index, _)| index.entries.iter().find(|e| e.get_oids().contains(oid)))
+            .map(|e| format!("{e} ({oid})"))
+            .unwrap_or_else(|| oid.to_string())
+    }
+}
+
+/// Returns the object id of an entry if it is a file
+/// # Arguments
+/// * `entry` - The entry to inspect
+fn file_oid(entry: Option<&TreeEntry>) -> Option<ObjectID> {
+    match entry {
+        Some(TreeEntry::File {
+            info: _,
+            name: _,
+            oid,
+        }) => Some(oid.clone()),
+        _ => None,
+    }
+}
+
+/// Returns the size of the data of all files of an entry, recursing into subtrees
+/// # Arguments
+/// * `odb` - The object database to read the files from
+/// * `entry` - The entry to measure
+fn files_size(odb: &ObjectDB, entry: Option<&TreeEntry>) -> Result<u64, Error> {
+    match entry {
+        Some(TreeEntry::File {
+            info: _,
+            name: _,
+            oid,
+        }) => io::copy(&mut odb.read(oid)?, &mut io::sink()).ctx(|| format!("Reading file {oid}")),
+        Some(TreeEntry::Subtree {
+            info: _,
+            name: _,
+            tree,
+        }) => tree.entries.iter().map(|e| files_size(odb, Some(e))).sum(),
+        _ => Ok(0),
+    }
+}
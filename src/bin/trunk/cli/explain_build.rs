@@ -0,0 +1,60 @@
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{odb_driver::FilesystemDriver, BuildReceipt, ObjectDB, ObjectID},
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandExplainBuild {
+    /// Print the receipts as JSON
+    #[arg(long, action)]
+    json: bool,
+
+    /// Only print the audit of the mounts and environment variables of each build
+    #[arg(long, action)]
+    audit: bool,
+
+    /// The object id of the package to explain
+    package: ObjectID,
+}
+
+impl CommandExplainBuild {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let receipts = BuildReceipt::find(&odb, &self.package)
+            .ctx(|| format!("Finding build receipts of {}", self.package))?;
+
+        if receipts.is_empty() {
+            return Err(Error::new(ErrorType::Other(format!(
+                "There is no build receipt for {}",
+                self.package
+            ))));
+        }
+
+        for (i, (oid, receipt)) in receipts.iter().enumerate() {
+            if self.json {
+                println!("{}", receipt.json());
+                continue;
+            }
+
+            if i > 0 {
+                println!();
+            }
+            println!("Receipt: {oid}");
+            if self.audit {
+                if receipt.tainted {
+                    println!("Tainted: Sources have been overridden by local directories");
+                }
+                println!("{}", receipt.get_audit());
+            } else {
+                println!("{receipt}");
+            }
+        }
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use log::info;
+use tooling::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{
+        odb_driver::FilesystemDriver, BuildReceipt, Formula, HomeLockScope, ObjectDB, ObjectID,
+        ObjectType, RepositoryIndex, RepositoryIndexEntry, Signature, Tree,
+    },
+    package::{write_package_archive, PackageDependency, PackageMetadata},
+    util::{
+        fs::{self, LockMode, PathUtil},
+        ODBUnpackable,
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandExportPackage {
+    /// The object id of the repository index listing the package,
+    /// searched in the indices depending on the package if omitted
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// The path of the `tar.xz` archive to create
+    #[arg(long, short)]
+    output: PathBuf,
+
+    /// The object id of the package to export
+    package: ObjectID,
+}
+
+impl CommandExportPackage {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[(HomeLockScope::ObjectDB, LockMode::Shared)],
+            cli.lock.wait(),
+        )?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let (index, entry) = self.find_entry(&odb)?;
+
+        let formula = match &entry.formula {
+            Some(formula) => Some(
+                Formula::from_odb(&odb, formula).ctx(|| format!("Reading formula of {entry}"))?,
+            ),
+            None => None,
+        };
+
+        let mut object = odb
+            .read(&self.package)
+            .ctx(|| format!("Reading package {}", self.package))?;
+        let tree = Tree::unpack_from_odb(&mut object, &odb)
+            .ctx(|| format!("Reading package tree {}", self.package))?;
+
+        info!("Exporting {entry} to {}", self.output.str_lossy());
+
+        // Runtime dependencies are named by the entries providing them
+        let depends = formula
+            .iter()
+            .flat_map(|f| f.target_dependencies.iter().chain(&f.extra_dependencies))
+            .filter_map(|oid| {
+                index
+                    .entries
+                    .iter()
+                    .find(|e| e.get_oids().contains(oid))
+                    .map(|e| PackageDependency {
+                        name: e.name.clone(),
+                        oid: Some(oid.clone()),
+                    })
+            })
+            .collect();
+
+        let receipt = BuildReceipt::find(&odb, &self.package)?
+            .pop()
+            .map(|(oid, _)| oid);
+        let signature = Signature::find(&odb, &self.package)?.into_iter().next();
+
+        let metadata = PackageMetadata::new(&entry, formula.as_ref())
+            .with_depends(depends)
+            .with_receipt(receipt)
+            .with_signature(signature);
+        let file = fs::file_create(&self.output)?;
+        write_package_archive(file, &metadata, &tree, &odb)?;
+
+        Ok(0)
+    }
+
+    /// Finds the repository index entry of the package in the index passed
+    /// using `--index` or in the repository indices depending on the package
+    /// # Arguments
+    /// * `odb` - The object database to read the indices from
+    /// # Returns
+    /// The repository index and the entry of the package
+    fn find_entry(&self, odb: &ObjectDB) -> Result<(RepositoryIndex, RepositoryIndexEntry), Error> {
+        let indices = match &self.index {
+            Some(index) => vec![index.clone()],
+            None => {
+                let mut indices = Vec::new();
+                for dependent in odb.reverse_dependencies(&self.package)? {
+                    if odb.get_object(&dependent)?.ty == ObjectType::AcaciaRepositoryIndex {
+                        indices.push(dependent);
+                    }
+                }
+                indices
+            }
+        };
+
+        for oid in indices {
+            let index = RepositoryIndex::from_odb(odb, &oid)
+                .ctx(|| format!("Reading repository index {oid}"))?;
+
+            if let Some(entry) = index
+                .entries
+                .iter()
+                .find(|e| e.package.as_ref() == Some(&self.package))
+            {
+                let entry = entry.clone();
+                return Ok((index, entry));
+            }
+        }
+
+        Err(Error::new(ErrorType::Other(format!(
+            "No repository index lists the package {}, use '--index'",
+            self.package
+        ))))
+    }
+}
@@ -0,0 +1,37 @@
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{odb_driver::FilesystemDriver, ObjectDB, ObjectID, RepositoryIndex},
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandImpact {
+    /// The object id of the repository index to search for affected packages
+    #[arg(long)]
+    index: ObjectID,
+
+    /// The object id of the changed formula
+    formula: ObjectID,
+}
+
+impl CommandImpact {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let index =
+            RepositoryIndex::from_odb(&odb, &self.index).ctx(|| "Reading repository index")?;
+
+        let affected = index
+            .rebuild_impact(&odb, &self.formula)
+            .ctx(|| format!("Analyzing rebuild impact of {}", self.formula))?;
+
+        for entry in affected {
+            println!("{entry}");
+        }
+
+        Ok(0)
+    }
+}
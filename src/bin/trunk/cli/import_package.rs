@@ -0,0 +1,155 @@
+use std::{io::Read, path::PathBuf};
+
+use clap::Parser;
+use log::info;
+use tooling::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{
+        odb_driver::FilesystemDriver, HomeLockScope, ObjectCompression, ObjectDB, ObjectID,
+        RepositoryIndex, RepositoryIndexEntry, Tree, TreeEntry,
+    },
+    package::{PackageMetadata, PACKAGE_METADATA_FILE, PACKAGE_ROOT_DIR},
+    util::{
+        architecture::Architecture,
+        archive,
+        fs::{LockMode, PathUtil},
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandImportPackage {
+    /// The object id of the repository index to resolve the dependencies from
+    /// and to add the package to, prints the object id of the new index
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// The package archive to import (`trunk export-package`)
+    archive: PathBuf,
+}
+
+impl CommandImportPackage {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[(HomeLockScope::ObjectDB, LockMode::Shared)],
+            cli.lock.wait(),
+        )?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let context = || format!("Importing package archive {}", self.archive.str_lossy());
+
+        let mut tar = archive::open_tar_infer(&self.archive)?;
+        let archive_tree =
+            Tree::index_tar(&mut tar, &mut odb, ObjectCompression::Xz).ctx(context)?;
+
+        let metadata = match archive_tree.get_entry_by_name(PACKAGE_METADATA_FILE) {
+            Some(TreeEntry::File {
+                info: _,
+                name: _,
+                oid,
+            }) => {
+                let mut toml = String::new();
+                odb.read(oid)?
+                    .read_to_string(&mut toml)
+                    .e_context(context)?;
+                PackageMetadata::parse(&toml).ctx(context)?
+            }
+            _ => return Err(missing(PACKAGE_METADATA_FILE)).ctx(context),
+        };
+
+        let tree = match archive_tree.get_entry_by_name(PACKAGE_ROOT_DIR) {
+            Some(TreeEntry::Subtree {
+                info: _,
+                name: _,
+                tree,
+            }) => tree,
+            _ => return Err(missing(PACKAGE_ROOT_DIR)).ctx(context),
+        };
+
+        let mut index = match &self.index {
+            Some(oid) => {
+                Some(RepositoryIndex::from_odb(&odb, oid).ctx(|| "Reading repository index")?)
+            }
+            None => None,
+        };
+
+        // The dependencies become dependencies of the package object, the ones with an
+        // object id available in the object database are used as is, keeping their order
+        let mut dependencies = Vec::new();
+        for dependency in &metadata.depends {
+            if let Some(oid) = &dependency.oid {
+                if odb.try_get_object(oid)?.is_some() {
+                    dependencies.push(oid.clone());
+                    continue;
+                }
+            }
+
+            let requirement = &dependency.name;
+            let index = index.as_ref().ok_or_else(|| {
+                Error::new(ErrorType::Other(format!(
+                    "{} depends on '{requirement}', use '--index' to resolve the dependencies",
+                    metadata.name,
+                )))
+            })?;
+
+            let arch = match &metadata.arch {
+                Some(arch) => arch.clone(),
+                None => Architecture::new_uname()?,
+            };
+
+            let entry = index.resolve(requirement, &arch).ok_or_else(|| {
+                Error::new(ErrorType::Other(format!(
+                    "No package for '{requirement}' available for {arch}"
+                )))
+            })?;
+            dependencies.extend(entry.package.clone());
+        }
+
+        let package = tree
+            .insert_into_odb_with_dependencies(&mut odb, ObjectCompression::Xz, dependencies)
+            .ctx(context)?;
+
+        info!(
+            "Imported {}@{} as {} (exported from {})",
+            metadata.name, metadata.version, package.oid, metadata.oid
+        );
+        println!("{}", package.oid);
+
+        if let Some(index) = &mut index {
+            // The formula is only referenced if it is available
+            let formula = match &metadata.formula {
+                Some(formula) => odb.try_get_object(formula)?.map(|_| formula.clone()),
+                None => None,
+            };
+
+            index.insert(RepositoryIndexEntry {
+                name: metadata.name,
+                version: metadata.version,
+                arch: metadata.arch,
+                provides: metadata.provides,
+                license: metadata.license,
+                formula,
+                package: Some(package.oid),
+            });
+
+            let object = index
+                .insert_into_odb(&mut odb, ObjectCompression::Xz)
+                .ctx(|| "Inserting the repository index")?;
+            println!("{}", object.oid);
+        }
+
+        Ok(0)
+    }
+}
+
+/// Returns the error for a package archive missing `name`
+/// # Arguments
+/// * `name` - The name of the missing entry
+fn missing(name: &str) -> Error {
+    Error::new(ErrorType::Other(format!(
+        "The archive has no '{name}', it is no package archive"
+    )))
+}
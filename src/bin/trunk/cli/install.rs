@@ -0,0 +1,206 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use log::{info, warn};
+use tooling::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{
+        odb_driver::FilesystemDriver, FileConflict, FileIndex, Formula, HomeLockScope, ObjectDB,
+        ObjectID, RepositoryIndex, RepositoryIndexEntry, Tree,
+    },
+    package::ScriptletKind,
+    util::{
+        architecture::Architecture,
+        fs::{LockMode, PathUtil},
+        progress::ProgressBar,
+        ODBUnpackable,
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandInstall {
+    /// The object id of the repository index to resolve the packages from
+    #[arg(long)]
+    index: ObjectID,
+
+    /// The root directory to install the packages to
+    #[arg(long, default_value = "/")]
+    root: PathBuf,
+
+    /// Do not run the `post_install` scriptlets (e.g. for offline image builds)
+    #[arg(long, action)]
+    no_scriptlets: bool,
+
+    /// Allow overwriting the files owned by this installed package, which the
+    /// installed packages take over, can be given multiple times
+    #[arg(long)]
+    replace_owner: Vec<String>,
+
+    /// The packages (names or provided capabilities) to install
+    #[arg(required = true)]
+    packages: Vec<String>,
+}
+
+/// A package resolved from a repository index
+pub struct ResolvedPackage {
+    /// The entry of the package in the repository index
+    pub entry: RepositoryIndexEntry,
+    /// The tree of the package's files
+    pub tree: Tree,
+    /// The formula of the package, if the entry has one
+    pub formula: Option<Formula>,
+}
+
+impl CommandInstall {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[(HomeLockScope::ObjectDB, LockMode::Shared)],
+            cli.lock.wait(),
+        )?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let packages = resolve_packages(&odb, &self.index, &self.packages)?;
+        let mut file_index = FileIndex::from_root(&odb, &self.root)?;
+        let replaced = self.check_conflicts(&file_index, &packages)?;
+
+        for package in &packages {
+            info!("Installing {} to {}", package.entry, self.root.str_lossy());
+            let mut bar = ProgressBar::new(&package.entry.to_string());
+            package
+                .tree
+                .deploy_with_progress(&self.root, &odb, &mut |p| bar.update_deploy(p))
+                .ctx(|| format!("Installing {}", package.entry))?;
+            bar.finish();
+
+            file_index.insert_package(&package.entry, &package.tree);
+        }
+
+        for conflict in &replaced {
+            for owner in &conflict.owners {
+                file_index.disown(&conflict.path, &owner.oid);
+            }
+        }
+
+        let keep = home.load_config()?.generations.keep;
+        file_index
+            .record_generation(&mut odb, keep)
+            .ctx(|| "Recording the generation of the root directory")?;
+        file_index
+            .store_for_root(&mut odb, &self.root)
+            .ctx(|| "Updating the file index of the installed packages")?;
+
+        // Scriptlets run once all packages are in place, they may depend on each other
+        for package in &packages {
+            let scriptlet = package
+                .formula
+                .as_ref()
+                .and_then(|f| f.get_scriptlet(ScriptletKind::PostInstall));
+
+            match scriptlet {
+                Some(scriptlet) if self.no_scriptlets => info!("Skipping {scriptlet}"),
+                Some(scriptlet) => scriptlet.run(&self.root)?,
+                None => {}
+            }
+        }
+
+        Ok(0)
+    }
+
+    /// Checks the files of `packages` against the files owned by the installed packages
+    /// and the packages installed before them, before any package gets deployed
+    /// # Arguments
+    /// * `file_index` - The file index of the installed packages
+    /// * `packages` - The packages to install, in installation order
+    /// # Returns
+    /// The conflicts with the owners given by `--replace-owner`, whose files get taken over
+    /// # Errors
+    /// Listing all other conflicts
+    fn check_conflicts(
+        &self,
+        file_index: &FileIndex,
+        packages: &[ResolvedPackage],
+    ) -> Result<Vec<FileConflict>, Error> {
+        let mut planned = file_index.clone();
+        let mut replaced = Vec::new();
+        let mut msg = String::new();
+
+        for package in packages {
+            let name = format!("{}@{}", package.entry.name, package.entry.version);
+
+            for conflict in planned.find_conflicts(&package.entry.name, &package.tree) {
+                if conflict
+                    .owners
+                    .iter()
+                    .all(|o| self.replace_owner.contains(&o.name))
+                {
+                    warn!("{name} takes over {conflict}");
+                    replaced.push(conflict);
+                } else {
+                    msg.push_str(&format!("\n  {name}: {conflict}"));
+                }
+            }
+
+            planned.insert_package(&package.entry, &package.tree);
+        }
+
+        if !msg.is_empty() {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Installing to {} would overwrite files of other packages \
+                 (use --replace-owner <PACKAGE> to take them over):{msg}",
+                self.root.str_lossy()
+            ))));
+        }
+
+        Ok(replaced)
+    }
+}
+
+/// Resolves `packages` from the repository index `index` for the host architecture
+/// # Arguments
+/// * `odb` - The object database to read the index, trees and formulae from
+/// * `index` - The object id of the repository index
+/// * `packages` - The package names or provided capabilities to resolve
+pub fn resolve_packages(
+    odb: &ObjectDB,
+    index: &ObjectID,
+    packages: &[String],
+) -> Result<Vec<ResolvedPackage>, Error> {
+    let index = RepositoryIndex::from_odb(odb, index).ctx(|| "Reading repository index")?;
+    let arch = Architecture::new_uname()?;
+
+    let mut resolved = Vec::new();
+    for package in packages {
+        let entry = index.resolve(package, &arch).ok_or_else(|| {
+            Error::new(ErrorType::Other(format!(
+                "No package for '{package}' available for {arch}"
+            )))
+        })?;
+        let oid = entry
+            .package
+            .as_ref()
+            .expect("Resolved entries have packages");
+
+        let mut object = odb.read(oid).ctx(|| format!("Reading package {oid}"))?;
+        let tree = Tree::unpack_from_odb(&mut object, odb)
+            .ctx(|| format!("Reading package tree {oid}"))?;
+
+        let formula = match &entry.formula {
+            Some(formula) => Some(
+                Formula::from_odb(odb, formula).ctx(|| format!("Reading formula of {entry}"))?,
+            ),
+            None => None,
+        };
+
+        resolved.push(ResolvedPackage {
+            entry: entry.clone(),
+            tree,
+            formula,
+        });
+    }
+
+    Ok(resolved)
+}
@@ -0,0 +1,90 @@
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{
+        odb_driver::{FilesystemDriver, HTTPDriver},
+        Formula, HomeLockScope, Keyring, ObjectCompression, ObjectDB, ObjectID,
+        RepositoryIndexEntry,
+    },
+    tools::publisher::Publisher,
+    util::fs::LockMode,
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandPublish {
+    /// The URL of the remote object database (`http://host:8080`)
+    #[arg(long)]
+    remote: String,
+
+    /// The upload token of the remote object database
+    #[arg(long)]
+    token: String,
+
+    /// The object id of the formula the package has been built from
+    #[arg(long)]
+    formula: ObjectID,
+
+    /// Sign the package using the signing key and upload the signature
+    #[arg(long, action)]
+    sign: bool,
+
+    /// The object id of the package to publish
+    package: ObjectID,
+}
+
+impl CommandPublish {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        // Partial downloads from the remote are kept in the temporary directory
+        let _locks = home.lock_all(
+            &[
+                (HomeLockScope::ObjectDB, LockMode::Shared),
+                (HomeLockScope::Builder, LockMode::Shared),
+            ],
+            cli.lock.wait(),
+        )?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver.clone())).ctx(|| "Opening object db")?;
+
+        let formula = Formula::from_odb(&odb, &self.formula)
+            .ctx(|| format!("Reading formula {}", self.formula))?;
+
+        let entry = RepositoryIndexEntry {
+            name: formula.name,
+            version: formula.version,
+            arch: formula.arch,
+            provides: formula.provides,
+            license: formula.license,
+            formula: Some(self.formula.clone()),
+            package: Some(self.package.clone()),
+        };
+
+        let remote = HTTPDriver::new(&self.remote, home.get_downloads_dir())?;
+        let publisher = Publisher::new(driver, remote, self.token.clone());
+
+        if self.sign {
+            let keyring = Keyring::load(&home.get_keys_dir())?;
+            let signature = odb
+                .sign(
+                    &self.package,
+                    keyring.get_signing_key()?,
+                    ObjectCompression::None,
+                )
+                .ctx(|| format!("Signing {}", self.package))?;
+
+            publisher
+                .upload_closure(&signature.oid)
+                .ctx(|| format!("Uploading signature {}", signature.oid))?;
+        }
+
+        let index = publisher
+            .publish(&mut odb, entry, ObjectCompression::Xz)
+            .ctx(|| format!("Publishing {} to {}", self.package, self.remote))?;
+
+        println!("{index}");
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use log::{debug, info};
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{odb_driver::FilesystemDriver, FileIndex, HomeLockScope, ObjectDB, ObjectID},
+    package::ScriptletKind,
+    util::fs::{self, LockMode, PathUtil},
+};
+
+use super::{install::resolve_packages, Cli};
+
+#[derive(Parser)]
+pub struct CommandRemove {
+    /// The object id of the repository index the packages were installed from
+    #[arg(long)]
+    index: ObjectID,
+
+    /// The root directory to remove the packages from
+    #[arg(long, default_value = "/")]
+    root: PathBuf,
+
+    /// Do not run the `pre_remove` scriptlets (e.g. for offline image builds)
+    #[arg(long, action)]
+    no_scriptlets: bool,
+
+    /// The packages (names or provided capabilities) to remove
+    #[arg(required = true)]
+    packages: Vec<String>,
+}
+
+impl CommandRemove {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[(HomeLockScope::ObjectDB, LockMode::Shared)],
+            cli.lock.wait(),
+        )?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let packages = resolve_packages(&odb, &self.index, &self.packages)?;
+        let mut file_index = FileIndex::from_root(&odb, &self.root)?;
+
+        // Scriptlets run while all packages are still in place
+        for package in &packages {
+            let scriptlet = package
+                .formula
+                .as_ref()
+                .and_then(|f| f.get_scriptlet(ScriptletKind::PreRemove));
+
+            match scriptlet {
+                Some(scriptlet) if self.no_scriptlets => info!("Skipping {scriptlet}"),
+                Some(scriptlet) => scriptlet.run(&self.root)?,
+                None => {}
+            }
+        }
+
+        // Files that other packages have taken over stay in place
+        let removed: Vec<&ObjectID> = packages
+            .iter()
+            .filter_map(|p| p.entry.package.as_ref())
+            .collect();
+        let owned_by_others = |file: &Path| {
+            file_index
+                .files
+                .get(&file.str_lossy())
+                .is_some_and(|owners| owners.iter().any(|o| !removed.contains(&o)))
+        };
+
+        for package in &packages {
+            info!("Removing {} from {}", package.entry, self.root.str_lossy());
+
+            let files = package.tree.get_file_paths();
+            for file in &files {
+                if owned_by_others(file) {
+                    debug!("Keeping {}, owned by another package", file.str_lossy());
+                    continue;
+                }
+
+                let path = self.root.join(file);
+
+                if path.symlink_metadata().is_ok() {
+                    fs::remove_file(&path).ctx(|| format!("Removing {}", package.entry))?;
+                }
+            }
+
+            // Remove the directories that are empty now, deepest first
+            let mut dirs: Vec<PathBuf> = files
+                .iter()
+                .flat_map(|f| f.ancestors().skip(1))
+                .filter(|d| !d.as_os_str().is_empty())
+                .map(|d| d.to_owned())
+                .collect();
+            dirs.sort_by(|a, b| {
+                b.components()
+                    .count()
+                    .cmp(&a.components().count())
+                    .then_with(|| a.cmp(b))
+            });
+            dirs.dedup();
+
+            for dir in dirs {
+                // Directories shared with other packages are not empty and stay
+                if std::fs::remove_dir(self.root.join(&dir)).is_ok() {
+                    debug!("Removed empty directory {}", dir.str_lossy());
+                }
+            }
+        }
+
+        for package in &packages {
+            if let Some(oid) = &package.entry.package {
+                file_index.remove_package(oid);
+            }
+        }
+        let keep = home.load_config()?.generations.keep;
+        file_index
+            .record_generation(&mut odb, keep)
+            .ctx(|| "Recording the generation of the root directory")?;
+        file_index
+            .store_for_root(&mut odb, &self.root)
+            .ctx(|| "Updating the file index of the installed packages")?;
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use log::info;
+use tooling::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{odb_driver::FilesystemDriver, FileIndex, HomeLockScope, ObjectDB, ObjectID, Tree},
+    util::{
+        fs::{LockMode, PathUtil},
+        ODBUnpackable,
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandRollback {
+    /// The root directory to roll back
+    #[arg(long, default_value = "/")]
+    root: PathBuf,
+
+    /// The number of the generation to roll back to [the previous one]
+    #[arg(long)]
+    to: Option<u32>,
+
+    /// List the generations of the root directory instead of rolling back
+    #[arg(long, action)]
+    list: bool,
+}
+
+impl CommandRollback {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(
+            &[(HomeLockScope::ObjectDB, LockMode::Shared)],
+            cli.lock.wait(),
+        )?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let mut file_index = FileIndex::from_root(&odb, &self.root)?;
+
+        if self.list {
+            for generation in &file_index.generations {
+                let marker = if file_index.generation == Some(generation.number) {
+                    '*'
+                } else {
+                    ' '
+                };
+
+                println!(
+                    "{marker} {:>4}  {}  {} packages  {}",
+                    generation.number,
+                    generation.timestamp,
+                    generation.packages.len(),
+                    generation.tree
+                );
+            }
+
+            return Ok(0);
+        }
+
+        let current = file_index.current_generation().cloned().ok_or_else(|| {
+            Error::new(ErrorType::Other(format!(
+                "No generation has been recorded for {}",
+                self.root.str_lossy()
+            )))
+        })?;
+
+        let target = match self.to {
+            Some(number) => file_index.get_generation(number),
+            None => file_index.previous_generation(),
+        }
+        .cloned()
+        .ok_or_else(|| {
+            let msg = match self.to {
+                Some(number) => format!("There is no generation {number}"),
+                None => format!("Generation {} is the oldest one", current.number),
+            };
+            Error::new(ErrorType::Other(msg))
+        })?;
+
+        if target.number == current.number {
+            eprintln!(
+                "{} is at generation {} already",
+                self.root.str_lossy(),
+                current.number
+            );
+            return Ok(0);
+        }
+
+        let previous = read_tree(&odb, &current.tree)?;
+        let tree = read_tree(&odb, &target.tree)?;
+
+        info!(
+            "Rolling back {} from generation {} to {}",
+            self.root.str_lossy(),
+            current.number,
+            target.number
+        );
+        tree.deploy_replacing(&previous, &self.root, &odb)
+            .ctx(|| format!("Rolling back to generation {}", target.number))?;
+
+        file_index.restore_generation(&odb, target.number)?;
+        file_index
+            .store_for_root(&mut odb, &self.root)
+            .ctx(|| "Updating the file index of the installed packages")?;
+
+        Ok(0)
+    }
+}
+
+/// Reads the tree of the generation with the tree `oid`
+/// # Arguments
+/// * `odb` - The object database to read the tree from
+/// * `oid` - The object id of the tree
+fn read_tree(odb: &ObjectDB, oid: &ObjectID) -> Result<Tree, Error> {
+    let mut object = odb.read(oid).ctx(|| format!("Reading tree {oid}"))?;
+    Tree::unpack_from_odb(&mut object, odb).ctx(|| format!("Reading tree {oid}"))
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use clap::Parser;
+use tooling::{
+    error::Error,
+    model::{odb_driver::FilesystemDriver, HomeLockScope, ObjectID},
+    tools::server::{ODBServer, DEFAULT_SERVER_WORKERS, INDEX_REF, MAX_OBJECT_BODY_SIZE},
+    util::fs::LockMode,
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandServe {
+    /// The address to bind the server to
+    #[arg(long, default_value = "0.0.0.0:8080")]
+    bind: String,
+
+    /// The object id of the repository index to serve as the `index` ref
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// Additional refs to serve in the form `<name>=<oid>`
+    #[arg(long = "ref", value_parser = parse_ref)]
+    refs: Vec<(String, ObjectID)>,
+
+    /// The token clients have to present to upload objects, uploads are disabled if unset
+    #[arg(long)]
+    upload_token: Option<String>,
+
+    /// The number of connections to handle concurrently
+    #[arg(long, default_value_t = DEFAULT_SERVER_WORKERS)]
+    workers: usize,
+
+    /// The maximum size of an uploaded object file in bytes, larger uploads are rejected
+    #[arg(long, default_value_t = MAX_OBJECT_BODY_SIZE)]
+    max_object_size: u64,
+}
+
+impl CommandServe {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        // Uploads insert objects for as long as the server runs
+        let scopes = match &self.upload_token {
+            Some(_) => vec![(HomeLockScope::ObjectDB, LockMode::Shared)],
+            None => Vec::new(),
+        };
+        let _locks = home.lock_all(&scopes, cli.lock.wait())?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+
+        let mut refs: HashMap<String, ObjectID> = self.refs.iter().cloned().collect();
+        if let Some(index) = &self.index {
+            refs.insert(INDEX_REF.to_owned(), index.clone());
+        }
+
+        ODBServer::new(driver, refs, self.upload_token.clone())
+            .with_refs_file(home.get_refs_path())?
+            .with_workers(self.workers)
+            .with_max_object_size(self.max_object_size)
+            .serve(self.bind.as_str())?;
+
+        Ok(0)
+    }
+}
+
+/// Parses a ref in the form `<name>=<oid>`
+fn parse_ref(s: &str) -> Result<(String, ObjectID), String> {
+    let (name, oid) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected '<name>=<oid>', got '{s}'"))?;
+
+    let oid = ObjectID::new_from_hex(oid).map_err(|e| e.to_string())?;
+
+    Ok((name.to_owned(), oid))
+}
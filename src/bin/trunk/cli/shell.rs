@@ -0,0 +1,139 @@
+use std::{path::Path, process::Command};
+
+use clap::Parser;
+use log::{info, warn};
+use tooling::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{
+        odb_driver::FilesystemDriver, HomeLockScope, MergeStrategy, ObjectDB, ObjectID,
+        RepositoryIndex, Tree,
+    },
+    util::{
+        architecture::Architecture,
+        fs,
+        fs::{LockMode, PathUtil},
+        progress::ProgressBar,
+        ODBUnpackable,
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandShell {
+    /// The object id of the repository index to resolve the packages from
+    #[arg(long)]
+    index: ObjectID,
+
+    /// The shell to spawn
+    #[arg(long, default_value = "sh")]
+    shell: String,
+
+    /// How to resolve files provided by multiple packages
+    /// (`keep-ours` keeps the first package's file, `keep-theirs` the last one's, `error` aborts)
+    #[arg(long, default_value_t = MergeStrategy::KeepOurs)]
+    on_conflict: MergeStrategy,
+
+    /// The packages (names or provided capabilities) to deploy into the environment
+    #[arg(required = true)]
+    packages: Vec<String>,
+}
+
+impl CommandShell {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        // The environment gets deployed to the temporary directory
+        let _locks = home.lock_all(
+            &[(HomeLockScope::Builder, LockMode::Shared)],
+            cli.lock.wait(),
+        )?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let index =
+            RepositoryIndex::from_odb(&odb, &self.index).ctx(|| "Reading repository index")?;
+        let arch = Architecture::new_uname()?;
+
+        // Merge the trees of all packages to deploy them at once
+        let mut tree = Tree {
+            entries: Vec::new(),
+        };
+        for package in &self.packages {
+            let entry = index.resolve(package, &arch).ok_or_else(|| {
+                Error::new(ErrorType::Other(format!(
+                    "No package for '{package}' available for {arch}"
+                )))
+            })?;
+            let oid = entry
+                .package
+                .as_ref()
+                .expect("Resolved entries have packages");
+
+            info!("Using {entry} for '{package}'");
+
+            let mut object = odb.read(oid).ctx(|| format!("Reading package {oid}"))?;
+            let package_tree = Tree::unpack_from_odb(&mut object, &odb)
+                .ctx(|| format!("Reading package tree {oid}"))?;
+
+            let conflicts = tree
+                .merge_with_strategy(package_tree, self.on_conflict)
+                .ctx(|| format!("Merging {entry} into the environment"))?;
+            for conflict in conflicts {
+                warn!("{entry} conflicts with the environment at {conflict}");
+            }
+        }
+
+        let root = home.get_temporary_directory();
+        let res = Self::spawn(&tree, &root, &self.shell, &odb);
+
+        info!("Removing environment @ {}", root.str_lossy());
+        fs::remove_dir_all(&root).ctx(|| "Removing environment")?;
+
+        res
+    }
+
+    /// Deploys `tree` to `root` and spawns `shell` with its
+    /// `PATH` and `LD_LIBRARY_PATH` pointing into `root`
+    /// # Arguments
+    /// * `tree` - The tree to deploy
+    /// * `root` - The directory to deploy the tree to
+    /// * `shell` - The shell to spawn
+    /// * `odb` - The object database to get the objects from
+    /// # Returns
+    /// The exit code of the shell
+    fn spawn(tree: &Tree, root: &Path, shell: &str, odb: &ObjectDB) -> Result<i32, Error> {
+        info!("Deploying environment @ {}", root.str_lossy());
+        let mut bar = ProgressBar::new("Deploying");
+        tree.deploy_with_progress(root, odb, &mut |p| bar.update_deploy(p))
+            .ctx(|| "Deploying environment")?;
+        bar.finish();
+
+        let join = |dirs: &[&str], var: &str| {
+            let mut paths: Vec<String> = dirs
+                .iter()
+                .map(|d| root.join(d).to_string_lossy().to_string())
+                .collect();
+
+            if let Ok(existing) = std::env::var(var) {
+                paths.push(existing);
+            }
+
+            paths.join(":")
+        };
+
+        let status = Command::new(shell)
+            .env(
+                "PATH",
+                join(&["usr/bin", "usr/sbin", "bin", "sbin"], "PATH"),
+            )
+            .env(
+                "LD_LIBRARY_PATH",
+                join(&["usr/lib", "lib"], "LD_LIBRARY_PATH"),
+            )
+            .env("ACACIA_ENV_ROOT", root)
+            .status()
+            .e_context(|| format!("Spawning shell '{shell}'"))?;
+
+        Ok(status.code().unwrap_or(1))
+    }
+}
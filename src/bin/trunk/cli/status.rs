@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use indexmap::IndexMap;
+use serde::Serialize;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{odb_driver::FilesystemDriver, Home, Keyring},
+    util::fs::{self, FileLock, PathUtil},
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandStatus {
+    /// Print the status as JSON
+    #[arg(long, action)]
+    json: bool,
+}
+
+/// The state of a home directory
+#[derive(Serialize)]
+struct HomeStatus {
+    /// The root of the home directory
+    home: String,
+    /// The number of objects in the object database
+    objects: usize,
+    /// The number of objects by their type
+    object_types: IndexMap<String, usize>,
+    /// The disk usage of the object database in bytes
+    object_db_size: u64,
+    /// The number of temporary files of insertions in the object database
+    object_db_temp_files: usize,
+    /// The disk usage of the partial downloads in bytes
+    downloads_size: u64,
+    /// Whether a signing key has been generated
+    signing_key: bool,
+    /// The names of the trusted public keys
+    trusted_keys: Vec<String>,
+    /// The temporary build directories
+    builds: Vec<BuildStatus>,
+    /// The mount points below the build directories
+    leaked_mounts: Vec<String>,
+}
+
+/// The state of a temporary build directory
+#[derive(Serialize)]
+struct BuildStatus {
+    /// The id of the build
+    id: String,
+    /// The seconds since the directory has been modified
+    age: u64,
+    /// Whether the directory is not in use by a running build
+    stale: bool,
+    /// The disk usage of the directory in bytes
+    size: u64,
+}
+
+impl CommandStatus {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let status = HomeStatus::collect(&home)?;
+
+        if self.json {
+            println!(
+                "{}",
+                serde_json::to_string(&status).expect("Serialize status should never fail")
+            );
+        } else {
+            status.print();
+        }
+
+        Ok(0)
+    }
+}
+
+impl HomeStatus {
+    /// Collects the state of `home`
+    /// # Arguments
+    /// * `home` - The home to inspect
+    fn collect(home: &Home) -> Result<Self, Error> {
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+
+        let objects = driver.list_objects().ctx(|| "Listing objects")?;
+        let mut object_types: IndexMap<String, usize> = IndexMap::new();
+        for object in &objects {
+            *object_types.entry(format!("{:?}", object.ty)).or_default() += 1;
+        }
+        object_types.sort_keys();
+
+        let keyring = Keyring::load(&home.get_keys_dir())?;
+
+        let builds_dir = home.get_builds_dir();
+        let mut builds = Vec::new();
+        if builds_dir.exists() {
+            let context = || format!("Reading build directories in {}", builds_dir.str_lossy());
+            for entry in std::fs::read_dir(&builds_dir).e_context(context)? {
+                let entry = entry.e_context(context)?;
+                if !entry.file_type().e_context(context)?.is_dir() {
+                    continue;
+                }
+
+                let age = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .e_context(context)?
+                    .elapsed()
+                    .unwrap_or_default();
+
+                let id = entry.file_name().to_string_lossy().to_string();
+                builds.push(BuildStatus {
+                    stale: !FileLock::is_locked(&home.get_build_lock_path(&id))?,
+                    id,
+                    age: age.as_secs(),
+                    size: fs::dir_size(&entry.path())?,
+                });
+            }
+        }
+        builds.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let leaked_mounts = fs::mount_points_under(&absolute(&builds_dir))?
+            .iter()
+            .map(|p| p.str_lossy())
+            .collect();
+
+        Ok(Self {
+            home: home.get_root().str_lossy(),
+            objects: objects.len(),
+            object_types,
+            object_db_size: fs::dir_size(driver.get_root())?,
+            object_db_temp_files: count_entries(&driver.get_temp_dir())?,
+            downloads_size: fs::dir_size(&home.get_downloads_dir())?,
+            signing_key: keyring.get_signing_key().is_ok(),
+            trusted_keys: keyring
+                .get_named_trusted_keys()
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+            builds,
+            leaked_mounts,
+        })
+    }
+
+    /// Prints the status in a human readable form
+    fn print(&self) {
+        println!("home: {}", self.home);
+
+        println!("objects: {} ({} bytes)", self.objects, self.object_db_size);
+        for (ty, count) in &self.object_types {
+            println!("  {ty}: {count}");
+        }
+        if self.object_db_temp_files > 0 {
+            println!("  temporary files: {}", self.object_db_temp_files);
+        }
+
+        println!("partial downloads: {} bytes", self.downloads_size);
+
+        println!(
+            "signing key: {}",
+            if self.signing_key { "yes" } else { "no" }
+        );
+        println!("trusted keys: {}", self.trusted_keys.join(", "));
+
+        let stale = self.builds.iter().filter(|b| b.stale).count();
+        println!("builds: {} ({stale} stale)", self.builds.len());
+        for build in &self.builds {
+            println!(
+                "  {} ({} bytes, modified {}s ago){}",
+                build.id,
+                build.size,
+                build.age,
+                if build.stale { " [stale]" } else { "" }
+            );
+        }
+
+        if !self.leaked_mounts.is_empty() {
+            println!("leaked mounts: {}", self.leaked_mounts.len());
+            for mount in &self.leaked_mounts {
+                println!("  {mount}");
+            }
+        }
+    }
+}
+
+/// Returns the number of entries in `dir`, `0` if it does not exist
+/// # Arguments
+/// * `dir` - The directory to count the entries of
+fn count_entries(dir: &Path) -> Result<usize, Error> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    Ok(std::fs::read_dir(dir)
+        .e_context(|| format!("Reading {}", dir.str_lossy()))?
+        .count())
+}
+
+/// Returns `path` as a canonical path to compare it with mount points
+/// # Arguments
+/// * `path` - The path to canonicalize
+fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_owned())
+}
@@ -0,0 +1,331 @@
+use std::{cmp::Ordering, path::PathBuf};
+
+use clap::Parser;
+use log::info;
+use tooling::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{
+        odb_driver::{FilesystemDriver, HTTPDriver},
+        FileIndex, FileIndexPackage, Formula, HomeLockScope, ObjectCompression, ObjectDB, ObjectID,
+        RepositoryIndex, RepositoryIndexEntry, Tree,
+    },
+    package::ScriptletKind,
+    tools::server::INDEX_REF,
+    util::{
+        architecture::Architecture,
+        fs::{LockMode, PathUtil},
+        parse::versionstring::compare_versions,
+        ODBUnpackable,
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandUpgrade {
+    /// The repository to upgrade from: The object id of a repository index or the
+    /// URL of a remote object database (`http://host:8080`) to use the index of
+    #[arg(long)]
+    repo: String,
+
+    /// The root directory to upgrade the installed packages of
+    #[arg(long, default_value = "/")]
+    root: PathBuf,
+
+    /// Print the upgrade plan without applying it
+    #[arg(long, action)]
+    dry_run: bool,
+
+    /// Do not run the `post_install` scriptlets (e.g. for offline image builds)
+    #[arg(long, action)]
+    no_scriptlets: bool,
+
+    /// The installed packages to upgrade [all]
+    packages: Vec<String>,
+}
+
+/// A package to install or upgrade as part of an upgrade plan
+struct PlannedPackage {
+    /// The entry of the package to install in the repository index
+    entry: RepositoryIndexEntry,
+    /// The installed package that gets replaced, `None` for new dependencies
+    installed: Option<FileIndexPackage>,
+}
+
+impl CommandUpgrade {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        // Partial downloads from the remote are kept in the temporary directory
+        let _locks = home.lock_all(
+            &[
+                (HomeLockScope::ObjectDB, LockMode::Shared),
+                (HomeLockScope::Builder, LockMode::Shared),
+            ],
+            cli.lock.wait(),
+        )?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let mut odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        // A repository given by URL is used through the `index` ref of the remote
+        let (index_oid, remote) = match ObjectID::new_from_hex(&self.repo) {
+            Ok(oid) => (oid, None),
+            Err(_) => {
+                let remote = HTTPDriver::new(&self.repo, home.get_downloads_dir())?
+                    .with_downloads(home.load_config()?.get_download_manager());
+                let oid = remote.get_ref(INDEX_REF)?.ok_or_else(|| {
+                    Error::new(ErrorType::Other(format!(
+                        "{} has no repository index",
+                        self.repo
+                    )))
+                })?;
+
+                odb.pull_from_driver(&remote, oid.clone(), ObjectCompression::Xz, false)
+                    .ctx(|| "Fetching remote repository index")?;
+                (oid, Some(remote))
+            }
+        };
+
+        let index = RepositoryIndex::from_odb(&odb, &index_oid)
+            .ctx(|| format!("Reading repository index {index_oid}"))?;
+        let arch = Architecture::new_uname()?;
+        let mut file_index = FileIndex::from_root(&odb, &self.root)?;
+
+        for name in &self.packages {
+            if !file_index.packages.iter().any(|p| &p.name == name) {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Package '{name}' is not installed to {}",
+                    self.root.str_lossy()
+                ))));
+            }
+        }
+
+        let mut planner = Planner {
+            odb: &mut odb,
+            remote: remote.as_ref(),
+            index: &index,
+            arch: &arch,
+            file_index: &file_index,
+        };
+
+        let mut plan = Vec::new();
+        for installed in &file_index.packages {
+            if !self.packages.is_empty() && !self.packages.contains(&installed.name) {
+                continue;
+            }
+
+            if let Some(entry) = find_newer(&index, &arch, &installed.name, &file_index) {
+                planner.plan(entry, &mut plan, &mut Vec::new())?;
+            }
+        }
+
+        if plan.is_empty() {
+            eprintln!("All packages are up to date");
+            return Ok(0);
+        }
+
+        for package in &plan {
+            match &package.installed {
+                Some(installed) => println!(
+                    "{}: {} -> {}",
+                    package.entry.name, installed.version, package.entry.version
+                ),
+                None => println!(
+                    "{}: {} (new dependency)",
+                    package.entry.name, package.entry.version
+                ),
+            }
+        }
+
+        if self.dry_run {
+            return Ok(0);
+        }
+
+        for package in &plan {
+            let oid = package
+                .entry
+                .package
+                .as_ref()
+                .expect("Planned entries have packages");
+
+            match &remote {
+                Some(remote) => odb
+                    .pull_from_driver(remote, oid.clone(), ObjectCompression::Xz, true)
+                    .ctx(|| format!("Fetching {}", package.entry))?,
+                None => {
+                    odb.get_object(oid).ctx(|| {
+                        format!("Package of {} is not in the object database", package.entry)
+                    })?;
+                }
+            }
+        }
+
+        // Every package is replaced as a whole and recorded in the file index right away,
+        // so an interrupted upgrade leaves every package either at its old or new version
+        let mut formulae = Vec::new();
+        for package in &plan {
+            let oid = package
+                .entry
+                .package
+                .as_ref()
+                .expect("Planned entries have packages");
+            let tree = read_tree(&odb, oid)?;
+            let previous = match &package.installed {
+                Some(installed) => read_tree(&odb, &installed.oid)?,
+                None => Tree {
+                    entries: Vec::new(),
+                },
+            };
+
+            info!("Upgrading {} in {}", package.entry, self.root.str_lossy());
+            tree.deploy_replacing(&previous, &self.root, &odb)
+                .ctx(|| format!("Upgrading {}", package.entry))?;
+
+            if let Some(installed) = &package.installed {
+                file_index.remove_package(&installed.oid);
+            }
+            file_index.insert_package(&package.entry, &tree);
+            file_index
+                .store_for_root(&mut odb, &self.root)
+                .ctx(|| "Updating the file index of the installed packages")?;
+
+            formulae.extend(read_formula(&mut odb, remote.as_ref(), &package.entry)?);
+        }
+
+        let keep = home.load_config()?.generations.keep;
+        file_index
+            .record_generation(&mut odb, keep)
+            .ctx(|| "Recording the generation of the root directory")?;
+        file_index
+            .store_for_root(&mut odb, &self.root)
+            .ctx(|| "Updating the file index of the installed packages")?;
+
+        // Scriptlets run once all packages are in place, they may depend on each other
+        for formula in &formulae {
+            match formula.get_scriptlet(ScriptletKind::PostInstall) {
+                Some(scriptlet) if self.no_scriptlets => info!("Skipping {scriptlet}"),
+                Some(scriptlet) => scriptlet.run(&self.root)?,
+                None => {}
+            }
+        }
+
+        Ok(0)
+    }
+}
+
+/// Returns the newest entry named `name` in `index` if it is newer than the installed package
+/// # Arguments
+/// * `index` - The repository index to search
+/// * `arch` - The architecture the package has to run on
+/// * `name` - The name of the package
+/// * `file_index` - The file index of the installed packages
+fn find_newer<'a>(
+    index: &'a RepositoryIndex,
+    arch: &Architecture,
+    name: &str,
+    file_index: &FileIndex,
+) -> Option<&'a RepositoryIndexEntry> {
+    let entry = index.resolve(name, arch).filter(|e| e.name == name)?;
+
+    match file_index.packages.iter().find(|p| p.name == name) {
+        Some(installed) => {
+            let newer = compare_versions(&entry.version, &installed.version) == Ordering::Greater;
+            newer.then_some(entry)
+        }
+        None => Some(entry),
+    }
+}
+
+/// Computes upgrade plans, fetching the formulae of the planned packages from the remote
+struct Planner<'a> {
+    /// The object database to read the formulae from
+    odb: &'a mut ObjectDB,
+    /// The remote object database to fetch missing formulae from
+    remote: Option<&'a HTTPDriver>,
+    /// The repository index to resolve the dependencies from
+    index: &'a RepositoryIndex,
+    /// The architecture the packages have to run on
+    arch: &'a Architecture,
+    /// The file index of the installed packages
+    file_index: &'a FileIndex,
+}
+
+impl Planner<'_> {
+    /// Adds `entry` to `plan` after the dependencies that have to be installed
+    /// or upgraded along with it, skipping packages that are planned already
+    /// # Arguments
+    /// * `entry` - The entry to plan
+    /// * `plan` - The plan to add to
+    /// * `visiting` - The names of the packages being planned, to break dependency cycles
+    fn plan(
+        &mut self,
+        entry: &RepositoryIndexEntry,
+        plan: &mut Vec<PlannedPackage>,
+        visiting: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        if visiting.contains(&entry.name) || plan.iter().any(|p| p.entry.name == entry.name) {
+            return Ok(());
+        }
+        visiting.push(entry.name.clone());
+
+        let formula = read_formula(self.odb, self.remote, entry)?;
+
+        // Runtime dependencies are named by the entries providing them
+        let index = self.index;
+        let dependencies = formula
+            .iter()
+            .flat_map(|f| f.target_dependencies.iter().chain(&f.extra_dependencies))
+            .filter_map(|oid| index.entries.iter().find(|e| e.get_oids().contains(oid)));
+
+        for dependency in dependencies {
+            if let Some(newer) = find_newer(index, self.arch, &dependency.name, self.file_index) {
+                self.plan(newer, plan, visiting)?;
+            }
+        }
+
+        visiting.pop();
+        plan.push(PlannedPackage {
+            entry: entry.clone(),
+            installed: self
+                .file_index
+                .packages
+                .iter()
+                .find(|p| p.name == entry.name)
+                .cloned(),
+        });
+
+        Ok(())
+    }
+}
+
+/// Reads the formula of `entry`, fetching it from `remote` first if it is missing
+/// # Arguments
+/// * `odb` - The object database to read the formula from
+/// * `remote` - The remote object database to fetch the formula from
+/// * `entry` - The entry to read the formula of
+fn read_formula(
+    odb: &mut ObjectDB,
+    remote: Option<&HTTPDriver>,
+    entry: &RepositoryIndexEntry,
+) -> Result<Option<Formula>, Error> {
+    let Some(oid) = &entry.formula else {
+        return Ok(None);
+    };
+
+    if let (Some(remote), None) = (remote, odb.try_get_object(oid)?) {
+        odb.pull_from_driver(remote, oid.clone(), ObjectCompression::Xz, false)
+            .ctx(|| format!("Fetching formula of {entry}"))?;
+    }
+
+    Formula::from_odb(odb, oid)
+        .ctx(|| format!("Reading formula of {entry}"))
+        .map(Some)
+}
+
+/// Reads the tree of the package `oid`
+/// # Arguments
+/// * `odb` - The object database to read the tree from
+/// * `oid` - The object id of the package
+fn read_tree(odb: &ObjectDB, oid: &ObjectID) -> Result<Tree, Error> {
+    let mut object = odb.read(oid).ctx(|| format!("Reading package {oid}"))?;
+    Tree::unpack_from_odb(&mut object, odb).ctx(|| format!("Reading package tree {oid}"))
+}
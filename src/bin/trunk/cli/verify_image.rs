@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{odb_driver::FilesystemDriver, ObjectDB, ObjectID, Tree},
+    util::{fs::PathUtil, ODBUnpackable},
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandVerifyImage {
+    /// The root directory the tree has been deployed to
+    #[arg(long)]
+    root: PathBuf,
+
+    /// The object id of the tree to verify against
+    #[arg(long)]
+    tree: ObjectID,
+
+    /// Glob patterns of paths to skip (e.g. `proc`), can be specified multiple times
+    #[arg(long)]
+    ignore: Vec<String>,
+}
+
+impl CommandVerifyImage {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let mut object = odb.read(&self.tree).ctx(|| "Reading tree object")?;
+        let tree = Tree::unpack_from_odb(&mut object, &odb).ctx(|| "Reading object contents")?;
+
+        let differences = tree.verify(&self.root, &self.ignore)?;
+        for difference in &differences {
+            println!("{difference}");
+        }
+
+        if differences.is_empty() {
+            eprintln!("{} matches tree {}", self.root.str_lossy(), self.tree);
+            Ok(0)
+        } else {
+            eprintln!(
+                "{} differs from tree {} in {} entries",
+                self.root.str_lossy(),
+                self.tree,
+                differences.len()
+            );
+            Ok(1)
+        }
+    }
+}
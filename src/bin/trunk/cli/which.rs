@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{
+        odb_driver::FilesystemDriver, FileIndex, ObjectDB, ObjectID, ObjectType, RepositoryIndex,
+    },
+};
+
+use super::Cli;
+
+#[derive(Parser)]
+pub struct CommandWhich {
+    /// The object id of a repository index or file index to search in
+    /// instead of the packages installed to the root directory
+    #[arg(long)]
+    index: Option<ObjectID>,
+
+    /// The root directory to search the installed packages of
+    #[arg(long, default_value = "/", conflicts_with = "index")]
+    root: PathBuf,
+
+    /// The path (containing a `/`) or file name to search for
+    file: String,
+}
+
+impl CommandWhich {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let file_index = match &self.index {
+            Some(oid) if odb.get_object(oid)?.ty == ObjectType::AcaciaRepositoryIndex => {
+                let index =
+                    RepositoryIndex::from_odb(&odb, oid).ctx(|| "Reading repository index")?;
+                FileIndex::from_repository_index(&odb, &index)
+                    .ctx(|| format!("Indexing the files of {oid}"))?
+            }
+            Some(oid) => FileIndex::from_odb(&odb, oid).ctx(|| "Reading file index")?,
+            None => FileIndex::from_root(&odb, &self.root)?,
+        };
+
+        let matches = file_index.which(&self.file);
+        if matches.is_empty() {
+            eprintln!("No package provides '{}'", self.file);
+            return Ok(1);
+        }
+
+        for (path, packages) in matches {
+            for package in packages {
+                println!("/{path}: {package}");
+            }
+        }
+
+        Ok(0)
+    }
+}
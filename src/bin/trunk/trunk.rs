@@ -0,0 +1,23 @@
+extern crate colored;
+use std::process::exit;
+
+use clap::Parser;
+use colored::Colorize;
+use tooling::error::Error;
+
+mod cli;
+
+fn main() {
+    match run() {
+        Ok(v) => exit(v),
+        Err(e) => {
+            println!("{}", e.to_string().red())
+        }
+    }
+}
+
+fn run() -> Result<i32, Error> {
+    let cli = cli::Cli::parse();
+
+    cli.run()
+}
@@ -3,16 +3,20 @@ use std::path::PathBuf;
 use clap::Parser;
 use tooling::{
     error::{Error, ErrorType},
-    model::Home,
+    model::{Home, HomeLockArgs},
 };
 
+mod browse;
 pub mod common;
 mod odb;
+mod repo;
 mod tree;
 
+use common::OutputFormat;
+
 #[derive(Parser)]
 pub struct Cli {
-    /// The log level to operate on (0 = info, 1 = debug, * = trace)
+    /// The log level to operate on (0 = warn, 1 = info, 2 = debug, * = trace)
     #[arg(long = "loglevel", short = 'v', default_value_t = 0, global = true)]
     pub loglevel: u8,
 
@@ -20,6 +24,13 @@ pub struct Cli {
     #[arg(long)]
     home: Option<PathBuf>,
 
+    #[command(flatten)]
+    pub lock: HomeLockArgs,
+
+    /// The format to print the output of read-only commands in
+    #[arg(long, global = true, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
     /// The command to execute
     #[command(subcommand)]
     command: TwigCommand,
@@ -31,13 +42,17 @@ pub enum TwigCommand {
     Odb(odb::CommandOdb),
     /// Work with or create trees
     Tree(tree::CommandTree),
+    /// Create and inspect repository indices
+    Repo(repo::CommandRepo),
+    /// Interactively browse the objects in the object database
+    Browse(browse::CommandBrowse),
 }
 
 impl Cli {
     pub fn run(&self) -> Result<i32, Error> {
         if std::env::var("RUST_LOG").is_err() {
             match &self.loglevel {
-                0 => {}
+                0 => std::env::set_var("RUST_LOG", "warn"),
                 1 => std::env::set_var("RUST_LOG", "info"),
                 2 => std::env::set_var("RUST_LOG", "debug"),
                 _ => std::env::set_var("RUST_LOG", "trace"),
@@ -72,6 +87,8 @@ impl TwigCommand {
         match self {
             Self::Odb(cmd) => cmd.run(cli),
             Self::Tree(cmd) => cmd.run(cli),
+            Self::Repo(cmd) => cmd.run(cli),
+            Self::Browse(cmd) => cmd.run(cli),
         }
     }
 }
@@ -0,0 +1,345 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Read, Write},
+};
+
+use clap::Parser;
+use colored::Colorize;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{
+        odb_driver::FilesystemDriver, Annotation, BuildReceipt, FileIndex, Formula, Object,
+        ObjectDB, ObjectID, ObjectType, RepositoryIndex, Signature, Tree, TreeEntry,
+    },
+    util::{ODBUnpackable, ReprU16},
+};
+
+use super::Cli;
+
+/// The amount of bytes to preview of text objects
+static PREVIEW_SIZE: u64 = 4096;
+
+/// The amount of lines to preview of text objects
+static PREVIEW_LINES: usize = 24;
+
+#[derive(Parser)]
+pub struct CommandBrowse {
+    /// The object id of the object to start browsing at, lists the object types if omitted
+    oid: Option<ObjectID>,
+}
+
+/// A page of the browser
+#[derive(Clone)]
+enum View {
+    /// All object types and the amount of objects of each type
+    Types,
+    /// All objects of a type
+    Objects(ObjectType),
+    /// A single object with its contents and dependencies
+    Object(ObjectID),
+}
+
+/// A line of a rendered view
+enum Line {
+    /// A section header
+    Header(String),
+    /// Plain text
+    Text(String),
+    /// A line that can be selected to navigate to another view
+    Link(String, View),
+}
+
+impl CommandBrowse {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+        let objects = driver.list_objects().ctx(|| "Listing objects")?;
+        let odb = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        let mut types: BTreeMap<u16, (ObjectType, Vec<Object>)> = BTreeMap::new();
+        for object in objects {
+            types
+                .entry(object.ty.into_u16())
+                .or_insert_with(|| (object.ty, Vec::new()))
+                .1
+                .push(object);
+        }
+
+        let mut stack = vec![View::Types];
+        if let Some(oid) = &self.oid {
+            stack.push(View::Object(oid.clone()));
+        }
+
+        let mut stdin = io::stdin().lock();
+        while let Some(view) = stack.last() {
+            let lines = match render(view, &types, &odb) {
+                Ok(lines) => lines,
+                Err(e) => vec![Line::Text(e.to_string().red().to_string())],
+            };
+
+            let mut links = Vec::new();
+            println!();
+            for line in lines {
+                match line {
+                    Line::Header(text) => println!("{}", text.bold()),
+                    Line::Text(text) => println!("  {text}"),
+                    Line::Link(text, view) => {
+                        links.push(view);
+                        println!("  {} {text}", format!("[{}]", links.len()).cyan());
+                    }
+                }
+            }
+
+            print!("\n[<n>] open, [b]ack, [t]ypes, [q]uit or <oid>: ");
+            io::stdout().flush().e_context(|| "Flushing stdout")?;
+
+            let mut input = String::new();
+            if stdin.read_line(&mut input).e_context(|| "Reading input")? == 0 {
+                break;
+            }
+
+            match input.trim() {
+                "" => {}
+                "q" => break,
+                "b" => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                }
+                "t" => stack.push(View::Types),
+                input => {
+                    if let Some(view) = input
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|n| n.checked_sub(1))
+                        .and_then(|n| links.get(n))
+                    {
+                        stack.push(view.clone());
+                    } else if let Ok(oid) = input.parse::<ObjectID>() {
+                        stack.push(View::Object(oid));
+                    } else {
+                        println!("{}", format!("Invalid input '{input}'").red());
+                    }
+                }
+            }
+        }
+
+        Ok(0)
+    }
+}
+
+/// Renders `view` into lines
+/// # Arguments
+/// * `view` - The view to render
+/// * `types` - The objects of the object database, keyed by their type
+/// * `odb` - The object database to read objects from
+fn render(
+    view: &View,
+    types: &BTreeMap<u16, (ObjectType, Vec<Object>)>,
+    odb: &ObjectDB,
+) -> Result<Vec<Line>, Error> {
+    let mut lines = Vec::new();
+
+    match view {
+        View::Types => {
+            lines.push(Line::Header("Object types".to_owned()));
+            for (ty, objects) in types.values() {
+                lines.push(Line::Link(
+                    format!("{ty:?} ({} objects)", objects.len()),
+                    View::Objects(*ty),
+                ));
+            }
+        }
+        View::Objects(ty) => {
+            lines.push(Line::Header(format!("Objects of type {ty:?}")));
+            let objects = types.get(&ty.into_u16()).map(|t| &t.1);
+            for object in objects.into_iter().flatten() {
+                let label = match object.ty {
+                    ObjectType::AcaciaFormula => match Formula::from_odb(odb, &object.oid) {
+                        Ok(formula) => {
+                            format!("{} {}@{}", object.oid, formula.name, formula.version)
+                        }
+                        Err(_) => object.oid.to_string(),
+                    },
+                    _ => object.oid.to_string(),
+                };
+
+                lines.push(Line::Link(label, View::Object(object.oid.clone())));
+            }
+        }
+        View::Object(oid) => render_object(oid, odb, &mut lines)?,
+    }
+
+    Ok(lines)
+}
+
+/// Renders the object `oid` with its contents, dependencies, reverse dependencies and annotations
+/// # Arguments
+/// * `oid` - The object id of the object to render
+/// * `odb` - The object database to read the object from
+/// * `lines` - The lines to render to
+fn render_object(oid: &ObjectID, odb: &ObjectDB, lines: &mut Vec<Line>) -> Result<(), Error> {
+    let object = odb.get_object(oid)?;
+
+    lines.push(Line::Header(format!("Object {oid}")));
+    lines.push(Line::Text(format!("type: {:?}", object.ty)));
+    lines.push(Line::Text(format!("compression: {}", object.compression)));
+
+    match object.ty {
+        ObjectType::AcaciaTree => {
+            let mut reader = odb.read(oid)?;
+            let tree = Tree::unpack_from_odb(&mut reader, odb).ctx(|| "Reading tree")?;
+
+            lines.push(Line::Header("Entries".to_owned()));
+            for entry in &tree.entries {
+                match entry {
+                    TreeEntry::File { info: _, name, oid } => {
+                        lines.push(Line::Link(name.clone(), View::Object(oid.clone())))
+                    }
+                    TreeEntry::Subtree {
+                        info: _,
+                        name,
+                        tree,
+                    } => lines.push(Line::Link(format!("{name}/"), View::Object(tree.oid()))),
+                    TreeEntry::Symlink {
+                        info: _,
+                        name,
+                        destination,
+                    } => lines.push(Line::Text(format!("{name} -> {destination}"))),
+                    TreeEntry::Device {
+                        info: _,
+                        name,
+                        kind,
+                        major,
+                        minor,
+                    } => lines.push(Line::Text(format!("{name} ({kind} {major}:{minor})"))),
+                    TreeEntry::Fifo { info: _, name } => {
+                        lines.push(Line::Text(format!("{name} (fifo)")))
+                    }
+                }
+            }
+        }
+        ObjectType::AcaciaFormula => {
+            let formula = Formula::from_odb(odb, oid)?;
+
+            lines.push(Line::Header("Formula".to_owned()));
+            lines.push(Line::Text(format!("name: {}", formula.name)));
+            lines.push(Line::Text(format!("version: {}", formula.version)));
+            lines.push(Line::Text(format!("description: {}", formula.description)));
+            if let Some(license) = &formula.license {
+                lines.push(Line::Text(format!("license: {license}")));
+            }
+            if formula.tainted {
+                lines.push(Line::Text(
+                    "tainted: sources have been overridden".to_owned(),
+                ));
+            }
+            lines.push(Line::Link(
+                "tree".to_owned(),
+                View::Object(formula.tree.clone()),
+            ));
+        }
+        ObjectType::AcaciaRepositoryIndex => {
+            let index = RepositoryIndex::from_odb(odb, oid)?;
+
+            lines.push(Line::Header("Entries".to_owned()));
+            for entry in &index.entries {
+                match entry.package.as_ref().or(entry.formula.as_ref()) {
+                    Some(oid) => {
+                        lines.push(Line::Link(entry.to_string(), View::Object(oid.clone())))
+                    }
+                    None => lines.push(Line::Text(entry.to_string())),
+                }
+            }
+        }
+        ObjectType::AcaciaAnnotation => {
+            let annotation = Annotation::from_odb(odb, oid)?;
+
+            lines.push(Line::Header("Annotation".to_owned()));
+            for (key, value) in &annotation.values {
+                lines.push(Line::Text(format!("{key}: {value}")));
+            }
+        }
+        ObjectType::AcaciaBuildReceipt => {
+            let receipt = BuildReceipt::from_odb(odb, oid)?;
+
+            lines.push(Line::Header("Build receipt".to_owned()));
+            lines.push(Line::Link(
+                "package".to_owned(),
+                View::Object(receipt.package.clone()),
+            ));
+            lines.push(Line::Text(format!("build: {}", receipt.build_id)));
+            for step in &receipt.steps {
+                match &step.snapshot {
+                    Some(snapshot) => lines.push(Line::Link(
+                        format!("step: {}", step.name),
+                        View::Object(snapshot.clone()),
+                    )),
+                    None => lines.push(Line::Text(format!("step: {}", step.name))),
+                }
+            }
+        }
+        ObjectType::AcaciaSignature => {
+            let signature = Signature::from_odb(odb, oid)?;
+
+            lines.push(Line::Header("Signature".to_owned()));
+            lines.push(Line::Link(
+                "object".to_owned(),
+                View::Object(signature.object.clone()),
+            ));
+            lines.push(Line::Text(format!("key: {}", signature.key)));
+        }
+        ObjectType::AcaciaFileIndex => {
+            let index = FileIndex::from_odb(odb, oid)?;
+
+            lines.push(Line::Header("File index".to_owned()));
+            lines.push(Line::Text(format!("files: {}", index.files.len())));
+            for package in &index.packages {
+                lines.push(Line::Link(
+                    package.to_string(),
+                    View::Object(package.oid.clone()),
+                ));
+            }
+        }
+        ObjectType::Text | ObjectType::Script | ObjectType::Other => {
+            let mut buf = Vec::new();
+            odb.read(oid)?
+                .take(PREVIEW_SIZE)
+                .read_to_end(&mut buf)
+                .e_context(|| "Reading object preview")?;
+
+            if !buf.contains(&0) {
+                lines.push(Line::Header("Preview".to_owned()));
+                for line in String::from_utf8_lossy(&buf).lines().take(PREVIEW_LINES) {
+                    lines.push(Line::Text(line.to_owned()));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    lines.push(Line::Header("Dependencies".to_owned()));
+    for dependency in &object.dependencies {
+        lines.push(Line::Link(
+            dependency.to_string(),
+            View::Object(dependency.clone()),
+        ));
+    }
+
+    // Not every object database provides reverse dependencies
+    if let Ok(rdeps) = odb.reverse_dependencies(oid) {
+        lines.push(Line::Header("Reverse dependencies".to_owned()));
+        for rdep in rdeps {
+            lines.push(Line::Link(rdep.to_string(), View::Object(rdep)));
+        }
+    }
+
+    let annotations = Annotation::collect(odb, oid)?;
+    if !annotations.is_empty() {
+        lines.push(Line::Header("Annotations".to_owned()));
+        for (key, value) in annotations {
+            lines.push(Line::Text(format!("{key}: {value}")));
+        }
+    }
+
+    Ok(())
+}
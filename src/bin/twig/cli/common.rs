@@ -1,7 +1,12 @@
-use std::fmt::Display;
+use std::{fmt::Display, io};
 
 use clap::ValueEnum;
-use tooling::model::ObjectCompression;
+use serde_json::{json, Value};
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{ObjectCompression, ObjectDB, TreeDifference, TreeEntry},
+    util::fs::{PathUtil, UNIXInfo},
+};
 
 /// Compression types available for the tooling
 #[derive(ValueEnum, Clone)]
@@ -33,3 +38,110 @@ impl From<Compression> for ObjectCompression {
         }
     }
 }
+
+/// The formats the read-only commands can print their output in
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// A single JSON document for scripting
+    Json,
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Text => "text",
+                Self::Json => "json",
+            }
+        )
+    }
+}
+
+/// Returns the UNIX information of a tree entry as JSON
+/// # Arguments
+/// * `info` - The information to convert
+pub fn info_json(info: &UNIXInfo) -> Value {
+    json!({
+        "uid": info.uid,
+        "gid": info.gid,
+        "mode": format!("{:o}", info.mode),
+    })
+}
+
+/// Returns a tree entry as JSON, including the size of the data of files
+/// # Arguments
+/// * `entry` - The entry to convert
+/// * `odb` - The object database to read the files from
+pub fn entry_json(entry: &TreeEntry, odb: &ObjectDB) -> Result<Value, Error> {
+    Ok(match entry {
+        TreeEntry::File { info, name, oid } => {
+            let size = io::copy(&mut odb.read(oid)?, &mut io::sink())
+                .ctx(|| format!("Reading file {oid}"))?;
+            json!({
+                "type": "file",
+                "name": name,
+                "oid": oid,
+                "size": size,
+                "info": info_json(info),
+            })
+        }
+        TreeEntry::Symlink {
+            info,
+            name,
+            destination,
+        } => json!({
+            "type": "symlink",
+            "name": name,
+            "destination": destination,
+            "info": info_json(info),
+        }),
+        TreeEntry::Subtree { info, name, tree } => json!({
+            "type": "subtree",
+            "name": name,
+            "oid": tree.oid(),
+            "info": info_json(info),
+        }),
+        TreeEntry::Device {
+            info,
+            name,
+            kind,
+            major,
+            minor,
+        } => json!({
+            "type": "device",
+            "name": name,
+            "kind": kind.to_string(),
+            "major": major,
+            "minor": minor,
+            "info": info_json(info),
+        }),
+        TreeEntry::Fifo { info, name } => json!({
+            "type": "fifo",
+            "name": name,
+            "info": info_json(info),
+        }),
+    })
+}
+
+/// Returns a difference between two trees as JSON
+/// # Arguments
+/// * `difference` - The difference to convert
+pub fn difference_json(difference: &TreeDifference) -> Value {
+    let path = difference.path().str_lossy();
+    match difference {
+        TreeDifference::Added(_) => json!({ "change": "added", "path": path }),
+        TreeDifference::Removed(_) => json!({ "change": "removed", "path": path }),
+        TreeDifference::TypeChanged(_) => json!({ "change": "type", "path": path }),
+        TreeDifference::ContentChanged(_) => json!({ "change": "contents", "path": path }),
+        TreeDifference::InfoChanged { path: _, old, new } => json!({
+            "change": "info",
+            "path": path,
+            "old": info_json(old),
+            "new": info_json(new),
+        }),
+    }
+}
@@ -1,16 +1,40 @@
-use std::{io, path::PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use log::warn;
 use tooling::{
     error::{Error, ErrorExt, ErrorType},
-    model::{odb_driver::FilesystemDriver, Object, ObjectDB, ObjectID, ObjectType},
-    util::fs::{file_create, PathUtil},
+    model::{
+        odb_driver::{FilesystemDriver, HTTPDriver},
+        parse_public_key, Annotation, DedupReport, Home, HomeLockScope, Keyring, ODBDriver, Object,
+        ObjectDB, ObjectDBStats, ObjectID, ObjectType, Tree,
+    },
+    util::{
+        chunker::Chunker,
+        fs::{file_create, walk_dir, LockMode, PathUtil},
+        ODBUnpackable,
+    },
 };
 
-use super::{common::Compression, Cli};
+use super::{
+    common::{difference_json, Compression, OutputFormat},
+    Cli,
+};
 
 #[derive(Parser)]
 pub struct CommandOdb {
+    /// Store large inserted objects in deduplicated chunks
+    #[arg(long, action)]
+    chunked: bool,
+
     /// The command to execute
     #[command(subcommand)]
     command: Command,
@@ -36,11 +60,33 @@ enum Command {
         /// The path to the file to put into the object database
         path: PathBuf,
     },
+    /// Insert every file under a directory as an individual object
+    /// and print a manifest of `<path>\t<oid>` lines
+    Import {
+        /// The type of the inserted objects, inferred for each file by default
+        #[arg(long = "type", short, default_value_t = ImportType::Infer)]
+        ty: ImportType,
+
+        /// The compression method to use
+        #[arg(long, short, default_value_t = Compression::None)]
+        compression: Compression,
+
+        /// The number of files to insert in parallel
+        #[arg(long, short, default_value_t = 1)]
+        jobs: usize,
+
+        /// The directory to import
+        dir: PathBuf,
+    },
     /// Pull an object from another object database
     Pull {
         /// The path to the other object database root
+        #[arg(long, required_unless_present = "url", conflicts_with = "url")]
+        other: Option<PathBuf>,
+
+        /// The URL of a remote object database (`trunk serve`) to pull from
         #[arg(long)]
-        other: PathBuf,
+        url: Option<String>,
 
         /// The compression method to use
         #[arg(long, short, default_value_t = Compression::None)]
@@ -50,9 +96,116 @@ enum Command {
         #[arg(long, short, action)]
         recursive: bool,
 
+        /// Only pull the object if it has been signed by a trusted key
+        #[arg(long, action)]
+        verify: bool,
+
         /// The object ID of the object to pull
         object: ObjectID,
     },
+    /// Export an object to a new object database
+    Export {
+        /// The root of the new object database, has to be empty or missing
+        #[arg(long)]
+        dest: PathBuf,
+
+        /// The compression method to use
+        #[arg(long, short, default_value_t = Compression::None)]
+        compression: Compression,
+
+        /// Whether to export the dependencies of the object, too
+        #[arg(long, short, action)]
+        recursive: bool,
+
+        /// The object ID of the object to export
+        oid: ObjectID,
+    },
+    /// Copy an object between the object databases of two homes
+    Copy {
+        /// The home directory to copy the object from
+        #[arg(long)]
+        from: PathBuf,
+
+        /// The home directory to copy the object to
+        #[arg(long)]
+        to: PathBuf,
+
+        /// The compression method to use
+        #[arg(long, short, default_value_t = Compression::None)]
+        compression: Compression,
+
+        /// Whether to copy the dependencies of the object, too
+        #[arg(long, short, action)]
+        recursive: bool,
+
+        /// The object ID of the object to copy
+        oid: ObjectID,
+    },
+    /// Show the differences between two objects
+    Diff {
+        /// The object id of the old object
+        old: ObjectID,
+
+        /// The object id of the new object
+        new: ObjectID,
+    },
+    /// Print the objects that depend on an object
+    Rdeps {
+        /// Recursively list the dependents of the dependents
+        #[arg(long, short, action)]
+        recursive: bool,
+
+        /// Rebuild the reverse dependency index before listing
+        #[arg(long, action)]
+        rebuild: bool,
+
+        /// The object ID to list the dependents of
+        oid: ObjectID,
+    },
+    /// Change the number of directory levels the objects are stored in
+    Reshard {
+        /// The new depth, prints the current depth if omitted
+        #[arg(long)]
+        depth: Option<usize>,
+    },
+    /// Annotate an object with key/value pairs or print its annotations
+    Annotate {
+        /// The compression method to use for the annotation object
+        #[arg(long, short, default_value_t = Compression::None)]
+        compression: Compression,
+
+        /// The object ID of the object to annotate
+        oid: ObjectID,
+
+        /// The annotations to add in the form `<key>=<value>`, prints the annotations if empty
+        #[arg(value_parser = parse_annotation)]
+        values: Vec<(String, String)>,
+    },
+    /// Sign an object using the signing key
+    Sign {
+        /// The compression method to use for the signature object
+        #[arg(long, short, default_value_t = Compression::None)]
+        compression: Compression,
+
+        /// The object ID of the object to sign
+        oid: ObjectID,
+    },
+    /// Verify that an object has been signed by a trusted key
+    Verify {
+        /// The object ID of the object to verify
+        oid: ObjectID,
+    },
+    /// Generate a new signing key and print its public key
+    Keygen,
+    /// Trust the signatures of a public key or list the trusted keys
+    Trust {
+        /// The name to store the key as
+        #[arg(requires = "key")]
+        name: Option<String>,
+
+        /// The hex encoded public key
+        key: Option<String>,
+    },
     /// Print the dependencies of an object
     Dependencies {
         /// List the dependencies in a tree form
@@ -62,19 +215,120 @@ enum Command {
         /// The object ID to list the dependencies of
         oid: ObjectID,
     },
+    /// Pin an object to keep it and its dependencies from being garbage collected
+    Pin {
+        /// The object ID of the object to pin
+        oid: ObjectID,
+
+        /// Why the object gets pinned
+        label: String,
+    },
+    /// Remove the pins of an object
+    Unpin {
+        /// Only remove the pin with this label
+        #[arg(long)]
+        label: Option<String>,
+
+        /// The object ID of the pinned object
+        oid: ObjectID,
+    },
+    /// List the pinned objects
+    Pins,
+    /// Print statistics about an object or the whole object database
+    Stat {
+        /// Print statistics about all objects instead of a single one
+        #[arg(long, action, required_unless_present = "oid", conflicts_with = "oid")]
+        all: bool,
+
+        /// Print the statistics as JSON, same as `--format json`
+        #[arg(long, action)]
+        json: bool,
+
+        /// The object ID of the object to inspect
+        oid: Option<ObjectID>,
+    },
+    /// Report how much the file objects are shared between the trees
+    DedupReport {
+        /// The number of most shared files to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+}
+
+/// The object type to insert imported files as
+#[derive(ValueEnum, Clone, Copy)]
+enum ImportType {
+    /// Infer the type of every file
+    Infer,
+    /// Any other object
+    Other,
+    /// ELF files
+    Elf,
+    /// Scripts with an interpreter line
+    Script,
+    /// Archives or compressed files
+    Archive,
+    /// UTF-8 text files
+    Text,
 }
 
 impl CommandOdb {
     pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
-        let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
-        let db = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(&self.command.locks(), cli.lock.wait())?;
+        let db = self
+            .open_odb(&home.object_db_path())?
+            .with_pins(home.get_pins_path())
+            .with_refs(home.get_refs_path());
 
-        self.command.run(cli, db)
+        self.command.run(cli, self, db)
+    }
+
+    /// Opens the object database at `path` using the options of this command
+    /// # Arguments
+    /// * `path` - The root of the object database
+    fn open_odb(&self, path: &Path) -> Result<ObjectDB, Error> {
+        let mut driver = FilesystemDriver::new(path.to_owned())?;
+        if self.chunked {
+            driver = driver.with_chunking(Chunker::default());
+        }
+
+        ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")
     }
 }
 
 impl Command {
-    pub fn run(&self, _cli: &Cli, mut odb: ObjectDB) -> Result<i32, Error> {
+    /// Returns the scopes of the home this command needs to lock
+    fn locks(&self) -> Vec<(HomeLockScope, LockMode)> {
+        match self {
+            Command::Put { .. } | Command::Import { .. } | Command::Sign { .. } => {
+                vec![(HomeLockScope::ObjectDB, LockMode::Shared)]
+            }
+            Command::Annotate { values, .. } if !values.is_empty() => {
+                vec![(HomeLockScope::ObjectDB, LockMode::Shared)]
+            }
+            Command::Pull { url, .. } => {
+                let mut locks = vec![(HomeLockScope::ObjectDB, LockMode::Shared)];
+                if url.is_some() {
+                    // Partial downloads are kept in the temporary directory
+                    locks.push((HomeLockScope::Builder, LockMode::Shared));
+                }
+                locks
+            }
+            Command::Reshard { depth: Some(_) } | Command::Rdeps { rebuild: true, .. } => {
+                vec![(HomeLockScope::ObjectDB, LockMode::Exclusive)]
+            }
+            Command::Pin { .. } | Command::Unpin { .. } => {
+                vec![(HomeLockScope::Refs, LockMode::Exclusive)]
+            }
+            Command::Pins => vec![(HomeLockScope::Refs, LockMode::Shared)],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn run(&self, cli: &Cli, parent: &CommandOdb, mut odb: ObjectDB) -> Result<i32, Error> {
+        let json = cli.format == OutputFormat::Json;
+
         match &self {
             Command::Get { output, oid } => {
                 let oid = match ObjectID::new_from_hex(oid) {
@@ -109,39 +363,345 @@ impl Command {
                     .e_context(|| format!("Putting {} into object database", path.str_lossy()))?;
                 println!("{}", object.oid);
             }
+            Command::Import {
+                ty,
+                compression,
+                jobs,
+                dir,
+            } => {
+                let odb_path = cli.get_home()?.object_db_path();
+                let manifest = import_dir(dir, *ty, compression.clone(), *jobs, || {
+                    parent.open_odb(&odb_path)
+                })?;
+
+                for (path, oid) in manifest {
+                    println!("{}\t{oid}", path.str_lossy());
+                }
+            }
             Command::Pull {
                 other,
+                url,
                 compression,
                 recursive,
+                verify,
                 object,
             } => {
-                let other_driver = FilesystemDriver::new(other.clone())?;
-                let other_odb = ObjectDB::init(Box::new(other_driver))?;
-
-                odb.pull(
-                    &other_odb,
-                    object.clone(),
-                    compression.clone().into(),
-                    *recursive,
+                let other_driver: Box<dyn ODBDriver> = match (other, url) {
+                    (Some(other), _) => Box::new(FilesystemDriver::new(other.clone())?),
+                    (None, Some(url)) => {
+                        let home = cli.get_home()?;
+                        Box::new(
+                            HTTPDriver::new(url, home.get_downloads_dir())?
+                                .with_downloads(home.load_config()?.get_download_manager()),
+                        )
+                    }
+                    (None, None) => unreachable!("Clap requires either '--other' or '--url'"),
+                };
+
+                if *verify {
+                    let keyring = Keyring::load(&cli.get_home()?.get_keys_dir())?;
+                    odb.pull_verified(
+                        other_driver.as_ref(),
+                        object.clone(),
+                        compression.clone().into(),
+                        *recursive,
+                        &keyring.get_trusted_keys(),
+                    )?;
+                } else {
+                    odb.pull_from_driver(
+                        other_driver.as_ref(),
+                        object.clone(),
+                        compression.clone().into(),
+                        *recursive,
+                    )?;
+                }
+            }
+            Command::Export {
+                dest,
+                compression,
+                recursive,
+                oid,
+            } => {
+                // Make sure the exported object exists before creating the new object database
+                odb.get_object(oid)?;
+
+                if dest.exists() && !is_empty_dir(dest)? {
+                    return Err(Error::new(ErrorType::Other(format!(
+                        "Refusing to export into {}, it is not an empty directory",
+                        dest.str_lossy()
+                    ))));
+                }
+
+                let mut dest_odb = parent.open_odb(dest)?;
+                dest_odb
+                    .pull(&odb, oid.clone(), compression.clone().into(), *recursive)
+                    .ctx(|| format!("Exporting {oid} to {}", dest.str_lossy()))?;
+            }
+            Command::Copy {
+                from,
+                to,
+                compression,
+                recursive,
+                oid,
+            } => {
+                if !from.is_dir() {
+                    return Err(Error::new(ErrorType::Other(format!(
+                        "There is no home @ {}",
+                        from.str_lossy()
+                    ))));
+                }
+
+                let from_home = Home::new(from.clone())?;
+                let to_home = Home::new(to.clone())?;
+                let canonical = |home: &Home| {
+                    std::fs::canonicalize(home.get_root())
+                        .e_context(|| format!("Canonicalizing {}", home.get_root().str_lossy()))
+                };
+                if canonical(&from_home)? == canonical(&to_home)? {
+                    return Err(Error::new(ErrorType::Other(
+                        "Refusing to copy within the same home".to_owned(),
+                    )));
+                }
+
+                let _locks = to_home.lock_all(
+                    &[(HomeLockScope::ObjectDB, LockMode::Shared)],
+                    cli.lock.wait(),
                 )?;
+
+                let from_driver = FilesystemDriver::new(from_home.object_db_path())?;
+                let from_odb =
+                    ObjectDB::init(Box::new(from_driver)).ctx(|| "Opening source object db")?;
+                let mut to_odb = parent.open_odb(&to_home.object_db_path())?;
+
+                to_odb
+                    .pull(
+                        &from_odb,
+                        oid.clone(),
+                        compression.clone().into(),
+                        *recursive,
+                    )
+                    .ctx(|| format!("Copying {oid} to {}", to.str_lossy()))?;
+            }
+            Command::Diff { old, new } => print_diff(old, new, &odb, json)?,
+            Command::Rdeps {
+                recursive,
+                rebuild,
+                oid,
+            } => {
+                if *rebuild {
+                    let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+                    driver
+                        .rebuild_reverse_dependencies()
+                        .ctx(|| "Rebuilding reverse dependency index")?;
+                }
+
+                let mut dependents = odb.reverse_dependencies(oid)?;
+                let mut i = 0;
+                while *recursive && i < dependents.len() {
+                    for dependent in odb.reverse_dependencies(&dependents[i])? {
+                        if !dependents.contains(&dependent) {
+                            dependents.push(dependent);
+                        }
+                    }
+                    i += 1;
+                }
+
+                if json {
+                    println!("{}", serde_json::json!(dependents));
+                } else {
+                    for dependent in dependents {
+                        println!("{dependent}");
+                    }
+                }
+            }
+            Command::Reshard { depth } => {
+                let mut driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+
+                match depth {
+                    Some(depth) => {
+                        let moved = driver.reshard(*depth)?;
+                        println!("Moved {moved} objects to depth {depth}");
+                    }
+                    None if json => {
+                        println!("{}", serde_json::json!({ "depth": driver.get_depth() }))
+                    }
+                    None => println!("{}", driver.get_depth()),
+                }
+            }
+            Command::Annotate {
+                compression,
+                oid,
+                values,
+            } => {
+                if values.is_empty() {
+                    let annotations = Annotation::collect(&odb, oid)?;
+                    if json {
+                        println!("{}", serde_json::json!(annotations));
+                    } else {
+                        for (key, value) in annotations {
+                            println!("{key}: {value}");
+                        }
+                    }
+                } else {
+                    // Make sure the annotated object exists
+                    odb.get_object(oid)?;
+
+                    let annotation = Annotation::new(oid.clone(), values.iter().cloned().collect());
+                    let object = annotation
+                        .insert_into_odb(&mut odb, compression.clone().into())
+                        .ctx(|| format!("Annotating {oid}"))?;
+
+                    println!("{}", object.oid);
+                }
+            }
+            Command::Sign { compression, oid } => {
+                let keyring = Keyring::load(&cli.get_home()?.get_keys_dir())?;
+                let object = odb
+                    .sign(oid, keyring.get_signing_key()?, compression.clone().into())
+                    .ctx(|| format!("Signing {oid}"))?;
+
+                println!("{}", object.oid);
+            }
+            Command::Verify { oid } => {
+                let keyring = Keyring::load(&cli.get_home()?.get_keys_dir())?;
+                let signature = odb.verify_signature(oid, &keyring.get_trusted_keys())?;
+
+                let key = signature.get_key()?;
+                let name = keyring
+                    .get_named_trusted_keys()
+                    .iter()
+                    .find(|(_, k)| *k == key)
+                    .map(|(name, _)| name.as_str())
+                    .unwrap_or_default();
+
+                if json {
+                    let verified = serde_json::json!({
+                        "oid": oid,
+                        "signer": name,
+                        "key": signature.key,
+                    });
+                    println!("{verified}");
+                } else {
+                    println!("{oid} has been signed by {name} ({})", signature.key);
+                }
+            }
+            Command::Keygen => {
+                let key = Keyring::generate(&cli.get_home()?.get_keys_dir())?;
+                println!("{}", hex::encode(key.as_bytes()));
+            }
+            Command::Trust { name, key } => {
+                let dir = cli.get_home()?.get_keys_dir();
+
+                match (name, key) {
+                    (Some(name), Some(key)) => {
+                        Keyring::trust(&dir, name, &parse_public_key(key)?)?;
+                    }
+                    _ => {
+                        let keyring = Keyring::load(&dir)?;
+                        let keys = keyring
+                            .get_named_trusted_keys()
+                            .iter()
+                            .map(|(name, key)| (name, hex::encode(key.as_bytes())));
+
+                        if json {
+                            let keys: Vec<_> = keys
+                                .map(|(name, key)| serde_json::json!({ "name": name, "key": key }))
+                                .collect();
+                            println!("{}", serde_json::json!(keys));
+                        } else {
+                            for (name, key) in keys {
+                                println!("{name}\t{key}");
+                            }
+                        }
+                    }
+                }
             }
             Command::Dependencies { tree, oid } => {
                 let object = odb.get_object(oid)?;
-                if *tree {
+                if *tree && json {
+                    println!("{}", dependency_tree_json(&object, &odb)?);
+                } else if *tree {
                     print_tree(&object, &odb, 0)?;
                 } else {
                     let deps = object.resolve_dependencies(&odb, true)?;
-                    for dep in deps {
-                        println!("{}", dep.oid);
+                    if json {
+                        let oids: Vec<_> = deps.into_iter().map(|dep| dep.oid).collect();
+                        println!("{}", serde_json::json!(oids));
+                    } else {
+                        for dep in deps {
+                            println!("{}", dep.oid);
+                        }
+                    }
+                }
+            }
+            Command::Pin { oid, label } => odb.pin(oid, label)?,
+            Command::Unpin { label, oid } => {
+                if odb.unpin(oid, label.as_deref())? == 0 {
+                    warn!("{oid} has not been pinned");
+                }
+            }
+            Command::Pins => {
+                let pins = odb.get_pins()?;
+                if json {
+                    let pins: Vec<_> = pins
+                        .into_iter()
+                        .map(|pin| serde_json::json!({ "oid": pin.oid, "label": pin.label }))
+                        .collect();
+                    println!("{}", serde_json::json!(pins));
+                } else {
+                    for pin in pins {
+                        println!("{}\t{}", pin.oid, pin.label);
                     }
                 }
             }
+            Command::Stat {
+                all: _,
+                json: stat_json,
+                oid,
+            } => match oid {
+                Some(oid) => print_object_stat(oid, &odb, json || *stat_json)?,
+                None => {
+                    let stats = odb.stats()?;
+                    if json || *stat_json {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&stats)
+                                .expect("Serialize statistics should never fail")
+                        );
+                    } else {
+                        print_stats(&stats);
+                    }
+                }
+            },
+            Command::DedupReport { top } => {
+                let report = odb.dedup_report(*top)?;
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&report)
+                            .expect("Serialize deduplication report should never fail")
+                    );
+                } else {
+                    print_dedup_report(&report);
+                }
+            }
         }
 
         Ok(0)
     }
 }
 
+/// Returns whether `path` is a directory without any entries
+/// # Arguments
+/// * `path` - The path to check
+fn is_empty_dir(path: &Path) -> Result<bool, Error> {
+    Ok(path.is_dir()
+        && std::fs::read_dir(path)
+            .e_context(|| format!("Reading directory {}", path.str_lossy()))?
+            .next()
+            .is_none())
+}
+
 fn print_tree(object: &Object, odb: &ObjectDB, depth: u32) -> Result<(), Error> {
     if depth > 0 {
         println!("{}|--- {}", "|  ".repeat(depth as usize - 1), object.oid);
@@ -155,3 +715,329 @@ fn print_tree(object: &Object, odb: &ObjectDB, depth: u32) -> Result<(), Error>
 
     Ok(())
 }
+
+/// Returns the dependency tree of an object as nested JSON objects
+/// # Arguments
+/// * `object` - The object at the root of the tree
+/// * `odb` - The object database to resolve the dependencies in
+fn dependency_tree_json(object: &Object, odb: &ObjectDB) -> Result<serde_json::Value, Error> {
+    let dependencies = object
+        .resolve_dependencies(odb, false)?
+        .iter()
+        .map(|dependency| dependency_tree_json(dependency, odb))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(serde_json::json!({
+        "oid": object.oid,
+        "dependencies": dependencies,
+    }))
+}
+
+/// Prints the type, compression, dependencies and size of an object
+/// # Arguments
+/// * `oid` - The object id of the object
+/// * `odb` - The object database to read the object from
+/// * `json` - Whether to print the information as JSON
+fn print_object_stat(oid: &ObjectID, odb: &ObjectDB, json: bool) -> Result<(), Error> {
+    let mut reader = odb.read(oid)?;
+    let chunks = reader.chunks().map(|c| c.len());
+    let size = io::copy(&mut reader, &mut io::sink()).ctx(|| format!("Reading object {oid}"))?;
+    let object = &reader.object;
+
+    if json {
+        let stat = serde_json::json!({
+            "oid": oid,
+            "type": format!("{:?}", object.ty),
+            "compression": format!("{:?}", object.compression),
+            "dependencies": object.dependencies,
+            "chunks": chunks,
+            "size": size,
+        });
+        println!("{stat}");
+        return Ok(());
+    }
+
+    println!("oid: {oid}");
+    println!("type: {:?}", object.ty);
+    println!("compression: {:?}", object.compression);
+    if let Some(chunks) = chunks {
+        println!("chunks: {chunks}");
+    }
+    println!("dependencies: {}", object.dependencies.len());
+    println!("size: {size} bytes");
+
+    Ok(())
+}
+
+/// Prints the deduplication report of an object database in a human readable form
+/// # Arguments
+/// * `report` - The report to print
+fn print_dedup_report(report: &DedupReport) {
+    println!("trees: {}", report.trees);
+    println!("files: {} ({} references)", report.files, report.references);
+    println!("logical size: {} bytes", report.logical_size);
+    println!("physical size: {} bytes", report.physical_size);
+    println!("deduplication: {:.2}", report.dedup_ratio());
+
+    if !report.shared.is_empty() {
+        println!("most shared files:");
+        for shared in &report.shared {
+            println!(
+                "  {}: {} trees, {} bytes",
+                shared.oid, shared.trees, shared.size
+            );
+        }
+    }
+}
+
+/// Prints the statistics of an object database in a human readable form
+/// # Arguments
+/// * `stats` - The statistics to print
+fn print_stats(stats: &ObjectDBStats) {
+    println!("objects: {} ({} bytes)", stats.objects, stats.stored_size);
+    for (ty, ty_stats) in &stats.types {
+        println!(
+            "  {ty}: {} ({} bytes)",
+            ty_stats.count, ty_stats.stored_size
+        );
+    }
+
+    println!("sizes:");
+    for bucket in &stats.size_histogram {
+        match bucket.up_to {
+            Some(up_to) => println!("  <= {up_to} bytes: {}", bucket.count),
+            None => println!("  larger: {}", bucket.count),
+        }
+    }
+
+    println!(
+        "deduplication: {:.2} ({} bytes referenced)",
+        stats.dedup_ratio(),
+        stats.referenced_size
+    );
+    println!(
+        "chunk deduplication: {:.2} ({} bytes chunked in {} bytes of chunks)",
+        stats.chunk_dedup_ratio(),
+        stats.chunked_size,
+        stats.chunks_stored_size
+    );
+}
+
+/// Prints the differences between the objects `old` and `new`,
+/// falling through to a tree diff if both are trees
+/// # Arguments
+/// * `old` - The object id of the old object
+/// * `new` - The object id of the new object
+/// * `odb` - The object database to read the objects from
+/// * `json` - Whether to print the differences as JSON
+fn print_diff(old: &ObjectID, new: &ObjectID, odb: &ObjectDB, json: bool) -> Result<(), Error> {
+    let mut old_reader = odb.read(old).ctx(|| format!("Reading object {old}"))?;
+    let mut new_reader = odb.read(new).ctx(|| format!("Reading object {new}"))?;
+
+    let (old_object, new_object) = (old_reader.object.clone(), new_reader.object.clone());
+
+    let removed: Vec<_> = old_object
+        .dependencies
+        .iter()
+        .filter(|dep| !new_object.dependencies.contains(dep))
+        .collect();
+    let added: Vec<_> = new_object
+        .dependencies
+        .iter()
+        .filter(|dep| !old_object.dependencies.contains(dep))
+        .collect();
+
+    let old_size = io::copy(&mut odb.read(old)?, &mut io::sink()).ctx(|| "Reading old object")?;
+    let new_size = io::copy(&mut odb.read(new)?, &mut io::sink()).ctx(|| "Reading new object")?;
+
+    let differences = if old_object.ty == ObjectType::AcaciaTree
+        && new_object.ty == ObjectType::AcaciaTree
+    {
+        let old_tree = Tree::unpack_from_odb(&mut old_reader, odb).ctx(|| "Reading old tree")?;
+        let new_tree = Tree::unpack_from_odb(&mut new_reader, odb).ctx(|| "Reading new tree")?;
+        old_tree.diff(&new_tree)
+    } else {
+        Vec::new()
+    };
+
+    if json {
+        let diff = serde_json::json!({
+            "old": old,
+            "new": new,
+            "type": {
+                "old": format!("{:?}", old_object.ty),
+                "new": format!("{:?}", new_object.ty),
+            },
+            "compression": {
+                "old": format!("{:?}", old_object.compression),
+                "new": format!("{:?}", new_object.compression),
+            },
+            "dependencies": {
+                "removed": removed,
+                "added": added,
+            },
+            "size": {
+                "old": old_size,
+                "new": new_size,
+            },
+            "differences": differences.iter().map(difference_json).collect::<Vec<_>>(),
+        });
+        println!("{diff}");
+        return Ok(());
+    }
+
+    println!("--- {old}");
+    println!("+++ {new}");
+
+    if old_object.ty != new_object.ty {
+        println!("type: {:?} => {:?}", old_object.ty, new_object.ty);
+    } else {
+        println!("type: {:?}", old_object.ty);
+    }
+
+    if old_object.compression != new_object.compression {
+        println!(
+            "compression: {:?} => {:?}",
+            old_object.compression, new_object.compression
+        );
+    }
+
+    for dep in removed {
+        println!("- dependency {dep}");
+    }
+    for dep in added {
+        println!("+ dependency {dep}");
+    }
+
+    if old_size != new_size {
+        println!("size: {old_size} => {new_size} bytes");
+    } else {
+        println!("size: {old_size} bytes");
+    }
+
+    for difference in differences {
+        println!("{difference}");
+    }
+
+    Ok(())
+}
+
+/// Inserts all regular files under `dir` into object databases opened using `open_odb`,
+/// using one object database per job
+/// # Arguments
+/// * `dir` - The directory to import
+/// * `ty` - The type to insert the files as
+/// * `compression` - The compression to use
+/// * `jobs` - The number of files to insert in parallel
+/// * `open_odb` - The function to open an object database for a job
+/// # Returns
+/// The paths of the files relative to `dir` and their object ids, sorted by path
+fn import_dir<F>(
+    dir: &Path,
+    ty: ImportType,
+    compression: Compression,
+    jobs: usize,
+    open_odb: F,
+) -> Result<Vec<(PathBuf, ObjectID)>, Error>
+where
+    F: Fn() -> Result<ObjectDB, Error> + Sync,
+{
+    let mut files = Vec::new();
+    walk_dir(dir, true, &mut |entry| {
+        let path = entry.path();
+        if path.is_symlink() || !path.is_file() {
+            if !path.is_dir() {
+                warn!("Skipping {}, not a regular file", path.str_lossy());
+            }
+        } else {
+            files.push(path);
+        }
+        true
+    })
+    .e_context(|| format!("Walking {}", dir.str_lossy()))?;
+    files.sort();
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let manifest = Mutex::new(Vec::new());
+
+    let import = || -> Result<(), Error> {
+        let mut odb = open_odb()?;
+
+        while !failed.load(Ordering::Relaxed) {
+            let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                break;
+            };
+
+            let context = || format!("Importing {}", path.str_lossy());
+            let result = match ty.into_object_type() {
+                Some(ty) => odb.insert_file(path, ty, compression.clone().into(), Vec::new()),
+                None => odb.insert_file_infer(path, compression.clone().into()),
+            };
+
+            let object = result.ctx(context).inspect_err(|_| {
+                failed.store(true, Ordering::Relaxed);
+            })?;
+
+            let relative = path.strip_prefix(dir).expect("Walked files are in dir");
+            manifest
+                .lock()
+                .expect("Lock manifest")
+                .push((relative.to_owned(), object.oid));
+        }
+
+        Ok(())
+    };
+
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..jobs.max(1)).map(|_| s.spawn(import)).collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|h| h.join().expect("Join import thread"))
+    })?;
+
+    let mut manifest = manifest.into_inner().expect("Unlock manifest");
+    manifest.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(manifest)
+}
+
+impl ImportType {
+    /// Returns the object type to insert as, `None` if it should be inferred
+    fn into_object_type(self) -> Option<ObjectType> {
+        match self {
+            Self::Infer => None,
+            Self::Other => Some(ObjectType::Other),
+            Self::Elf => Some(ObjectType::ELF),
+            Self::Script => Some(ObjectType::Script),
+            Self::Archive => Some(ObjectType::Archive),
+            Self::Text => Some(ObjectType::Text),
+        }
+    }
+}
+
+impl std::fmt::Display for ImportType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Infer => "infer",
+                Self::Other => "other",
+                Self::Elf => "elf",
+                Self::Script => "script",
+                Self::Archive => "archive",
+                Self::Text => "text",
+            }
+        )
+    }
+}
+
+/// Parses an annotation in the form `<key>=<value>`
+fn parse_annotation(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected '<key>=<value>', got '{s}'"))?;
+
+    Ok((key.to_owned(), value.to_owned()))
+}
@@ -0,0 +1,125 @@
+use clap::Parser;
+use tooling::{
+    error::{Error, ErrorExt},
+    model::{odb_driver::FilesystemDriver, HomeLockScope, ObjectDB, ObjectID, RepositoryIndex},
+    util::fs::LockMode,
+};
+
+use super::{
+    common::{Compression, OutputFormat},
+    Cli,
+};
+
+#[derive(Parser)]
+pub struct CommandRepo {
+    /// The command to execute
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Parser)]
+enum Command {
+    /// Create a repository index from a set of objects
+    Index {
+        /// The compression to apply to the index object
+        #[arg(long, short, default_value_t = Compression::Xz)]
+        compression: Compression,
+
+        /// Other repository indices to merge into the new index,
+        /// the entries of the objects take precedence over merged ones
+        #[arg(long)]
+        merge: Vec<ObjectID>,
+
+        /// The object ids of the formulae to index
+        oids: Vec<ObjectID>,
+    },
+    /// List the entries of a repository index
+    List {
+        /// Only list entries for this package name
+        #[arg(long, conflicts_with = "provides")]
+        name: Option<String>,
+
+        /// Only list entries that provide this capability or package name
+        #[arg(long)]
+        provides: Option<String>,
+
+        /// The object id of the repository index
+        oid: ObjectID,
+    },
+}
+
+impl CommandRepo {
+    pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let home = cli.get_home()?;
+        let _locks = home.lock_all(&self.command.locks(), cli.lock.wait())?;
+
+        let driver = FilesystemDriver::new(home.object_db_path())?;
+        let db = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+        self.command.run(cli, db)
+    }
+}
+
+impl Command {
+    /// Returns the scopes of the home this command needs to lock
+    fn locks(&self) -> Vec<(HomeLockScope, LockMode)> {
+        match self {
+            Command::Index { .. } => vec![(HomeLockScope::ObjectDB, LockMode::Shared)],
+            Command::List { .. } => Vec::new(),
+        }
+    }
+
+    fn run(&self, cli: &Cli, mut odb: ObjectDB) -> Result<i32, Error> {
+        match self {
+            Command::Index {
+                compression,
+                merge,
+                oids,
+            } => {
+                let mut index = RepositoryIndex::new();
+
+                for oid in merge {
+                    let other = RepositoryIndex::from_odb(&odb, oid)
+                        .ctx(|| format!("Reading repository index {oid} to merge"))?;
+                    index.merge(other);
+                }
+
+                // The entries of the objects take precedence over the merged indices
+                index.merge(
+                    RepositoryIndex::from_objects(oids, &odb)
+                        .ctx(|| "Creating repository index")?,
+                );
+
+                let object = index
+                    .insert_into_odb(&mut odb, compression.clone().into())
+                    .ctx(|| "Inserting repository index")?;
+
+                println!("{}", object.oid);
+            }
+            Command::List {
+                name,
+                provides,
+                oid,
+            } => {
+                let index =
+                    RepositoryIndex::from_odb(&odb, oid).ctx(|| "Reading repository index")?;
+
+                let entries = match (name, provides) {
+                    (Some(name), _) => index.find(name),
+                    (None, Some(provides)) => index.find_providers(provides),
+                    (None, None) => index.entries.iter().collect(),
+                };
+
+                if cli.format == OutputFormat::Json {
+                    println!("{}", serde_json::json!(entries));
+                } else {
+                    for entry in entries {
+                        println!("{entry}");
+                    }
+                }
+            }
+        }
+
+        Ok(0)
+    }
+}
@@ -1,13 +1,26 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 
 use clap::Parser;
+use log::{info, warn};
 use tooling::{
     error::{Error, ErrorExt},
-    model::{odb_driver::FilesystemDriver, ObjectDB, ObjectID, Tree},
-    util::{fs::PathUtil, ODBUnpackable},
+    model::{
+        odb_driver::FilesystemDriver, Formula, HomeLockScope, ObjectDB, ObjectID, PycAction, Tree,
+    },
+    util::{
+        archive,
+        chunker::Chunker,
+        fs::{LockMode, PathUtil},
+        parse::spdx::SpdxExpression,
+        progress::ProgressBar,
+        ODBUnpackable,
+    },
 };
 
-use super::{common::Compression, Cli};
+use super::{
+    common::{entry_json, Compression, OutputFormat},
+    Cli,
+};
 
 #[derive(Parser)]
 pub struct CommandTree {
@@ -28,18 +41,148 @@ enum Command {
         #[arg(long, default_value_t = false)]
         stat: bool,
 
+        /// Store large files in deduplicated chunks
+        #[arg(long, action)]
+        chunked: bool,
+
         /// The path to index
         path: PathBuf,
     },
+    /// Create a new tree from the entries of a tar archive without extracting it
+    FromArchive {
+        /// The compression to apply to the indexed objects
+        #[arg(long, short, default_value_t = Compression::Xz)]
+        compression: Compression,
+
+        /// Display a stat of the created tree
+        #[arg(long, default_value_t = false)]
+        stat: bool,
+
+        /// Store large files in deduplicated chunks
+        #[arg(long, action)]
+        chunked: bool,
+
+        /// The tar archive to index, optionally compressed using xz or gzip
+        archive: PathBuf,
+    },
     /// Deploy a tree to a directory
     Deploy {
         /// The object id of the tree to deploy
         #[arg(long, short)]
         tree: ObjectID,
 
+        /// Stage the deployment and roll back on failure
+        #[arg(long, action)]
+        atomic: bool,
+
+        /// Only deploy the subtree at this path within the tree
+        #[arg(long)]
+        subpath: Option<PathBuf>,
+
+        /// Cache small objects read repeatedly in memory, up to this many MiB
+        #[arg(long)]
+        cache: Option<usize>,
+
+        /// Deploy from the object database at this path, opened read-only,
+        /// instead of the one of the home
+        #[arg(long)]
+        odb: Option<PathBuf>,
+
         /// The directory to deploy to
         root: PathBuf,
     },
+    /// Deploy only the paths of a tree matching glob patterns to a directory
+    Checkout {
+        /// Only deploy the paths matching this glob pattern (`?`, `*` and `**`)
+        /// and their contents, can be given multiple times [everything]
+        #[arg(long)]
+        include: Vec<String>,
+
+        /// Do not deploy the paths matching this glob pattern and their contents,
+        /// can be given multiple times
+        #[arg(long)]
+        exclude: Vec<String>,
+
+        /// Stage the deployment and roll back on failure
+        #[arg(long, action)]
+        atomic: bool,
+
+        /// The object id of the tree to deploy
+        oid: ObjectID,
+
+        /// The directory to deploy to
+        root: PathBuf,
+    },
+    /// Check the symlinks of a tree for dangling and absolute destinations,
+    /// optionally its license files against the declared license, its ELF files
+    /// for hardening and search paths and its Python files for build root paths
+    Check {
+        /// Rewrite absolute destinations into relative ones and insert the fixed tree
+        #[arg(long, action)]
+        fix: bool,
+
+        /// Warn about license files that do not match this SPDX license expression
+        #[arg(long)]
+        license: Option<SpdxExpression>,
+
+        /// Warn about ELF files missing RELRO, PIE, a non-executable stack or stack canaries
+        #[arg(long, action)]
+        hardening: bool,
+
+        /// Remove RPATH and RUNPATH entries outside of the dist directory and duplicates
+        /// from the ELF files and insert the fixed tree
+        #[arg(long, action)]
+        runpaths: bool,
+
+        /// Warn about Python bytecode compiled against paths other than the ones of its sources
+        /// and `RECORD` and `.pth` entries pointing outside of the tree
+        #[arg(long, action)]
+        python: bool,
+
+        /// Fix the Python files and insert the fixed tree, bytecode compiled against
+        /// the wrong paths gets rewritten (`rewrite`) or deleted (`delete`)
+        #[arg(long, value_parser = PycAction::from_str)]
+        python_fix: Option<PycAction>,
+
+        /// Warn about extra dependencies of the formula that provide none of the
+        /// libraries and interpreters the ELF files and scripts of the tree need
+        #[arg(long, action, requires = "formula")]
+        unused_dependencies: bool,
+
+        /// Insert the tree depending on the runtime dependencies of the formula,
+        /// dropping the unused extra dependencies
+        #[arg(long, action, requires = "formula")]
+        prune_dependencies: bool,
+
+        /// Only check and fix the ELF files in the executable and library
+        /// directories of the layout of this formula
+        #[arg(long)]
+        formula: Option<ObjectID>,
+
+        /// The compression to apply to the fixed tree
+        #[arg(long, short, default_value_t = Compression::Xz)]
+        compression: Compression,
+
+        /// The object id of the tree to check
+        oid: ObjectID,
+    },
+    /// Create a new tree containing only the paths matching glob patterns
+    Filter {
+        /// Drop the matching paths instead of keeping them
+        #[arg(long, action)]
+        exclude: bool,
+
+        /// The compression to apply to the filtered tree
+        #[arg(long, short, default_value_t = Compression::Xz)]
+        compression: Compression,
+
+        /// The object id of the tree to filter
+        oid: ObjectID,
+
+        /// The glob patterns to match the paths against (`?`, `*` and `**`)
+        #[arg(required = true)]
+        patterns: Vec<String>,
+    },
     /// List the contents of a tree file
     List {
         /// The object id of the tree to read
@@ -49,21 +192,48 @@ enum Command {
 
 impl CommandTree {
     pub fn run(&self, cli: &Cli) -> Result<i32, Error> {
+        let _locks = cli
+            .get_home()?
+            .lock_all(&self.command.locks(), cli.lock.wait())?;
+
         self.command.run(cli)
     }
 }
 
 impl Command {
+    /// Returns the scopes of the home this command needs to lock
+    fn locks(&self) -> Vec<(HomeLockScope, LockMode)> {
+        match self {
+            Command::Create { .. } | Command::FromArchive { .. } | Command::Filter { .. } => {
+                vec![(HomeLockScope::ObjectDB, LockMode::Shared)]
+            }
+            Command::Check {
+                fix,
+                runpaths,
+                python_fix,
+                prune_dependencies,
+                ..
+            } if *fix || *runpaths || python_fix.is_some() || *prune_dependencies => {
+                vec![(HomeLockScope::ObjectDB, LockMode::Shared)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
     fn run(&self, cli: &Cli) -> Result<i32, Error> {
         match self {
             Command::Create {
                 compression,
                 stat,
+                chunked,
                 path,
             } => {
                 let context = || format!("Indexing {}", path.str_lossy(),);
 
-                let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+                let mut driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+                if *chunked {
+                    driver = driver.with_chunking(Chunker::default());
+                }
                 let mut db = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
 
                 let tree = Tree::index(path, &mut db, compression.clone().into()).ctx(context)?;
@@ -81,15 +251,260 @@ impl Command {
 
                 println!("{}", tree_object.oid);
             }
-            Command::Deploy { tree, root } => {
+            Command::FromArchive {
+                compression,
+                stat,
+                chunked,
+                archive,
+            } => {
+                let context = || format!("Indexing archive {}", archive.str_lossy());
+
+                let mut driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+                if *chunked {
+                    driver = driver.with_chunking(Chunker::default());
+                }
+                let mut db = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+                let mut tar = archive::open_tar_infer(archive)?;
+                let tree =
+                    Tree::index_tar(&mut tar, &mut db, compression.clone().into()).ctx(context)?;
+
+                let tree_object = tree
+                    .insert_into_odb(&mut db, compression.clone().into())
+                    .ctx(|| "Inserting the tree")
+                    .ctx(context)?;
+
+                if *stat {
+                    for cmd in &tree.entries {
+                        println!("{cmd}");
+                    }
+                }
+
+                println!("{}", tree_object.oid);
+            }
+            Command::Deploy {
+                tree,
+                atomic,
+                subpath,
+                cache,
+                odb,
+                root,
+            } => {
+                let mut db = match odb {
+                    Some(odb) => ObjectDB::open_readonly(odb)?,
+                    None => {
+                        let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+                        ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?
+                    }
+                };
+                if let Some(cache) = cache {
+                    db = db.with_cache(cache * 1024 * 1024);
+                }
+
+                let tree = match subpath {
+                    Some(subpath) => Tree::resolve_subtree(tree, subpath, &db)?,
+                    None => tree.clone(),
+                };
+
+                let mut tree_object = db.read(&tree).ctx(|| "Opening tree object")?;
+
+                let tree =
+                    Tree::unpack_from_odb(&mut tree_object, &db).ctx(|| "Reading tree object")?;
+                let mut bar = ProgressBar::new("Deploying");
+                let mut callback = |p: &_| bar.update_deploy(p);
+                if *atomic {
+                    tree.deploy_atomic_with_progress(root, &db, &mut callback)
+                        .ctx(|| "Deploying tree")?;
+                } else {
+                    tree.deploy_with_progress(root, &db, &mut callback)
+                        .ctx(|| "Deploying tree")?;
+                }
+                bar.finish();
+
+                if let Some(stats) = db.cache_stats() {
+                    info!("Object cache: {stats}");
+                }
+            }
+            Command::Checkout {
+                include,
+                exclude,
+                atomic,
+                oid,
+                root,
+            } => {
                 let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
                 let db = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
 
-                let mut tree_object = db.read(tree).ctx(|| "Opening tree object")?;
+                let mut object = db.read(oid).ctx(|| "Opening tree object")?;
+                let tree = Tree::unpack_from_odb(&mut object, &db)
+                    .ctx(|| "Reading tree object")?
+                    .sparse(include, exclude);
+
+                if tree.entries.is_empty() {
+                    warn!("No path of {oid} matches the patterns");
+                }
+
+                let mut bar = ProgressBar::new("Deploying");
+                let mut callback = |p: &_| bar.update_deploy(p);
+                if *atomic {
+                    tree.deploy_atomic_with_progress(root, &db, &mut callback)
+                        .ctx(|| "Deploying tree")?;
+                } else {
+                    tree.deploy_with_progress(root, &db, &mut callback)
+                        .ctx(|| "Deploying tree")?;
+                }
+                bar.finish();
+            }
+            Command::Check {
+                fix,
+                license,
+                hardening,
+                runpaths,
+                python,
+                python_fix,
+                unused_dependencies,
+                prune_dependencies,
+                formula,
+                compression,
+                oid,
+            } => {
+                let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+                let mut db = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+                let mut object = db.read(oid).ctx(|| "Reading tree object")?;
+                let mut tree =
+                    Tree::unpack_from_odb(&mut object, &db).ctx(|| "Reading object contents")?;
+
+                let formula = match formula {
+                    Some(formula) => Some(
+                        Formula::from_odb(&db, formula)
+                            .ctx(|| format!("Reading formula {formula}"))?,
+                    ),
+                    None => None,
+                };
+                let scan_dirs = formula.as_ref().map(|f| f.get_layout().get_scan_dirs());
+
+                if *fix {
+                    let count = tree.relativize_symlinks();
+                    eprintln!("Rewrote {count} symlinks");
+                }
+
+                if *runpaths {
+                    let compression = compression.clone().into();
+                    let removals = match &scan_dirs {
+                        Some(dirs) => tree.sanitize_runpaths_in_dirs(&mut db, compression, dirs),
+                        None => tree.sanitize_runpaths(&mut db, compression),
+                    }
+                    .ctx(|| "Sanitizing search paths")?;
+                    for removal in removals {
+                        eprintln!("{removal}");
+                    }
+                }
+
+                if let Some(action) = python_fix {
+                    let issues = tree
+                        .fix_python_paths(&mut db, compression.clone().into(), *action)
+                        .ctx(|| "Fixing Python files")?;
+                    for issue in issues {
+                        eprintln!("{issue}");
+                    }
+                }
+
+                // The runtime dependencies of the pruned tree
+                let mut dependencies = Vec::new();
+                if let Some(formula) =
+                    formula.filter(|_| *unused_dependencies || *prune_dependencies)
+                {
+                    let needs = match &scan_dirs {
+                        Some(dirs) => tree.find_runtime_needs_in_dirs(&db, dirs)?,
+                        None => tree.find_runtime_needs(&db)?,
+                    };
+                    let unused =
+                        tree.find_unused_dependencies(&db, &needs, &formula.extra_dependencies)?;
 
+                    if *unused_dependencies {
+                        for oid in &unused {
+                            warn!("Extra dependency {oid} is not needed by any file");
+                        }
+                    }
+
+                    if *prune_dependencies {
+                        dependencies = formula.target_dependencies;
+                        for oid in formula.extra_dependencies {
+                            if !unused.contains(&oid) && !dependencies.contains(&oid) {
+                                dependencies.push(oid);
+                            }
+                        }
+                    }
+                }
+
+                if *fix || *runpaths || python_fix.is_some() || *prune_dependencies {
+                    let tree_object = tree
+                        .insert_into_odb_with_dependencies(
+                            &mut db,
+                            compression.clone().into(),
+                            dependencies,
+                        )
+                        .ctx(|| "Inserting the fixed tree")?;
+
+                    println!("{}", tree_object.oid);
+                }
+
+                if let Some(license) = license {
+                    for mismatch in tree.check_licenses(license, &db)? {
+                        warn!("{mismatch}");
+                    }
+                }
+
+                if *hardening {
+                    let issues = match &scan_dirs {
+                        Some(dirs) => tree.check_hardening_in_dirs(&db, dirs)?,
+                        None => tree.check_hardening(&db)?,
+                    };
+                    for issue in issues {
+                        warn!("{issue}");
+                    }
+                }
+
+                if *python && python_fix.is_none() {
+                    for issue in tree.check_python_paths(&db)? {
+                        warn!("{issue}");
+                    }
+                }
+
+                let issues = tree.check_symlinks();
+                for issue in &issues {
+                    eprintln!("{issue}");
+                }
+
+                if !issues.is_empty() {
+                    return Ok(1);
+                }
+            }
+            Command::Filter {
+                exclude,
+                compression,
+                oid,
+                patterns,
+            } => {
+                let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
+                let mut db = ObjectDB::init(Box::new(driver)).ctx(|| "Opening object db")?;
+
+                let mut object = db.read(oid).ctx(|| "Reading tree object")?;
                 let tree =
-                    Tree::unpack_from_odb(&mut tree_object, &db).ctx(|| "Reading tree object")?;
-                tree.deploy(root, &db).ctx(|| "Deploying tree")?;
+                    Tree::unpack_from_odb(&mut object, &db).ctx(|| "Reading object contents")?;
+
+                let tree = if *exclude {
+                    tree.exclude_glob(patterns)
+                } else {
+                    tree.filter_glob(patterns)
+                };
+
+                let tree_object = tree
+                    .insert_into_odb(&mut db, compression.clone().into())
+                    .ctx(|| "Inserting the filtered tree")?;
+
+                println!("{}", tree_object.oid);
             }
             Command::List { oid } => {
                 let driver = FilesystemDriver::new(cli.get_home()?.object_db_path())?;
@@ -99,8 +514,17 @@ impl Command {
                 let tree =
                     Tree::unpack_from_odb(&mut object, &db).ctx(|| "Reading object contents")?;
 
-                for cmd in tree.entries {
-                    println!("{cmd}");
+                if cli.format == OutputFormat::Json {
+                    let entries = tree
+                        .entries
+                        .iter()
+                        .map(|entry| entry_json(entry, &db))
+                        .collect::<Result<Vec<_>, Error>>()?;
+                    println!("{}", serde_json::json!(entries));
+                } else {
+                    for cmd in tree.entries {
+                        println!("{cmd}");
+                    }
                 }
             }
         }
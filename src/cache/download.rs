@@ -4,6 +4,7 @@ use base64::{prelude::BASE64_URL_SAFE, Engine};
 
 use std::{
     fs::remove_file,
+    io::Write,
     path::{Path, PathBuf},
 };
 
@@ -18,6 +19,9 @@ use crate::{
     },
 };
 
+/// The extension of the files recording the URL a cached file has been downloaded from
+static MIRROR_FILE_EXTENSION: &str = "url";
+
 /// A download cache
 pub struct DownloadCache {
     /// The directory to use for caching
@@ -59,8 +63,7 @@ impl DownloadCache {
         message: &str,
         expect_success: bool,
     ) -> Result<StatusCode, Error> {
-        let hash = util::hash::hash_string(url);
-        let hash = BASE64_URL_SAFE.encode(hash);
+        let hash = Self::get_key(url);
 
         let cache_path = self.workdir.join(&hash);
         if cache_path.exists() {
@@ -98,4 +101,70 @@ impl DownloadCache {
             Ok(res)
         }
     }
+
+    /// Downloads the first of `urls` that succeeds through the cache,
+    /// refer to [download::download_to_file_mirrored()].
+    ///
+    /// The cached file is stored for the first URL, so all mirrors share the same cached file.
+    /// The URL that succeeded is recorded next to the cached file
+    /// # Arguments
+    /// * `urls` - The URL and its mirrors to fetch from
+    /// * `file` - The file to download to
+    /// * `message` - The message to log when downloading
+    /// # Returns
+    /// The URL that provided the file or `None` if it is unknown for a cached file
+    pub fn download_mirrored(
+        &self,
+        urls: &[String],
+        file: &Path,
+        message: &str,
+    ) -> Result<Option<String>, Error> {
+        let Some(primary) = urls.first() else {
+            return download::download_to_file_mirrored(urls, file, message)
+                .map(|(_, url)| Some(url));
+        };
+
+        let hash = Self::get_key(primary);
+        let cache_path = self.workdir.join(&hash);
+        let mirror_path = self.workdir.join(format!("{hash}.{MIRROR_FILE_EXTENSION}"));
+
+        if cache_path.exists() {
+            info!("{}", message);
+            debug!("Using cached value {hash}");
+
+            match copy(&cache_path, file) {
+                Ok(_) => return Ok(std::fs::read_to_string(&mirror_path).ok()),
+                Err(e) => {
+                    warn!("Couldn't use cache for {}: {} - DROPPING", primary, e);
+
+                    remove_file(&cache_path)
+                        .e_context(|| format!("Dropping cached value {} for {}", hash, primary))?;
+                }
+            }
+        }
+
+        // Download the file to a temporary path
+        let temp_path = self.workdir.join(format!("{}_temp", &hash));
+        let (_, url) = download::download_to_file_mirrored(urls, &temp_path, message)?;
+
+        debug!("Creating cached value {hash} from {url}");
+
+        rename(&temp_path, &cache_path)
+            .e_context(|| format!("Creating cache value {} for {}", hash, primary))?;
+        util::fs::file_create(&mirror_path)?
+            .write_all(url.as_bytes())
+            .e_context(|| format!("Recording mirror of cache value {} for {}", hash, primary))?;
+
+        copy(&cache_path, file)
+            .e_context(|| format!("Using cache value {} for {}", hash, primary))?;
+
+        Ok(Some(url))
+    }
+
+    /// Returns the name of the cached file for `url`
+    /// # Arguments
+    /// * `url` - The URL to get the name for
+    fn get_key(url: &str) -> String {
+        BASE64_URL_SAFE.encode(util::hash::hash_string(url))
+    }
 }
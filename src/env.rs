@@ -5,11 +5,28 @@ mod buildenv;
 #[cfg(feature = "mount")]
 pub use buildenv::*;
 
+#[cfg(feature = "mount")]
+mod emulatedenv;
+#[cfg(feature = "mount")]
+pub use emulatedenv::*;
+
+mod hermetic;
+pub use hermetic::*;
+
+mod session;
+pub use session::*;
+
 pub mod executable;
 
-use std::{collections::HashMap, ffi::OsString, path::Path};
+use std::{
+    collections::HashMap, ffi::OsString, fmt::Display, path::Path, str::FromStr, time::Duration,
+};
+
+use log::warn;
 
-use crate::{error::Error, util::signal::SignalDispatcher};
+use crate::{error::Error, model::StepRecord, util::signal::SignalDispatcher};
+
+use executable::CustomExecutable;
 
 /// An environment that can execute `EnvironmentExecutables`
 pub trait Environment {
@@ -22,6 +39,153 @@ pub trait Environment {
         executable: &dyn EnvironmentExecutable,
         signal_dispatcher: &SignalDispatcher,
     ) -> Result<std::process::ExitStatus, Error>;
+
+    /// Records how `executable` gets executed in the environment (the exact
+    /// arguments, environment variables, mounts and kernel) for a [crate::model::BuildReceipt]
+    /// # Arguments
+    /// * `executable` - A reference to the executable to record
+    fn record(&self, executable: &dyn EnvironmentExecutable) -> StepRecord;
+
+    /// Spawns an interactive shell in the environment, using the
+    /// environment variables and working directory of `executable`
+    /// # Arguments
+    /// * `executable` - A reference to the executable to take the environment from
+    /// * `signal_dispatcher` - A reference to the `SignalDispatcher` to register signals for the shell
+    /// # Returns
+    /// The exit status of the shell once the user exits it
+    fn spawn_shell(
+        &self,
+        executable: &dyn EnvironmentExecutable,
+        signal_dispatcher: &SignalDispatcher,
+    ) -> Result<std::process::ExitStatus, Error> {
+        let shell = CustomExecutable::new(
+            "exec sh".to_owned(),
+            executable.get_workdir().to_owned(),
+            executable.get_env_variables(),
+        )
+        .with_interactive(true);
+
+        self.execute(&shell, signal_dispatcher)
+    }
+
+    /// Executes a `EnvironmentExecutable` in the environment,
+    /// retrying it if it fails or times out
+    /// # Arguments
+    /// * `executable` - A reference to the executable to execute
+    /// * `signal_dispatcher` - A reference to the `SignalDispatcher` to register signals for the executed process
+    /// * `retries` - How often to retry the executable before giving up
+    /// # Returns
+    /// The result of the last attempt
+    fn execute_retrying(
+        &self,
+        executable: &dyn EnvironmentExecutable,
+        signal_dispatcher: &SignalDispatcher,
+        retries: u32,
+    ) -> Result<std::process::ExitStatus, Error> {
+        let mut attempt = 0;
+
+        loop {
+            let res = self.execute(executable, signal_dispatcher);
+
+            let failure = match &res {
+                Ok(status) if status.success() => return res,
+                Ok(status) => status.to_string(),
+                Err(e) => e.oneline(),
+            };
+
+            if attempt >= retries {
+                return res;
+            }
+
+            attempt += 1;
+            warn!(
+                "'{}' failed ({}), retrying ({}/{})",
+                executable.get_name(),
+                failure,
+                attempt,
+                retries
+            );
+        }
+    }
+
+    /// Executes a `EnvironmentExecutable` in the environment like `execute_retrying()`,
+    /// taking `on_failure` if the last attempt fails
+    /// # Arguments
+    /// * `executable` - A reference to the executable to execute
+    /// * `signal_dispatcher` - A reference to the `SignalDispatcher` to register signals for the executed process
+    /// * `retries` - How often to retry the executable before giving up
+    /// * `on_failure` - The action to take if the executable fails
+    /// # Returns
+    /// The result of the last attempt, after the failure action has completed
+    fn execute_or_fail_with(
+        &self,
+        executable: &dyn EnvironmentExecutable,
+        signal_dispatcher: &SignalDispatcher,
+        retries: u32,
+        on_failure: FailureAction,
+    ) -> Result<std::process::ExitStatus, Error> {
+        let res = self.execute_retrying(executable, signal_dispatcher, retries);
+
+        if matches!(&res, Ok(status) if status.success()) {
+            return res;
+        }
+
+        match on_failure {
+            FailureAction::Abort => {}
+            FailureAction::Shell => {
+                warn!(
+                    "'{}' failed, spawning a shell in the environment. Exit it to continue",
+                    executable.get_name()
+                );
+                self.spawn_shell(executable, signal_dispatcher)?;
+            }
+        }
+
+        res
+    }
+
+    /// Starts a [Session] to run multiple executables one after another in this environment
+    /// # Arguments
+    /// * `signal_dispatcher` - A reference to the `SignalDispatcher` to register signals for the executed processes
+    fn session<'a>(&'a self, signal_dispatcher: &'a SignalDispatcher) -> Session<'a>
+    where
+        Self: Sized,
+    {
+        Session::new(self, signal_dispatcher)
+    }
+}
+
+/// The action to take if an executable fails in an environment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureAction {
+    /// Abort immediately, tearing down the environment
+    #[default]
+    Abort,
+    /// Spawn an interactive shell in the environment, tearing it down once the shell exits
+    Shell,
+}
+
+impl FromStr for FailureAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "shell" => Ok(Self::Shell),
+            _ => Err(format!(
+                "Unknown failure action '{s}', expected 'abort' or 'shell'"
+            )),
+        }
+    }
+}
+
+impl Display for FailureAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Abort => write!(f, "abort"),
+            Self::Shell => write!(f, "shell"),
+        }
+    }
 }
 
 /// An executable that can be executed in a `Environment`
@@ -35,6 +199,78 @@ pub trait EnvironmentExecutable {
     /// Returns the command to execute in the environment
     fn get_command(&self) -> OsString;
 
+    /// Returns the arguments to pass to the command as its positional parameters (`$1`, ...),
+    /// so they don't have to be quoted within the command
+    fn get_arguments(&self) -> Vec<OsString> {
+        Vec::new()
+    }
+
     /// Returns the directory to run the command in
     fn get_workdir(&self) -> &Path;
+
+    /// Returns the time after which the executable gets killed, `None` to wait indefinitely
+    fn get_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns whether the executable interacts with the user (e.g. a shell or a setup prompt):
+    /// It gets the terminal of the user as its stdio instead of having its `stdout`
+    /// redirected to `stderr`, and interrupts from the terminal reach it directly
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+/// Variables that are set by environments themselves and never passed through from the host
+static RESERVED_ENV_VARIABLES: &[&str] = &["PATH"];
+
+/// Collects the host environment variables named in `allowlist` to pass them into an environment.
+///
+/// Entries ending in `*` match all variables starting with the prefix (`LC_*`).
+/// Reserved variables (`PATH`), variables with non-UTF-8 values and values
+/// containing control characters are dropped with a warning
+/// # Arguments
+/// * `allowlist` - The names of the host variables to pass through
+pub fn collect_passthrough_env(allowlist: &[String]) -> HashMap<String, String> {
+    let matches = |name: &str| {
+        allowlist.iter().any(|entry| match entry.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == entry,
+        })
+    };
+
+    let mut vars = HashMap::new();
+
+    for (name, value) in std::env::vars_os() {
+        let name = name.to_string_lossy().to_string();
+
+        if !matches(&name) {
+            continue;
+        }
+
+        if RESERVED_ENV_VARIABLES.contains(&name.as_str()) {
+            warn!("Not passing through reserved variable '{name}'");
+            continue;
+        }
+
+        match value.into_string() {
+            Ok(value) if !value.chars().any(|c| c.is_control()) => {
+                vars.insert(name, value);
+            }
+            _ => warn!("Not passing through variable '{name}' with invalid value"),
+        }
+    }
+
+    vars
+}
+
+/// Returns the release of the running kernel (`uname -r`), `unknown` if it can't be determined
+pub fn kernel_release() -> String {
+    match uname::uname() {
+        Ok(info) => info.release,
+        Err(e) => {
+            warn!("Failed to determine the kernel release: {e}");
+            "unknown".to_owned()
+        }
+    }
 }
@@ -1,30 +1,39 @@
 use std::{
-    io::{self},
+    collections::HashMap,
+    io::{self, IsTerminal},
     path::{Path, PathBuf},
     process::Stdio,
     sync::{Arc, Mutex},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use indexmap::IndexMap;
 use log::{debug, error, info, warn};
 use std::process::Command;
 
 use crate::{
     error::{Error, ErrorExt},
+    files::homeconfig::RemoteCompileConfig,
+    model::{MountRecord, StepRecord},
     util::{
-        mount::{BindMount, Mount, VKFSMount},
+        mount::{BindMount, Mount, MountOptions, VKFSMount},
+        sched::{confine_command, CpuList},
         signal::SignalDispatcher,
     },
 };
 
-use super::{Environment, EnvironmentExecutable};
+use super::{collect_passthrough_env, kernel_release, Environment, EnvironmentExecutable};
+
+/// The size limit of the `tmpfs` mounted at `/run`
+static RUN_TMPFS_SIZE: &str = "64m";
 
 /// Represents a build environment that can be used to build a package.
 ///
 /// Expects the following directories in the `toolchain_dir`:
 /// - `/bin`: Binaries
 /// - `/sbin`: Superuser binaries
+///
 /// Expects the following programs:
 /// - `env`: The `env` program that can be found using the PATH variable
 /// - `sh`: The `sh` program that can be found using the PATH variable
@@ -35,17 +44,25 @@ pub struct BuildEnvironment {
     mounts: Vec<Box<dyn Mount>>,
     /// The path to search for the host toolchain to prepend the PATH variable
     toolchain_dir: PathBuf,
+    /// The host environment variables to pass into the environment
+    passthrough_env: HashMap<String, String>,
+    /// The remote compilation the compilers get wrapped with, if enabled
+    remote_compile: Option<RemoteCompileConfig>,
+    /// The CPUs the build processes are confined to, if any
+    cpuset: Option<CpuList>,
+    /// The nice level of the build processes, if any
+    nice: Option<i32>,
 }
 
 impl BuildEnvironment {
-    /// Creates a new build environment from the `overlay_mount`, mounting in the following vkfs:
-    /// - `/dev (bind)`==> `<merged>/dev`
-    /// - `/dev/pts (bind)`==> `<merged>/dev/pts`
-    /// - `proc (vkfs)`==> `<merged>/proc`
-    /// - `sysfs (vkfs)`==> `<merged>/sys`
-    /// - `tmpfs (vkfs)`==> `<merged>/run`
+    /// Creates a new build environment from the `root_mount`, mounting in the following vkfs:
+    /// - `/dev (bind, nosuid)`==> `<merged>/dev`
+    /// - `/dev/pts (bind, nosuid, noexec)`==> `<merged>/dev/pts`
+    /// - `proc (vkfs, nosuid, nodev, noexec)`==> `<merged>/proc`
+    /// - `sysfs (vkfs, ro, nosuid, nodev, noexec)`==> `<merged>/sys`
+    /// - `tmpfs (vkfs, nosuid, nodev, size=64m)`==> `<merged>/run`
     /// # Arguments
-    /// * `overlay_mount` - The overlay mount to construct the build environment in
+    /// * `root_mount` - The mount to construct the build environment in
     /// * `toolchain_dir` - The directory to search for toolchain files (PATH)
     pub fn new(
         root_mount: Box<dyn Mount>,
@@ -55,16 +72,32 @@ impl BuildEnvironment {
         let target = root_mount.get_target_path();
 
         // Mount the virtual kernel filesystems
-        let m_dev =
-            BindMount::new(Path::new("/dev"), &target.join("dev"), false).e_context(context)?;
+        let m_dev = BindMount::new(
+            Path::new("/dev"),
+            &target.join("dev"),
+            MountOptions::new().nosuid(),
+        )
+        .e_context(context)?;
         let m_dev_pts = BindMount::new(
             Path::new("/dev/pts"),
             &target.join("dev").join("pts"),
-            false,
+            MountOptions::new().nosuid().noexec(),
+        )?;
+        let m_proc = VKFSMount::new(
+            "proc",
+            &target.join("proc"),
+            MountOptions::new().nosuid().nodev().noexec(),
+        )?;
+        let m_sysfs = VKFSMount::new(
+            "sysfs",
+            &target.join("sys"),
+            MountOptions::new().readonly().nosuid().nodev().noexec(),
+        )?;
+        let m_tmpfs = VKFSMount::new(
+            "tmpfs",
+            &target.join("run"),
+            MountOptions::new().nosuid().nodev().size(RUN_TMPFS_SIZE),
         )?;
-        let m_proc = VKFSMount::new("proc", &target.join("proc"))?;
-        let m_sysfs = VKFSMount::new("sysfs", &target.join("sys"))?;
-        let m_tmpfs = VKFSMount::new("tmpfs", &target.join("run"))?;
 
         Ok(BuildEnvironment {
             root: root_mount,
@@ -76,6 +109,67 @@ impl BuildEnvironment {
                 Box::new(m_tmpfs),
             ],
             toolchain_dir,
+            passthrough_env: HashMap::new(),
+            remote_compile: None,
+            cpuset: None,
+            nice: None,
+        })
+    }
+
+    /// Creates a new build environment from the `root_mount` that does not require `root`
+    /// privileges, mounting in the following filesystems:
+    /// - `/dev (rbind)`==> `<merged>/dev`
+    /// - `/proc (rbind)`==> `<merged>/proc`
+    /// - `/sys (rbind)`==> `<merged>/sys`
+    /// - `tmpfs (vkfs, nosuid, nodev, size=64m)`==> `<merged>/run`
+    ///
+    /// The process has to be in a user namespace ([crate::util::userns::UserNamespace::enter()])
+    /// before creating the `root_mount`, files created in the environment are owned by the
+    /// real user that entered the namespace or its subordinate ids on disk.
+    /// # Arguments
+    /// * `root_mount` - The mount to construct the build environment in
+    /// * `toolchain_dir` - The directory to search for toolchain files (PATH)
+    pub fn new_unprivileged(
+        root_mount: Box<dyn Mount>,
+        toolchain_dir: PathBuf,
+    ) -> Result<BuildEnvironment, Error> {
+        let context = || "Creating unprivileged build environment";
+        let target = root_mount.get_target_path();
+
+        // Kernel filesystems can't be mounted freshly from within a user namespace
+        // Remounting them with other flags is not permitted either
+        let m_dev =
+            BindMount::new_recursive(Path::new("/dev"), &target.join("dev"), MountOptions::new())
+                .e_context(context)?;
+        let m_proc = BindMount::new_recursive(
+            Path::new("/proc"),
+            &target.join("proc"),
+            MountOptions::new(),
+        )
+        .e_context(context)?;
+        let m_sys =
+            BindMount::new_recursive(Path::new("/sys"), &target.join("sys"), MountOptions::new())
+                .e_context(context)?;
+        let m_tmpfs = VKFSMount::new(
+            "tmpfs",
+            &target.join("run"),
+            MountOptions::new().nosuid().nodev().size(RUN_TMPFS_SIZE),
+        )
+        .e_context(context)?;
+
+        Ok(BuildEnvironment {
+            root: root_mount,
+            mounts: vec![
+                Box::new(m_dev),
+                Box::new(m_proc),
+                Box::new(m_sys),
+                Box::new(m_tmpfs),
+            ],
+            toolchain_dir,
+            passthrough_env: HashMap::new(),
+            remote_compile: None,
+            cpuset: None,
+            nice: None,
         })
     }
 
@@ -86,18 +180,70 @@ impl BuildEnvironment {
         self.mounts.push(mount);
     }
 
+    /// Sets the host environment variables to pass into the environment,
+    /// all other host variables are cleared. Refer to [collect_passthrough_env()]
+    /// for the format of `allowlist` and the variables that get dropped
+    /// # Arguments
+    /// * `allowlist` - The names of the host variables to pass through
+    pub fn set_passthrough_env(&mut self, allowlist: &[String]) {
+        self.passthrough_env = collect_passthrough_env(allowlist);
+    }
+
+    /// Distributes the compilation in this environment using the client of `config`:
+    /// Bind mounts the client binary of the host to `/usr/bin/<client>` and wraps
+    /// the compilers (`CC="distcc gcc"`). Variables set by the executables take precedence
+    /// # Arguments
+    /// * `config` - The remote compilation configuration of the home
+    pub fn enable_remote_compile(&mut self, config: &RemoteCompileConfig) -> Result<(), Error> {
+        let context = || format!("Setting up remote compilation using {}", config.client);
+
+        let target = self
+            .root
+            .get_target_path()
+            .join("usr/bin")
+            .join(config.client.get_binary_name());
+        let mount = BindMount::new_file(
+            &config.get_host_path(),
+            &target,
+            MountOptions::new().readonly(),
+        )
+        .ctx(context)?;
+        self.add_mount(Box::new(mount));
+
+        info!("Distributing compilation using {}", config.client);
+        self.remote_compile = Some(config.clone());
+
+        Ok(())
+    }
+
+    /// Confines the build processes to the CPUs in `cpuset` and runs them at the nice
+    /// level `nice`, e.g. to keep builds from starving other users of a shared machine.
+    /// Both are applied to the processes before they execute (see [confine_command()])
+    /// # Arguments
+    /// * `cpuset` - The CPUs to confine the processes to, `None` to use all of them
+    /// * `nice` - The nice level to run the processes at, `None` to keep the current one
+    pub fn set_scheduling(&mut self, cpuset: Option<CpuList>, nice: Option<i32>) {
+        if let Some(cpuset) = &cpuset {
+            info!("Confining build processes to CPUs {cpuset}");
+        }
+        if let Some(nice) = nice {
+            info!("Running build processes at nice level {nice}");
+        }
+
+        self.cpuset = cpuset;
+        self.nice = nice;
+    }
+
     /// Returns a reference to the `OverlayMount` used for the build environment
     pub fn get_root_mount(&self) -> &dyn Mount {
         self.root.as_ref()
     }
-}
 
-impl Environment for BuildEnvironment {
-    fn execute(
-        &self,
-        executable: &dyn EnvironmentExecutable,
-        signal_dispatcher: &SignalDispatcher,
-    ) -> Result<std::process::ExitStatus, Error> {
+    /// Returns the command that runs `executable` in the build environment,
+    /// with the environment variables set and stdio not configured yet
+    /// # Arguments
+    /// * `executable` - The executable to run
+    fn command(&self, executable: &dyn EnvironmentExecutable) -> Command {
         let mut command = Command::new("/bin/chroot");
 
         command
@@ -111,15 +257,40 @@ impl Environment for BuildEnvironment {
             .arg("-c")
             .arg(executable.get_command());
 
+        // The first argument after the command becomes `$0`
+        let arguments = executable.get_arguments();
+        if !arguments.is_empty() {
+            command.arg("sh").args(arguments);
+        }
+
+        command
+            .envs(&self.passthrough_env)
+            .env("PATH", self.get_path())
+            .envs(self.remote_compile.iter().flat_map(|c| c.get_env()))
+            .envs(executable.get_env_variables());
+
+        command
+    }
+
+    /// Returns the `PATH` variable for commands in the build environment
+    fn get_path(&self) -> String {
         let tc_dir = self.toolchain_dir.to_string_lossy();
-        let path = format!(
+        format!(
             "/bin:/sbin:/usr/bin:/usr/sbin:{}/bin:{}/sbin",
             tc_dir, tc_dir
-        );
+        )
+    }
+}
 
-        command
-            .env("PATH", path)
-            .envs(executable.get_env_variables());
+impl Environment for BuildEnvironment {
+    fn execute(
+        &self,
+        executable: &dyn EnvironmentExecutable,
+        signal_dispatcher: &SignalDispatcher,
+    ) -> Result<std::process::ExitStatus, Error> {
+        let mut command = self.command(executable);
+        confine_command(&mut command, self.cpuset.as_ref(), self.nice)
+            .ctx(|| format!("Confining build step '{}'", executable.get_name()))?;
 
         debug!(
             "Running build step '{}', executing command 'chroot' with following arguments:",
@@ -143,21 +314,43 @@ impl Environment for BuildEnvironment {
         }
 
         let executable_name = executable.get_name();
+        let timeout = executable.get_timeout();
+        let interactive = executable.is_interactive();
+
+        if interactive {
+            if !io::stdin().is_terminal() {
+                warn!("Running '{executable_name}' interactively, but stdin is not a terminal");
+            }
+
+            command
+                .stdin(Stdio::inherit())
+                .stdout(Stdio::inherit())
+                .stderr(Stdio::inherit());
+        } else {
+            command.stdout(Stdio::piped());
+        }
+
+        let start = Instant::now();
         let mut child = command
-            .stdout(Stdio::piped())
             .spawn()
             .e_context(|| "Spawing child process".to_owned())?;
 
         // Get the `stdout` of the child to redirect it
-        let mut child_stdout = child.stdout.take().expect("Stdout");
+        let child_stdout = child.stdout.take();
 
         let process_arc = Arc::new(Mutex::new(child));
 
         let handler_arc = process_arc.clone();
 
         thread::scope(|s| {
-            // Construct a signal handler that will kill the child process
+            // Construct a signal handler that will kill the child process. Interactive
+            // children receive interrupts from the terminal themselves (e.g. to cancel a
+            // command in a shell), the handler only keeps them from reaching the caller's
             let guard = signal_dispatcher.add_handler(Box::new(move || {
+                if interactive {
+                    return;
+                }
+
                 match handler_arc.lock().expect("Lock handler mutex").kill() {
                     Ok(_) => warn!("Killed build step '{}'", executable_name),
                     Err(_) => error!("Failed to kill build step {}", executable_name),
@@ -165,11 +358,13 @@ impl Environment for BuildEnvironment {
             }));
 
             // Redirect `stdout` of the child to `stderr`
-            let _redirect_thread = s.spawn(|| {
-                let mut stderr = io::stderr().lock();
+            if let Some(mut child_stdout) = child_stdout {
+                s.spawn(move || {
+                    let mut stderr = io::stderr().lock();
 
-                io::copy(&mut child_stdout, &mut stderr).expect("Redirect stderr");
-            });
+                    io::copy(&mut child_stdout, &mut stderr).expect("Redirect stderr");
+                });
+            }
 
             // Loop until the child exits
             loop {
@@ -188,12 +383,74 @@ impl Environment for BuildEnvironment {
                     return Ok(res);
                 }
 
+                // Kill the child if it exceeded its timeout
+                if let Some(timeout) = timeout {
+                    if start.elapsed() > timeout {
+                        child
+                            .kill()
+                            .e_context(|| "Killing timed out child process".to_owned())?;
+                        child
+                            .wait()
+                            .e_context(|| "Waiting for killed child to join".to_owned())?;
+                        drop(guard);
+
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("Timed out after {} seconds", timeout.as_secs()),
+                        ))
+                        .e_context(|| format!("Running build step '{}'", executable.get_name()));
+                    }
+                }
+
                 // Drop the mutex to free for the signal handler
                 drop(child);
                 std::thread::sleep(Duration::from_millis(100));
             }
         })
     }
+
+    fn record(&self, executable: &dyn EnvironmentExecutable) -> StepRecord {
+        let command = self.command(executable);
+
+        let mut argv = vec![command.get_program().to_string_lossy().to_string()];
+        argv.extend(command.get_args().map(|a| a.to_string_lossy().to_string()));
+
+        let mut env = IndexMap::new();
+        for (name, value) in command.get_envs() {
+            if let Some(value) = value {
+                env.insert(
+                    name.to_string_lossy().to_string(),
+                    value.to_string_lossy().to_string(),
+                );
+            }
+        }
+        env.sort_keys();
+
+        let mounts = std::iter::once(&self.root)
+            .chain(&self.mounts)
+            .map(|mount| MountRecord {
+                fs_type: mount.get_fs_type(),
+                sources: mount
+                    .get_source_paths()
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect(),
+                target: mount.get_target_path().to_string_lossy().to_string(),
+                flags: mount.get_flags(),
+            })
+            .collect();
+
+        StepRecord {
+            name: executable.get_name(),
+            argv,
+            env,
+            workdir: executable.get_workdir().to_string_lossy().to_string(),
+            mounts,
+            kernel: kernel_release(),
+            remote_compile: self.remote_compile.as_ref().map(|c| c.client.to_string()),
+            snapshot: None,
+        }
+    }
 }
 
 impl Drop for BuildEnvironment {
@@ -0,0 +1,221 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info, warn};
+
+use crate::{
+    error::{architecture::ArchitectureError, Error, ErrorExt, ErrorType},
+    model::StepRecord,
+    util::{
+        architecture::Architecture,
+        mount::{BindMount, MountOptions},
+        signal::SignalDispatcher,
+    },
+};
+
+use super::{BuildEnvironment, Environment, EnvironmentExecutable};
+
+/// The directory of the `binfmt_misc` filesystem
+static BINFMT_MISC_DIR: &str = "/proc/sys/fs/binfmt_misc";
+
+/// The `binfmt_misc` magic and mask to detect ELF executables of an architecture,
+/// keyed by the name of the architecture for `qemu-user` (`qemu-<name>-static`).
+/// Taken from `qemu-binfmt-conf.sh`
+static BINFMT_ARCHS: &[(&str, &str, &str)] = &[
+    (
+        "x86_64",
+        r"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x3e\x00",
+        r"\xff\xff\xff\xff\xff\xfe\xfe\x00\xff\xff\xff\xff\xff\xff\xff\xff\xfe\xff\xff\xff",
+    ),
+    (
+        "i386",
+        r"\x7fELF\x01\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x03\x00",
+        r"\xff\xff\xff\xff\xff\xfe\xfe\x00\xff\xff\xff\xff\xff\xff\xff\xff\xfe\xff\xff\xff",
+    ),
+    (
+        "aarch64",
+        r"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\xb7\x00",
+        r"\xff\xff\xff\xff\xff\xff\xff\x00\xff\xff\xff\xff\xff\xff\xff\xff\xfe\xff\xff\xff",
+    ),
+    (
+        "arm",
+        r"\x7fELF\x01\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x28\x00",
+        r"\xff\xff\xff\xff\xff\xff\xff\x00\xff\xff\xff\xff\xff\xff\xff\xff\xfe\xff\xff\xff",
+    ),
+    (
+        "riscv64",
+        r"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\xf3\x00",
+        r"\xff\xff\xff\xff\xff\xff\xff\x00\xff\xff\xff\xff\xff\xff\xff\xff\xfe\xff\xff\xff",
+    ),
+];
+
+/// A build environment for a foreign architecture that runs its binaries using `qemu-user`.
+///
+/// A static `qemu-<arch>-static` binary gets bind mounted into the environment
+/// and registered with `binfmt_misc`, so foreign executables run transparently.
+/// Every build registers its own entry, which gets removed once the environment is dropped
+pub struct EmulatedEnvironment {
+    /// The environment the emulated binaries run in
+    inner: BuildEnvironment,
+    /// The architecture that gets emulated
+    arch: Architecture,
+    /// The `binfmt_misc` entry that has been registered for this build
+    binfmt_entry: PathBuf,
+}
+
+impl EmulatedEnvironment {
+    /// Wraps `inner` to emulate `arch`, requiring `root` privileges to register `binfmt_misc`
+    /// # Arguments
+    /// * `inner` - The build environment to emulate in
+    /// * `arch` - The architecture to emulate
+    /// * `qemu_dir` - The directory on the host containing the static `qemu-<arch>-static` binaries
+    /// * `build_id` - The id of the build, naming the `binfmt_misc` entry
+    pub fn new(
+        mut inner: BuildEnvironment,
+        arch: Architecture,
+        qemu_dir: &Path,
+        build_id: &str,
+    ) -> Result<Self, Error> {
+        let context = || format!("Setting up emulation of {arch}");
+
+        let (name, magic, mask) = BINFMT_ARCHS
+            .iter()
+            .find(|(name, _, _)| *name == qemu_arch_name(&arch))
+            .ok_or_else(|| {
+                Error::new(ErrorType::Architecture(ArchitectureError::NotSupported {
+                    arch: arch.clone(),
+                    supported: BINFMT_ARCHS
+                        .iter()
+                        .map(|(name, _, _)| Architecture::new_arch(name.to_string()))
+                        .collect(),
+                }))
+            })
+            .ctx(context)?;
+
+        let binary = format!("qemu-{name}-static");
+        let interpreter = Path::new("/usr/bin").join(&binary);
+
+        // The interpreter gets looked up within the chroot when executing a foreign binary
+        let target = inner.get_root_mount().get_target_path().join(
+            interpreter
+                .strip_prefix("/")
+                .expect("Interpreter is absolute"),
+        );
+        let mount = BindMount::new_file(
+            &qemu_dir.join(&binary),
+            &target,
+            MountOptions::new().readonly(),
+        )
+        .ctx(context)?;
+        inner.add_mount(Box::new(mount));
+
+        // The entries are global to the host, concurrent builds must not share one
+        let entry_name = format!("acacia-qemu-{name}-{build_id}");
+        let binfmt_entry = Path::new(BINFMT_MISC_DIR).join(&entry_name);
+
+        if binfmt_entry.exists() {
+            // A previous run of this build did not get to remove it
+            debug!("binfmt_misc entry '{entry_name}' exists already, reusing it");
+        } else {
+            let registration = format!(
+                ":{entry_name}:M::{magic}:{mask}:{}:",
+                interpreter.to_string_lossy()
+            );
+            debug!("Registering binfmt_misc entry {registration}");
+
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(Path::new(BINFMT_MISC_DIR).join("register"))
+                .and_then(|mut f| f.write_all(registration.as_bytes()))
+                .e_context(|| format!("Registering binfmt_misc entry '{entry_name}'"))
+                .ctx(context)?;
+        }
+
+        info!("Emulating {arch} using {binary}");
+
+        Ok(Self {
+            inner,
+            arch,
+            binfmt_entry,
+        })
+    }
+
+    /// Returns `inner` if `arch` runs on the host, else wraps it in an
+    /// emulated environment if emulation is enabled by providing `qemu_dir`
+    /// # Arguments
+    /// * `inner` - The build environment to use
+    /// * `arch` - The architecture the environment needs to run
+    /// * `qemu_dir` - The directory containing the `qemu-<arch>-static` binaries, `None` to disable emulation
+    /// * `build_id` - The id of the build, refer to [EmulatedEnvironment::new()]
+    pub fn select(
+        inner: BuildEnvironment,
+        arch: &Architecture,
+        qemu_dir: Option<&Path>,
+        build_id: &str,
+    ) -> Result<Box<dyn Environment>, Error> {
+        let host = Architecture::new_uname()?;
+
+        if arch.can_run_on(&host) {
+            return Ok(Box::new(inner));
+        }
+
+        match qemu_dir {
+            Some(qemu_dir) => Ok(Box::new(Self::new(
+                inner,
+                arch.clone(),
+                qemu_dir,
+                build_id,
+            )?)),
+            None => Err(Error::new_context(
+                ErrorType::Architecture(ArchitectureError::NotSupported {
+                    arch: arch.clone(),
+                    supported: vec![host],
+                }),
+                "Building for a foreign architecture requires emulation".to_owned(),
+            )),
+        }
+    }
+
+    /// Returns the architecture that gets emulated
+    pub fn get_arch(&self) -> &Architecture {
+        &self.arch
+    }
+}
+
+impl Environment for EmulatedEnvironment {
+    fn execute(
+        &self,
+        executable: &dyn EnvironmentExecutable,
+        signal_dispatcher: &SignalDispatcher,
+    ) -> Result<std::process::ExitStatus, Error> {
+        self.inner.execute(executable, signal_dispatcher)
+    }
+
+    fn record(&self, executable: &dyn EnvironmentExecutable) -> StepRecord {
+        self.inner.record(executable)
+    }
+}
+
+impl Drop for EmulatedEnvironment {
+    fn drop(&mut self) {
+        let entry = &self.binfmt_entry;
+        debug!("Removing binfmt_misc entry {}", entry.to_string_lossy());
+
+        if let Err(e) = std::fs::write(entry, "-1") {
+            warn!(
+                "Failed to remove binfmt_misc entry {}: {e}",
+                entry.to_string_lossy()
+            );
+        }
+    }
+}
+
+/// Returns the name `qemu-user` uses for `arch` from the architecture database,
+/// the name of the architecture itself if it is unknown
+/// # Arguments
+/// * `arch` - The architecture to get the name for
+fn qemu_arch_name(arch: &Architecture) -> &str {
+    arch.info().map_or(arch.arch.as_str(), |info| info.qemu)
+}
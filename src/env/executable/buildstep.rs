@@ -1,11 +1,15 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
+use indexmap::IndexMap;
+
 use crate::{
     abs_dist_dir,
-    env::EnvironmentExecutable,
+    env::{EnvironmentExecutable, HermeticSettings},
+    files::formulavars::BUILTIN_VARIABLES,
     package::{info::PackageInfo, CorePackage, NamedPackage, VersionedPackage},
     util::architecture::Architecture,
 };
@@ -20,10 +24,23 @@ pub struct BuildStep {
     pub arch: Architecture,
     /// The command to execute when building
     pub command: String,
+    /// The arguments to pass to the command as its positional parameters
+    pub arguments: Vec<String>,
     /// The working directory for the build process in the chroot
     pub workdir: PathBuf,
     /// The directory to install into in the chroot
     pub install_dir: PathBuf,
+    /// The time after which the build step gets killed
+    pub timeout: Option<Duration>,
+    /// How often to retry the build step if it fails
+    pub retries: u32,
+    /// The formula variables to export, refer to [crate::files::formulavars::FormulaVariables]
+    pub variables: IndexMap<String, String>,
+    /// The environment variables of the formula's `[env]` tables with their values substituted,
+    /// refer to [crate::files::formulafile::FormulaFile::get_env()]
+    pub environment: IndexMap<String, String>,
+    /// The fixed clock and locale settings to export, `None` if the formula opted out
+    pub hermetic: Option<HermeticSettings>,
 }
 
 impl EnvironmentExecutable for BuildStep {
@@ -36,7 +53,7 @@ impl EnvironmentExecutable for BuildStep {
 
         let pkg_root = self
             .pkg_info
-            .get_root_dir(&abs_dist_dir())
+            .get_root_dir(&abs_dist_dir(), &self.arch)
             .to_string_lossy()
             .to_string();
 
@@ -48,16 +65,42 @@ impl EnvironmentExecutable for BuildStep {
         map.insert("PKG_INSTALL_DIR", &install_dir);
         map.insert("PKG_ROOT", &pkg_root);
 
-        map.into_iter()
-            .map(|p| (p.0.to_string(), p.1.to_string()))
-            .collect()
+        for (name, value) in &self.variables {
+            map.insert(name, value);
+        }
+
+        // The `[env]` tables override the formula variables, but not the built-in ones
+        for (name, value) in &self.environment {
+            if !BUILTIN_VARIABLES.contains(&name.as_str()) && !name.starts_with("PKG_") {
+                map.insert(name, value);
+            }
+        }
+
+        // The formula variables and the package information take precedence
+        let mut vars = self
+            .hermetic
+            .as_ref()
+            .map(|h| h.get_env_variables())
+            .unwrap_or_default();
+
+        vars.extend(map.into_iter().map(|p| (p.0.to_string(), p.1.to_string())));
+
+        vars
     }
 
     fn get_command(&self) -> std::ffi::OsString {
         self.command.clone().into()
     }
 
+    fn get_arguments(&self) -> Vec<std::ffi::OsString> {
+        self.arguments.iter().map(|a| a.into()).collect()
+    }
+
     fn get_workdir(&self) -> &Path {
         &self.workdir
     }
+
+    fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
 }
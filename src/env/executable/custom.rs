@@ -14,6 +14,8 @@ pub struct CustomExecutable {
     pub workdir: PathBuf,
     /// The environment variables
     pub env_vars: HashMap<String, String>,
+    /// Whether the program interacts with the user, refer to [EnvironmentExecutable::is_interactive()]
+    pub interactive: bool,
 }
 
 impl CustomExecutable {
@@ -27,8 +29,18 @@ impl CustomExecutable {
             program,
             workdir,
             env_vars,
+            interactive: false,
         }
     }
+
+    /// Sets whether the program interacts with the user,
+    /// refer to [EnvironmentExecutable::is_interactive()]
+    /// # Arguments
+    /// * `interactive` - Whether the program gets the terminal of the user
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
 }
 
 impl EnvironmentExecutable for CustomExecutable {
@@ -47,4 +59,8 @@ impl EnvironmentExecutable for CustomExecutable {
     fn get_workdir(&self) -> &Path {
         &self.workdir
     }
+
+    fn is_interactive(&self) -> bool {
+        self.interactive
+    }
 }
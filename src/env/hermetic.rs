@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    util::fs::{walk_dir, PathUtil},
+};
+
+/// The time zone build steps run in
+static HERMETIC_TZ: &str = "UTC";
+
+/// The locale build steps run with
+static HERMETIC_LOCALE: &str = "C";
+
+/// Fixed clock and locale settings for build steps, so builds
+/// don't depend on when and on which machine they run:
+/// - `SOURCE_DATE_EPOCH`: The timestamp tools should use instead of the current time
+/// - `TZ`: `UTC`
+/// - `LC_ALL`: `C`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HermeticSettings {
+    /// The timestamp (seconds since the UNIX epoch) to export as `SOURCE_DATE_EPOCH`
+    pub source_date_epoch: i64,
+}
+
+impl HermeticSettings {
+    /// Creates new hermetic settings
+    /// # Arguments
+    /// * `source_date_epoch` - The timestamp to export as `SOURCE_DATE_EPOCH`
+    pub fn new(source_date_epoch: i64) -> Self {
+        Self { source_date_epoch }
+    }
+
+    /// Creates hermetic settings using the `SOURCE_DATE_EPOCH` of the host,
+    /// falling back to `fallback` if it is not set
+    /// # Arguments
+    /// * `fallback` - The timestamp to use if the host does not set `SOURCE_DATE_EPOCH`
+    pub fn from_host_or(fallback: i64) -> Result<Self, Error> {
+        let source_date_epoch = match std::env::var("SOURCE_DATE_EPOCH") {
+            Ok(value) => value.trim().parse().map_err(|_| {
+                Error::new(ErrorType::Other(format!(
+                    "Invalid SOURCE_DATE_EPOCH '{value}', expected seconds since the UNIX epoch"
+                )))
+            })?,
+            Err(_) => fallback,
+        };
+
+        Ok(Self::new(source_date_epoch))
+    }
+
+    /// Returns the environment variables to export to build steps
+    pub fn get_env_variables(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "SOURCE_DATE_EPOCH".to_owned(),
+                self.source_date_epoch.to_string(),
+            ),
+            ("TZ".to_owned(), HERMETIC_TZ.to_owned()),
+            ("LC_ALL".to_owned(), HERMETIC_LOCALE.to_owned()),
+        ])
+    }
+
+    /// Finds all entries below `root` that have been modified after `SOURCE_DATE_EPOCH`,
+    /// these end up with timestamps depending on the time of the build.
+    /// Symlinks are not followed
+    /// # Arguments
+    /// * `root` - The directory to search, e.g. the install directory of a package
+    /// # Returns
+    /// The paths of the entries relative to `root`
+    pub fn find_newer_files(&self, root: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut newer = Vec::new();
+        let mut error = None;
+
+        walk_dir(root, true, &mut |entry| match entry.metadata() {
+            Ok(metadata) => {
+                if metadata.mtime() > self.source_date_epoch {
+                    let path = entry.path();
+                    newer.push(path.strip_prefix(root).unwrap_or(&path).to_owned());
+                }
+                true
+            }
+            Err(e) => {
+                error = Some(e);
+                false
+            }
+        })
+        .e_context(|| format!("Walking {}", root.str_lossy()))?;
+
+        if let Some(e) = error {
+            return Err(e).e_context(|| format!("Reading timestamps in {}", root.str_lossy()));
+        }
+
+        newer.sort();
+        Ok(newer)
+    }
+}
@@ -0,0 +1,89 @@
+use std::process::ExitStatus;
+
+use log::debug;
+
+use crate::{
+    error::{Error, ErrorType},
+    util::signal::SignalDispatcher,
+};
+
+use super::{Environment, EnvironmentExecutable};
+
+/// Runs multiple executables one after another in the same environment,
+/// stopping at the first one that fails:
+/// ```ignore
+/// env.session(&signal_dispatcher).run(&prepare)?.run(&build)?;
+/// ```
+///
+/// The mounts of an environment are set up when creating it and stay alive
+/// until it is dropped, so every executable of a session sees the files
+/// the executables before it left behind
+pub struct Session<'a> {
+    /// The environment to run the executables in
+    env: &'a dyn Environment,
+    /// The `SignalDispatcher` to register signals for the executed processes
+    signal_dispatcher: &'a SignalDispatcher,
+    /// The names and exit statuses of the executables that have been run
+    statuses: Vec<(String, ExitStatus)>,
+}
+
+impl<'a> Session<'a> {
+    /// Creates a new session in `env`, refer to [Environment::session()]
+    /// # Arguments
+    /// * `env` - The environment to run the executables in
+    /// * `signal_dispatcher` - The `SignalDispatcher` to register signals for the executed processes
+    pub fn new(env: &'a dyn Environment, signal_dispatcher: &'a SignalDispatcher) -> Self {
+        Self {
+            env,
+            signal_dispatcher,
+            statuses: Vec::new(),
+        }
+    }
+
+    /// Runs `executable` in the environment of this session
+    /// # Arguments
+    /// * `executable` - The executable to run
+    /// # Errors
+    /// If the executable could not be run or exited unsuccessfully
+    pub fn run(self, executable: &dyn EnvironmentExecutable) -> Result<Self, Error> {
+        self.run_retrying(executable, 0)
+    }
+
+    /// Runs `executable` in the environment of this session,
+    /// retrying it if it fails (see [Environment::execute_retrying()])
+    /// # Arguments
+    /// * `executable` - The executable to run
+    /// * `retries` - How often to retry the executable before giving up
+    /// # Errors
+    /// If the executable could not be run or its last attempt exited unsuccessfully
+    pub fn run_retrying(
+        mut self,
+        executable: &dyn EnvironmentExecutable,
+        retries: u32,
+    ) -> Result<Self, Error> {
+        let name = executable.get_name();
+        debug!(
+            "Running '{name}' in session ({} run before)",
+            self.statuses.len()
+        );
+
+        let status = self
+            .env
+            .execute_retrying(executable, self.signal_dispatcher, retries)?;
+
+        if !status.success() {
+            return Err(Error::new_context(
+                ErrorType::Other(format!("'{name}' exited with {status}")),
+                format!("Running '{name}' in session"),
+            ));
+        }
+
+        self.statuses.push((name, status));
+        Ok(self)
+    }
+
+    /// Returns the names and exit statuses of the executables that have been run
+    pub fn get_statuses(&self) -> &[(String, ExitStatus)] {
+        &self.statuses
+    }
+}
@@ -23,12 +23,16 @@ pub mod dependency;
 pub mod version;
 
 /// The type of error at hand
+///
+/// New variants may be added, so matching on this enum requires a wildcard arm
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ErrorType {
     Assert(AssertionError),
     IO(std::io::Error),
     ELFParse(elf::ParseError),
     TOML(TOMLError),
+    JSON(serde_json::Error),
     #[cfg(feature = "builder")]
     Builder(BuilderError),
     CURL(CURLError),
@@ -111,6 +115,55 @@ impl Error {
     pub fn oneline(&self) -> String {
         self.error.to_string()
     }
+
+    /// Returns whether this error is transient and the failed operation may
+    /// succeed if retried, refer to [ErrorType::is_transient()]
+    pub fn is_transient(&self) -> bool {
+        self.error.is_transient()
+    }
+}
+
+impl ErrorType {
+    /// Returns whether this error is transient (e.g. network timeouts, HTTP `5xx` responses)
+    /// and the failed operation may succeed if retried. Permanent errors
+    /// (e.g. HTTP `404`, object id mismatches) return `false`
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::IO(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+            ),
+            Self::CURL(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+
+    /// Returns the error wrapped by this variant, `None` for [ErrorType::Other]
+    pub fn inner(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Assert(e) => Some(e),
+            Self::IO(e) => Some(e),
+            Self::ELFParse(e) => Some(e),
+            Self::TOML(e) => Some(e),
+            Self::JSON(e) => Some(e),
+            #[cfg(feature = "builder")]
+            Self::Builder(e) => Some(e),
+            Self::CURL(e) => Some(e),
+            Self::Dependency(e) => Some(e),
+            Self::Architecture(e) => Some(e),
+            Self::FromUTF8(e) => Some(e),
+            Self::XzStream(e) => Some(e),
+            Self::ObjectDB(e) => Some(e),
+            Self::Version(e) => Some(e),
+            Self::Other(_) => None,
+        }
+    }
 }
 
 impl std::fmt::Display for ErrorType {
@@ -120,6 +173,7 @@ impl std::fmt::Display for ErrorType {
             Self::IO(e) => e.fmt(f),
             Self::ELFParse(e) => e.fmt(f),
             Self::TOML(e) => e.fmt(f),
+            Self::JSON(e) => e.fmt(f),
             #[cfg(feature = "builder")]
             Self::Builder(e) => e.fmt(f),
             Self::CURL(e) => e.fmt(f),
@@ -134,7 +188,19 @@ impl std::fmt::Display for ErrorType {
     }
 }
 
-impl std::error::Error for Error {}
+/// The error type is transparent, it displays as the wrapped error and has its source
+impl std::error::Error for ErrorType {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner().and_then(|e| e.source())
+    }
+}
+
+/// The source of an error is the wrapped error, refer to [ErrorType::inner()]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.inner()
+    }
+}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -1,6 +1,6 @@
 //! Architecture errors
 
-use crate::util::architecture::Architecture;
+use crate::util::architecture::{Architecture, ArchitectureInfo};
 
 /// An error when working with dependencies
 #[derive(Debug)]
@@ -13,6 +13,10 @@ pub enum ArchitectureError {
         /// The supported architectures
         supported: Vec<Architecture>,
     },
+    /// The main architecture is not in the architecture database
+    Unknown(String),
+    /// The architecture string is not in the form `<ARCH>[+<SUBARCH>...]`
+    InvalidName(String),
 }
 
 impl std::fmt::Display for ArchitectureError {
@@ -26,6 +30,20 @@ impl std::fmt::Display for ArchitectureError {
                     supported.join(", ")
                 )
             }
+            Self::Unknown(arch) => {
+                let known: Vec<&str> = ArchitectureInfo::all().iter().map(|a| a.name).collect();
+                write!(
+                    f,
+                    "Unknown architecture '{arch}' (known: {})",
+                    known.join(", ")
+                )
+            }
+            Self::InvalidName(name) => write!(
+                f,
+                "Invalid architecture '{name}', expected <ARCH>[+<SUBARCH>...] ([A-Za-z0-9_]+)"
+            ),
         }
     }
 }
+
+impl std::error::Error for ArchitectureError {}
@@ -65,3 +65,5 @@ impl std::fmt::Display for AssertionError {
         write!(f, "!!!!{}!!!!", "!".repeat(len))
     }
 }
+
+impl std::error::Error for AssertionError {}
@@ -30,3 +30,5 @@ impl std::fmt::Display for DependencyError {
         }
     }
 }
+
+impl std::error::Error for DependencyError {}
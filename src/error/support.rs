@@ -74,6 +74,15 @@ impl std::fmt::Display for TOMLError {
     }
 }
 
+impl std::error::Error for TOMLError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Serialize(e) => e.source(),
+            Self::Deserialize(e) => e.source(),
+        }
+    }
+}
+
 impl<T> ErrorExt<T> for Result<T, toml::de::Error> {
     fn e_context<S: ToString, F: Fn() -> S>(self, context: F) -> Result<T, Error> {
         match self {
@@ -110,6 +119,24 @@ impl Throwable for toml::ser::Error {
     }
 }
 
+impl<T> ErrorExt<T> for Result<T, serde_json::Error> {
+    fn e_context<S: ToString, F: Fn() -> S>(self, context: F) -> Result<T, Error> {
+        match self {
+            Ok(v) => Ok(v),
+            Err(e) => Err(Error::new_context(
+                ErrorType::JSON(e),
+                context().to_string(),
+            )),
+        }
+    }
+}
+
+impl Throwable for serde_json::Error {
+    fn throw(self, context: String) -> Error {
+        Error::new_context(ErrorType::JSON(self), context)
+    }
+}
+
 /// A CURL error
 #[derive(Debug)]
 pub enum CURLError {
@@ -131,6 +158,40 @@ impl std::fmt::Display for CURLError {
     }
 }
 
+impl CURLError {
+    /// Returns whether the request may succeed if retried: Timeouts, connection and
+    /// transfer failures as well as `408`, `429` and `5xx` responses
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::CURL(e) => {
+                e.is_operation_timedout()
+                    || e.is_couldnt_resolve_host()
+                    || e.is_couldnt_connect()
+                    || e.is_send_error()
+                    || e.is_recv_error()
+                    || e.is_partial_file()
+                    || e.is_got_nothing()
+                    || e.is_ssl_connect_error()
+            }
+            Self::InvalidStatus(_) => false,
+            Self::ErrorStatus(status) => {
+                status.is_server_error()
+                    || *status == StatusCode::REQUEST_TIMEOUT
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+}
+
+impl std::error::Error for CURLError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CURL(e) => e.source(),
+            Self::InvalidStatus(_) | Self::ErrorStatus(_) => None,
+        }
+    }
+}
+
 impl<T> ErrorExt<T> for Result<T, curl::Error> {
     fn e_context<S: ToString, F: Fn() -> S>(self, context: F) -> Result<T, Error> {
         match self {
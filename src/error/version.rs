@@ -21,3 +21,5 @@ impl std::fmt::Display for VersionError {
         }
     }
 }
+
+impl std::error::Error for VersionError {}
@@ -1,3 +1,7 @@
 //! Parsing structures for the possible file formats
 
 pub mod formulafile;
+pub mod formulaset;
+pub mod formulavars;
+pub mod homeconfig;
+pub mod validationpolicy;
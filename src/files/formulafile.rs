@@ -1,22 +1,49 @@
 //! The data structures to parse from the formula file, refer to <https://acacialinux.github.io/concept/formula> for more information
 
+use std::{
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml::Table;
 
 use crate::{
-    package::{CorePackage, NameVersionPackage, NamedPackage, VersionedPackage},
+    error::{Error, ErrorExt, ErrorType},
+    package::{
+        CorePackage, NameVersionPackage, NamedPackage, PackageLayout, VersionedPackage,
+        LAYOUT_EXECUTABLES, LAYOUT_LIBRARIES,
+    },
     util::{
         architecture::{deserialize_archs, Architecture},
-        parse::versionstring::VersionString,
-        string::replace_package_variables,
+        fs::{self, PathUtil},
+        git::GitReference,
+        hash::hash_file,
+        parse::{spdx::SpdxExpression, versionstring::VersionString},
+        string::glob_match,
     },
 };
 
+use super::{formulavars::FormulaVariables, validationpolicy::ValidationPolicy};
+
 /// The contents of a formula file
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FormulaFile {
     /// The version of the file
     pub version: u32,
+    /// Other formula files to merge into this one, relative to this file.
+    /// Refer to [FormulaFile::parse()] for the precedence rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// User-defined variables, refer to [FormulaVariables]
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub vars: IndexMap<String, String>,
+    /// Environment variables for the build steps (e.g. `CFLAGS`),
+    /// refer to [FormulaFile::get_env()]
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
     /// There can be multiple formulae
     pub package: FormulaPackage,
 }
@@ -28,13 +55,24 @@ pub struct FormulaPackage {
     pub version: String,
     pub description: String,
 
+    /// The license of the package as an SPDX expression (e.g. `MIT OR Apache-2.0`)
+    pub license: Option<SpdxExpression>,
+
     pub host_dependencies: Option<Vec<VersionString>>,
     pub target_dependencies: Option<Vec<VersionString>>,
     pub extra_dependencies: Option<Vec<VersionString>>,
 
+    /// Virtual capabilities this package provides (e.g. `sh`, `libc.so.6`, `cc`)
+    pub provides: Option<Vec<String>>,
+
     #[serde(default = "default_formula_package_strip")]
     pub strip: bool,
 
+    /// Whether the build steps run with a fixed clock and locale
+    /// (`SOURCE_DATE_EPOCH`, `TZ=UTC`, `LC_ALL=C`)
+    #[serde(default = "default_formula_package_hermetic")]
+    pub hermetic: bool,
+
     #[serde(default, deserialize_with = "deserialize_archs")]
     pub arch: Option<Vec<Architecture>>,
 
@@ -43,22 +81,114 @@ pub struct FormulaPackage {
     pub check: Option<String>,
     pub package: Option<String>,
 
+    /// A script to run in the target root after the package has been installed
+    pub post_install: Option<String>,
+    /// A script to run in the target root before the package gets removed
+    pub pre_remove: Option<String>,
+
     pub sources: Option<Vec<FormulaPackageSource>>,
 
+    /// Patches to apply between the `prepare` and `build` steps using `patch`,
+    /// relative to the formula file
+    pub patches: Option<Vec<String>>,
+
+    /// The directory to apply the patches in, relative to the formula directory,
+    /// refer to [FormulaFile::get_patch_dir()]
+    pub patch_dir: Option<String>,
+
+    /// The number of leading path components to strip from the file names
+    /// in the patches (`patch -p<N>`)
+    #[serde(default = "default_formula_package_patch_strip")]
+    pub patch_strip: u32,
+
+    /// The purposes and the paths within the package root serving them,
+    /// `executables` and `libraries` list the directories the validators scan
     #[serde(default)]
     pub layout: IndexMap<String, Vec<String>>,
+
+    /// The policies for the build steps, keyed by the step name
+    /// (`prepare`, `build`, `check`, `package`)
+    #[serde(default)]
+    pub policy: IndexMap<String, FormulaStepPolicy>,
+
+    /// How validation findings are treated, overriding the
+    /// `[builder.validation]` section of the home configuration
+    #[serde(default)]
+    pub validation: ValidationPolicy,
+
+    /// Environment variables for the build steps of this package,
+    /// overriding the ones of the formula's `[env]` table
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
 }
 
-/// A source for a package
+/// The execution policy for a build step
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormulaStepPolicy {
+    /// The time in seconds after which the step gets killed
+    pub timeout: Option<u64>,
+    /// How often to retry the step if it fails
+    pub retries: Option<u32>,
+    /// Glob patterns (refer to [glob_match()]) of the files the step produces,
+    /// relative to the formula directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<String>,
+}
+
+/// The names of the build steps that can have a policy
+static STEP_NAMES: &[&str] = &["prepare", "build", "check", "package"];
+
+/// A problem with a parsed formula found by [FormulaFile::validate()]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FormulaIssue {
+    /// The path to the offending field (e.g. `package.sources[0].dest`)
+    pub field: String,
+    /// What is wrong with the field
+    pub message: String,
+}
+
+/// The artifacts of a build step found after running it, refer to [FormulaStepPolicy::find_artifacts()]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StepArtifacts {
+    /// The files matching the artifact patterns, relative to the searched directory
+    pub found: Vec<PathBuf>,
+    /// The artifact patterns that matched no file
+    pub missing: Vec<String>,
+}
+
+/// A source for a package, either downloaded from `url` or cloned from a `git` repository
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FormulaPackageSource {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub url: String,
     pub dest: Option<String>,
 
+    /// The git repository to clone instead of downloading `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<FormulaGitSource>,
+
+    /// Additional URLs providing the same file, tried in order if `url` fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+
     #[serde(default = "default_formula_package_source_extract")]
     pub extract: bool,
 }
 
+/// A git repository to fetch a source from, at most one of `rev`, `tag` and `branch`
+/// may be set. Without any of them, the default branch is used
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormulaGitSource {
+    /// The URL of the repository
+    pub url: String,
+    /// The commit hash to check out
+    pub rev: Option<String>,
+    /// The tag to check out
+    pub tag: Option<String>,
+    /// The branch to check out the tip of
+    pub branch: Option<String>,
+}
+
 impl NamedPackage for FormulaPackage {
     fn get_name(&self) -> &str {
         &self.name
@@ -81,6 +211,14 @@ impl VersionedPackage for FormulaPackage {
 
 impl NameVersionPackage for FormulaPackage {}
 
+impl FormulaPackage {
+    /// Returns the executable and library directories of this package,
+    /// refer to [PackageLayout::from_layout()]
+    pub fn get_layout(&self) -> PackageLayout {
+        PackageLayout::from_layout(&self.layout)
+    }
+}
+
 impl CorePackage for FormulaPackage {}
 
 /// Provides the default value for the `strip` field: `true`
@@ -88,11 +226,319 @@ fn default_formula_package_strip() -> bool {
     true
 }
 
+/// Provides the default value for the `hermetic` field: `true`
+fn default_formula_package_hermetic() -> bool {
+    true
+}
+
+/// Provides the default value for the `patch_strip` field: `1`
+fn default_formula_package_patch_strip() -> u32 {
+    1
+}
+
 /// Provides the default value for the `extract` field: `false`
 fn default_formula_package_source_extract() -> bool {
     false
 }
 
+impl FormulaFile {
+    /// Parses a formula file, resolving all of its includes.
+    ///
+    /// Includes are merged in the following order, later ones taking precedence:
+    /// 1. All files in `include`, in the order they are listed
+    /// 2. The including file itself
+    ///
+    /// Tables get merged recursively, all other values (including arrays)
+    /// replace the value of the file with lower precedence
+    /// # Arguments
+    /// * `path` - The path to the formula file to parse
+    pub fn parse(path: &Path) -> Result<Self, Error> {
+        let context = || format!("Parsing formula {}", path.str_lossy());
+
+        let table = Self::parse_table(path, &mut Vec::new())?;
+        let file: Self = table.try_into().ctx(context)?;
+
+        let issues = file.validate();
+        if !issues.is_empty() {
+            let mut message = format!("Formula has {} problem(s):", issues.len());
+            for issue in issues {
+                message.push_str(&format!("\n  {issue}"));
+            }
+
+            return Err(Error::new(ErrorType::Other(message))).ctx(context);
+        }
+
+        Ok(file)
+    }
+
+    /// Checks the semantic constraints the formula has to fulfill beyond its structure
+    /// # Returns
+    /// All problems found, empty if the formula is valid
+    pub fn validate(&self) -> Vec<FormulaIssue> {
+        let mut issues = Vec::new();
+        let mut issue = |field: String, message: &str| {
+            issues.push(FormulaIssue {
+                field,
+                message: message.to_owned(),
+            })
+        };
+
+        for name in self.vars.keys() {
+            if let Err(e) = FormulaVariables::validate_name(name) {
+                issue(format!("vars.{name}"), &e.oneline());
+            }
+        }
+        for (table, env) in [("env", &self.env), ("package.env", &self.package.env)] {
+            for name in env.keys() {
+                if let Err(e) = FormulaVariables::validate_name(name) {
+                    issue(format!("{table}.{name}"), &e.oneline());
+                }
+            }
+        }
+
+        let package = &self.package;
+        for (field, value) in [("name", &package.name), ("version", &package.version)] {
+            if value.trim().is_empty() {
+                issue(format!("package.{field}"), "must not be empty");
+            } else if value.contains(|c: char| c.is_whitespace() || c == '/') {
+                issue(
+                    format!("package.{field}"),
+                    "must not contain whitespace or '/'",
+                );
+            }
+        }
+
+        for (i, arch) in package.arch.iter().flatten().enumerate() {
+            if let Err(e) = Architecture::parse(&arch.arch) {
+                issue(format!("package.arch[{i}]"), &e.oneline());
+            }
+        }
+
+        let steps = [
+            ("prepare", &package.prepare),
+            ("build", &package.build),
+            ("check", &package.check),
+            ("package", &package.package),
+            ("post_install", &package.post_install),
+            ("pre_remove", &package.pre_remove),
+        ];
+        for (step, script) in steps {
+            if script.as_ref().is_some_and(|s| s.trim().is_empty()) {
+                issue(
+                    format!("package.{step}"),
+                    "must not be empty, omit it instead",
+                );
+            }
+        }
+
+        for (i, source) in package.sources.iter().flatten().enumerate() {
+            match &source.git {
+                None if source.url.trim().is_empty() => {
+                    issue(format!("package.sources[{i}].url"), "must not be empty");
+                }
+                None => {}
+                Some(_) if !source.url.is_empty() => {
+                    issue(
+                        format!("package.sources[{i}]"),
+                        "must have either 'url' or 'git', not both",
+                    );
+                }
+                Some(git) => {
+                    if git.url.trim().is_empty() {
+                        issue(format!("package.sources[{i}].git.url"), "must not be empty");
+                    } else if let Some(message) = check_git_url(&git.url) {
+                        issue(format!("package.sources[{i}].git.url"), message);
+                    }
+                    if [&git.rev, &git.tag, &git.branch]
+                        .iter()
+                        .filter(|r| r.is_some())
+                        .count()
+                        > 1
+                    {
+                        issue(
+                            format!("package.sources[{i}].git"),
+                            "must have at most one of 'rev', 'tag' and 'branch'",
+                        );
+                    }
+                    if !source.mirrors.is_empty() || source.extract {
+                        issue(
+                            format!("package.sources[{i}]"),
+                            "'mirrors' and 'extract' are not supported for git sources",
+                        );
+                    }
+                }
+            }
+            for (j, mirror) in source.mirrors.iter().enumerate() {
+                if mirror.trim().is_empty() {
+                    issue(
+                        format!("package.sources[{i}].mirrors[{j}]"),
+                        "must not be empty",
+                    );
+                }
+            }
+            if source
+                .dest
+                .as_ref()
+                .is_some_and(|d| Path::new(d).is_absolute())
+            {
+                issue(format!("package.sources[{i}].dest"), "has to be relative");
+            }
+        }
+
+        for (i, patch) in package.patches.iter().flatten().enumerate() {
+            if patch.trim().is_empty() {
+                issue(format!("package.patches[{i}]"), "must not be empty");
+            } else if !is_within_formula_dir(patch) {
+                issue(
+                    format!("package.patches[{i}]"),
+                    "has to be relative to the formula file without '..'",
+                );
+            }
+        }
+
+        if let Some(dir) = &package.patch_dir {
+            if !is_within_formula_dir(dir) {
+                issue(
+                    "package.patch_dir".to_owned(),
+                    "has to be relative to the formula file without '..'",
+                );
+            }
+        }
+
+        for (purpose, patterns) in &package.layout {
+            for (i, pattern) in patterns.iter().enumerate() {
+                if let Some(message) = check_glob(pattern) {
+                    issue(format!("package.layout.{purpose}[{i}]"), message);
+                } else if (purpose == LAYOUT_EXECUTABLES || purpose == LAYOUT_LIBRARIES)
+                    && pattern.contains(['*', '?'])
+                {
+                    issue(
+                        format!("package.layout.{purpose}[{i}]"),
+                        "has to be a directory, not a glob pattern",
+                    );
+                }
+            }
+        }
+
+        for (step, policy) in &package.policy {
+            if !STEP_NAMES.contains(&step.as_str()) {
+                issue(
+                    format!("package.policy.{step}"),
+                    &format!(
+                        "is not a build step, expected one of {}",
+                        STEP_NAMES.join(", ")
+                    ),
+                );
+            }
+            for (i, pattern) in policy.artifacts.iter().enumerate() {
+                if let Some(message) = check_glob(pattern) {
+                    issue(format!("package.policy.{step}.artifacts[{i}]"), message);
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// Returns the environment variables for the build steps: The `[env]` table
+    /// merged with the `[package.env]` table, which takes precedence.
+    ///
+    /// The values are not substituted yet, refer to [FormulaVariables::replace()]
+    pub fn get_env(&self) -> IndexMap<String, String> {
+        let mut env = self.env.clone();
+        env.extend(self.package.env.clone());
+        env
+    }
+
+    /// Returns the variables of this formula for building it for `arch`,
+    /// refer to [FormulaVariables]
+    /// # Arguments
+    /// * `arch` - The build architecture
+    pub fn get_variables(&self, arch: &Architecture) -> Result<FormulaVariables, Error> {
+        FormulaVariables::new(&self.package.name, &self.package.version, arch)
+            .with_user_variables(&self.vars)
+    }
+
+    /// Returns the directory to apply the patches in, relative to the formula directory:
+    /// `patch_dir` if set, otherwise the directory of the first source
+    /// (refer to [FormulaPackageSource::get_dir()]), `None` if there is none of both
+    /// # Arguments
+    /// * `variables` - The variables to substitute
+    pub fn get_patch_dir(&self, variables: &FormulaVariables) -> Option<String> {
+        match &self.package.patch_dir {
+            Some(dir) => Some(variables.replace(dir)),
+            None => self
+                .package
+                .sources
+                .iter()
+                .flatten()
+                .next()
+                .map(|src| src.get_dir(variables)),
+        }
+    }
+
+    /// Parses `path` into a TOML table with all of its includes merged in
+    /// # Arguments
+    /// * `path` - The path to the file to parse
+    /// * `stack` - The files currently being included to detect include loops
+    fn parse_table(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Table, Error> {
+        let context = || format!("Parsing formula {}", path.str_lossy());
+
+        let canonical = path.canonicalize().ctx(context)?;
+        if stack.contains(&canonical) {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Include loop detected at {}",
+                path.str_lossy()
+            ))))
+            .ctx(context);
+        }
+
+        let mut table: Table = toml::from_str(&fs::file_read_to_string(path)?).ctx(context)?;
+
+        let includes: Vec<String> = match table.remove("include") {
+            Some(includes) => includes
+                .try_into()
+                .ctx(|| "Parsing 'include'")
+                .ctx(context)?,
+            None => Vec::new(),
+        };
+
+        let parent = path.parent().unwrap_or(Path::new("."));
+        let mut merged = Table::new();
+
+        stack.push(canonical);
+        for include in includes {
+            let include = parent.join(include);
+            let included = Self::parse_table(&include, stack)
+                .ctx(|| format!("Including {}", include.str_lossy()))
+                .ctx(context)?;
+            merge_tables(&mut merged, included);
+        }
+        stack.pop();
+
+        merge_tables(&mut merged, table);
+
+        Ok(merged)
+    }
+}
+
+/// Recursively merges `other` into `table`, values in `other` take precedence
+/// # Arguments
+/// * `table` - The table to merge into
+/// * `other` - The table to merge
+fn merge_tables(table: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(value)) => {
+                merge_tables(existing, value)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
 impl FormulaPackage {
     /// Returns the full name of the package, using the supplied architecture
     pub fn get_full_name(&self, arch: &str) -> String {
@@ -103,30 +549,213 @@ impl FormulaPackage {
     pub fn get_architectures(&self) -> Option<Vec<Architecture>> {
         self.arch.as_ref().cloned()
     }
+
+    /// Returns the policy for the build step `step`, using the default
+    /// policy (no timeout and no retries) if the formula doesn't specify one
+    /// # Arguments
+    /// * `step` - The name of the step (`prepare`, `build`, `check`, `package`)
+    pub fn get_step_policy(&self, step: &str) -> FormulaStepPolicy {
+        self.policy.get(step).cloned().unwrap_or_default()
+    }
+}
+
+impl FormulaStepPolicy {
+    /// Returns the timeout as a duration, if there is one
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+
+    /// Searches `dir` for the files matching the artifact patterns
+    /// # Arguments
+    /// * `dir` - The directory the step ran in
+    pub fn find_artifacts(&self, dir: &Path) -> Result<StepArtifacts, Error> {
+        let mut files = Vec::new();
+        fs::walk_dir(dir, true, &mut |entry| {
+            let path = entry.path();
+            if !path.is_symlink() && path.is_file() {
+                files.push(path);
+            }
+            true
+        })
+        .e_context(|| format!("Searching artifacts in {}", dir.str_lossy()))?;
+
+        let files: Vec<PathBuf> = files
+            .into_iter()
+            .map(|f| {
+                f.strip_prefix(dir)
+                    .expect("Walked files are in dir")
+                    .to_owned()
+            })
+            .collect();
+
+        let mut artifacts = StepArtifacts::default();
+        for pattern in &self.artifacts {
+            let matching: Vec<&PathBuf> = files
+                .iter()
+                .filter(|f| glob_match(pattern, &f.str_lossy()))
+                .collect();
+
+            if matching.is_empty() {
+                artifacts.missing.push(pattern.clone());
+            }
+            artifacts.found.extend(matching.into_iter().cloned());
+        }
+
+        artifacts.found.sort();
+        artifacts.found.dedup();
+
+        Ok(artifacts)
+    }
+
+    /// Creates a fingerprint (SHA256 as hex) of the contents of the artifacts in `dir`
+    /// and `inputs`. If the fingerprint did not change, the outputs derived from the
+    /// artifacts (e.g. by the `package` step) are up to date
+    /// # Arguments
+    /// * `dir` - The directory the step ran in
+    /// * `inputs` - The inputs to include in the fingerprint (e.g. the command of the following step)
+    pub fn fingerprint_artifacts(&self, dir: &Path, inputs: &str) -> Result<String, Error> {
+        let mut hasher = Sha256::new();
+        hasher.update(inputs);
+
+        for artifact in self.find_artifacts(dir)?.found {
+            hasher.update([0]);
+            hasher.update(artifact.str_lossy());
+            hasher.update([0]);
+            hasher.update(hash_file(&dir.join(&artifact))?);
+        }
+
+        Ok(hex::encode(hasher.finalize()))
+    }
+}
+
+impl std::fmt::Display for FormulaIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Returns whether `path` is relative and stays within the directory
+/// it is relative to, i.e. contains no `..` components
+/// # Arguments
+/// * `path` - The path to check
+pub fn is_within_formula_dir(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// Checks the URL of a git source, rejecting URLs `git` would take as an option
+/// and the `ext::` and `file` transports, which run commands or read the host
+/// # Arguments
+/// * `url` - The URL to check
+/// # Returns
+/// What is wrong with the URL, `None` if it is allowed
+fn check_git_url(url: &str) -> Option<&'static str> {
+    let url = url.trim().to_lowercase();
+
+    if url.starts_with('-') {
+        Some("must not start with '-'")
+    } else if url.starts_with("ext::") {
+        Some("must not use the 'ext' transport")
+    } else if url.starts_with("file:") {
+        Some("must not use the 'file' transport")
+    } else {
+        None
+    }
+}
+
+/// Checks a glob pattern (refer to [glob_match()])
+/// # Arguments
+/// * `pattern` - The pattern to check
+/// # Returns
+/// What is wrong with the pattern, `None` if it is valid
+fn check_glob(pattern: &str) -> Option<&'static str> {
+    if pattern.is_empty() {
+        Some("must not be empty")
+    } else if pattern
+        .split('/')
+        .any(|component| component.contains("**") && component != "**")
+    {
+        Some("'**' has to be a whole path component")
+    } else {
+        None
+    }
 }
 
 impl FormulaPackageSource {
-    /// Returns the URL of the source with the variables replaced using [crate::util::string::replace_package_variables()]
+    /// Returns the URL of the source with the variables replaced using [FormulaVariables::replace()]
+    /// # Arguments
+    /// * `variables` - The variables to substitute
+    pub fn get_url(&self, variables: &FormulaVariables) -> String {
+        match &self.git {
+            Some(git) => variables.replace(&git.url),
+            None => variables.replace(&self.url),
+        }
+    }
+
+    /// Returns the URL and the mirrors of the source in the order to try them,
+    /// with the variables replaced using [FormulaVariables::replace()]
     /// # Arguments
-    /// * `package` - The package to pull the variables from
-    pub fn get_url(&self, package: &dyn CorePackage) -> String {
-        replace_package_variables(&self.url, package)
+    /// * `variables` - The variables to substitute
+    pub fn get_urls(&self, variables: &FormulaVariables) -> Vec<String> {
+        std::iter::once(self.get_url(variables))
+            .chain(self.mirrors.iter().map(|url| variables.replace(url)))
+            .collect()
     }
 
-    /// Returns the destination of the source with the variables replaced using [crate::util::string::replace_package_variables()]
+    /// Returns the destination of the source with the variables replaced using [FormulaVariables::replace()]
     /// # Arguments
-    /// * `package` - The package to pull the variables from
-    pub fn get_dest(&self, package: &dyn CorePackage) -> String {
+    /// * `variables` - The variables to substitute
+    pub fn get_dest(&self, variables: &FormulaVariables) -> String {
         let dest = match &self.dest {
             Some(d) => d.to_owned(),
-            None => self
-                .get_url(package)
-                .split('/')
-                .last()
-                .unwrap_or("download")
-                .to_owned(),
+            None => {
+                let url = self.get_url(variables);
+                let name = match &self.git {
+                    Some(_) => url.trim_end_matches('/').trim_end_matches(".git"),
+                    None => &url,
+                };
+
+                name.split('/').next_back().unwrap_or("download").to_owned()
+            }
         };
 
-        replace_package_variables(&dest, package)
+        variables.replace(&dest)
+    }
+
+    /// Returns the directory the source is available in after fetching it, relative to the
+    /// formula directory: The destination with the archive extension (`.tar.gz`, `.tgz`,
+    /// `.tar.xz`, `.txz`) removed for extracted sources, as archives conventionally
+    /// contain a directory of that name, the destination itself otherwise
+    /// # Arguments
+    /// * `variables` - The variables to substitute
+    pub fn get_dir(&self, variables: &FormulaVariables) -> String {
+        let dest = self.get_dest(variables);
+        if !self.extract {
+            return dest;
+        }
+
+        for extension in [".tar.gz", ".tgz", ".tar.xz", ".txz"] {
+            if let Some(dir) = dest.strip_suffix(extension) {
+                return dir.to_owned();
+            }
+        }
+
+        dest
+    }
+
+    /// Returns the revision to check out if this is a git source,
+    /// with the variables replaced using [FormulaVariables::replace()]
+    /// # Arguments
+    /// * `variables` - The variables to substitute
+    pub fn get_git_reference(&self, variables: &FormulaVariables) -> Option<GitReference> {
+        let git = self.git.as_ref()?;
+
+        Some(match (&git.rev, &git.tag, &git.branch) {
+            (Some(rev), _, _) => GitReference::Rev(variables.replace(rev)),
+            (None, Some(tag), _) => GitReference::Tag(variables.replace(tag)),
+            (None, None, Some(branch)) => GitReference::Branch(variables.replace(branch)),
+            (None, None, None) => GitReference::Head,
+        })
     }
 }
@@ -0,0 +1,292 @@
+//! A directory of formula files that get built together (e.g. to bootstrap a system)
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    util::{fs::PathUtil, parse::versionstring::VersionString},
+};
+
+use super::formulafile::FormulaFile;
+
+/// The name of the formula file in every formula directory of a formula set
+pub static FORMULA_SET_FILE_NAME: &str = "formula.toml";
+
+/// A set of formulae, one per subdirectory (`<dir>/<name>/formula.toml`)
+#[derive(Debug, Clone)]
+pub struct FormulaSet {
+    /// The formulae of the set, ordered by the name of their directory
+    pub formulae: Vec<FormulaSetEntry>,
+}
+
+/// A formula of a [FormulaSet]
+#[derive(Debug, Clone)]
+pub struct FormulaSetEntry {
+    /// The path to the formula file
+    pub path: PathBuf,
+    /// The parsed formula
+    pub formula: FormulaFile,
+}
+
+/// The order to build the formulae of a [FormulaSet] in to bootstrap a system from it
+#[derive(Debug)]
+pub struct BootstrapPlan<'a> {
+    /// Pass 1: The cross tools, all formulae that are (transitively) host dependencies
+    /// of other formulae of the set, built using the host's tools
+    pub cross: Vec<&'a FormulaSetEntry>,
+    /// Pass 2: All formulae of the set, built using the cross tools of pass 1
+    pub system: Vec<&'a FormulaSetEntry>,
+}
+
+impl FormulaSet {
+    /// Loads all formulae in the subdirectories of `dir`, skipping
+    /// subdirectories without a [FORMULA_SET_FILE_NAME] file
+    /// # Arguments
+    /// * `dir` - The directory to load the formulae from
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let context = || format!("Loading formula set {}", dir.str_lossy());
+
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).e_context(context)? {
+            let path = entry.e_context(context)?.path().join(FORMULA_SET_FILE_NAME);
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut formulae = Vec::new();
+        for path in paths {
+            let formula = FormulaFile::parse(&path).ctx(context)?;
+            formulae.push(FormulaSetEntry { path, formula });
+        }
+
+        Ok(Self { formulae })
+    }
+
+    /// Loads all formulae in the directory tree below `dir`: Every directory containing a
+    /// [FORMULA_SET_FILE_NAME] file is a formula directory, its subdirectories are not searched
+    /// # Arguments
+    /// * `dir` - The directory to search for formulae
+    pub fn load_recursive(dir: &Path) -> Result<Self, Error> {
+        let context = || format!("Loading formula set {}", dir.str_lossy());
+
+        let mut paths = Vec::new();
+        let mut queue = vec![dir.to_owned()];
+        while let Some(dir) = queue.pop() {
+            let path = dir.join(FORMULA_SET_FILE_NAME);
+            if path.is_file() {
+                paths.push(path);
+                continue;
+            }
+
+            for entry in std::fs::read_dir(&dir).e_context(context)? {
+                let entry = entry.e_context(context)?;
+                if entry.file_type().e_context(context)?.is_dir() {
+                    queue.push(entry.path());
+                }
+            }
+        }
+        paths.sort();
+
+        let mut formulae = Vec::new();
+        for path in paths {
+            let formula = FormulaFile::parse(&path).ctx(context)?;
+            formulae.push(FormulaSetEntry { path, formula });
+        }
+
+        Ok(Self { formulae })
+    }
+
+    /// Finds the formula that satisfies `requirement`, preferring
+    /// a formula with that name over one providing it as a capability
+    /// # Arguments
+    /// * `requirement` - The package name or capability to search for
+    pub fn find(&self, requirement: &str) -> Option<&FormulaSetEntry> {
+        self.find_index(requirement).map(|i| &self.formulae[i])
+    }
+
+    /// Returns the dependencies of the formulae that are not satisfied
+    /// by the set and have to be provided by the host
+    pub fn external_dependencies(&self) -> Vec<String> {
+        let mut external: Vec<String> = self
+            .formulae
+            .iter()
+            .flat_map(|e| e.requirements(true, true))
+            .filter(|r| self.find_index(&r.name).is_none())
+            .map(|r| r.name.clone())
+            .collect();
+
+        external.sort();
+        external.dedup();
+        external
+    }
+
+    /// Plans the passes to bootstrap a system from this set, refer to [BootstrapPlan].
+    ///
+    /// In pass 1, the cross tools only get ordered by their host dependencies,
+    /// their target dependencies are provided by the host.
+    /// In pass 2, the host dependencies are satisfied by pass 1, so only the
+    /// target and extra dependencies determine the order.
+    /// Fails if there is a dependency cycle that can't be resolved that way
+    pub fn bootstrap_plan(&self) -> Result<BootstrapPlan<'_>, Error> {
+        // Every formula that is needed at build time, including the tools to build it
+        let mut cross: Vec<usize> = Vec::new();
+        let mut queue: Vec<usize> = (0..self.formulae.len())
+            .flat_map(|i| self.dependencies(i, true, false))
+            .collect();
+
+        while let Some(i) = queue.pop() {
+            if !cross.contains(&i) {
+                cross.push(i);
+                queue.extend(self.dependencies(i, true, false));
+            }
+        }
+
+        let all: Vec<usize> = (0..self.formulae.len()).collect();
+
+        let cross = self
+            .order(&cross, true, false)
+            .ctx(|| "Ordering pass 1 (cross tools)")?;
+        let system = self
+            .order(&all, false, true)
+            .ctx(|| "Ordering pass 2 (final)")?;
+
+        Ok(BootstrapPlan {
+            cross: cross.into_iter().map(|i| &self.formulae[i]).collect(),
+            system: system.into_iter().map(|i| &self.formulae[i]).collect(),
+        })
+    }
+
+    /// Groups the formulae of this set into waves to build them in:
+    /// The formulae of a wave only depend (host, target and extra dependencies)
+    /// on formulae of earlier waves, so they can be built in parallel.
+    /// Fails if there is a dependency cycle
+    pub fn build_waves(&self) -> Result<Vec<Vec<&FormulaSetEntry>>, Error> {
+        let all: Vec<usize> = (0..self.formulae.len()).collect();
+
+        Ok(self
+            .waves(&all, true, true)?
+            .into_iter()
+            .map(|wave| wave.into_iter().map(|i| &self.formulae[i]).collect())
+            .collect())
+    }
+
+    /// Finds the index of the formula that satisfies `requirement`, refer to [FormulaSet::find()]
+    /// # Arguments
+    /// * `requirement` - The package name or capability to search for
+    fn find_index(&self, requirement: &str) -> Option<usize> {
+        self.formulae
+            .iter()
+            .position(|e| e.formula.package.name == requirement)
+            .or_else(|| {
+                self.formulae.iter().position(|e| {
+                    e.formula
+                        .package
+                        .provides
+                        .as_ref()
+                        .is_some_and(|p| p.iter().any(|p| p == requirement))
+                })
+            })
+    }
+
+    /// Returns the indices of the formulae in this set the formula at `index` depends on
+    /// # Arguments
+    /// * `index` - The index of the formula
+    /// * `host` - Whether to include the host dependencies
+    /// * `target` - Whether to include the target and extra dependencies
+    fn dependencies(&self, index: usize, host: bool, target: bool) -> Vec<usize> {
+        self.formulae[index]
+            .requirements(host, target)
+            .into_iter()
+            .filter_map(|r| self.find_index(&r.name))
+            .filter(|i| *i != index)
+            .collect()
+    }
+
+    /// Orders `members` so that dependencies come before the formulae depending on them
+    /// # Arguments
+    /// * `members` - The indices of the formulae to order
+    /// * `host` - Whether to respect the host dependencies
+    /// * `target` - Whether to respect the target and extra dependencies
+    fn order(&self, members: &[usize], host: bool, target: bool) -> Result<Vec<usize>, Error> {
+        Ok(self
+            .waves(members, host, target)?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    /// Groups `members` into waves that only depend on the members of earlier waves
+    /// # Arguments
+    /// * `members` - The indices of the formulae to group
+    /// * `host` - Whether to respect the host dependencies
+    /// * `target` - Whether to respect the target and extra dependencies
+    fn waves(&self, members: &[usize], host: bool, target: bool) -> Result<Vec<Vec<usize>>, Error> {
+        let mut waves: Vec<Vec<usize>> = Vec::new();
+        let mut remaining: Vec<usize> = members.to_vec();
+        remaining.sort();
+
+        while !remaining.is_empty() {
+            let ready: Vec<usize> = remaining
+                .iter()
+                .copied()
+                .filter(|i| {
+                    self.dependencies(*i, host, target)
+                        .iter()
+                        .all(|d| !remaining.contains(d))
+                })
+                .collect();
+
+            if ready.is_empty() {
+                let names: Vec<&str> = remaining
+                    .iter()
+                    .map(|i| self.formulae[*i].formula.package.name.as_str())
+                    .collect();
+
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Dependency cycle between {}",
+                    names.join(", ")
+                ))));
+            }
+
+            remaining.retain(|i| !ready.contains(i));
+            waves.push(ready);
+        }
+
+        Ok(waves)
+    }
+}
+
+impl FormulaSetEntry {
+    /// Returns the dependencies of the formula
+    /// # Arguments
+    /// * `host` - Whether to include the host dependencies
+    /// * `target` - Whether to include the target and extra dependencies
+    fn requirements(&self, host: bool, target: bool) -> Vec<&VersionString> {
+        let package = &self.formula.package;
+        let mut requirements = Vec::new();
+
+        if host {
+            requirements.extend(package.host_dependencies.iter().flatten());
+        }
+        if target {
+            requirements.extend(package.target_dependencies.iter().flatten());
+            requirements.extend(package.extra_dependencies.iter().flatten());
+        }
+
+        requirements
+    }
+}
+
+impl std::fmt::Display for FormulaSetEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}@{} ({})",
+            self.formula.package.name,
+            self.formula.package.version,
+            self.path.str_lossy()
+        )
+    }
+}
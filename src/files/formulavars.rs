@@ -0,0 +1,190 @@
+//! The variables available to formulae for substitution and in build steps
+
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+
+use crate::{
+    error::{Error, ErrorType},
+    util::{architecture::Architecture, fs::PathUtil},
+};
+
+/// The names of the built-in variables, these can't be redefined in `[vars]`
+pub static BUILTIN_VARIABLES: &[&str] = &["ARCH", "PKGNAME", "PKGVER", "SOURCE_DIR", "JOBS"];
+
+/// The variables of a formula, substituted in sources (`${NAME}`)
+/// and exported as environment variables in build steps:
+/// - `ARCH`: The build architecture
+/// - `PKGNAME`: The package name
+/// - `PKGVER`: The package version
+/// - `SOURCE_DIR`: The directory containing the formula and its sources in the build root
+/// - `JOBS`: The number of parallel jobs to use for building
+///
+/// and the user-defined variables of the `[vars]` table, which
+/// can reference the built-in and previously defined variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaVariables {
+    /// The name of the package
+    name: String,
+    /// The version of the package
+    version: String,
+    /// The build architecture
+    arch: Architecture,
+    /// The directory containing the formula and its sources in the build root
+    source_dir: PathBuf,
+    /// The number of parallel jobs
+    jobs: usize,
+    /// The user-defined variables, unexpanded
+    user: IndexMap<String, String>,
+}
+
+impl FormulaVariables {
+    /// Creates the built-in variables with `SOURCE_DIR` set to `/` (the working
+    /// directory of the build steps) and `JOBS` set to the available parallelism
+    /// # Arguments
+    /// * `name` - The name of the package
+    /// * `version` - The version of the package
+    /// * `arch` - The build architecture
+    pub fn new(name: &str, version: &str, arch: &Architecture) -> Self {
+        Self {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            arch: arch.clone(),
+            source_dir: PathBuf::from("/"),
+            jobs: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            user: IndexMap::new(),
+        }
+    }
+
+    /// Sets the `SOURCE_DIR` variable
+    /// # Arguments
+    /// * `source_dir` - The directory containing the formula and its sources in the build root
+    pub fn with_source_dir(mut self, source_dir: &Path) -> Self {
+        self.source_dir = source_dir.to_owned();
+        self
+    }
+
+    /// Sets the `JOBS` variable
+    /// # Arguments
+    /// * `jobs` - The number of parallel jobs
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Adds the user-defined variables of a `[vars]` table.
+    ///
+    /// Fails if a name is not a valid environment variable name
+    /// (`[A-Za-z_][A-Za-z0-9_]*`), is built-in or starts with `PKG_`,
+    /// which is reserved for the build steps
+    /// # Arguments
+    /// * `vars` - The variables to add, in the order they get expanded
+    pub fn with_user_variables(mut self, vars: &IndexMap<String, String>) -> Result<Self, Error> {
+        for (name, value) in vars {
+            Self::validate_name(name)?;
+            self.user.insert(name.clone(), value.clone());
+        }
+
+        Ok(self)
+    }
+
+    /// Ensures `name` can be used for a user-defined variable, refer to [FormulaVariables::with_user_variables()]
+    /// # Arguments
+    /// * `name` - The name of the variable
+    pub fn validate_name(name: &str) -> Result<(), Error> {
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        let reason = if !valid {
+            "is not a valid variable name"
+        } else if BUILTIN_VARIABLES.contains(&name) {
+            "is a built-in variable"
+        } else if name.starts_with("PKG_") {
+            "uses the reserved prefix 'PKG_'"
+        } else {
+            return Ok(());
+        };
+
+        Err(Error::new(ErrorType::Other(format!(
+            "Formula variable '{name}' {reason}"
+        ))))
+    }
+
+    /// Returns all variables with their values expanded, built-in variables first
+    pub fn get_all(&self) -> IndexMap<String, String> {
+        let mut variables = IndexMap::new();
+
+        variables.insert("ARCH".to_owned(), self.arch.arch.clone());
+        variables.insert("PKGNAME".to_owned(), self.name.clone());
+        variables.insert("PKGVER".to_owned(), self.version.clone());
+        variables.insert("SOURCE_DIR".to_owned(), self.source_dir.str_lossy());
+        variables.insert("JOBS".to_owned(), self.jobs.to_string());
+
+        for (name, value) in &self.user {
+            let value = replace_variables(value, &variables);
+            variables.insert(name.clone(), value);
+        }
+
+        variables
+    }
+
+    /// Returns the value of the variable `name`, if it exists
+    /// # Arguments
+    /// * `name` - The name of the variable
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.get_all().swap_remove(name)
+    }
+
+    /// Substitutes all known variables (`${NAME}`) in `string`,
+    /// unknown ones are left untouched.
+    ///
+    /// For compatibility, `$PKG_NAME`, `$PKG_VERSION` and `$PKG_ARCH` get substituted, too
+    /// # Arguments
+    /// * `string` - The string to substitute in
+    pub fn replace(&self, string: &str) -> String {
+        let string = string
+            .replace("$PKG_NAME", &self.name)
+            .replace("$PKG_VERSION", &self.version)
+            .replace("$PKG_ARCH", &self.arch.arch);
+
+        replace_variables(&string, &self.get_all())
+    }
+}
+
+/// Substitutes every `${NAME}` in `string` with the value of `NAME`
+/// in `variables`, leaving unknown variables untouched
+/// # Arguments
+/// * `string` - The string to substitute in
+/// * `variables` - The variables to substitute
+fn replace_variables(string: &str, variables: &IndexMap<String, String>) -> String {
+    let mut result = String::with_capacity(string.len());
+    let mut rest = string;
+
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest
+            .find('}')
+            .and_then(|end| Some((end, variables.get(&rest[2..end])?)));
+
+        match value {
+            Some((end, value)) => {
+                result.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push_str("${");
+                rest = &rest[2..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,240 @@
+//! The data structures to parse from the configuration file of a home (`<home>/config.toml`)
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, ErrorExt},
+    files::validationpolicy::ValidationPolicy,
+    model::DEFAULT_KEPT_GENERATIONS,
+    util::{
+        download::{DownloadManager, DEFAULT_PARALLEL_DOWNLOADS},
+        fs::{self, PathUtil},
+        sched::CpuList,
+    },
+};
+
+/// The contents of the configuration file of a home,
+/// every section is optional and falls back to its defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HomeConfig {
+    /// The configuration of the builder
+    #[serde(default)]
+    pub builder: BuilderConfig,
+    /// The configuration of downloads
+    #[serde(default)]
+    pub download: DownloadConfig,
+    /// The configuration of the generations of installed root directories
+    #[serde(default)]
+    pub generations: GenerationsConfig,
+}
+
+/// The configuration of downloads, used for fetching the
+/// sources of formulae and objects from remote object databases
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadConfig {
+    /// The maximum number of concurrent downloads
+    #[serde(default = "default_download_parallel")]
+    pub parallel: usize,
+    /// The maximum bandwidth of all concurrent downloads together in KiB per second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_limit: Option<u64>,
+}
+
+/// The configuration of the generations recorded for root directories
+/// packages get installed to, refer to [crate::model::FileIndex::record_generation()]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationsConfig {
+    /// The number of generations to keep, `0` to keep all
+    #[serde(default = "default_generations_keep")]
+    pub keep: usize,
+}
+
+/// The configuration of the builder
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuilderConfig {
+    /// Distribute the compilation of the build steps to a compile farm
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_compile: Option<RemoteCompileConfig>,
+    /// Confine the build processes to these CPUs (`0-3,8`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpuset: Option<CpuList>,
+    /// The nice level to run the build processes at (`-20` to `19`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+    /// The host environment variables to pass into the build environments,
+    /// refer to [crate::env::collect_passthrough_env()]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub passthrough_env: Vec<String>,
+    /// How validation findings are treated, refer to [ValidationPolicy]
+    #[serde(default)]
+    pub validation: ValidationPolicy,
+    /// The implementation of the overlay filesystem the build roots get mounted with
+    #[serde(default)]
+    pub overlay: OverlayBackend,
+}
+
+/// The implementations of the overlay filesystem
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlayBackend {
+    /// The kernel's `overlayfs`, falling back to `fuse-overlayfs` if mounting it fails
+    #[default]
+    Auto,
+    /// The kernel's `overlayfs` only
+    Kernel,
+    /// `fuse-overlayfs` only, for hosts that can't mount the kernel's `overlayfs`
+    Fuse,
+}
+
+/// The configuration for distributing compilation using `distcc` or `icecc`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteCompileConfig {
+    /// Whether to use remote compilation, allows keeping the section around while disabled
+    #[serde(default = "default_remote_compile_enabled")]
+    pub enabled: bool,
+    /// The client to wrap the compilers with
+    pub client: RemoteCompileClient,
+    /// The path to the client binary on the host, `/usr/bin/<client>` if omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// The hosts to distribute to (`DISTCC_HOSTS`), `icecc` asks its local daemon instead
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// The C compiler to wrap (`CC`)
+    #[serde(default = "default_remote_compile_cc")]
+    pub cc: String,
+    /// The C++ compiler to wrap (`CXX`)
+    #[serde(default = "default_remote_compile_cxx")]
+    pub cxx: String,
+}
+
+/// The clients available for remote compilation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteCompileClient {
+    /// `distcc`, distributing to the hosts listed in `DISTCC_HOSTS`
+    Distcc,
+    /// `icecc` (icecream), distributing through the scheduler of the local `iceccd`
+    Icecc,
+}
+
+impl HomeConfig {
+    /// Parses the configuration file at `path`, returning the
+    /// default configuration if there is no such file
+    /// # Arguments
+    /// * `path` - The path to the configuration file
+    pub fn parse(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        toml::from_str(&fs::file_read_to_string(path)?)
+            .ctx(|| format!("Parsing home configuration {}", path.str_lossy()))
+    }
+
+    /// Returns the download manager to use for concurrent downloads
+    pub fn get_download_manager(&self) -> DownloadManager {
+        DownloadManager::new(
+            self.download.parallel,
+            self.download.bandwidth_limit.map(|l| l * 1024),
+        )
+    }
+
+    /// Returns the remote compilation configuration if it is enabled
+    pub fn get_remote_compile(&self) -> Option<&RemoteCompileConfig> {
+        self.builder.remote_compile.as_ref().filter(|c| c.enabled)
+    }
+}
+
+impl RemoteCompileConfig {
+    /// Returns the path to the client binary on the host
+    pub fn get_host_path(&self) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| Path::new("/usr/bin").join(self.client.get_binary_name()))
+    }
+
+    /// Returns the environment variables that wrap the compilers
+    /// using the client (e.g. `CC="distcc gcc"`)
+    pub fn get_env(&self) -> Vec<(String, String)> {
+        let client = self.client.get_binary_name();
+        let mut env = vec![
+            ("CC".to_owned(), format!("{client} {}", self.cc)),
+            ("CXX".to_owned(), format!("{client} {}", self.cxx)),
+        ];
+
+        if self.client == RemoteCompileClient::Distcc && !self.hosts.is_empty() {
+            env.push(("DISTCC_HOSTS".to_owned(), self.hosts.join(" ")));
+        }
+
+        env
+    }
+}
+
+impl RemoteCompileClient {
+    /// Returns the name of the client binary
+    pub fn get_binary_name(&self) -> &'static str {
+        match self {
+            Self::Distcc => "distcc",
+            Self::Icecc => "icecc",
+        }
+    }
+}
+
+impl Default for GenerationsConfig {
+    fn default() -> Self {
+        Self {
+            keep: DEFAULT_KEPT_GENERATIONS,
+        }
+    }
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            parallel: DEFAULT_PARALLEL_DOWNLOADS,
+            bandwidth_limit: None,
+        }
+    }
+}
+
+impl Display for OverlayBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Kernel => write!(f, "kernel"),
+            Self::Fuse => write!(f, "fuse"),
+        }
+    }
+}
+
+impl Display for RemoteCompileClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get_binary_name())
+    }
+}
+
+fn default_remote_compile_enabled() -> bool {
+    true
+}
+
+fn default_remote_compile_cc() -> String {
+    "gcc".to_owned()
+}
+
+fn default_remote_compile_cxx() -> String {
+    "g++".to_owned()
+}
+
+fn default_download_parallel() -> usize {
+    DEFAULT_PARALLEL_DOWNLOADS
+}
+
+fn default_generations_keep() -> usize {
+    DEFAULT_KEPT_GENERATIONS
+}
@@ -0,0 +1,130 @@
+//! The policy deciding which validation findings fail a build,
+//! configured in the home configuration (`[builder.validation]`)
+//! and the formula (`[package.validation]`)
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// How a finding of a validation category is treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationSeverity {
+    /// Fail the build before the package gets inserted
+    Error,
+    /// Record the finding as a warning
+    Warn,
+    /// Drop the finding
+    Ignore,
+}
+
+/// The categories of validation findings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationCategory {
+    /// Files of the package need files no dependency provides
+    UnresolvedDependencies,
+    /// Declared dependencies provide nothing the package needs
+    UnusedDependencies,
+    /// ELF files miss hardening features
+    Hardening,
+    /// Files have been modified after `SOURCE_DATE_EPOCH`
+    Timestamps,
+    /// Python bytecode, `RECORD` or `.pth` files reference paths outside of the package
+    PythonPaths,
+    /// Symlinks are dangling or point to absolute paths outside of the dist directory
+    Symlinks,
+    /// License files contain a license the declared license does not mention
+    Licenses,
+}
+
+/// The severity of each validation category, unset categories
+/// fall back to their default (refer to [ValidationCategory::default_severity()])
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unresolved_dependencies: Option<ValidationSeverity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unused_dependencies: Option<ValidationSeverity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hardening: Option<ValidationSeverity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamps: Option<ValidationSeverity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python_paths: Option<ValidationSeverity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlinks: Option<ValidationSeverity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub licenses: Option<ValidationSeverity>,
+}
+
+impl ValidationPolicy {
+    /// Returns whether no category is set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the severity of `category`
+    /// # Arguments
+    /// * `category` - The category to get the severity of
+    pub fn get(&self, category: ValidationCategory) -> ValidationSeverity {
+        let severity = match category {
+            ValidationCategory::UnresolvedDependencies => self.unresolved_dependencies,
+            ValidationCategory::UnusedDependencies => self.unused_dependencies,
+            ValidationCategory::Hardening => self.hardening,
+            ValidationCategory::Timestamps => self.timestamps,
+            ValidationCategory::PythonPaths => self.python_paths,
+            ValidationCategory::Symlinks => self.symlinks,
+            ValidationCategory::Licenses => self.licenses,
+        };
+
+        severity.unwrap_or(category.default_severity())
+    }
+
+    /// Returns this policy with the categories set in `overrides` replaced,
+    /// used to let the formula override the home configuration
+    /// # Arguments
+    /// * `overrides` - The policy taking precedence
+    pub fn merged(&self, overrides: &ValidationPolicy) -> Self {
+        Self {
+            unresolved_dependencies: overrides
+                .unresolved_dependencies
+                .or(self.unresolved_dependencies),
+            unused_dependencies: overrides.unused_dependencies.or(self.unused_dependencies),
+            hardening: overrides.hardening.or(self.hardening),
+            timestamps: overrides.timestamps.or(self.timestamps),
+            python_paths: overrides.python_paths.or(self.python_paths),
+            symlinks: overrides.symlinks.or(self.symlinks),
+            licenses: overrides.licenses.or(self.licenses),
+        }
+    }
+}
+
+impl ValidationCategory {
+    /// Returns the severity of this category if no policy sets it:
+    /// Unresolved dependencies fail the build, everything else is a warning
+    pub fn default_severity(&self) -> ValidationSeverity {
+        match self {
+            Self::UnresolvedDependencies => ValidationSeverity::Error,
+            Self::UnusedDependencies
+            | Self::Hardening
+            | Self::Timestamps
+            | Self::PythonPaths
+            | Self::Symlinks
+            | Self::Licenses => ValidationSeverity::Warn,
+        }
+    }
+}
+
+impl Display for ValidationCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnresolvedDependencies => write!(f, "unresolved_dependencies"),
+            Self::UnusedDependencies => write!(f, "unused_dependencies"),
+            Self::Hardening => write!(f, "hardening"),
+            Self::Timestamps => write!(f, "timestamps"),
+            Self::PythonPaths => write!(f, "python_paths"),
+            Self::Symlinks => write!(f, "symlinks"),
+            Self::Licenses => write!(f, "licenses"),
+        }
+    }
+}
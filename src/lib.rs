@@ -23,9 +23,15 @@ pub static OBJECT_FILE_EXTENSION: &str = "aobj";
 /// The base64 engine
 pub static BASE64_ENGINE: GeneralPurpose = BASE64_URL_SAFE;
 
-/// The (currently) hardcoded depth for the object database
+/// The default depth for new object databases
 pub static ODB_DEPTH: usize = 5;
 
+/// The maximum depth of object databases
+pub static MAX_ODB_DEPTH: usize = 16;
+
+/// The size from which on object databases store objects chunked, if enabled (1 MiB)
+pub static CHUNKING_THRESHOLD: u64 = 1024 * 1024;
+
 /// Provide a relative `PathBuf` pointing to the `DIST_DIR`
 pub fn dist_dir() -> PathBuf {
     PathBuf::from(DIST_DIR)
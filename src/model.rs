@@ -1,5 +1,14 @@
 //! Data structures the tooling uses for representing data
 
+mod annotation;
+pub use annotation::*;
+
+mod buildreceipt;
+pub use buildreceipt::*;
+
+mod fileindex;
+pub use fileindex::*;
+
 mod formula;
 pub use formula::*;
 
@@ -9,5 +18,14 @@ pub use object::*;
 mod home;
 pub use home::*;
 
+mod keyring;
+pub use keyring::*;
+
+mod repositoryindex;
+pub use repositoryindex::*;
+
+mod signature;
+pub use signature::*;
+
 mod tree;
 pub use tree::*;
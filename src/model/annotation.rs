@@ -0,0 +1,142 @@
+//! Key/value annotations attached to objects
+
+use std::{
+    io::Cursor,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use indexmap::IndexMap;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorExt, ErrorType};
+
+use super::{Object, ObjectCompression, ObjectDB, ObjectDBError, ObjectID, ObjectType};
+
+/// Annotations (e.g. creation time, license, upstream URL) attached to an object.
+///
+/// Annotations are stored as separate objects depending on the annotated object,
+/// so annotating an object does not change its object id. The annotations of an
+/// object are found using the reverse dependency index of the object database.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    /// The object id of the annotated object
+    pub object: ObjectID,
+    /// The annotations as key/value pairs
+    pub values: IndexMap<String, String>,
+    /// When the annotation has been created in nanoseconds since the UNIX epoch,
+    /// deciding which annotation wins, `0` for annotations recorded without one
+    #[serde(default)]
+    pub timestamp: u64,
+}
+
+impl Annotation {
+    /// Creates a new annotation for `object`, timestamped with the current time
+    /// # Arguments
+    /// * `object` - The object id of the object to annotate
+    /// * `values` - The key/value pairs to annotate the object with
+    pub fn new(object: ObjectID, values: IndexMap<String, String>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Self {
+            object,
+            values,
+            timestamp,
+        }
+    }
+
+    /// Reads an annotation from `odb`
+    /// # Arguments
+    /// * `odb` - The object database to read from
+    /// * `oid` - The object id of the annotation object
+    pub fn from_odb(odb: &ObjectDB, oid: &ObjectID) -> Result<Self, Error> {
+        let reader = odb.read(oid)?;
+
+        if reader.object.ty != ObjectType::AcaciaAnnotation {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::UnexpectedObjectType {
+                    oid: oid.clone(),
+                    expected: ObjectType::AcaciaAnnotation,
+                    received: reader.object.ty,
+                },
+            )));
+        }
+
+        serde_json::from_reader(reader).ctx(|| format!("Parsing annotation {oid}"))
+    }
+
+    /// Returns all annotations attached to `oid` ordered by their timestamp.
+    ///
+    /// The reverse dependency index does not keep the order the annotations have been
+    /// inserted in (e.g. after rebuilding it), annotations with the same timestamp
+    /// stay in the order of the index
+    /// # Arguments
+    /// * `odb` - The object database to search in
+    /// * `oid` - The object id of the annotated object
+    /// # Returns
+    /// The object ids of the annotation objects and the annotations
+    pub fn find(odb: &ObjectDB, oid: &ObjectID) -> Result<Vec<(ObjectID, Self)>, Error> {
+        let mut annotations = Vec::new();
+
+        for dependent in odb.reverse_dependencies(oid)? {
+            if odb.get_object(&dependent)?.ty != ObjectType::AcaciaAnnotation {
+                continue;
+            }
+
+            let annotation = Self::from_odb(odb, &dependent)?;
+            if &annotation.object == oid {
+                annotations.push((dependent, annotation));
+            }
+        }
+
+        annotations.sort_by_key(|(_, annotation)| annotation.timestamp);
+
+        Ok(annotations)
+    }
+
+    /// Collects the annotations attached to `oid` into one set of key/value pairs,
+    /// annotations with a later timestamp override the values of earlier ones
+    /// # Arguments
+    /// * `odb` - The object database to search in
+    /// * `oid` - The object id of the annotated object
+    pub fn collect(odb: &ObjectDB, oid: &ObjectID) -> Result<IndexMap<String, String>, Error> {
+        let mut values = IndexMap::new();
+
+        for (_, annotation) in Self::find(odb, oid)? {
+            values.extend(annotation.values);
+        }
+
+        Ok(values)
+    }
+
+    /// Returns the `JSON` string for this annotation
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).expect("Serialize annotation should never fail")
+    }
+
+    /// Inserts this annotation into `odb`, depending on the annotated object
+    /// # Arguments
+    /// * `odb` - The object database to insert into
+    /// * `compression` - The compression to apply for inserting
+    pub fn insert_into_odb(
+        &self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+    ) -> Result<Object, Error> {
+        let mut cursor = Cursor::new(self.json());
+
+        let object = odb.insert_stream(
+            &mut cursor,
+            ObjectType::AcaciaAnnotation,
+            compression,
+            vec![self.object.clone()],
+        )?;
+
+        debug!("Annotated {} with {}", self.object, object.oid);
+
+        Ok(object)
+    }
+}
@@ -0,0 +1,309 @@
+//! Records of the environment packages were built in, to debug irreproducible builds
+
+use std::{fmt::Display, io::Cursor};
+
+use indexmap::IndexMap;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorExt, ErrorType};
+
+use super::{Object, ObjectCompression, ObjectDB, ObjectDBError, ObjectID, ObjectType};
+
+/// The receipt of a build, recording how every build step of a package has been executed.
+///
+/// Like [super::Annotation]s, receipts are stored as separate objects depending on
+/// the package they describe and are found using the reverse dependency index
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BuildReceipt {
+    /// The object id of the built package
+    pub package: ObjectID,
+    /// The unique id of the build
+    pub build_id: String,
+    /// The commit hash of the tooling that executed the build
+    pub tooling_commit: String,
+    /// The executed build steps in the order of their execution
+    pub steps: Vec<StepRecord>,
+    /// Whether the package has been built from a [tainted](super::Formula::tainted) formula
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tainted: bool,
+    /// The audit of the mounts and environment variables of all steps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit: Option<BuildAudit>,
+}
+
+/// The combined environment of all build steps, to review what a build had access to
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BuildAudit {
+    /// Every distinct mount of the build steps in the order of their first use
+    pub mounts: Vec<MountRecord>,
+    /// The final environment variables, later steps overriding the values of earlier ones
+    pub env: IndexMap<String, String>,
+}
+
+/// The exact invocation of a build step in an environment
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StepRecord {
+    /// The name of the build step
+    pub name: String,
+    /// The program and arguments that got executed on the host
+    pub argv: Vec<String>,
+    /// The environment variables the program got executed with
+    pub env: IndexMap<String, String>,
+    /// The working directory within the environment
+    pub workdir: String,
+    /// The mounts making up the environment, the root first
+    pub mounts: Vec<MountRecord>,
+    /// The release of the host kernel (`uname -r`)
+    pub kernel: String,
+    /// The client the compilation has been distributed with (`distcc`, `icecc`), if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_compile: Option<String>,
+    /// The tree of the files the step changed in the build root (the upper
+    /// directory of the overlay after the step), if it has been retained
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<ObjectID>,
+}
+
+/// A mount of an environment a build step got executed in
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MountRecord {
+    /// The type of the mount (`overlayfs`, `vkfs`...)
+    pub fs_type: String,
+    /// The source paths (or strings) of the mount
+    pub sources: Vec<String>,
+    /// The path the mount is mounted at
+    pub target: String,
+    /// The flags the mount has been mounted with (`bind`, `ro`, `nosuid`...)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+}
+
+impl BuildReceipt {
+    /// Creates a new receipt, auditing the mounts and environment variables of `steps`
+    /// # Arguments
+    /// * `package` - The object id of the built package
+    /// * `build_id` - The unique id of the build
+    /// * `tooling_commit` - The commit hash of the tooling that executed the build
+    /// * `steps` - The executed build steps in the order of their execution
+    /// * `tainted` - Whether the package has been built from a tainted formula
+    pub fn new(
+        package: ObjectID,
+        build_id: String,
+        tooling_commit: String,
+        steps: Vec<StepRecord>,
+        tainted: bool,
+    ) -> Self {
+        let audit = BuildAudit::from_steps(&steps);
+
+        Self {
+            package,
+            build_id,
+            tooling_commit,
+            steps,
+            tainted,
+            audit: Some(audit),
+        }
+    }
+
+    /// Returns the audit of this receipt, deriving it from the steps
+    /// for receipts that have been recorded without one
+    pub fn get_audit(&self) -> BuildAudit {
+        self.audit
+            .clone()
+            .unwrap_or_else(|| BuildAudit::from_steps(&self.steps))
+    }
+
+    /// Reads a build receipt from `odb`
+    /// # Arguments
+    /// * `odb` - The object database to read from
+    /// * `oid` - The object id of the receipt object
+    pub fn from_odb(odb: &ObjectDB, oid: &ObjectID) -> Result<Self, Error> {
+        let reader = odb.read(oid)?;
+
+        if reader.object.ty != ObjectType::AcaciaBuildReceipt {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::UnexpectedObjectType {
+                    oid: oid.clone(),
+                    expected: ObjectType::AcaciaBuildReceipt,
+                    received: reader.object.ty,
+                },
+            )));
+        }
+
+        serde_json::from_reader(reader).ctx(|| format!("Parsing build receipt {oid}"))
+    }
+
+    /// Returns all build receipts of the package `oid` in the order they were inserted
+    /// # Arguments
+    /// * `odb` - The object database to search in
+    /// * `oid` - The object id of the package
+    /// # Returns
+    /// The object ids of the receipt objects and the receipts
+    pub fn find(odb: &ObjectDB, oid: &ObjectID) -> Result<Vec<(ObjectID, Self)>, Error> {
+        let mut receipts = Vec::new();
+
+        for dependent in odb.reverse_dependencies(oid)? {
+            if odb.get_object(&dependent)?.ty != ObjectType::AcaciaBuildReceipt {
+                continue;
+            }
+
+            let receipt = Self::from_odb(odb, &dependent)?;
+            if &receipt.package == oid {
+                receipts.push((dependent, receipt));
+            }
+        }
+
+        Ok(receipts)
+    }
+
+    /// Returns the object ids of the step snapshots of this receipt
+    pub fn snapshots(&self) -> Vec<ObjectID> {
+        self.steps
+            .iter()
+            .filter_map(|step| step.snapshot.clone())
+            .collect()
+    }
+
+    /// Returns the `JSON` string for this receipt
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).expect("Serialize build receipt should never fail")
+    }
+
+    /// Inserts this receipt into `odb`, depending on the built package
+    /// and the step snapshots, so they are retained as long as the receipt
+    /// # Arguments
+    /// * `odb` - The object database to insert into
+    /// * `compression` - The compression to apply for inserting
+    pub fn insert_into_odb(
+        &self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+    ) -> Result<Object, Error> {
+        let mut cursor = Cursor::new(self.json());
+
+        let mut dependencies = vec![self.package.clone()];
+        dependencies.extend(self.snapshots());
+
+        let object = odb.insert_stream(
+            &mut cursor,
+            ObjectType::AcaciaBuildReceipt,
+            compression,
+            dependencies,
+        )?;
+
+        debug!("Recorded build of {} as {}", self.package, object.oid);
+
+        Ok(object)
+    }
+}
+
+impl BuildAudit {
+    /// Audits the mounts and environment variables of `steps`
+    /// # Arguments
+    /// * `steps` - The executed build steps in the order of their execution
+    pub fn from_steps(steps: &[StepRecord]) -> Self {
+        let mut audit = Self::default();
+
+        for step in steps {
+            for mount in &step.mounts {
+                if !audit.mounts.contains(mount) {
+                    audit.mounts.push(mount.clone());
+                }
+            }
+
+            for (name, value) in &step.env {
+                audit.env.insert(name.clone(), value.clone());
+            }
+        }
+        audit.env.sort_keys();
+
+        audit
+    }
+}
+
+impl Display for BuildReceipt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Package: {}", self.package)?;
+        writeln!(f, "Build:   {}", self.build_id)?;
+        write!(f, "Tooling: {}", self.tooling_commit)?;
+        if self.tainted {
+            write!(
+                f,
+                "\nTainted: Sources have been overridden by local directories"
+            )?;
+        }
+
+        if let Some(audit) = &self.audit {
+            write!(f, "\n\n{audit}")?;
+        }
+
+        for step in &self.steps {
+            write!(f, "\n\n{step}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for StepRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Step '{}':", self.name)?;
+        writeln!(f, "  Kernel:  {}", self.kernel)?;
+        writeln!(f, "  Workdir: {}", self.workdir)?;
+        if let Some(client) = &self.remote_compile {
+            writeln!(f, "  Remote compilation: {client}")?;
+        }
+        if let Some(snapshot) = &self.snapshot {
+            writeln!(f, "  Snapshot: {snapshot}")?;
+        }
+        writeln!(f, "  Command:")?;
+        for arg in &self.argv {
+            writeln!(f, "    {arg}")?;
+        }
+        writeln!(f, "  Environment:")?;
+        for (name, value) in &self.env {
+            writeln!(f, "    {name}={value}")?;
+        }
+        write!(f, "  Mounts:")?;
+        for mount in &self.mounts {
+            write!(f, "\n    {mount}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for BuildAudit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Audit:")?;
+        writeln!(f, "  Environment:")?;
+        for (name, value) in &self.env {
+            writeln!(f, "    {name}={value}")?;
+        }
+        write!(f, "  Mounts:")?;
+        for mount in &self.mounts {
+            write!(f, "\n    {mount}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for MountRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}) => {}",
+            self.sources.join(":"),
+            self.fs_type,
+            self.target
+        )?;
+
+        if !self.flags.is_empty() {
+            write!(f, " [{}]", self.flags.join(","))?;
+        }
+
+        Ok(())
+    }
+}
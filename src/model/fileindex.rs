@@ -0,0 +1,537 @@
+//! An index of the files the packages of a repository or an installed system provide
+
+use std::{
+    collections::BTreeMap,
+    io::Cursor,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    util::{
+        fs::{self, PathUtil},
+        ODBUnpackable,
+    },
+};
+
+use super::{
+    MergeStrategy, Object, ObjectCompression, ObjectDB, ObjectDBError, ObjectID, ObjectType,
+    RepositoryIndex, RepositoryIndexEntry, Tree,
+};
+
+/// The number of generations of a root directory kept by default
+pub const DEFAULT_KEPT_GENERATIONS: usize = 10;
+
+/// The path of the file holding the object id of the file index
+/// of the packages installed to a root directory, relative to the root
+pub static INSTALLED_FILE_INDEX_PATH: &str = "var/lib/acacia/file-index";
+
+/// Maps the files (and symlinks) of a set of packages to the packages providing them,
+/// e.g. to find out which package a file of an installed system belongs to.
+///
+/// The index can be built from a whole [RepositoryIndex] or be updated package by
+/// package while installing and removing them
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+pub struct FileIndex {
+    /// The indexed packages
+    pub packages: Vec<FileIndexPackage>,
+    /// The paths relative to the root (without a leading `/`)
+    /// and the object ids of the packages providing them
+    pub files: BTreeMap<String, Vec<ObjectID>>,
+    /// The generations of the root directory the packages have been installed to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub generations: Vec<Generation>,
+    /// The number of the generation the root directory is at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u32>,
+}
+
+/// A package in a [FileIndex]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileIndexPackage {
+    /// The object id of the package
+    pub oid: ObjectID,
+    /// The name of the package
+    pub name: String,
+    /// The version of the package
+    pub version: String,
+}
+
+/// A file a package provides that is owned by other packages in a [FileIndex]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileConflict {
+    /// The path relative to the root (without a leading `/`)
+    pub path: String,
+    /// The indexed packages owning the path
+    pub owners: Vec<FileIndexPackage>,
+}
+
+/// A state of a root directory recorded after installing, removing or upgrading packages,
+/// so the root directory can be rolled back to it (refer to [FileIndex::record_generation()])
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Generation {
+    /// The number of the generation, counting up from `1`
+    pub number: u32,
+    /// The object id of the tree of all files the installed packages provide
+    pub tree: ObjectID,
+    /// The time the generation has been recorded at, in seconds since the UNIX epoch
+    pub timestamp: u64,
+    /// The installed packages
+    pub packages: Vec<FileIndexPackage>,
+}
+
+impl FileIndex {
+    /// Creates a new, empty file index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a file index of all built packages in a repository index
+    /// # Arguments
+    /// * `odb` - The object database to read the package trees from
+    /// * `index` - The repository index to index the packages of
+    pub fn from_repository_index(odb: &ObjectDB, index: &RepositoryIndex) -> Result<Self, Error> {
+        let mut file_index = Self::new();
+
+        for entry in &index.entries {
+            let Some(oid) = &entry.package else {
+                continue;
+            };
+
+            let tree = read_package_tree(odb, oid)?;
+            file_index.insert_package(entry, &tree);
+        }
+
+        Ok(file_index)
+    }
+
+    /// Reads a file index from `odb`
+    /// # Arguments
+    /// * `odb` - The object database to read from
+    /// * `oid` - The object id of the file index object
+    pub fn from_odb(odb: &ObjectDB, oid: &ObjectID) -> Result<Self, Error> {
+        let reader = odb.read(oid)?;
+
+        if reader.object.ty != ObjectType::AcaciaFileIndex {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::UnexpectedObjectType {
+                    oid: oid.clone(),
+                    expected: ObjectType::AcaciaFileIndex,
+                    received: reader.object.ty,
+                },
+            )));
+        }
+
+        serde_json::from_reader(reader).ctx(|| format!("Parsing file index {oid}"))
+    }
+
+    /// Reads the file index of the packages installed to `root`
+    /// # Arguments
+    /// * `odb` - The object database to read the index from
+    /// * `root` - The root directory the packages have been installed to
+    /// # Returns
+    /// The index, an empty one if nothing has been installed to `root` yet
+    pub fn from_root(odb: &ObjectDB, root: &Path) -> Result<Self, Error> {
+        let path = root.join(INSTALLED_FILE_INDEX_PATH);
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        let content = fs::file_read_to_string(&path)?;
+        let oid = ObjectID::new_from_hex(content.trim()).map_err(|e| {
+            Error::new(ErrorType::Other(format!(
+                "Invalid object id in {}: {e}",
+                path.str_lossy()
+            )))
+        })?;
+
+        Self::from_odb(odb, &oid).ctx(|| format!("Reading file index of {}", root.str_lossy()))
+    }
+
+    /// Inserts this index into `odb` and records it as the file index of the packages
+    /// installed to `root`, so it can be updated by the next installation or removal
+    /// # Arguments
+    /// * `odb` - The object database to insert into
+    /// * `root` - The root directory the packages have been installed to
+    pub fn store_for_root(&self, odb: &mut ObjectDB, root: &Path) -> Result<Object, Error> {
+        let object = self.insert_into_odb(odb, ObjectCompression::Xz)?;
+
+        let path = root.join(INSTALLED_FILE_INDEX_PATH);
+        fs::create_parent_dir_all(&path)?;
+        std::fs::write(&path, format!("{}\n", object.oid))
+            .e_context(|| format!("Writing {}", path.str_lossy()))?;
+
+        Ok(object)
+    }
+
+    /// Adds the files of a package to the index, replacing the ones
+    /// recorded for the package if it has already been indexed.
+    ///
+    /// Entries without a package are ignored
+    /// # Arguments
+    /// * `entry` - The repository index entry of the package
+    /// * `tree` - The tree of the package's files
+    pub fn insert_package(&mut self, entry: &RepositoryIndexEntry, tree: &Tree) {
+        let Some(oid) = &entry.package else {
+            return;
+        };
+
+        self.insert_indexed_package(
+            FileIndexPackage {
+                oid: oid.clone(),
+                name: entry.name.clone(),
+                version: entry.version.clone(),
+            },
+            tree,
+        );
+    }
+
+    /// Adds the files of a package to the index, refer to [FileIndex::insert_package()]
+    /// # Arguments
+    /// * `package` - The package
+    /// * `tree` - The tree of the package's files
+    fn insert_indexed_package(&mut self, package: FileIndexPackage, tree: &Tree) {
+        self.remove_package(&package.oid);
+
+        for path in tree.get_file_paths() {
+            self.files
+                .entry(path.str_lossy())
+                .or_default()
+                .push(package.oid.clone());
+        }
+
+        self.packages.push(package);
+    }
+
+    /// Removes a package and the files only it provides from the index
+    /// # Arguments
+    /// * `oid` - The object id of the package to remove
+    /// # Returns
+    /// Whether the package has been in the index
+    pub fn remove_package(&mut self, oid: &ObjectID) -> bool {
+        let len = self.packages.len();
+        self.packages.retain(|p| &p.oid != oid);
+
+        if self.packages.len() == len {
+            return false;
+        }
+
+        self.files.retain(|_, packages| {
+            packages.retain(|p| p != oid);
+            !packages.is_empty()
+        });
+
+        true
+    }
+
+    /// Removes `oid` from the packages owning `path`, e.g. once another
+    /// package has replaced the file. Paths without owners are dropped
+    /// # Arguments
+    /// * `path` - The path relative to the root (without a leading `/`)
+    /// * `oid` - The object id of the package
+    pub fn disown(&mut self, path: &str, oid: &ObjectID) {
+        if let Some(owners) = self.files.get_mut(path) {
+            owners.retain(|o| o != oid);
+
+            if owners.is_empty() {
+                self.files.remove(path);
+            }
+        }
+    }
+
+    /// Finds the files of `tree` that are owned by indexed packages other than
+    /// the ones named `name`, which installing the package would overwrite.
+    /// Other versions of the package itself are no conflict
+    /// # Arguments
+    /// * `name` - The name of the package to install
+    /// * `tree` - The tree of the package's files
+    /// # Returns
+    /// The conflicting paths and their owners, sorted by path
+    pub fn find_conflicts(&self, name: &str, tree: &Tree) -> Vec<FileConflict> {
+        let mut conflicts: Vec<FileConflict> = tree
+            .get_file_paths()
+            .iter()
+            .filter_map(|path| {
+                let path = path.str_lossy();
+                let owners: Vec<FileIndexPackage> = self
+                    .files
+                    .get(&path)?
+                    .iter()
+                    .filter_map(|oid| self.get_package(oid))
+                    .filter(|p| p.name != name)
+                    .cloned()
+                    .collect();
+
+                (!owners.is_empty()).then_some(FileConflict { path, owners })
+            })
+            .collect();
+
+        conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+        conflicts
+    }
+
+    /// Returns the indexed package with the object id `oid`
+    /// # Arguments
+    /// * `oid` - The object id of the package
+    pub fn get_package(&self, oid: &ObjectID) -> Option<&FileIndexPackage> {
+        self.packages.iter().find(|p| &p.oid == oid)
+    }
+
+    /// Finds the packages providing `file`.
+    ///
+    /// A `file` containing a `/` is matched against the whole path (a leading `/` is ignored),
+    /// otherwise against the file names of all indexed paths
+    /// # Arguments
+    /// * `file` - The path or file name to search for
+    /// # Returns
+    /// The matching paths and the packages providing them, sorted by path
+    pub fn which(&self, file: &str) -> Vec<(&str, Vec<&FileIndexPackage>)> {
+        let matches: Vec<(&String, &Vec<ObjectID>)> = if file.contains('/') {
+            self.files
+                .get_key_value(file.trim_start_matches('/'))
+                .into_iter()
+                .collect()
+        } else {
+            self.files
+                .iter()
+                .filter(|(path, _)| {
+                    Path::new(path)
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy() == file)
+                })
+                .collect()
+        };
+
+        matches
+            .into_iter()
+            .map(|(path, oids)| {
+                let packages = oids
+                    .iter()
+                    .filter_map(|oid| self.get_package(oid))
+                    .collect();
+                (path.as_str(), packages)
+            })
+            .collect()
+    }
+
+    /// Merges the trees of the indexed packages into one tree, in the order they have
+    /// been installed in. Files provided by multiple packages keep the one installed
+    /// last, which has replaced the others in the root directory
+    /// # Arguments
+    /// * `odb` - The object database to read the package trees from
+    pub fn get_tree(&self, odb: &ObjectDB) -> Result<Tree, Error> {
+        let mut tree = Tree {
+            entries: Vec::new(),
+        };
+
+        for package in &self.packages {
+            tree.merge_with_strategy(
+                read_package_tree(odb, &package.oid)?,
+                MergeStrategy::KeepTheirs,
+            )
+            .ctx(|| format!("Merging the tree of {}", package.oid))?;
+        }
+
+        Ok(tree)
+    }
+
+    /// Records the installed packages as a new generation and makes it the current one.
+    ///
+    /// The merged tree of the packages (see [FileIndex::get_tree()]) gets inserted into `odb`.
+    /// A new generation is numbered one higher than all existing ones, also if the root
+    /// directory has been rolled back to an older generation before.
+    /// Afterwards, the generations exceeding `keep` get dropped (see [FileIndex::prune_generations()])
+    /// # Arguments
+    /// * `odb` - The object database to insert the tree into
+    /// * `keep` - The number of generations to keep, `0` to keep all
+    /// # Returns
+    /// The new generation
+    pub fn record_generation(
+        &mut self,
+        odb: &mut ObjectDB,
+        keep: usize,
+    ) -> Result<&Generation, Error> {
+        let tree = self
+            .get_tree(odb)?
+            .insert_into_odb(odb, ObjectCompression::Xz)
+            .ctx(|| "Inserting the tree of the generation")?;
+
+        let number = self.generations.iter().map(|g| g.number).max().unwrap_or(0) + 1;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        debug!("Recording generation {number} with tree {}", tree.oid);
+        self.generations.push(Generation {
+            number,
+            tree: tree.oid,
+            timestamp,
+            packages: self.packages.clone(),
+        });
+        self.generation = Some(number);
+
+        for number in self.prune_generations(keep) {
+            debug!("Dropped generation {number}");
+        }
+
+        Ok(self
+            .generations
+            .last()
+            .expect("Generation has just been pushed"))
+    }
+
+    /// Drops the oldest generations, keeping the newest `keep` ones and the current one.
+    /// Their trees and packages are no longer dependencies of the index,
+    /// so they can be collected from the object database
+    /// # Arguments
+    /// * `keep` - The number of generations to keep, `0` to keep all
+    /// # Returns
+    /// The numbers of the dropped generations
+    pub fn prune_generations(&mut self, keep: usize) -> Vec<u32> {
+        if keep == 0 || self.generations.len() <= keep {
+            return Vec::new();
+        }
+
+        let mut numbers: Vec<u32> = self.generations.iter().map(|g| g.number).collect();
+        numbers.sort_unstable();
+
+        let dropped: Vec<u32> = numbers[..numbers.len() - keep]
+            .iter()
+            .copied()
+            .filter(|number| Some(*number) != self.generation)
+            .collect();
+
+        self.generations.retain(|g| !dropped.contains(&g.number));
+
+        dropped
+    }
+
+    /// Returns the generation numbered `number`
+    /// # Arguments
+    /// * `number` - The number of the generation
+    pub fn get_generation(&self, number: u32) -> Option<&Generation> {
+        self.generations.iter().find(|g| g.number == number)
+    }
+
+    /// Returns the generation the root directory is at
+    pub fn current_generation(&self) -> Option<&Generation> {
+        self.generation.and_then(|n| self.get_generation(n))
+    }
+
+    /// Returns the newest generation older than the current one
+    pub fn previous_generation(&self) -> Option<&Generation> {
+        let current = self.generation?;
+
+        self.generations
+            .iter()
+            .filter(|g| g.number < current)
+            .max_by_key(|g| g.number)
+    }
+
+    /// Replaces the indexed packages by the ones of the generation numbered `number`
+    /// and makes it the current one, used after rolling the root directory back to it
+    /// # Arguments
+    /// * `odb` - The object database to read the package trees from
+    /// * `number` - The number of the generation
+    pub fn restore_generation(&mut self, odb: &ObjectDB, number: u32) -> Result<(), Error> {
+        let generation = self.get_generation(number).cloned().ok_or_else(|| {
+            Error::new(ErrorType::Other(format!("There is no generation {number}")))
+        })?;
+
+        self.packages.clear();
+        self.files.clear();
+        for package in generation.packages {
+            let tree = read_package_tree(odb, &package.oid)?;
+            self.insert_indexed_package(package, &tree);
+        }
+        self.generation = Some(number);
+
+        Ok(())
+    }
+
+    /// Returns the object ids of the indexed packages
+    /// and the packages and trees of all generations
+    pub fn get_dependencies(&self) -> Vec<ObjectID> {
+        let mut dependencies: Vec<ObjectID> = self.packages.iter().map(|p| p.oid.clone()).collect();
+
+        for generation in &self.generations {
+            let oids = generation.packages.iter().map(|p| &p.oid);
+
+            for oid in std::iter::once(&generation.tree).chain(oids) {
+                if !dependencies.contains(oid) {
+                    dependencies.push(oid.clone());
+                }
+            }
+        }
+
+        dependencies
+    }
+
+    /// Returns the `JSON` string for this index
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).expect("Serialize file index should never fail")
+    }
+
+    /// Inserts this index into `odb`, depending on the indexed packages
+    /// # Arguments
+    /// * `odb` - The object database to insert into
+    /// * `compression` - The compression to apply for inserting
+    pub fn insert_into_odb(
+        &self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+    ) -> Result<Object, Error> {
+        let mut cursor = Cursor::new(self.json());
+
+        let object = odb.insert_stream(
+            &mut cursor,
+            ObjectType::AcaciaFileIndex,
+            compression,
+            self.get_dependencies(),
+        )?;
+
+        debug!(
+            "Inserted file index with {} files of {} packages as {}",
+            self.files.len(),
+            self.packages.len(),
+            object.oid
+        );
+
+        Ok(object)
+    }
+}
+
+/// Reads the tree of the package `oid`
+/// # Arguments
+/// * `odb` - The object database to read the tree from
+/// * `oid` - The object id of the package
+fn read_package_tree(odb: &ObjectDB, oid: &ObjectID) -> Result<Tree, Error> {
+    let mut object = odb.read(oid).ctx(|| format!("Reading package {oid}"))?;
+    Tree::unpack_from_odb(&mut object, odb).ctx(|| format!("Reading package tree {oid}"))
+}
+
+impl std::fmt::Display for FileConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/{} (owned by ", self.path)?;
+
+        for (i, owner) in self.owners.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{owner}")?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+impl std::fmt::Display for FileIndexPackage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{} [{}]", self.name, self.version, self.oid)
+    }
+}
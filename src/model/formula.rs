@@ -4,23 +4,31 @@ use std::{
 };
 
 use indexmap::IndexMap;
-use log::debug;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::{architecture::ArchitectureError, Error, ErrorExt, ErrorType},
-    files::formulafile::FormulaFile,
+    error::{
+        architecture::ArchitectureError, dependency::DependencyError, Error, ErrorExt, ErrorType,
+    },
+    files::{
+        formulafile::{is_within_formula_dir, FormulaFile, FormulaStepPolicy},
+        formulavars::FormulaVariables,
+        validationpolicy::ValidationPolicy,
+    },
+    package::{PackageLayout, Scriptlet, ScriptletKind},
     util::{
         architecture::Architecture,
-        download::download_to_file,
+        download::download_to_file_mirrored_with_progress,
         fs::{self, PathUtil},
-        parse::versionstring::VersionString,
+        git::clone_shallow,
+        parse::{spdx::SpdxExpression, versionstring::VersionString},
     },
 };
 
 use super::{
-    odb_driver::FilesystemDriver, Home, Object, ObjectCompression, ObjectDB, ObjectID, ObjectType,
-    Tree,
+    Home, Object, ObjectCompression, ObjectDB, ObjectDBError, ObjectID, ObjectType,
+    RepositoryIndex, Tree,
 };
 
 /// A resolved formula that uniquely describes a package's
@@ -33,11 +41,19 @@ pub struct Formula {
     pub version: String,
     /// A short description of the package's contents
     pub description: String,
+    /// The license of the package as an SPDX expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<SpdxExpression>,
 
     /// Whether the package's binaries should be stripped
     /// using the `strip` command
     pub strip: bool,
 
+    /// Whether the build steps run with a fixed clock and locale,
+    /// refer to [crate::env::HermeticSettings]
+    #[serde(default = "default_hermetic", skip_serializing_if = "is_true")]
+    pub hermetic: bool,
+
     /// The architecture the package is built for
     pub arch: Option<Architecture>,
 
@@ -52,35 +68,115 @@ pub struct Formula {
     /// by the dependency checker
     pub extra_dependencies: Vec<ObjectID>,
 
+    /// The virtual capabilities this package provides in
+    /// addition to its name (e.g. `sh`, `libc.so.6`, `cc`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provides: Vec<String>,
+
     /// The instructions for the `prepare` step
     pub prepare: Option<String>,
+    /// The patches to apply between the `prepare` and `build` steps,
+    /// relative to the root of `tree`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<String>,
     /// The instructions for the `build` step
     pub build: Option<String>,
     /// The instructions for the `check` step
     pub check: Option<String>,
     /// The instructions for the `package` step
     pub package: Option<String>,
+    /// The script to run in the target root after installing the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_install: Option<String>,
+    /// The script to run in the target root before removing the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_remove: Option<String>,
+    /// The policies (timeout, retries) for the steps
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub policy: IndexMap<String, FormulaStepPolicy>,
+    /// How validation findings of the package are treated
+    #[serde(default, skip_serializing_if = "ValidationPolicy::is_empty")]
+    pub validation: ValidationPolicy,
 
     /// The layout describing the purposes and
     /// special directories within the package root
     pub layout: IndexMap<String, Vec<String>>,
 
+    /// The user-defined variables, refer to [FormulaVariables]
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub vars: IndexMap<String, String>,
+    /// The environment variables for the build steps, with the
+    /// package's ones merged in (refer to [FormulaFile::get_env()])
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
+
     /// The tree of files that is shipped with this formula
     pub tree: ObjectID,
+    /// The commits the git sources have been checked out at,
+    /// their checkouts are part of `tree`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub git_sources: Vec<FormulaGitCommit>,
+
+    /// Whether sources have been replaced by local directories
+    /// (`--source-override`), so the formula does not match its upstream
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tainted: bool,
+}
+
+/// The commit a git source of a formula has been checked out at, recorded for reproducibility
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FormulaGitCommit {
+    /// The destination of the source within the formula tree
+    pub dest: String,
+    /// The URL of the repository
+    pub url: String,
+    /// The hash of the checked out commit
+    pub commit: String,
+}
+
+/// Provides the default value for the `hermetic` field: `true`
+fn default_hermetic() -> bool {
+    true
 }
 
-/// Helper function to resolve an optional vector of
-/// package strings to a vector of object ids
+/// Skips serializing fields that are `true`, which is their default
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+/// Helper function to resolve an optional vector of package strings to the object ids
+/// of the newest built packages satisfying them (refer to [RepositoryIndex::resolve()])
 /// # Arguments
 /// * `packages` - The packages to resolve
-fn resolve_packages(packages: Option<Vec<VersionString>>) -> Vec<ObjectID> {
-    let oids = Vec::new();
+/// * `index` - The repository index to resolve the packages from
+/// * `arch` - The architecture the packages have to run on
+fn resolve_packages(
+    packages: Option<Vec<VersionString>>,
+    index: &RepositoryIndex,
+    arch: &Architecture,
+) -> Result<Vec<ObjectID>, Error> {
+    let mut oids = Vec::new();
+
+    for package in packages.unwrap_or_default() {
+        let entry = index.resolve(&package.name, arch).ok_or_else(|| {
+            Error::new(ErrorType::Dependency(DependencyError::Unresolved {
+                arch: arch.to_string(),
+                name: package.name.clone(),
+                version: package.version.clone(),
+                pkgver: package.pkgver,
+            }))
+        })?;
+
+        if entry.name == package.name && entry.version != package.version {
+            warn!("Using {entry} for '{}@{}'", package.name, package.version);
+        } else {
+            debug!("Using {entry} for '{}'", package.name);
+        }
 
-    for _ in packages.unwrap_or_default() {
-        todo!("Implement package resolving")
+        oids.extend(entry.package.clone());
     }
 
-    oids
+    Ok(oids)
 }
 
 impl FormulaFile {
@@ -88,33 +184,87 @@ impl FormulaFile {
     /// - Dependencies
     /// - Files
     ///
-    /// All of these things get inserted into the `object_db`
+    /// All of these things get inserted into `object_db`
     /// to resolve all dependencies as object references.
     ///
-    /// This will also insert the formula into the object database
+    /// This will also insert the formula into the object database.
+    ///
+    /// Sources whose destination (`dest`) is a key of `source_overrides` are not
+    /// downloaded, the local directory gets indexed in their place instead.
+    /// This marks the formula as [tainted](Formula::tainted)
+    ///
+    /// The dependencies are resolved to the newest packages of `dependencies` satisfying
+    /// them: The host dependencies for the host architecture, the others for `build_architecture`
     /// # Arguments
     /// * `formula_path` - The path to the formula file
     /// * `home` - The home to use for the resolving process
+    /// * `object_db` - The object database to insert the objects into
     /// * `build_architecture` - The architecture the formula is built for
     /// * `compression` - The compression method to use for inserting the objects
+    /// * `source_overrides` - Local directories to use instead of sources, keyed by the source destination
+    /// * `dependencies` - The repository index to resolve the dependencies from
     pub fn parse_and_resolve(
         formula_path: &Path,
         home: &Home,
+        object_db: &mut ObjectDB,
         build_architecture: Architecture,
         compression: ObjectCompression,
+        source_overrides: &IndexMap<String, PathBuf>,
+        dependencies: &RepositoryIndex,
     ) -> Result<(Formula, Object), Error> {
-        let formula: FormulaFile = toml::from_str(&fs::file_read_to_string(formula_path)?)
-            .e_context(|| "Parsing formula source")?;
+        let formula = FormulaFile::parse(formula_path).ctx(|| "Parsing formula source")?;
 
         let parent = formula_path
             .parent()
             .expect("Parent directory of formula file");
 
         let file_sources = formula.package.sources.clone().unwrap_or_default();
-        let odb_driver = FilesystemDriver::new(home.object_db_path())?;
-        let mut object_db = ObjectDB::init(Box::new(odb_driver)).ctx(|| "Opening object db")?;
+        let patches = formula.package.patches.clone().unwrap_or_default();
+
+        // Patches are shipped in the formula tree, make sure they exist
+        for patch in &patches {
+            if !is_within_formula_dir(patch) {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Patch '{patch}' has to be relative to the formula directory without '..'"
+                ))));
+            }
+
+            let path = parent.join(patch);
+            if !path.is_file() {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Patch '{}' not found in formula directory {}",
+                    patch,
+                    parent.str_lossy()
+                ))));
+            }
+        }
+
         let temp_dir = home.get_temporary_directory();
 
+        let variables = formula
+            .get_variables(&build_architecture)
+            .ctx(|| "Resolving formula variables")?;
+
+        let host_architecture = Architecture::new_uname()?;
+        let host_dependencies = resolve_packages(
+            formula.package.host_dependencies.clone(),
+            dependencies,
+            &host_architecture,
+        )
+        .ctx(|| "Resolving host dependencies")?;
+        let target_dependencies = resolve_packages(
+            formula.package.target_dependencies.clone(),
+            dependencies,
+            &build_architecture,
+        )
+        .ctx(|| "Resolving target dependencies")?;
+        let extra_dependencies = resolve_packages(
+            formula.package.extra_dependencies.clone(),
+            dependencies,
+            &build_architecture,
+        )
+        .ctx(|| "Resolving extra dependencies")?;
+
         // If the formula has some supported architectures,
         // make sure the build architecture is in them
         let architecture = match formula.package.get_architectures() {
@@ -140,61 +290,165 @@ impl FormulaFile {
         .e_context(|| "Resolving formula architecture")?;
 
         let mut tree =
-            Tree::index(parent, &mut object_db, compression).ctx(|| "Indexing formula files")?;
+            Tree::index(parent, object_db, compression).ctx(|| "Indexing formula files")?;
+
+        if let Some(name) = source_overrides.keys().find(|name| {
+            !file_sources
+                .iter()
+                .any(|s| s.get_dest(&variables) == **name)
+        }) {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Formula has no source '{name}' to override"
+            ))));
+        }
 
+        let mut overridden: Vec<(PathBuf, &PathBuf)> = Vec::new();
+        let mut git_sources: Vec<FormulaGitCommit> = Vec::new();
+        let mut downloads: Vec<(Vec<String>, PathBuf, PathBuf)> = Vec::new();
         for source in file_sources {
-            let url = source.get_url(&formula.package);
-            let dest = PathBuf::from(source.get_dest(&formula.package));
+            let urls = source.get_urls(&variables);
+            let dest_str = source.get_dest(&variables);
+            let dest = PathBuf::from(&dest_str);
 
             let path = temp_dir.join(&dest);
+
+            if let Some(dir) = source_overrides.get(&dest_str) {
+                warn!(
+                    "Overriding source {} with {}, the formula is tainted",
+                    dest.str_lossy(),
+                    dir.str_lossy()
+                );
+
+                // Create a placeholder for the subtree to replace once indexed
+                fs::create_dir_all(&path).ctx(|| "Creating source override placeholder")?;
+                overridden.push((dest, dir));
+                continue;
+            }
+
             if let Some(parent) = path.parent() {
                 fs::create_dir_all(parent).ctx(|| "Creating source parent directory")?;
             }
 
-            download_to_file(
-                &url,
-                &path,
-                &format!("Fetching source {}", dest.str_lossy()),
-                true,
-            )?;
+            if let Some(reference) = source.get_git_reference(&variables) {
+                let url = source.get_url(&variables);
+                let commit = clone_shallow(&url, &reference, &path)
+                    .ctx(|| format!("Fetching source {}", dest.str_lossy()))?;
+                debug!("Fetched source {} at {commit}", dest.str_lossy());
+
+                git_sources.push(FormulaGitCommit {
+                    dest: dest_str,
+                    url,
+                    commit,
+                });
+                continue;
+            }
+
+            downloads.push((urls, path, dest));
+        }
+
+        // Sources are fetched concurrently, sharing the bandwidth limit of the home
+        let urls = home.load_config()?.get_download_manager().run(
+            "Fetching sources",
+            &downloads,
+            |(urls, path, dest), callback| {
+                let message = format!("Fetching source {}", dest.str_lossy());
+                download_to_file_mirrored_with_progress(urls, path, &message, callback)
+                    .map(|(_, url)| url)
+            },
+        )?;
+        for ((_, _, dest), url) in downloads.iter().zip(urls) {
+            debug!("Fetched source {} from {url}", dest.str_lossy());
         }
 
-        let sources_tree =
-            Tree::index(&temp_dir, &mut object_db, compression).ctx(|| "Creating sources tree")?;
+        let mut sources_tree =
+            Tree::index(&temp_dir, object_db, compression).ctx(|| "Creating sources tree")?;
+
+        for (dest, dir) in &overridden {
+            let override_tree = Tree::index(dir, object_db, compression)
+                .ctx(|| format!("Indexing source override {}", dir.str_lossy()))?;
+
+            *sources_tree
+                .get_subtree_mut(dest)
+                .expect("Source override placeholders are subtrees") = override_tree;
+        }
         tree.merge(sources_tree);
 
         let tree_obj = tree
-            .insert_into_odb(&mut object_db, compression)
+            .insert_into_odb(object_db, compression)
             .ctx(|| "Inserting tree")?;
 
+        let env = formula.get_env();
         let formula = Formula {
             name: formula.package.name,
             version: formula.package.version,
             description: formula.package.description,
+            license: formula.package.license,
 
             strip: formula.package.strip,
+            hermetic: formula.package.hermetic,
             arch: architecture,
 
-            host_dependencies: resolve_packages(formula.package.host_dependencies),
-            target_dependencies: resolve_packages(formula.package.target_dependencies),
-            extra_dependencies: resolve_packages(formula.package.extra_dependencies),
+            host_dependencies,
+            target_dependencies,
+            extra_dependencies,
+            provides: formula.package.provides.unwrap_or_default(),
 
             prepare: formula.package.prepare,
+            patches,
             build: formula.package.build,
             check: formula.package.check,
             package: formula.package.package,
+            post_install: formula.package.post_install,
+            pre_remove: formula.package.pre_remove,
+            policy: formula.package.policy,
+            validation: formula.package.validation,
 
             layout: formula.package.layout,
+            env,
+            vars: formula.vars,
             tree: tree_obj.oid,
+            git_sources,
+            tainted: !overridden.is_empty(),
         };
 
-        let object = formula.insert(&mut object_db, compression)?;
+        let object = formula.insert(object_db, compression)?;
 
         Ok((formula, object))
     }
 }
 
 impl Formula {
+    /// Returns the variables of this formula for building it for `arch`,
+    /// refer to [FormulaVariables]
+    /// # Arguments
+    /// * `arch` - The build architecture
+    pub fn get_variables(&self, arch: &Architecture) -> Result<FormulaVariables, Error> {
+        FormulaVariables::new(&self.name, &self.version, arch).with_user_variables(&self.vars)
+    }
+
+    /// Returns the scriptlet of `kind` for this formula's package, if there is one
+    /// # Arguments
+    /// * `kind` - The kind of scriptlet to return
+    pub fn get_scriptlet(&self, kind: ScriptletKind) -> Option<Scriptlet> {
+        let script = match kind {
+            ScriptletKind::PostInstall => self.post_install.as_ref(),
+            ScriptletKind::PreRemove => self.pre_remove.as_ref(),
+        }?;
+
+        Some(Scriptlet {
+            kind,
+            package: self.name.clone(),
+            version: self.version.clone(),
+            script: script.clone(),
+        })
+    }
+
+    /// Returns the executable and library directories of this formula's package,
+    /// refer to [PackageLayout::from_layout()]
+    pub fn get_layout(&self) -> PackageLayout {
+        PackageLayout::from_layout(&self.layout)
+    }
+
     /// Returns the `TOML` string for this formula
     pub fn toml(&self) -> String {
         toml::to_string_pretty(self).expect("Serialize formula file should never fail")
@@ -205,6 +459,26 @@ impl Formula {
         serde_json::to_string(self).expect("Serialize formula file should never fail")
     }
 
+    /// Reads a formula from `object_db`
+    /// # Arguments
+    /// * `object_db` - The object db to read the formula from
+    /// * `oid` - The object id of the formula object
+    pub fn from_odb(object_db: &ObjectDB, oid: &ObjectID) -> Result<Self, Error> {
+        let reader = object_db.read(oid)?;
+
+        if reader.object.ty != ObjectType::AcaciaFormula {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::UnexpectedObjectType {
+                    oid: oid.clone(),
+                    expected: ObjectType::AcaciaFormula,
+                    received: reader.object.ty,
+                },
+            )));
+        }
+
+        serde_json::from_reader(reader).ctx(|| format!("Parsing formula {oid}"))
+    }
+
     /// Inserts this formula into `object_db`
     /// # Arguments
     /// * `object_db` - The objet db to insert the formula into
@@ -1,10 +1,14 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
-use log::debug;
+use log::{debug, info};
 
 use crate::{
-    error::{Error, ErrorExt},
-    util::fs::{self, PathUtil},
+    error::{Error, ErrorExt, ErrorType},
+    files::homeconfig::HomeConfig,
+    util::fs::{self, FileLock, LockMode, PathUtil},
 };
 
 /// The home directory all tooling works in
@@ -12,6 +16,43 @@ pub struct Home {
     root: PathBuf,
 }
 
+/// A part of the home that processes lock (see [Home::lock()])
+/// to prevent concurrent operations from conflicting.
+///
+/// Processes needing multiple scopes lock them in the order of this enum
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HomeLockScope {
+    /// The object database: Shared for inserting objects,
+    /// exclusive for restructuring it (e.g. resharding)
+    ObjectDB,
+    /// The references to objects (the pins): Shared for reading, exclusive for modifying them
+    Refs,
+    /// The temporary directory builds, ingestion and downloads work in:
+    /// Shared for working in it, exclusive for cleaning it up
+    Builder,
+}
+
+/// The command line options of the binaries controlling
+/// whether to wait for the locks of the home (see [Home::lock_all()])
+#[derive(clap::Args, Debug, Clone, Default)]
+#[command(about = None, long_about = None)]
+pub struct HomeLockArgs {
+    /// Wait for other processes using the home to finish [default]
+    #[arg(long, action, global = true, overrides_with = "no_wait")]
+    wait: bool,
+
+    /// Fail instead of waiting for other processes using the home
+    #[arg(long, action, global = true, overrides_with = "wait")]
+    no_wait: bool,
+}
+
+impl HomeLockArgs {
+    /// Returns whether to wait for other processes holding conflicting locks
+    pub fn wait(&self) -> bool {
+        !self.no_wait
+    }
+}
+
 impl Home {
     /// Opens or creates a new home directory
     pub fn new(root: PathBuf) -> Result<Self, Error> {
@@ -35,6 +76,40 @@ impl Home {
         self.root.join("objects")
     }
 
+    /// Returns the path to the directory holding the signing
+    /// key and the trusted public keys (see [super::Keyring])
+    pub fn get_keys_dir(&self) -> PathBuf {
+        self.root.join("keys")
+    }
+
+    /// Returns the path to the configuration file of the home (see [HomeConfig])
+    pub fn get_config_path(&self) -> PathBuf {
+        self.root.join("config.toml")
+    }
+
+    /// Reads the configuration of the home, the defaults if there is no configuration file
+    pub fn load_config(&self) -> Result<HomeConfig, Error> {
+        HomeConfig::parse(&self.get_config_path())
+    }
+
+    /// Returns the path to the dump of security advisories
+    /// packages are audited against (see [crate::tools::auditor::Auditor])
+    pub fn get_advisories_path(&self) -> PathBuf {
+        self.root.join("advisories.json")
+    }
+
+    /// Returns the path to the file holding the pinned objects
+    /// of the object database (see [super::ObjectDB::pin()])
+    pub fn get_pins_path(&self) -> PathBuf {
+        self.root.join("pins")
+    }
+
+    /// Returns the path to the file holding the refs served
+    /// by `trunk serve` (see [crate::tools::server::ODBServer])
+    pub fn get_refs_path(&self) -> PathBuf {
+        self.root.join("refs")
+    }
+
     /// Returns the path to a temporary directory
     /// in the home
     fn get_tmp_dir(&self) -> PathBuf {
@@ -59,4 +134,89 @@ impl Home {
     pub fn get_builds_dir(&self) -> PathBuf {
         self.get_tmp_dir().join("builds")
     }
+
+    /// Returns the path to the lock file a build holds
+    /// while using its build folder (see [crate::util::fs::FileLock])
+    /// # Arguments
+    /// * `id` - The id of the build
+    pub fn get_build_lock_path(&self, id: &str) -> PathBuf {
+        self.get_builds_dir().join(id).join(".lock")
+    }
+
+    /// Returns the path to the directory that keeps
+    /// partial downloads to resume them later on
+    pub fn get_downloads_dir(&self) -> PathBuf {
+        self.get_tmp_dir().join("downloads")
+    }
+
+    /// Returns the path to the lock file of `scope` (see [Home::lock()])
+    /// # Arguments
+    /// * `scope` - The scope to get the lock file of
+    pub fn get_lock_path(&self, scope: HomeLockScope) -> PathBuf {
+        self.root.join("locks").join(format!("{scope}.lock"))
+    }
+
+    /// Locks `scope` of this home for as long as the returned lock lives
+    /// # Arguments
+    /// * `scope` - The scope to lock
+    /// * `mode` - How to hold the lock
+    /// * `wait` - Whether to wait for other processes holding a conflicting lock
+    /// # Errors
+    /// If `wait` is `false` and another process holds a conflicting lock
+    pub fn lock(
+        &self,
+        scope: HomeLockScope,
+        mode: LockMode,
+        wait: bool,
+    ) -> Result<FileLock, Error> {
+        let path = self.get_lock_path(scope);
+        fs::create_parent_dir_all(&path)?;
+
+        if let Some(lock) = FileLock::lock_with(&path, mode, false)? {
+            return Ok(lock);
+        }
+
+        if !wait {
+            return Err(Error::new(ErrorType::Other(format!(
+                "The '{scope}' lock of the home @ {} is held by another process",
+                self.root.str_lossy()
+            ))));
+        }
+
+        info!("Waiting for another process to release the '{scope}' lock...");
+        Ok(FileLock::lock_with(&path, mode, true)?.expect("Waiting for a lock always acquires it"))
+    }
+
+    /// Locks `scopes` of this home in the order of [HomeLockScope]
+    /// for as long as the returned locks live, refer to [Home::lock()]
+    /// # Arguments
+    /// * `scopes` - The scopes to lock and how to hold them
+    /// * `wait` - Whether to wait for other processes holding conflicting locks
+    pub fn lock_all(
+        &self,
+        scopes: &[(HomeLockScope, LockMode)],
+        wait: bool,
+    ) -> Result<Vec<FileLock>, Error> {
+        let mut scopes = scopes.to_vec();
+        scopes.sort_by_key(|(scope, _)| *scope);
+
+        scopes
+            .into_iter()
+            .map(|(scope, mode)| self.lock(scope, mode, wait))
+            .collect()
+    }
+}
+
+impl Display for HomeLockScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::ObjectDB => "odb",
+                Self::Refs => "refs",
+                Self::Builder => "builder",
+            }
+        )
+    }
 }
@@ -0,0 +1,180 @@
+//! Management of the keys used to sign and verify objects
+
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use log::debug;
+use zeroize::Zeroizing;
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    util::fs::{self, PathUtil},
+};
+
+use super::parse_public_key;
+
+/// The name of the file holding the signing key
+static SIGNING_KEY_FILE: &str = "signing.key";
+
+/// The name of the directory holding the trusted public keys
+static TRUSTED_KEYS_DIR: &str = "trusted";
+
+/// The extension of trusted public key files
+static PUBLIC_KEY_EXTENSION: &str = "pub";
+
+/// The keys in a keys directory (see [super::Home::get_keys_dir()]):
+/// - `signing.key`: The hex encoded secret key to sign objects with
+/// - `trusted/<NAME>.pub`: The hex encoded public keys whose signatures are trusted
+pub struct Keyring {
+    /// The signing key, if one has been generated, gets zeroized when dropped
+    signing_key: Option<SigningKey>,
+    /// The trusted public keys by their names
+    trusted_keys: Vec<(String, VerifyingKey)>,
+}
+
+impl Keyring {
+    /// Loads the keys in `dir`, a missing directory yields an empty keyring
+    /// # Arguments
+    /// * `dir` - The keys directory to load from
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let signing_key_path = dir.join(SIGNING_KEY_FILE);
+        let signing_key = match signing_key_path.exists() {
+            true => {
+                // The secret key material is wiped from memory once the key has been created
+                let hex = Zeroizing::new(fs::file_read_to_string(&signing_key_path)?);
+                let decoded = Zeroizing::new(hex::decode(hex.trim()).unwrap_or_default());
+                let bytes: Zeroizing<[u8; 32]> = <[u8; 32]>::try_from(decoded.as_slice())
+                    .map(Zeroizing::new)
+                    .map_err(|_| {
+                        Error::new(ErrorType::Other(format!(
+                            "Invalid signing key in {}",
+                            signing_key_path.str_lossy()
+                        )))
+                    })?;
+                Some(SigningKey::from_bytes(&bytes))
+            }
+            false => None,
+        };
+
+        let mut trusted_keys = Vec::new();
+        let trusted_dir = dir.join(TRUSTED_KEYS_DIR);
+        if trusted_dir.exists() {
+            for entry in std::fs::read_dir(&trusted_dir)
+                .e_context(|| format!("Reading trusted keys in {}", trusted_dir.str_lossy()))?
+            {
+                let path = entry.e_context(|| "Reading trusted key entry")?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some(PUBLIC_KEY_EXTENSION) {
+                    continue;
+                }
+
+                let name = path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string();
+                let key = parse_public_key(&fs::file_read_to_string(&path)?)
+                    .ctx(|| format!("Loading trusted key {}", path.str_lossy()))?;
+
+                debug!("Trusting key {name}");
+                trusted_keys.push((name, key));
+            }
+        }
+        trusted_keys.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(Self {
+            signing_key,
+            trusted_keys,
+        })
+    }
+
+    /// Generates a new signing key in `dir`, the public key of
+    /// the new key gets trusted as `self`.
+    /// Refuses to replace an existing signing key
+    /// # Arguments
+    /// * `dir` - The keys directory to generate the key in
+    /// # Returns
+    /// The public key of the new signing key
+    pub fn generate(dir: &Path) -> Result<VerifyingKey, Error> {
+        let path = dir.join(SIGNING_KEY_FILE);
+        let context = || format!("Generating signing key {}", path.str_lossy());
+
+        if path.exists() {
+            return Err(Error::new(ErrorType::Other(format!(
+                "There is a signing key at {} already",
+                path.str_lossy()
+            ))));
+        }
+
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        fs::file_open(Path::new("/dev/urandom"))?
+            .read_exact(bytes.as_mut())
+            .e_context(context)?;
+        let key = SigningKey::from_bytes(&bytes);
+
+        fs::create_dir_all(dir)?;
+        // Only the owner may read the secret key, `create_new` keeps existing keys
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .e_context(context)?;
+        let hex = Zeroizing::new(hex::encode(key.as_bytes()));
+        writeln!(file, "{}", hex.as_str()).e_context(context)?;
+
+        let public_key = key.verifying_key();
+        Self::trust(dir, "self", &public_key)?;
+
+        Ok(public_key)
+    }
+
+    /// Trusts signatures of `key` by storing it as `trusted/<name>.pub` in `dir`
+    /// # Arguments
+    /// * `dir` - The keys directory to store the key in
+    /// * `name` - The name to store the key as, must not be empty
+    ///   or contain `/` or `..`, so the key stays in the trusted keys directory
+    /// * `key` - The public key to trust
+    pub fn trust(dir: &Path, name: &str, key: &VerifyingKey) -> Result<PathBuf, Error> {
+        if name.is_empty() || name.contains('/') || name.contains("..") {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Invalid key name '{}', it has to be non-empty without '/' or '..'",
+                name.escape_debug()
+            ))));
+        }
+
+        let path = dir
+            .join(TRUSTED_KEYS_DIR)
+            .join(format!("{name}.{PUBLIC_KEY_EXTENSION}"));
+
+        fs::create_parent_dir_all(&path)?;
+        let mut file = fs::file_create(&path)?;
+        writeln!(file, "{}", hex::encode(key.as_bytes()))
+            .e_context(|| format!("Writing trusted key {}", path.str_lossy()))?;
+
+        Ok(path)
+    }
+
+    /// Returns the signing key or an error if none has been generated
+    pub fn get_signing_key(&self) -> Result<&SigningKey, Error> {
+        self.signing_key.as_ref().ok_or_else(|| {
+            Error::new(ErrorType::Other(
+                "There is no signing key, generate one first".to_owned(),
+            ))
+        })
+    }
+
+    /// Returns the trusted public keys
+    pub fn get_trusted_keys(&self) -> Vec<VerifyingKey> {
+        self.trusted_keys.iter().map(|(_, key)| *key).collect()
+    }
+
+    /// Returns the trusted public keys by their names
+    pub fn get_named_trusted_keys(&self) -> &[(String, VerifyingKey)] {
+        &self.trusted_keys
+    }
+}
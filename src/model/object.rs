@@ -5,6 +5,12 @@ use crate::{
     util::{Packable, Unpackable},
 };
 
+mod objectcache;
+pub use objectcache::*;
+
+mod objectchunk;
+pub use objectchunk::*;
+
 mod objectcompression;
 pub use objectcompression::*;
 
@@ -24,7 +30,7 @@ mod objecttype;
 pub use objecttype::*;
 
 /// A container for generic data to be handled by the AcaciaLinux system
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Object {
     /// The unique object ID calculated from the contents
     pub oid: ObjectID,
@@ -122,6 +128,11 @@ impl Object {
 
                 Box::new(xz::write::XzEncoder::new_stream(output, stream))
             }
+            ObjectCompression::Chunked => {
+                return Err(Error::new(ErrorType::Other(
+                    "Chunked objects cannot be created from a stream".to_owned(),
+                )))
+            }
         };
 
         let mut output = ObjectIDHasher::new(output, &object.dependencies);
@@ -181,6 +192,11 @@ impl Object {
 
                 Box::new(xz::write::XzEncoder::new_stream(output, stream))
             }
+            ObjectCompression::Chunked => {
+                return Err(Error::new(ErrorType::Other(
+                    "Chunked objects cannot be created from a stream".to_owned(),
+                )))
+            }
         };
 
         input
@@ -192,6 +208,35 @@ impl Object {
         Ok(object)
     }
 
+    /// Creates a chunked object whose data is the concatenation of `chunks`
+    /// # Arguments
+    /// * `oid` - The object id of the data of the chunks
+    /// * `output` - The output stream to write the object file's contents to
+    /// * `dependencies` - The dependencies of the new object
+    /// * `ty` - The type of object at hand
+    /// * `chunks` - The chunks making up the object data
+    ///
+    /// This does not verify that `oid` matches the data of the chunks
+    pub fn create_chunked<W: Write>(
+        oid: ObjectID,
+        mut output: W,
+        dependencies: Vec<ObjectID>,
+        ty: ObjectType,
+        chunks: &[ObjectChunk],
+    ) -> Result<Self, Error> {
+        let object = Self {
+            oid,
+            dependencies,
+            ty,
+            compression: ObjectCompression::Chunked,
+        };
+
+        object.pack_header(&mut output)?;
+        ObjectChunk::pack_list(chunks, &mut output).ctx(|| "Writing chunk list")?;
+
+        Ok(object)
+    }
+
     /// Packs the object header contents to `output`
     /// # Arguments
     /// * `output` - The stream to write to
@@ -0,0 +1,114 @@
+use std::{fmt::Display, sync::Arc};
+
+use indexmap::IndexMap;
+
+use super::{Object, ObjectID};
+
+/// The maximum size of the data of an object to be cached (64 KiB),
+/// larger objects are not read repeatedly often enough to be worth it
+pub static MAX_CACHED_OBJECT_SIZE: usize = 64 * 1024;
+
+/// A least-recently-used cache of the decompressed data of small objects
+/// (e.g. trees and small files), refer to [super::ObjectDB::with_cache()]
+#[derive(Debug)]
+pub struct ObjectCache {
+    /// The cached objects and their data, least recently used first
+    entries: IndexMap<ObjectID, (Object, Arc<[u8]>)>,
+    /// The maximum size of the cached data in bytes
+    capacity: usize,
+    /// The current size of the cached data in bytes
+    size: usize,
+    /// The statistics about the cache lookups
+    stats: ObjectCacheStats,
+}
+
+/// Statistics about the lookups in an [ObjectCache]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjectCacheStats {
+    /// The number of lookups that found the object in the cache
+    pub hits: u64,
+    /// The number of lookups that had to read the object from the driver
+    pub misses: u64,
+}
+
+impl ObjectCache {
+    /// Creates a new empty cache
+    /// # Arguments
+    /// * `capacity` - The maximum size of the cached data in bytes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: IndexMap::new(),
+            capacity,
+            size: 0,
+            stats: ObjectCacheStats::default(),
+        }
+    }
+
+    /// Looks up `oid`, marking it as most recently used and recording the hit or miss
+    /// # Arguments
+    /// * `oid` - The object id of the object to look up
+    pub fn get(&mut self, oid: &ObjectID) -> Option<(Object, Arc<[u8]>)> {
+        match self.entries.shift_remove(oid) {
+            Some(entry) => {
+                self.stats.hits += 1;
+                self.entries.insert(oid.clone(), entry.clone());
+                Some(entry)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Inserts an object and its data, evicting the least recently used objects
+    /// if the capacity is exceeded. Data larger than [MAX_CACHED_OBJECT_SIZE]
+    /// or the capacity is not cached
+    /// # Arguments
+    /// * `object` - The object to insert
+    /// * `data` - The decompressed data of the object
+    pub fn insert(&mut self, object: Object, data: Arc<[u8]>) {
+        if data.len() > MAX_CACHED_OBJECT_SIZE.min(self.capacity) {
+            return;
+        }
+
+        self.size += data.len();
+        if let Some((_, old)) = self.entries.insert(object.oid.clone(), (object, data)) {
+            self.size -= old.len();
+        }
+
+        while self.size > self.capacity {
+            match self.entries.shift_remove_index(0) {
+                Some((_, (_, data))) => self.size -= data.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Returns the statistics about the lookups so far
+    pub fn stats(&self) -> ObjectCacheStats {
+        self.stats
+    }
+}
+
+impl ObjectCacheStats {
+    /// Returns the share of lookups that hit the cache, `0` if there were none
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+impl Display for ObjectCacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate)",
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0
+        )
+    }
+}
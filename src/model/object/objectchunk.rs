@@ -0,0 +1,133 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+};
+
+use crate::{
+    error::{Error, ErrorExt},
+    util::{Packable, Unpackable},
+};
+
+use super::{ObjectID, ObjectReader};
+
+/// A chunk of the data of a [chunked](super::ObjectCompression::Chunked) object.
+///
+/// The chunk data is stored as an [AcaciaChunk](super::ObjectType::AcaciaChunk)
+/// object, the data of the chunked object is the concatenation of its chunks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectChunk {
+    /// The object id of the chunk object
+    pub oid: ObjectID,
+    /// The size of the chunk data in bytes
+    pub size: u64,
+}
+
+impl ObjectChunk {
+    /// Packs a list of chunks as stored in the data of a chunked object
+    /// # Arguments
+    /// * `chunks` - The chunks to pack
+    /// * `output` - The stream to write to
+    pub fn pack_list<W: std::io::Write>(
+        chunks: &[ObjectChunk],
+        output: &mut W,
+    ) -> Result<(), Error> {
+        (chunks.len() as u32)
+            .pack(output)
+            .ctx(|| "Packing chunk count")?;
+
+        for chunk in chunks {
+            chunk.pack(output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Unpacks a list of chunks as stored in the data of a chunked object
+    /// # Arguments
+    /// * `input` - The stream to read from
+    pub fn unpack_list<R: Read>(input: &mut R) -> Result<Vec<ObjectChunk>, Error> {
+        let count = u32::try_unpack(input).ctx(|| "Unpacking chunk count")?;
+
+        // The count comes from untrusted data, so only preallocate a bounded amount
+        let mut chunks = Vec::with_capacity(count.min(1024) as usize);
+        for i in 0..count {
+            chunks.push(ObjectChunk::try_unpack(input).ctx(|| format!("Unpacking chunk {i}"))?);
+        }
+
+        Ok(chunks)
+    }
+}
+
+impl Packable for ObjectChunk {
+    fn pack<W: std::io::Write>(&self, output: &mut W) -> Result<(), Error> {
+        let context = || format!("Packing chunk {}", self.oid);
+
+        self.oid.pack(output).ctx(context)?;
+        self.size.pack(output).ctx(context)?;
+
+        Ok(())
+    }
+}
+
+impl Unpackable for ObjectChunk {
+    fn unpack<R: Read>(input: &mut R) -> Result<Option<Self>, Error> {
+        let context = || "Unpacking chunk";
+
+        let oid = ObjectID::try_unpack(input).ctx(context)?;
+        let size = u64::try_unpack(input).ctx(context)?;
+
+        Ok(Some(Self { oid, size }))
+    }
+}
+
+/// A reader that reads the chunks of a chunked object one after another,
+/// opening each chunk object only once it is needed
+pub struct ChunkedReader<F: Fn(&ObjectID) -> Result<ObjectReader, Error>> {
+    /// The chunks that have not been opened yet
+    chunks: VecDeque<ObjectChunk>,
+    /// The chunk that is currently read from
+    current: Option<ObjectReader>,
+    /// The function to open chunk objects with
+    open: F,
+}
+
+impl<F: Fn(&ObjectID) -> Result<ObjectReader, Error>> ChunkedReader<F> {
+    /// Creates a new reader over `chunks`
+    /// # Arguments
+    /// * `chunks` - The chunks to read
+    /// * `open` - The function to open chunk objects with
+    pub fn new(chunks: Vec<ObjectChunk>, open: F) -> Self {
+        Self {
+            chunks: chunks.into(),
+            current: None,
+            open,
+        }
+    }
+}
+
+impl<F: Fn(&ObjectID) -> Result<ObjectReader, Error>> Read for ChunkedReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let current = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    let chunk = match self.chunks.pop_front() {
+                        Some(chunk) => chunk,
+                        None => return Ok(0),
+                    };
+
+                    let reader = (self.open)(&chunk.oid).map_err(|e| {
+                        io::Error::other(format!("Opening chunk {}: {}", chunk.oid, e.oneline()))
+                    })?;
+
+                    self.current.insert(reader)
+                }
+            };
+
+            match current.read(buf)? {
+                0 if !buf.is_empty() => self.current = None,
+                n => return Ok(n),
+            }
+        }
+    }
+}
@@ -15,6 +15,10 @@ pub enum ObjectCompression {
     None = 0,
     /// XZ compression
     Xz = 1,
+    /// The data is split into chunk objects, the object data lists the chunks.
+    /// This is a storage detail of object databases and not selectable
+    #[value(skip)]
+    Chunked = 2,
 }
 
 impl Display for ObjectCompression {
@@ -25,6 +29,7 @@ impl Display for ObjectCompression {
             match self {
                 Self::None => "none",
                 Self::Xz => "xz",
+                Self::Chunked => "chunked",
             }
         )
     }
@@ -55,6 +60,7 @@ impl Unpackable for ObjectCompression {
         Ok(match input {
             0 => Some(Self::None),
             1 => Some(Self::Xz),
+            2 => Some(Self::Chunked),
             _ => None,
         })
     }
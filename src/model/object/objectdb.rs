@@ -1,25 +1,50 @@
 use std::{
     fmt::Display,
     fs::File,
-    io::{copy, Read, Seek, SeekFrom},
-    path::Path,
+    io::{copy, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
+use ed25519_dalek::{SigningKey, VerifyingKey};
 use log::{debug, trace};
 
 use crate::{
     error::{Error, ErrorExt, ErrorType, Throwable},
+    model::Signature,
     util::fs::{self, file_create, PathUtil},
+    MAX_ODB_DEPTH,
 };
 
-use super::{Object, ObjectCompression, ObjectID, ObjectReader, ObjectType};
+use super::{
+    Object, ObjectCache, ObjectCacheStats, ObjectCompression, ObjectID, ObjectReader, ObjectType,
+    MAX_CACHED_OBJECT_SIZE,
+};
+
+mod dedup;
+pub use dedup::*;
 
 mod driver;
 pub use driver::*;
 
+mod pin;
+pub use pin::*;
+
+mod refs;
+pub use refs::*;
+
+mod stats;
+pub use stats::*;
+
 /// A database for storing AcaciaLinux objects
 pub struct ObjectDB {
     driver: Box<dyn ODBDriver>,
+    /// The cache of small objects, `None` if caching is disabled
+    cache: Option<Mutex<ObjectCache>>,
+    /// The file the pins are persisted in, `None` if pinning is not available
+    pins: Option<PathBuf>,
+    /// The file the refs are persisted in, `None` if there are no refs
+    refs: Option<PathBuf>,
 }
 
 impl ObjectDB {
@@ -27,13 +52,48 @@ impl ObjectDB {
     /// # Arguments
     /// * `driver` - The underlying driver for the odb to operate on top of
     pub fn init(driver: Box<dyn ODBDriver>) -> Result<Self, Error> {
-        Ok(Self { driver })
+        Ok(Self {
+            driver,
+            cache: None,
+            pins: None,
+            refs: None,
+        })
+    }
+
+    /// Opens the filesystem object database at `root` without ever writing to it,
+    /// refer to [FilesystemDriver::new_readonly()](odb_driver::FilesystemDriver::new_readonly())
+    /// # Arguments
+    /// * `root` - The root of the object database
+    pub fn open_readonly(root: &Path) -> Result<Self, Error> {
+        let driver = odb_driver::FilesystemDriver::new_readonly(root.to_owned())?;
+
+        Self::init(Box::new(driver))
+    }
+
+    /// Returns whether this database has been opened read-only, so inserting objects fails
+    pub fn is_readonly(&self) -> bool {
+        self.driver.is_readonly()
+    }
+
+    /// Enables caching the decompressed data of small objects that are read
+    /// repeatedly (e.g. shared subtrees when deploying), refer to [ObjectCache]
+    /// # Arguments
+    /// * `capacity` - The maximum size of the cached data in bytes
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(Mutex::new(ObjectCache::new(capacity)));
+        self
+    }
+
+    /// Returns the statistics of the object cache, `None` if caching is disabled
+    pub fn cache_stats(&self) -> Option<ObjectCacheStats> {
+        self.cache
+            .as_ref()
+            .map(|c| c.lock().expect("Lock object cache").stats())
     }
 
     /// Inserts a file and tries to infer its type and dependencies (TODO)
     ///
-    /// Currently, this function does a normal [insert_file()](ObjectDB::insert_file())
-    /// using the [Other](ObjectType::Other) object type and no dependencies
+    /// The type is inferred using [ObjectType::infer()], dependencies are not inferred yet
     /// # Arguments
     /// * `path` - The path to the file to be inserted
     /// * `compression` - The compression to use on this file
@@ -46,7 +106,23 @@ impl ObjectDB {
         path: &Path,
         compression: ObjectCompression,
     ) -> Result<Object, Error> {
-        self.insert_file(path, ObjectType::Other, compression, Vec::new())
+        let mut src_file = fs::file_open(path)?;
+
+        let ty = ObjectType::infer(&mut src_file)
+            .ctx(|| format!("Inferring object type of {}", path.str_lossy()))?;
+        src_file
+            .rewind()
+            .e_context(|| format!("Rewinding {}", path.str_lossy()))?;
+
+        let object = self.insert_stream(&mut src_file, ty, compression, Vec::new())?;
+        debug!(
+            "Inserted file {} as {} ({:?})",
+            path.str_lossy(),
+            object.oid,
+            ty
+        );
+
+        Ok(object)
     }
 
     /// Inserts a file into the database
@@ -113,7 +189,44 @@ impl ObjectDB {
     /// # Returns
     /// An [ObjectReader](super::ObjectReader) for reading object data
     pub fn read(&self, oid: &ObjectID) -> Result<ObjectReader, Error> {
-        self.driver.retrieve(oid)
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.driver.retrieve(oid),
+        };
+
+        if let Some((object, data)) = cache.lock().expect("Lock object cache").get(oid) {
+            trace!("Reading {oid} from cache");
+            return Ok(ObjectReader::from_data(object, data));
+        }
+
+        let mut reader = self.driver.retrieve(oid)?;
+
+        // Chunked objects are too large to be cached anyway
+        if reader.chunks().is_some() {
+            return Ok(reader);
+        }
+
+        let mut data = Vec::new();
+        (&mut reader)
+            .take(MAX_CACHED_OBJECT_SIZE as u64 + 1)
+            .read_to_end(&mut data)
+            .e_context(|| format!("Reading object {oid}"))?;
+
+        if data.len() > MAX_CACHED_OBJECT_SIZE {
+            let object = reader.object.clone();
+            return Ok(ObjectReader::from_read(
+                object,
+                Box::new(Cursor::new(data).chain(reader)),
+            ));
+        }
+
+        let data: Arc<[u8]> = data.into();
+        cache
+            .lock()
+            .expect("Lock object cache")
+            .insert(reader.object.clone(), data.clone());
+
+        Ok(ObjectReader::from_data(reader.object, data))
     }
 
     /// Reads an object from the database and copies it to a file
@@ -159,6 +272,39 @@ impl ObjectDB {
         }
     }
 
+    /// Returns the object ids of all objects that depend on `oid`
+    /// # Arguments
+    /// * `oid` - The object id to search the dependents of
+    pub fn reverse_dependencies(&self, oid: &ObjectID) -> Result<Vec<ObjectID>, Error> {
+        self.driver.reverse_dependencies(oid)
+    }
+
+    /// Returns whether this database keeps an index of the reverse dependencies,
+    /// refer to [ObjectDB::reverse_dependencies()]
+    pub fn has_reverse_dependencies(&self) -> bool {
+        self.driver.has_reverse_dependencies()
+    }
+
+    /// Collects statistics about all objects in this database: The number
+    /// and disk usage of objects by type, a histogram of their sizes and how much
+    /// is saved by sharing objects between dependents and by chunking
+    pub fn stats(&self) -> Result<ObjectDBStats, Error> {
+        self.driver
+            .stats()
+            .ctx(|| "Collecting object database statistics")
+    }
+
+    /// Reports how much the file objects in this database are shared between trees:
+    /// How many trees reference each file, the most shared files and the size of
+    /// the files if every tree had its own copy compared to their disk usage
+    /// # Arguments
+    /// * `top` - The number of most shared files to list
+    pub fn dedup_report(&self, top: usize) -> Result<DedupReport, Error> {
+        self.driver
+            .dedup_report(top)
+            .ctx(|| "Collecting deduplication report")
+    }
+
     /// Pulls `oid` from `other`
     /// # Arguments
     /// * `other` - The object database to pull the data from
@@ -176,6 +322,73 @@ impl ObjectDB {
             .pull(other.driver.as_ref(), oid, compression, recursive)
     }
 
+    /// Pulls `oid` from `other` only if it has been signed by one of `trusted_keys`.
+    ///
+    /// The trusted signature gets pulled along, so it can be verified locally later on.
+    /// As the object ids of the dependencies are covered by the object id of `oid`,
+    /// pulling recursively cannot inject objects that have not been signed either
+    /// # Arguments
+    /// * `other` - The object database driver to pull the data from
+    /// * `oid` - The object id of the object to pull
+    /// * `compression` - The compression to apply when inserting
+    /// * `recursive` - Whether to operate recursively
+    /// * `trusted_keys` - The public keys to accept signatures of
+    pub fn pull_verified(
+        &mut self,
+        other: &dyn ODBDriver,
+        oid: ObjectID,
+        compression: ObjectCompression,
+        recursive: bool,
+        trusted_keys: &[VerifyingKey],
+    ) -> Result<(), Error> {
+        let (signature_oid, signature) = Signature::find_trusted(other, &oid, trusted_keys)
+            .ctx(|| format!("Verifying signature of {oid}"))?
+            .ok_or_else(|| {
+                Error::new(ErrorType::ObjectDB(ObjectDBError::Untrusted(oid.clone())))
+            })?;
+
+        debug!("{oid} has been signed by {}", signature.key);
+
+        self.driver.pull(other, oid, compression, recursive)?;
+        self.driver.pull(other, signature_oid, compression, false)
+    }
+
+    /// Signs `oid` using `key` and inserts the signature
+    /// # Arguments
+    /// * `oid` - The object id of the object to sign
+    /// * `key` - The key to sign with
+    /// * `compression` - The compression to apply to the signature object
+    /// # Returns
+    /// The signature object
+    pub fn sign(
+        &mut self,
+        oid: &ObjectID,
+        key: &SigningKey,
+        compression: ObjectCompression,
+    ) -> Result<Object, Error> {
+        // Make sure the signed object exists
+        self.get_object(oid)?;
+
+        Signature::new(oid.clone(), key).insert_into_odb(self, compression)
+    }
+
+    /// Verifies that `oid` has been signed by one of `trusted_keys`
+    /// # Arguments
+    /// * `oid` - The object id of the object to verify
+    /// * `trusted_keys` - The public keys to accept signatures of
+    /// # Returns
+    /// The trusted signature
+    pub fn verify_signature(
+        &self,
+        oid: &ObjectID,
+        trusted_keys: &[VerifyingKey],
+    ) -> Result<Signature, Error> {
+        Signature::find_trusted(self.driver.as_ref(), oid, trusted_keys)
+            .ctx(|| format!("Verifying signature of {oid}"))?
+            .map(|(_, signature)| signature)
+            .ok_or_else(|| Error::new(ErrorType::ObjectDB(ObjectDBError::Untrusted(oid.clone()))))
+    }
+
     /// Pulls `oid` from `other` driver
     /// # Arguments
     /// * `other` - The object database driver to pull the data from
@@ -202,6 +415,18 @@ pub enum ObjectDBError {
         expected: ObjectID,
         received: ObjectID,
     },
+    /// An object is not of the type that was expected
+    UnexpectedObjectType {
+        oid: ObjectID,
+        expected: ObjectType,
+        received: ObjectType,
+    },
+    /// The depth of an object database is not supported
+    InvalidDepth(usize),
+    /// An object database that is read-only has been written to
+    ReadOnly(PathBuf),
+    /// An object has not been signed by a trusted key
+    Untrusted(ObjectID),
 }
 
 impl Display for ObjectDBError {
@@ -212,10 +437,28 @@ impl Display for ObjectDBError {
                 f,
                 "Object ID mismatch - expected {expected}, got {received}"
             ),
+            Self::UnexpectedObjectType {
+                oid,
+                expected,
+                received,
+            } => write!(
+                f,
+                "Object {oid} is of type {received:?}, expected {expected:?}"
+            ),
+            Self::InvalidDepth(depth) => write!(
+                f,
+                "Invalid object database depth {depth}, expected 1 to {MAX_ODB_DEPTH}"
+            ),
+            Self::Untrusted(oid) => write!(f, "Object {oid} has not been signed by a trusted key"),
+            Self::ReadOnly(root) => {
+                write!(f, "The object database @ {} is read-only", root.str_lossy())
+            }
         }
     }
 }
 
+impl std::error::Error for ObjectDBError {}
+
 impl<T> ErrorExt<T> for Result<T, ObjectDBError> {
     fn e_context<S: ToString, F: Fn() -> S>(self, context: F) -> Result<T, Error> {
         match self {
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::error::Error;
+
+use super::{Object, ObjectID, ObjectType};
+
+/// A report on how much the file objects of an object database are shared between trees
+/// (refer to [ObjectDB::dedup_report()](super::ObjectDB::dedup_report())), to quantify
+/// what storing the files by their content saves
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DedupReport {
+    /// The number of tree objects
+    pub trees: usize,
+    /// The number of distinct file objects referenced by the trees
+    pub files: usize,
+    /// The number of references from trees to file objects
+    pub references: usize,
+    /// The size of the data of the files if every referencing tree had its own copy
+    pub logical_size: u64,
+    /// The disk usage of the referenced file objects, as stored (compressed, with headers)
+    pub physical_size: u64,
+    /// The files referenced by the most trees, most referenced first
+    pub shared: Vec<SharedObject>,
+}
+
+/// A file object referenced by multiple trees
+#[derive(Serialize, Debug, Clone)]
+pub struct SharedObject {
+    /// The object id of the file
+    pub oid: ObjectID,
+    /// The number of trees referencing the file
+    pub trees: usize,
+    /// The size of the data of the file
+    pub size: u64,
+}
+
+/// Collects a [DedupReport] from the objects of an object database driver
+pub struct DedupReportCollector {
+    /// The types and disk usage of the objects
+    objects: HashMap<ObjectID, (ObjectType, u64)>,
+    /// The dependencies of the tree objects
+    trees: Vec<Vec<ObjectID>>,
+}
+
+impl DedupReportCollector {
+    /// Creates a new, empty collector
+    pub fn new() -> Self {
+        Self {
+            objects: HashMap::new(),
+            trees: Vec::new(),
+        }
+    }
+
+    /// Adds an object to the report
+    /// # Arguments
+    /// * `object` - The object to add
+    /// * `stored_size` - The disk usage of the object
+    pub fn add(&mut self, object: &Object, stored_size: u64) {
+        self.objects
+            .insert(object.oid.clone(), (object.ty, stored_size));
+
+        if object.ty == ObjectType::AcaciaTree {
+            self.trees.push(object.dependencies.clone());
+        }
+    }
+
+    /// Finishes collecting and returns the report.
+    ///
+    /// Trees reference their subtrees and the packages they depend on, too,
+    /// only references to objects that are not trees count as file references
+    /// # Arguments
+    /// * `top` - The number of most shared files to list
+    /// * `data_size` - Returns the size of the data of a file object
+    pub fn finish<F: FnMut(&ObjectID) -> Result<u64, Error>>(
+        self,
+        top: usize,
+        mut data_size: F,
+    ) -> Result<DedupReport, Error> {
+        let mut references: HashMap<&ObjectID, usize> = HashMap::new();
+        for dependencies in &self.trees {
+            for oid in dependencies {
+                match self.objects.get(oid) {
+                    Some((ObjectType::AcaciaTree, _)) | None => {}
+                    Some(_) => *references.entry(oid).or_default() += 1,
+                }
+            }
+        }
+
+        let mut report = DedupReport {
+            trees: self.trees.len(),
+            files: references.len(),
+            ..Default::default()
+        };
+
+        let mut shared = Vec::new();
+        for (oid, trees) in references {
+            let size = data_size(oid)?;
+
+            report.references += trees;
+            report.logical_size += size * trees as u64;
+            report.physical_size += self.objects.get(oid).map(|(_, s)| *s).unwrap_or(0);
+
+            if trees > 1 {
+                shared.push(SharedObject {
+                    oid: oid.clone(),
+                    trees,
+                    size,
+                });
+            }
+        }
+
+        shared.sort_by(|a, b| {
+            b.trees
+                .cmp(&a.trees)
+                .then(b.size.cmp(&a.size))
+                .then(a.oid.bytes().cmp(b.oid.bytes()))
+        });
+        shared.truncate(top);
+        report.shared = shared;
+
+        Ok(report)
+    }
+}
+
+impl Default for DedupReportCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DedupReport {
+    /// Returns how many times the data of the files is larger
+    /// than the disk usage of the file objects holding it
+    pub fn dedup_ratio(&self) -> f64 {
+        match self.physical_size {
+            0 => 1.0,
+            physical => self.logical_size as f64 / physical as f64,
+        }
+    }
+}
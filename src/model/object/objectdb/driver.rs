@@ -4,15 +4,18 @@ use log::debug;
 
 use crate::{
     error::{Error, ErrorType},
-    model::{Object, ObjectCompression, ObjectID, ObjectReader, ObjectType, SeekRead},
+    model::{Object, ObjectChunk, ObjectCompression, ObjectID, ObjectReader, ObjectType, SeekRead},
 };
 
-use super::ObjectDBError;
+use super::{DedupReport, ObjectDBError, ObjectDBStats};
 
 pub mod odb_driver {
     //! Drivers for the object database
     mod odb_fs_driver;
     pub use odb_fs_driver::*;
+
+    mod odb_http_driver;
+    pub use odb_http_driver::*;
 }
 
 /// A common trait for all object database drivers that allows layered
@@ -30,6 +33,15 @@ pub trait ODBDriver {
         compression: ObjectCompression,
     ) -> Result<Object, Error>;
 
+    /// Inserts a chunked object whose chunks already exist in the underlying object database
+    /// # Arguments
+    /// * `object` - The object to insert, the compression is ignored
+    /// * `chunks` - The chunks making up the object data
+    /// # Returns
+    /// The object that was created
+    fn insert_chunked(&mut self, object: Object, chunks: Vec<ObjectChunk>)
+        -> Result<Object, Error>;
+
     /// Retrieves an object from the object database
     /// # Arguments
     /// * `oid` - The object ID of the object to retrieve
@@ -51,6 +63,49 @@ pub trait ODBDriver {
     /// * `oid` - The object id to search for
     fn exists(&self, oid: &ObjectID) -> bool;
 
+    /// Returns whether this driver never writes, so inserting fails with [ObjectDBError::ReadOnly]
+    fn is_readonly(&self) -> bool {
+        false
+    }
+
+    /// Returns the object ids of all objects that depend on `oid`
+    /// # Arguments
+    /// * `oid` - The object id to search the dependents of
+    fn reverse_dependencies(&self, oid: &ObjectID) -> Result<Vec<ObjectID>, Error>;
+
+    /// Returns whether this driver keeps an index of the reverse dependencies,
+    /// without it [ODBDriver::reverse_dependencies()] finds no dependents
+    fn has_reverse_dependencies(&self) -> bool {
+        true
+    }
+
+    /// Collects statistics about all objects stored by this driver,
+    /// drivers that cannot enumerate their objects do not support this
+    fn stats(&self) -> Result<ObjectDBStats, Error> {
+        Err(Error::new(ErrorType::Other(
+            "The object database driver does not support collecting statistics".to_owned(),
+        )))
+    }
+
+    /// Reports how much the file objects stored by this driver are shared between trees
+    /// (see [DedupReport]), drivers that cannot enumerate their objects do not support this
+    /// # Arguments
+    /// * `top` - The number of most shared files to list
+    fn dedup_report(&self, _top: usize) -> Result<DedupReport, Error> {
+        Err(Error::new(ErrorType::Other(
+            "The object database driver does not support deduplication reports".to_owned(),
+        )))
+    }
+
+    /// Fetches the objects `oids` ahead of retrieving them one by one, drivers of
+    /// remote object databases download them concurrently. Failures are left to
+    /// retrieving the objects to report, the default does nothing
+    /// # Arguments
+    /// * `oids` - The object ids of the objects that are about to be retrieved
+    fn prefetch(&self, _oids: &[ObjectID]) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Pulls `oid` from `other`
     /// # Arguments
     /// * `other` - The object database driver to pull the data from
@@ -72,15 +127,46 @@ pub trait ODBDriver {
         } else {
             debug!("Pulling {oid}");
             let mut object = other.retrieve(&oid)?;
-            let ty = object.object.ty;
-            let dependencies = object.object.dependencies.clone();
-
-            let template = ObjectTemplate::new_prehashed(&mut object, oid, ty, dependencies);
 
-            self.insert(template, compression)?
+            match object.chunks() {
+                // Only transfer the chunks that are missing
+                Some(chunks) => {
+                    let chunks = chunks.to_vec();
+
+                    let missing: Vec<ObjectID> = chunks
+                        .iter()
+                        .map(|chunk| chunk.oid.clone())
+                        .filter(|oid| !self.exists(oid))
+                        .collect();
+                    other.prefetch(&missing)?;
+
+                    for chunk in &chunks {
+                        self.pull(other, chunk.oid.clone(), compression, false)?;
+                    }
+
+                    self.insert_chunked(object.object, chunks)?
+                }
+                None => {
+                    let ty = object.object.ty;
+                    let dependencies = object.object.dependencies.clone();
+
+                    let template =
+                        ObjectTemplate::new_prehashed(&mut object, oid, ty, dependencies);
+
+                    self.insert(template, compression)?
+                }
+            }
         };
 
         if recursive {
+            let missing: Vec<ObjectID> = object
+                .dependencies
+                .iter()
+                .filter(|oid| !self.exists(oid))
+                .cloned()
+                .collect();
+            other.prefetch(&missing)?;
+
             for dependency in object.dependencies {
                 self.pull(other, dependency, compression, recursive)?;
             }
@@ -1,28 +1,191 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fs::OpenOptions,
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use log::{debug, info, warn};
+use nix::unistd::{self, AccessFlags};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::{Error, ErrorExt},
-    model::{Object, ObjectCompression, ObjectID, ObjectReader},
-    util::fs,
-    OBJECT_FILE_EXTENSION, ODB_DEPTH,
+    error::{Error, ErrorExt, ErrorType},
+    model::{
+        DedupReport, DedupReportCollector, Object, ObjectChunk, ObjectCompression, ObjectDBError,
+        ObjectDBStats, ObjectDBStatsCollector, ObjectID, ObjectIDHasher, ObjectReader, ObjectType,
+    },
+    util::{
+        chunker::Chunker,
+        fs::{self, PathUtil},
+        Unpackable,
+    },
+    CHUNKING_THRESHOLD, MAX_ODB_DEPTH, OBJECT_FILE_EXTENSION, ODB_DEPTH,
 };
 
 use super::super::{ODBDriver, ObjectTemplate};
 
+/// The age after which temporary files are considered left behind by a crashed insertion (1 day)
+static STALE_TEMP_FILE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The name of the file in the root of the object database storing its [FilesystemDriverMeta]
+static META_FILE_NAME: &str = "odb.toml";
+
 /// Represents an object database implemented using a filesystem tree structure
+#[derive(Clone)]
 pub struct FilesystemDriver {
     root: PathBuf,
+    /// The metadata of the object database, as of opening it or the last resharding
+    meta: FilesystemDriverMeta,
+    /// The chunker to split large objects with, `None` to store objects whole
+    chunker: Option<Chunker>,
+    /// Whether the database has been opened read-only and must never be written to
+    readonly: bool,
+}
+
+/// The metadata of a filesystem object database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FilesystemDriverMeta {
+    /// The number of directory levels the object files are stored in
+    depth: usize,
+    /// The depth objects are being moved away from by an unfinished [FilesystemDriver::reshard()]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resharding_from: Option<usize>,
+}
+
+impl Default for FilesystemDriverMeta {
+    fn default() -> Self {
+        Self {
+            depth: ODB_DEPTH,
+            resharding_from: None,
+        }
+    }
 }
 
 impl FilesystemDriver {
     /// Create a new filesystem driver that uses the filesystem
-    /// to represent an object database
+    /// to represent an object database.
+    ///
+    /// Removes stale temporary files left behind by interrupted insertions.
+    /// New object databases are created with a depth of [ODB_DEPTH],
+    /// which is also assumed for existing ones without metadata
     /// # Arguments
     /// * `root` - The root to initialize the object database in
+    /// # Errors
+    /// [ObjectDBError::ReadOnly] if `root` exists, but is not writable,
+    /// open it using [FilesystemDriver::new_readonly()] instead
     pub fn new(root: PathBuf) -> Result<Self, Error> {
+        if root.exists() && unistd::access(&root, AccessFlags::W_OK).is_err() {
+            return Err(Error::new(ErrorType::ObjectDB(ObjectDBError::ReadOnly(
+                root.clone(),
+            ))))
+            .ctx(|| format!("Opening object database {}", root.str_lossy()));
+        }
+
         fs::create_dir_all(&root).ctx(|| "Creating ODB root")?;
 
-        Ok(Self { root })
+        let mut driver = Self {
+            root,
+            meta: FilesystemDriverMeta::default(),
+            chunker: None,
+            readonly: false,
+        };
+
+        let meta = match driver.read_meta()? {
+            Some(meta) => meta,
+            None => {
+                let meta = FilesystemDriverMeta::default();
+                driver.write_meta(&meta)?;
+                meta
+            }
+        };
+        driver.apply_meta(meta)?;
+
+        let removed = driver
+            .remove_stale_temp_files(STALE_TEMP_FILE_AGE)
+            .ctx(|| "Removing stale temporary files")?;
+        if removed > 0 {
+            warn!("Removed {removed} stale temporary files of interrupted insertions");
+        }
+
+        Ok(driver)
+    }
+
+    /// Opens an existing object database without ever writing to it,
+    /// e.g. on a read-only medium (ISO images, NFS snapshots).
+    ///
+    /// Inserting objects and restructuring the database fail with [ObjectDBError::ReadOnly]
+    /// and stale temporary files are left alone. Object databases without metadata
+    /// are assumed to have a depth of [ODB_DEPTH]
+    /// # Arguments
+    /// * `root` - The root of the object database
+    pub fn new_readonly(root: PathBuf) -> Result<Self, Error> {
+        let context = || format!("Opening object database {} read-only", root.str_lossy());
+
+        if !root.is_dir() {
+            return Err(Error::new(ErrorType::Other(format!(
+                "There is no object database @ {}",
+                root.str_lossy()
+            ))))
+            .ctx(context);
+        }
+
+        let mut driver = Self {
+            root: root.clone(),
+            meta: FilesystemDriverMeta::default(),
+            chunker: None,
+            readonly: true,
+        };
+
+        if let Some(meta) = driver.read_meta().ctx(context)? {
+            driver.apply_meta(meta).ctx(context)?;
+        }
+
+        debug!("Opened object database {} read-only", root.str_lossy());
+
+        Ok(driver)
+    }
+
+    /// Validates the depth of `meta` and uses it for this driver
+    /// # Arguments
+    /// * `meta` - The metadata read from the object database
+    fn apply_meta(&mut self, meta: FilesystemDriverMeta) -> Result<(), Error> {
+        if !(1..=MAX_ODB_DEPTH).contains(&meta.depth) {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::InvalidDepth(meta.depth),
+            )))
+            .ctx(|| format!("Opening object database {}", self.root.str_lossy()));
+        }
+
+        if let Some(from) = meta.resharding_from {
+            warn!(
+                "Resharding from depth {from} to {} has not finished, run it again",
+                meta.depth
+            );
+        }
+        self.meta = meta;
+
+        Ok(())
+    }
+
+    /// Returns an error if the object database has been opened read-only
+    fn ensure_writable(&self) -> Result<(), Error> {
+        if self.readonly {
+            return Err(Error::new(ErrorType::ObjectDB(ObjectDBError::ReadOnly(
+                self.root.clone(),
+            ))));
+        }
+
+        Ok(())
+    }
+
+    /// Enables storing objects of at least [CHUNKING_THRESHOLD] bytes chunked,
+    /// deduplicating the data that is shared between objects
+    /// # Arguments
+    /// * `chunker` - The chunker to split the objects with
+    pub fn with_chunking(mut self, chunker: Chunker) -> Self {
+        self.chunker = Some(chunker);
+        self
     }
 
     /// Returns the root directory
@@ -30,6 +193,11 @@ impl FilesystemDriver {
         &self.root
     }
 
+    /// Returns the number of directory levels the object files are stored in
+    pub fn get_depth(&self) -> usize {
+        self.meta.depth
+    }
+
     /// Returns the path to the internal temporary directory
     pub fn get_temp_dir(&self) -> PathBuf {
         self.get_root().join("temp")
@@ -41,12 +209,398 @@ impl FilesystemDriver {
         self.get_temp_dir().join(uuid.to_string())
     }
 
-    fn get_oid_path(&self, oid: &ObjectID) -> PathBuf {
-        let mut path = self.root.join(oid.to_path(ODB_DEPTH));
+    /// Removes the temporary files that have not been modified for `max_age`.
+    ///
+    /// Insertions write to temporary files that only get moved into place once complete,
+    /// so temporary files of insertions that have been interrupted by a crash are left behind.
+    /// Files of insertions that are still running in other processes are younger than `max_age`
+    /// # Arguments
+    /// * `max_age` - The age from which on temporary files are considered stale
+    /// # Returns
+    /// The number of removed files
+    pub fn remove_stale_temp_files(&self, max_age: Duration) -> Result<usize, Error> {
+        self.ensure_writable()?;
+
+        let temp_dir = self.get_temp_dir();
+        if !temp_dir.exists() {
+            return Ok(0);
+        }
+
+        let context = || format!("Reading temporary directory {}", temp_dir.str_lossy());
+        let now = SystemTime::now();
+
+        let mut removed = 0;
+        for entry in std::fs::read_dir(&temp_dir).e_context(context)? {
+            let entry = entry.e_context(context)?;
+            let modified = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .e_context(context)?;
+
+            // Files from the future are not stale
+            let is_stale = now.duration_since(modified).is_ok_and(|age| age >= max_age);
+
+            if is_stale && entry.file_type().e_context(context)?.is_file() {
+                debug!("Removing stale temporary file {}", entry.path().str_lossy());
+                fs::remove_file(&entry.path())?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the path to the object file for `oid`.
+    ///
+    /// While a [FilesystemDriver::reshard()] is in progress, objects that do not
+    /// exist at the depth of this driver are searched at the old depth, too
+    /// # Arguments
+    /// * `oid` - The object id to get the path for
+    pub fn get_oid_path(&self, oid: &ObjectID) -> PathBuf {
+        let path = self.get_oid_path_at(oid, self.meta.depth);
+
+        if let Some(from) = self.meta.resharding_from {
+            if !path.exists() {
+                let old = self.get_oid_path_at(oid, from);
+                if old.exists() {
+                    return old;
+                }
+            }
+        }
+
+        path
+    }
+
+    /// Returns the path to the object file for `oid` in a layout of `depth`
+    /// # Arguments
+    /// * `oid` - The object id to get the path for
+    /// * `depth` - The depth of the layout
+    fn get_oid_path_at(&self, oid: &ObjectID, depth: usize) -> PathBuf {
+        let mut path = self.root.join(oid.to_path(depth));
         path.set_extension(OBJECT_FILE_EXTENSION);
 
         path
     }
+
+    /// Reads the metadata of the object database, `None` if there is none
+    fn read_meta(&self) -> Result<Option<FilesystemDriverMeta>, Error> {
+        let path = self.root.join(META_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let meta = toml::from_str(&fs::file_read_to_string(&path)?)
+            .ctx(|| format!("Parsing object database metadata {}", path.str_lossy()))?;
+
+        Ok(Some(meta))
+    }
+
+    /// Atomically replaces the metadata of the object database
+    /// # Arguments
+    /// * `meta` - The metadata to write
+    fn write_meta(&self, meta: &FilesystemDriverMeta) -> Result<(), Error> {
+        let path = self.root.join(META_FILE_NAME);
+        let context = || format!("Writing object database metadata {}", path.str_lossy());
+
+        let content = toml::to_string(meta).ctx(context)?;
+
+        let temp_path = self.get_temp_file_path();
+        fs::create_parent_dir_all(&temp_path).ctx(context)?;
+        std::fs::write(&temp_path, content).e_context(context)?;
+        fs::rename(&temp_path, &path).ctx(context)
+    }
+
+    /// Changes the depth of the object database, moving all object files to the new layout.
+    ///
+    /// Objects stay accessible while they are moved, as lookups fall back to the old layout.
+    /// Objects are moved until none is left in another layout, so objects inserted by
+    /// processes that opened the database before get moved, too. An interrupted
+    /// resharding is finished by calling this function with the same depth again.
+    /// The reverse dependency index is not affected
+    /// # Arguments
+    /// * `depth` - The new depth, from 1 to [MAX_ODB_DEPTH]
+    /// # Returns
+    /// The number of moved object files
+    pub fn reshard(&mut self, depth: usize) -> Result<usize, Error> {
+        let context = || format!("Resharding object database to depth {depth}");
+        self.ensure_writable().ctx(context)?;
+
+        if !(1..=MAX_ODB_DEPTH).contains(&depth) {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::InvalidDepth(depth),
+            )))
+            .ctx(context);
+        }
+
+        let mut meta = self.read_meta()?.unwrap_or(self.meta);
+
+        if let Some(from) = meta.resharding_from {
+            if meta.depth != depth {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Resharding from depth {from} to {} has not finished, finish it first",
+                    meta.depth
+                ))))
+                .ctx(context);
+            }
+        } else if meta.depth != depth {
+            meta.resharding_from = Some(meta.depth);
+            meta.depth = depth;
+        }
+
+        // From now on, new objects get inserted in the new layout
+        self.write_meta(&meta).ctx(context)?;
+        self.meta = meta;
+
+        info!("Moving objects to depth {depth}");
+
+        let mut moved = 0;
+        loop {
+            let pass = self.move_objects_to_depth().ctx(context)?;
+            if pass == 0 {
+                break;
+            }
+            moved += pass;
+        }
+
+        meta.resharding_from = None;
+        self.write_meta(&meta).ctx(context)?;
+        self.meta = meta;
+
+        for entry in std::fs::read_dir(&self.root).e_context(context)? {
+            let path = entry.e_context(context)?.path();
+            if path.is_dir() && path != self.get_temp_dir() && path != self.get_rdeps_dir() {
+                remove_empty_dirs(&path).ctx(context)?;
+            }
+        }
+
+        Ok(moved)
+    }
+
+    /// Moves all object files that are not stored at the depth of this driver to it
+    /// # Returns
+    /// The number of moved object files
+    fn move_objects_to_depth(&self) -> Result<usize, Error> {
+        let mut paths = Vec::new();
+        fs::walk_dir(&self.root, true, &mut |entry| {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == OBJECT_FILE_EXTENSION) {
+                paths.push(path);
+            }
+            true
+        })
+        .ctx(|| "Walking object database")?;
+
+        let mut moved = 0;
+        for path in paths {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let oid = match ObjectID::new_from_hex(&stem) {
+                Ok(oid) => oid,
+                Err(e) => {
+                    warn!("Skipping unknown file {}: {e}", path.str_lossy());
+                    continue;
+                }
+            };
+
+            let target = self.get_oid_path_at(&oid, self.meta.depth);
+            if path == target {
+                continue;
+            }
+
+            fs::create_parent_dir_all(&target)?;
+
+            // Object ids identify the contents, so an existing target is the same object
+            let result = if target.exists() {
+                std::fs::remove_file(&path)
+            } else {
+                std::fs::rename(&path, &target)
+            };
+
+            match result {
+                Ok(()) => moved += 1,
+                // Another process may have moved the object already
+                Err(_) if !path.exists() => {}
+                Err(e) => {
+                    return Err(e).e_context(|| {
+                        format!("Moving {} to {}", path.str_lossy(), target.str_lossy())
+                    })
+                }
+            }
+        }
+
+        Ok(moved)
+    }
+
+    /// Returns the path to the directory holding the reverse dependency index
+    pub fn get_rdeps_dir(&self) -> PathBuf {
+        self.get_root().join("rdeps")
+    }
+
+    /// Returns the path to the file listing the reverse dependencies of `oid`
+    /// # Arguments
+    /// * `oid` - The object id to get the path for
+    fn get_rdeps_path(&self, oid: &ObjectID) -> PathBuf {
+        self.get_rdeps_dir().join(oid.to_path(ODB_DEPTH))
+    }
+
+    /// Records `dependent` as a reverse dependency of `oid`
+    /// # Arguments
+    /// * `oid` - The object that is depended on
+    /// * `dependent` - The object that depends on `oid`
+    fn add_reverse_dependency(&self, oid: &ObjectID, dependent: &ObjectID) -> Result<(), Error> {
+        if self.reverse_dependencies(oid)?.contains(dependent) {
+            return Ok(());
+        }
+
+        let path = self.get_rdeps_path(oid);
+        fs::create_parent_dir_all(&path)?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .ctx(|| format!("Opening reverse dependency file {}", path.str_lossy()))?;
+
+        writeln!(file, "{dependent}")
+            .ctx(|| format!("Writing reverse dependency file {}", path.str_lossy()))
+    }
+
+    /// Moves a temporary object file to its final path and records its reverse dependencies.
+    ///
+    /// The file gets synced to disk and atomically renamed into place,
+    /// so a crash can never leave a truncated object file behind
+    /// # Arguments
+    /// * `temp_file_path` - The path to the temporary object file
+    /// * `object` - The object stored in the file
+    fn commit_object(&self, temp_file_path: &Path, object: &Object) -> Result<(), Error> {
+        fs::file_open(temp_file_path)?
+            .sync_all()
+            .ctx(|| "Syncing temporary object file")?;
+
+        let file_path = self.get_oid_path_at(&object.oid, self.meta.depth);
+        fs::create_parent_dir_all(&file_path).ctx(|| "Creating object parent directory")?;
+        fs::rename(temp_file_path, &file_path).ctx(|| "Moving object file to final path")?;
+
+        for dependency in &object.dependencies {
+            self.add_reverse_dependency(dependency, &object.oid)
+                .ctx(|| format!("Recording reverse dependency of {}", object.oid))?;
+        }
+
+        Ok(())
+    }
+
+    /// Splits the data of the object file at `path` into chunk objects
+    /// # Arguments
+    /// * `path` - The path to the object file to split
+    /// * `chunker` - The chunker to split the data with
+    /// * `compression` - The compression to apply to the chunk objects
+    /// # Returns
+    /// The inserted chunks
+    fn insert_chunks(
+        &mut self,
+        path: &Path,
+        chunker: &Chunker,
+        compression: ObjectCompression,
+    ) -> Result<Vec<ObjectChunk>, Error> {
+        let reader = ObjectReader::from_stream(fs::file_open(path)?)?;
+
+        let mut chunks = Vec::new();
+        for chunk in chunker.chunks(reader) {
+            let chunk = chunk.e_context(|| "Reading chunk")?;
+            let size = chunk.len() as u64;
+
+            let mut stream = Cursor::new(chunk);
+            let template = ObjectTemplate::new(&mut stream, ObjectType::AcaciaChunk, Vec::new());
+            let object = self.insert(template, compression)?;
+
+            chunks.push(ObjectChunk {
+                oid: object.oid,
+                size,
+            });
+        }
+
+        Ok(chunks)
+    }
+
+    /// Lists all objects in the database by reading their headers
+    pub fn list_objects(&self) -> Result<Vec<Object>, Error> {
+        let mut objects = Vec::new();
+        for path in &self.list_object_paths()? {
+            let mut file = fs::file_open(path)?;
+            let object = Object::try_unpack(&mut file)
+                .ctx(|| format!("Reading object header of {}", path.str_lossy()))?;
+
+            objects.push(object);
+        }
+
+        Ok(objects)
+    }
+
+    /// Returns the paths of all object files in the database
+    fn list_object_paths(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut paths = Vec::new();
+        fs::walk_dir(&self.root, true, &mut |entry| {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == OBJECT_FILE_EXTENSION) {
+                paths.push(path);
+            }
+            true
+        })
+        .ctx(|| "Walking object database")?;
+
+        Ok(paths)
+    }
+
+    /// Rebuilds the reverse dependency index by reading every object in the database
+    /// # Returns
+    /// The number of objects that have been indexed
+    pub fn rebuild_reverse_dependencies(&self) -> Result<usize, Error> {
+        self.ensure_writable()
+            .ctx(|| "Rebuilding reverse dependency index")?;
+
+        let rdeps_dir = self.get_rdeps_dir();
+        if rdeps_dir.exists() {
+            fs::remove_dir_all(&rdeps_dir)?;
+        }
+
+        let objects = self.list_objects()?;
+
+        for object in &objects {
+            for dependency in &object.dependencies {
+                self.add_reverse_dependency(dependency, &object.oid)?;
+            }
+        }
+
+        debug!(
+            "Rebuilt reverse dependency index of {} objects",
+            objects.len()
+        );
+
+        Ok(objects.len())
+    }
+}
+
+/// Recursively removes `dir` and its subdirectories if they are empty
+/// # Arguments
+/// * `dir` - The directory to remove
+/// # Returns
+/// Whether `dir` has been removed
+fn remove_empty_dirs(dir: &Path) -> Result<bool, Error> {
+    let context = || format!("Removing empty directory {}", dir.str_lossy());
+
+    let mut empty = true;
+    for entry in std::fs::read_dir(dir).e_context(context)? {
+        let path = entry.e_context(context)?.path();
+
+        if path.is_symlink() || !path.is_dir() || !remove_empty_dirs(&path)? {
+            empty = false;
+        }
+    }
+
+    if empty {
+        fs::remove_dir(dir)?;
+    }
+
+    Ok(empty)
 }
 
 impl ODBDriver for FilesystemDriver {
@@ -55,6 +609,8 @@ impl ODBDriver for FilesystemDriver {
         object_template: ObjectTemplate,
         compression: ObjectCompression,
     ) -> Result<Object, Error> {
+        self.ensure_writable().ctx(|| "Inserting object")?;
+
         let temp_file_path = self.get_temp_file_path();
         fs::create_parent_dir_all(&temp_file_path)
             .ctx(|| "Creating temporary object file parent")?;
@@ -65,9 +621,88 @@ impl ODBDriver for FilesystemDriver {
         let object = Object::create_from_template(object_template, temp_file, compression)
             .ctx(|| "Creating object file")?;
 
-        let file_path = self.get_oid_path(&object.oid);
-        fs::create_parent_dir_all(&file_path).ctx(|| "Creating object parent directory")?;
-        fs::copy(&temp_file_path, &file_path).ctx(|| "Copying object file to final path")?;
+        // Chunks themselves are never chunked again
+        if let (Some(chunker), false) = (self.chunker, object.ty == ObjectType::AcaciaChunk) {
+            let mut reader = ObjectReader::from_stream(fs::file_open(&temp_file_path)?)?;
+            let size = io::copy(&mut reader, &mut io::sink()).ctx(|| "Determining object size")?;
+
+            if size >= CHUNKING_THRESHOLD {
+                let chunks = self
+                    .insert_chunks(&temp_file_path, &chunker, compression)
+                    .ctx(|| format!("Chunking object {}", object.oid))?;
+                fs::remove_file(&temp_file_path)?;
+
+                debug!("Storing {} in {} chunks", object.oid, chunks.len());
+
+                return self.insert_chunked(object, chunks);
+            }
+        }
+
+        self.commit_object(&temp_file_path, &object)?;
+
+        Ok(object)
+    }
+
+    fn insert_chunked(
+        &mut self,
+        object: Object,
+        chunks: Vec<ObjectChunk>,
+    ) -> Result<Object, Error> {
+        let oid = object.oid.clone();
+        let context = || format!("Inserting chunked object {oid}");
+        self.ensure_writable().ctx(context)?;
+
+        for chunk in &chunks {
+            if !self.exists(&chunk.oid) {
+                return Err(Error::new(ErrorType::ObjectDB(
+                    ObjectDBError::ObjectNotFound(chunk.oid.clone()),
+                )))
+                .ctx(context);
+            }
+        }
+
+        let temp_file_path = self.get_temp_file_path();
+        fs::create_parent_dir_all(&temp_file_path)
+            .ctx(|| "Creating temporary object file parent")?;
+
+        let temp_file =
+            fs::file_create(&temp_file_path).ctx(|| "Creating temporary object file")?;
+
+        let object = Object::create_chunked(
+            oid.clone(),
+            temp_file,
+            object.dependencies,
+            object.ty,
+            &chunks,
+        )
+        .ctx(context)?;
+
+        // Make sure the chunks make up the object
+        let driver = self.clone();
+        let mut reader =
+            ObjectReader::from_stream_chunked(fs::file_open(&temp_file_path)?, move |oid| {
+                driver.retrieve(oid)
+            })
+            .ctx(context)?;
+
+        let mut hasher = ObjectIDHasher::new(io::sink(), &object.dependencies);
+        io::copy(&mut reader, &mut hasher)
+            .ctx(|| "Hashing chunks")
+            .ctx(context)?;
+        let (_, hashed_oid) = hasher.finalize();
+
+        if hashed_oid != oid {
+            fs::remove_file(&temp_file_path)?;
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::ObjectIDMismatch {
+                    expected: oid.clone(),
+                    received: hashed_oid,
+                },
+            )))
+            .ctx(context);
+        }
+
+        self.commit_object(&temp_file_path, &object)?;
 
         Ok(object)
     }
@@ -81,8 +716,10 @@ impl ODBDriver for FilesystemDriver {
 
         let file = fs::file_open(&file_path).ctx(|| "Opening object file")?;
 
+        let driver = self.clone();
         Ok(Some(
-            ObjectReader::from_stream(file).ctx(|| "Reading object")?,
+            ObjectReader::from_stream_chunked(file, move |oid| driver.retrieve(oid))
+                .ctx(|| "Reading object")?,
         ))
     }
 
@@ -91,4 +728,73 @@ impl ODBDriver for FilesystemDriver {
 
         file_path.exists()
     }
+
+    fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn stats(&self) -> Result<ObjectDBStats, Error> {
+        let mut collector = ObjectDBStatsCollector::new();
+
+        for path in self.list_object_paths()? {
+            let context = || format!("Reading object {}", path.str_lossy());
+
+            let size = std::fs::metadata(&path).e_context(context)?.len();
+
+            // Chunked objects list their chunks in place of the data
+            let reader = ObjectReader::from_stream(fs::file_open(&path)?).ctx(context)?;
+            let chunked_size = reader
+                .chunks()
+                .map(|chunks| chunks.iter().map(|c| c.size).sum());
+
+            collector.add(&reader.object, size, chunked_size);
+        }
+
+        Ok(collector.finish())
+    }
+
+    fn dedup_report(&self, top: usize) -> Result<DedupReport, Error> {
+        let mut collector = DedupReportCollector::new();
+
+        for path in self.list_object_paths()? {
+            let context = || format!("Reading object {}", path.str_lossy());
+
+            let size = std::fs::metadata(&path).e_context(context)?.len();
+            let object = Object::try_unpack(&mut fs::file_open(&path)?).ctx(context)?;
+
+            collector.add(&object, size);
+        }
+
+        collector.finish(top, |oid| {
+            io::copy(&mut self.retrieve(oid)?, &mut io::sink())
+                .e_context(|| format!("Reading object {oid}"))
+        })
+    }
+
+    fn reverse_dependencies(&self, oid: &ObjectID) -> Result<Vec<ObjectID>, Error> {
+        let path = self.get_rdeps_path(oid);
+
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut dependents = Vec::new();
+        for line in fs::file_read_to_string(&path)?.lines() {
+            match ObjectID::new_from_hex(line) {
+                Ok(oid) => dependents.push(oid),
+                Err(e) => {
+                    return Err(Error::new_context(
+                        ErrorType::Other(format!("Failed to parse object id '{line}': {e}")),
+                        format!("Parsing reverse dependency file {}", path.str_lossy()),
+                    ))
+                }
+            }
+        }
+
+        Ok(dependents)
+    }
+
+    fn has_reverse_dependencies(&self) -> bool {
+        self.get_rdeps_dir().is_dir()
+    }
 }
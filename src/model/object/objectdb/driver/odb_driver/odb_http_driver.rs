@@ -0,0 +1,247 @@
+use std::path::PathBuf;
+
+use curl::easy::Easy;
+use http::StatusCode;
+use log::debug;
+
+use crate::{
+    error::{support::CURLError, Error, ErrorExt, ErrorType},
+    model::{Object, ObjectChunk, ObjectCompression, ObjectDBError, ObjectID, ObjectReader},
+    util::{
+        download::{
+            download, download_to_file_resume, download_to_file_resume_with_progress, with_retries,
+            DownloadManager,
+        },
+        fs,
+    },
+};
+
+use super::super::{ODBDriver, ObjectTemplate};
+
+/// Represents a remote object database served over HTTP (`trunk serve`)
+///
+/// Objects are downloaded to a local directory first to be able to resume
+/// interrupted transfers. This driver is read-only.
+/// Reverse dependencies are listed by the server without downloading the dependents.
+/// Objects that are about to be pulled are downloaded concurrently (see [ODBDriver::prefetch()]).
+#[derive(Clone)]
+pub struct HTTPDriver {
+    url: String,
+    download_dir: PathBuf,
+    downloads: DownloadManager,
+}
+
+impl HTTPDriver {
+    /// Create a new driver that pulls objects from a remote object database
+    /// # Arguments
+    /// * `url` - The base URL of the server (`http://host:8080`)
+    /// * `download_dir` - The directory to keep (partial) downloads in
+    pub fn new(url: &str, download_dir: PathBuf) -> Result<Self, Error> {
+        fs::create_dir_all(&download_dir).ctx(|| "Creating download directory")?;
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            download_dir,
+            downloads: DownloadManager::default(),
+        })
+    }
+
+    /// Uses `downloads` for downloading objects concurrently instead of the default
+    /// # Arguments
+    /// * `downloads` - The download manager to use
+    pub fn with_downloads(mut self, downloads: DownloadManager) -> Self {
+        self.downloads = downloads;
+        self
+    }
+
+    /// Returns the base URL of the server
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the URL to retrieve the object file for `oid` from
+    /// # Arguments
+    /// * `oid` - The object id to get the URL for
+    pub fn get_oid_url(&self, oid: &ObjectID) -> String {
+        format!("{}/objects/{}", self.url, oid)
+    }
+
+    /// Returns the URL of the ref `name` of the server
+    /// # Arguments
+    /// * `name` - The name of the ref
+    pub fn get_ref_url(&self, name: &str) -> String {
+        format!("{}/refs/{}", self.url, name)
+    }
+
+    /// Returns the object id the ref `name` of the server points to
+    /// # Arguments
+    /// * `name` - The name of the ref
+    pub fn get_ref(&self, name: &str) -> Result<Option<ObjectID>, Error> {
+        let url = self.get_ref_url(name);
+        let mut body = Vec::new();
+
+        let status = download(&url, &format!("Fetching ref {name}"), false, |data| {
+            body.extend_from_slice(data);
+            true
+        })?;
+
+        match status {
+            StatusCode::OK => {
+                let body = String::from_utf8_lossy(&body);
+                ObjectID::new_from_hex(body.trim()).map(Some).map_err(|e| {
+                    Error::new(ErrorType::Other(format!(
+                        "Invalid object id for ref {name} from {url}: {e}"
+                    )))
+                })
+            }
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(Error::new_context(
+                ErrorType::CURL(CURLError::ErrorStatus(status)),
+                format!("Fetching ref {name} from {url}"),
+            )),
+        }
+    }
+
+    /// Returns the path the object file for `oid` gets downloaded to
+    /// # Arguments
+    /// * `oid` - The object id to get the path for
+    fn get_download_path(&self, oid: &ObjectID) -> PathBuf {
+        self.download_dir.join(format!("{oid}.part"))
+    }
+}
+
+impl ODBDriver for HTTPDriver {
+    fn insert(
+        &mut self,
+        _object_template: ObjectTemplate,
+        _compression: ObjectCompression,
+    ) -> Result<Object, Error> {
+        Err(Error::new(ErrorType::ObjectDB(ObjectDBError::ReadOnly(
+            PathBuf::from(&self.url),
+        ))))
+    }
+
+    fn insert_chunked(
+        &mut self,
+        _object: Object,
+        _chunks: Vec<ObjectChunk>,
+    ) -> Result<Object, Error> {
+        Err(Error::new(ErrorType::ObjectDB(ObjectDBError::ReadOnly(
+            PathBuf::from(&self.url),
+        ))))
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn try_retrieve(&self, oid: &ObjectID) -> Result<Option<ObjectReader>, Error> {
+        let url = self.get_oid_url(oid);
+        let path = self.get_download_path(oid);
+
+        let status = download_to_file_resume(&url, &path, &format!("Fetching object {oid}"))?;
+
+        match status {
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT => {}
+            // The download has been completed in an earlier attempt
+            StatusCode::RANGE_NOT_SATISFIABLE => debug!("Object {oid} is already downloaded"),
+            StatusCode::NOT_FOUND => {
+                fs::remove_file(&path)?;
+                return Ok(None);
+            }
+            status => {
+                return Err(Error::new_context(
+                    ErrorType::CURL(CURLError::ErrorStatus(status)),
+                    format!("Fetching object {oid} from {url}"),
+                ))
+            }
+        }
+
+        let file = fs::file_open(&path).ctx(|| "Opening downloaded object file")?;
+
+        // The open file handle stays valid, a failed verification restarts the download
+        fs::remove_file(&path)?;
+
+        // Chunks of chunked objects are fetched once they are read
+        let driver = self.clone();
+        let reader = ObjectReader::from_stream_chunked(file, move |oid| driver.retrieve(oid))
+            .ctx(|| "Reading object")?;
+
+        if &reader.object.oid != oid {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::ObjectIDMismatch {
+                    expected: oid.clone(),
+                    received: reader.object.oid,
+                },
+            )));
+        }
+
+        Ok(Some(reader))
+    }
+
+    fn prefetch(&self, oids: &[ObjectID]) -> Result<(), Error> {
+        // A single object gets downloaded when it is retrieved
+        if oids.len() < 2 {
+            return Ok(());
+        }
+
+        // Completed downloads are picked up by `try_retrieve()`, which reports failures
+        self.downloads
+            .run("Fetching objects", oids, |oid, callback| {
+                let url = self.get_oid_url(oid);
+                let path = self.get_download_path(oid);
+                let message = format!("Fetching object {oid}");
+
+                if let Err(e) =
+                    download_to_file_resume_with_progress(&url, &path, &message, callback)
+                {
+                    debug!("Prefetching object {oid} failed: {}", e.oneline());
+                }
+
+                Ok(())
+            })?;
+
+        Ok(())
+    }
+
+    fn reverse_dependencies(&self, oid: &ObjectID) -> Result<Vec<ObjectID>, Error> {
+        let url = format!("{}/rdeps/{}", self.url, oid);
+        let message = format!("Fetching reverse dependencies of {oid}");
+
+        let body = with_retries(&message, || {
+            let mut body = Vec::new();
+            download(&url, &message, true, |data| {
+                body.extend_from_slice(data);
+                true
+            })?;
+            Ok(body)
+        })?;
+
+        let mut dependents = Vec::new();
+        for line in String::from_utf8_lossy(&body).lines() {
+            match ObjectID::new_from_hex(line) {
+                Ok(oid) => dependents.push(oid),
+                Err(e) => {
+                    return Err(Error::new_context(
+                        ErrorType::Other(format!("Failed to parse object id '{line}': {e}")),
+                        format!("Fetching reverse dependencies from {url}"),
+                    ))
+                }
+            }
+        }
+
+        Ok(dependents)
+    }
+
+    fn exists(&self, oid: &ObjectID) -> bool {
+        let mut easy = Easy::new();
+
+        let res = easy
+            .url(&self.get_oid_url(oid))
+            .and_then(|_| easy.nobody(true))
+            .and_then(|_| easy.perform())
+            .and_then(|_| easy.response_code());
+
+        matches!(res, Ok(200))
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    util::fs::{self, PathUtil},
+};
+
+use super::{ObjectDB, ObjectID};
+
+/// An object that has been pinned to keep it (and its dependencies)
+/// from being garbage collected, refer to [ObjectDB::pin()]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    /// The object id of the pinned object
+    pub oid: ObjectID,
+    /// Why the object has been pinned, e.g. `release-1.0`
+    pub label: String,
+}
+
+impl ObjectDB {
+    /// Persists the pins of this object database in the file at `path`,
+    /// usually [Home::get_pins_path()](crate::model::Home::get_pins_path()).
+    ///
+    /// Without a pins file, pinning fails and there are no pins
+    /// # Arguments
+    /// * `path` - The path to the pins file
+    pub fn with_pins(mut self, path: PathBuf) -> Self {
+        self.pins = Some(path);
+        self
+    }
+
+    /// Pins `oid`, so it is a root for garbage collection.
+    ///
+    /// An object can be pinned multiple times using different labels,
+    /// pinning it again with the same label does nothing
+    /// # Arguments
+    /// * `oid` - The object id of the object to pin, has to exist in this database
+    /// * `label` - Why the object gets pinned, must not contain tabs or newlines
+    pub fn pin(&mut self, oid: &ObjectID, label: &str) -> Result<(), Error> {
+        let path = self.get_pins_path()?;
+
+        if label.is_empty() || label.contains(['\t', '\n']) {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Invalid pin label '{}', it has to be non-empty without tabs or newlines",
+                label.escape_debug()
+            ))));
+        }
+        self.get_object(oid).ctx(|| format!("Pinning {oid}"))?;
+
+        let mut pins = read_pins(path)?;
+        if pins.iter().any(|p| &p.oid == oid && p.label == label) {
+            return Ok(());
+        }
+
+        pins.push(Pin {
+            oid: oid.clone(),
+            label: label.to_owned(),
+        });
+        write_pins(path, &pins)?;
+
+        debug!("Pinned {oid} as '{label}'");
+        Ok(())
+    }
+
+    /// Removes the pins of `oid`
+    /// # Arguments
+    /// * `oid` - The object id of the pinned object
+    /// * `label` - Only remove the pin with this label, all pins of `oid` if `None`
+    /// # Returns
+    /// The number of removed pins
+    pub fn unpin(&mut self, oid: &ObjectID, label: Option<&str>) -> Result<usize, Error> {
+        let path = self.get_pins_path()?;
+
+        let mut pins = read_pins(path)?;
+        let len = pins.len();
+        pins.retain(|p| &p.oid != oid || label.is_some_and(|l| l != p.label));
+
+        let removed = len - pins.len();
+        if removed > 0 {
+            write_pins(path, &pins)?;
+            debug!("Removed {removed} pins of {oid}");
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the pins in the order they have been created
+    pub fn get_pins(&self) -> Result<Vec<Pin>, Error> {
+        match &self.pins {
+            Some(path) => read_pins(path),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the roots for garbage collection: Every object reachable
+    /// from one of these through its dependencies has to be kept.
+    ///
+    /// These are the pinned objects and the objects the persisted refs
+    /// (refer to [ObjectDB::with_refs()]) point to
+    pub fn get_gc_roots(&self) -> Result<Vec<ObjectID>, Error> {
+        let mut roots: Vec<ObjectID> = Vec::new();
+
+        for pin in self.get_pins()? {
+            if !roots.contains(&pin.oid) {
+                roots.push(pin.oid);
+            }
+        }
+
+        let mut refs: Vec<ObjectID> = self.get_refs()?.into_values().collect();
+        refs.sort_by_key(|oid| oid.to_hex_str());
+        for oid in refs {
+            if !roots.contains(&oid) {
+                roots.push(oid);
+            }
+        }
+
+        Ok(roots)
+    }
+
+    /// Returns the path to the pins file or an error if there is none
+    fn get_pins_path(&self) -> Result<&Path, Error> {
+        self.pins.as_deref().ok_or_else(|| {
+            Error::new(ErrorType::Other(
+                "The object database has no pins file to persist pins in".to_owned(),
+            ))
+        })
+    }
+}
+
+/// Reads the pins file at `path`, a missing file has no pins.
+///
+/// Every line holds a pin in the form `<OID>\t<LABEL>`
+/// # Arguments
+/// * `path` - The path to the pins file
+fn read_pins(path: &Path) -> Result<Vec<Pin>, Error> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut pins = Vec::new();
+    for (i, line) in fs::file_read_to_string(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let pin = line.split_once('\t').and_then(|(oid, label)| {
+            Some(Pin {
+                oid: ObjectID::new_from_hex(oid).ok()?,
+                label: label.to_owned(),
+            })
+        });
+
+        match pin {
+            Some(pin) => pins.push(pin),
+            None => {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Invalid pin in {}:{}: '{line}'",
+                    path.str_lossy(),
+                    i + 1
+                ))))
+            }
+        }
+    }
+
+    Ok(pins)
+}
+
+/// Replaces the pins file at `path` with `pins`.
+///
+/// The new file gets written next to the old one and renamed over it,
+/// so the file never holds only a part of the pins
+/// # Arguments
+/// * `path` - The path to the pins file
+/// * `pins` - The pins to write
+fn write_pins(path: &Path, pins: &[Pin]) -> Result<(), Error> {
+    let content: String = pins
+        .iter()
+        .map(|p| format!("{}\t{}\n", p.oid, p.label))
+        .collect();
+
+    let temp = path.with_extension("new");
+    fs::create_parent_dir_all(path)?;
+    std::fs::write(&temp, content).e_context(|| format!("Writing {}", temp.str_lossy()))?;
+    fs::rename(&temp, path)
+}
@@ -0,0 +1,88 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    util::fs::{self, PathUtil},
+};
+
+use super::{ObjectDB, ObjectID};
+
+impl ObjectDB {
+    /// Takes the refs persisted in the file at `path` into account, usually
+    /// [Home::get_refs_path()](crate::model::Home::get_refs_path()): The objects
+    /// they point to are roots for garbage collection (refer to [ObjectDB::get_gc_roots()])
+    /// # Arguments
+    /// * `path` - The path to the refs file
+    pub fn with_refs(mut self, path: PathBuf) -> Self {
+        self.refs = Some(path);
+        self
+    }
+
+    /// Returns the persisted refs, none if there is no refs file
+    pub fn get_refs(&self) -> Result<HashMap<String, ObjectID>, Error> {
+        match &self.refs {
+            Some(path) => read_refs(path),
+            None => Ok(HashMap::new()),
+        }
+    }
+}
+
+/// Reads the refs file at `path`, a missing file has no refs.
+///
+/// Every line holds a ref in the form `<NAME> <OID>`
+/// # Arguments
+/// * `path` - The path to the refs file
+pub fn read_refs(path: &Path) -> Result<HashMap<String, ObjectID>, Error> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let mut refs = HashMap::new();
+    for (i, line) in fs::file_read_to_string(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry = line
+            .rsplit_once(' ')
+            .and_then(|(name, oid)| Some((name.to_owned(), ObjectID::new_from_hex(oid).ok()?)));
+
+        match entry {
+            Some((name, oid)) => {
+                refs.insert(name, oid);
+            }
+            None => {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Invalid ref in {}:{}: '{line}'",
+                    path.str_lossy(),
+                    i + 1
+                ))))
+            }
+        }
+    }
+
+    Ok(refs)
+}
+
+/// Replaces the refs file at `path` with `refs`, sorted by their names.
+///
+/// The new file gets written next to the old one and renamed over it,
+/// so the file never holds only a part of the refs
+/// # Arguments
+/// * `path` - The path to the refs file
+/// * `refs` - The refs to write
+pub fn write_refs(path: &Path, refs: &HashMap<String, ObjectID>) -> Result<(), Error> {
+    let mut lines: Vec<String> = refs
+        .iter()
+        .map(|(name, oid)| format!("{name} {oid}\n"))
+        .collect();
+    lines.sort();
+
+    let temp = path.with_extension("new");
+    fs::create_parent_dir_all(path)?;
+    std::fs::write(&temp, lines.concat()).e_context(|| format!("Writing {}", temp.str_lossy()))?;
+    fs::rename(&temp, path)
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use super::{Object, ObjectCompression, ObjectID, ObjectType};
+
+/// The upper bounds of the buckets of [ObjectDBStats::size_histogram],
+/// the last bucket holds all larger objects
+static SIZE_BUCKETS: [u64; 9] = [
+    1 << 10,
+    4 << 10,
+    16 << 10,
+    64 << 10,
+    256 << 10,
+    1 << 20,
+    4 << 20,
+    16 << 20,
+    64 << 20,
+];
+
+/// Statistics about the objects stored in an object database
+/// (refer to [ObjectDB::stats()](super::ObjectDB::stats())), e.g. for capacity planning
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ObjectDBStats {
+    /// The number of objects
+    pub objects: usize,
+    /// The disk usage of all objects in bytes, as stored (compressed, with headers)
+    pub stored_size: u64,
+    /// The number and disk usage of the objects by their type
+    pub types: IndexMap<String, ObjectTypeStats>,
+    /// The number of objects by their disk usage
+    pub size_histogram: Vec<SizeBucket>,
+    /// The disk usage if every dependent of an object had its own copy of it,
+    /// compare with `stored_size` to get the deduplication by object ids
+    pub referenced_size: u64,
+    /// The size of the data of the chunked objects
+    pub chunked_size: u64,
+    /// The disk usage of the chunk objects holding the chunked data,
+    /// compare with `chunked_size` to get the deduplication by chunking
+    pub chunks_stored_size: u64,
+}
+
+/// The number and disk usage of the objects of a type
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ObjectTypeStats {
+    /// The number of objects
+    pub count: usize,
+    /// The disk usage in bytes
+    pub stored_size: u64,
+}
+
+/// A bucket of the size histogram of an object database
+#[derive(Serialize, Debug, Clone)]
+pub struct SizeBucket {
+    /// The largest size in bytes counted in this bucket, `None` for the unbounded last one
+    pub up_to: Option<u64>,
+    /// The number of objects in this bucket
+    pub count: usize,
+}
+
+/// Collects [ObjectDBStats] from the objects of an object database driver
+pub struct ObjectDBStatsCollector {
+    /// The statistics collected so far
+    stats: ObjectDBStats,
+    /// The disk usage of the objects
+    sizes: HashMap<ObjectID, u64>,
+    /// The number of dependents of the objects
+    references: HashMap<ObjectID, u64>,
+}
+
+impl ObjectDBStatsCollector {
+    /// Creates a new, empty collector
+    pub fn new() -> Self {
+        let size_histogram = SIZE_BUCKETS
+            .iter()
+            .map(|b| Some(*b))
+            .chain([None])
+            .map(|up_to| SizeBucket { up_to, count: 0 })
+            .collect();
+
+        Self {
+            stats: ObjectDBStats {
+                size_histogram,
+                ..Default::default()
+            },
+            sizes: HashMap::new(),
+            references: HashMap::new(),
+        }
+    }
+
+    /// Adds an object to the statistics
+    /// # Arguments
+    /// * `object` - The object to add
+    /// * `stored_size` - The disk usage of the object
+    /// * `chunked_size` - The size of the data of the object if it is chunked
+    pub fn add(&mut self, object: &Object, stored_size: u64, chunked_size: Option<u64>) {
+        let stats = &mut self.stats;
+
+        stats.objects += 1;
+        stats.stored_size += stored_size;
+
+        let ty = stats.types.entry(format!("{:?}", object.ty)).or_default();
+        ty.count += 1;
+        ty.stored_size += stored_size;
+
+        if object.ty == ObjectType::AcaciaChunk {
+            stats.chunks_stored_size += stored_size;
+        }
+        if object.compression == ObjectCompression::Chunked {
+            stats.chunked_size += chunked_size.unwrap_or_default();
+        }
+
+        let bucket = SIZE_BUCKETS
+            .iter()
+            .position(|b| stored_size <= *b)
+            .unwrap_or(SIZE_BUCKETS.len());
+        stats.size_histogram[bucket].count += 1;
+
+        self.sizes.insert(object.oid.clone(), stored_size);
+        for dependency in &object.dependencies {
+            *self.references.entry(dependency.clone()).or_default() += 1;
+        }
+    }
+
+    /// Finishes collecting and returns the statistics
+    pub fn finish(mut self) -> ObjectDBStats {
+        self.stats.types.sort_keys();
+
+        // Objects nothing depends on count once
+        self.stats.referenced_size = self
+            .sizes
+            .iter()
+            .map(|(oid, size)| size * self.references.get(oid).copied().unwrap_or(1).max(1))
+            .sum();
+
+        self.stats
+    }
+}
+
+impl Default for ObjectDBStatsCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ObjectDBStats {
+    /// Returns how many times the stored data would be larger
+    /// if every dependent of an object had its own copy of it
+    pub fn dedup_ratio(&self) -> f64 {
+        ratio(self.referenced_size, self.stored_size)
+    }
+
+    /// Returns how many times the data of the chunked objects
+    /// is larger than the chunk objects holding it
+    pub fn chunk_dedup_ratio(&self) -> f64 {
+        ratio(self.chunked_size, self.chunks_stored_size)
+    }
+}
+
+/// Returns `a / b`, `1` if `b` is `0`
+/// # Arguments
+/// * `a` - The dividend
+/// * `b` - The divisor
+fn ratio(a: u64, b: u64) -> f64 {
+    match b {
+        0 => 1.0,
+        b => a as f64 / b as f64,
+    }
+}
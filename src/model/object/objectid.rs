@@ -8,6 +8,7 @@ use std::{
 use hex::FromHexError;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 
 use crate::{
     error::{Error, ErrorExt},
@@ -96,6 +97,15 @@ impl ObjectID {
         &self.hash
     }
 
+    /// Compares this object id to `other` in constant time, so the time taken
+    /// does not reveal how many leading bytes match. Use this instead of `==`
+    /// where object ids are matched against untrusted input (e.g. signatures)
+    /// # Arguments
+    /// * `other` - The object id to compare to
+    pub fn ct_eq(&self, other: &ObjectID) -> bool {
+        self.hash.ct_eq(&other.hash).into()
+    }
+
     /// Constructs a path for this object id and a depth:
     ///
     /// - `abcdef` => `abcdef` (depth = 1)
@@ -1,11 +1,14 @@
-use std::io::{Read, Seek};
+use std::{
+    io::{Cursor, Read, Seek},
+    sync::Arc,
+};
 
 use crate::{
-    error::{Error, ErrorExt},
+    error::{Error, ErrorExt, ErrorType},
     util::Unpackable,
 };
 
-use super::{Object, ObjectCompression};
+use super::{ChunkedReader, Object, ObjectChunk, ObjectCompression, ObjectID};
 
 /// A wrapper for reading (possibly) compressed object data from an object
 pub struct ObjectReader {
@@ -13,24 +16,84 @@ pub struct ObjectReader {
     pub object: Object,
     /// The read stream
     read: Box<dyn Read>,
+    /// The chunks of the object if it is chunked
+    chunks: Option<Vec<ObjectChunk>>,
 }
 
 pub trait SeekRead: Seek + Read {}
 impl<T: Seek + Read> SeekRead for T {}
 
 impl ObjectReader {
-    /// Parses object data from a stream and constructs a reader
+    /// Parses object data from a stream and constructs a reader.
+    ///
+    /// Chunked objects cannot be read using this reader, use
+    /// [from_stream_chunked()](ObjectReader::from_stream_chunked) for them
+    /// # Arguments
+    /// * `read` - The input stream to read from
+    pub fn from_stream<R: SeekRead + 'static>(read: R) -> Result<Self, Error> {
+        Self::from_stream_chunked(read, |oid| {
+            Err(Error::new(ErrorType::Other(format!(
+                "Cannot resolve chunk {oid} without an object database"
+            ))))
+        })
+    }
+
+    /// Parses object data from a stream and constructs a reader,
+    /// reading the chunks of chunked objects using `open`
     /// # Arguments
     /// * `read` - The input stream to read from
-    pub fn from_stream<R: SeekRead + 'static>(mut read: R) -> Result<Self, Error> {
+    /// * `open` - The function to open chunk objects with
+    pub fn from_stream_chunked<R, F>(mut read: R, open: F) -> Result<Self, Error>
+    where
+        R: SeekRead + 'static,
+        F: Fn(&ObjectID) -> Result<ObjectReader, Error> + 'static,
+    {
         let object = Object::try_unpack(&mut read).e_context(|| "Unpacking object")?;
 
+        let mut chunks = None;
+
         let read: Box<dyn Read> = match object.compression {
             ObjectCompression::None => Box::new(read),
             ObjectCompression::Xz => Box::new(xz::read::XzDecoder::new(read)),
+            ObjectCompression::Chunked => {
+                let list = ObjectChunk::unpack_list(&mut read)
+                    .ctx(|| format!("Reading chunks of {}", object.oid))?;
+                chunks = Some(list.clone());
+
+                Box::new(ChunkedReader::new(list, open))
+            }
         };
 
-        Ok(Self { object, read })
+        Ok(Self {
+            object,
+            read,
+            chunks,
+        })
+    }
+
+    /// Constructs a reader that reads the decompressed object data from `read`
+    /// # Arguments
+    /// * `object` - The object the data belongs to
+    /// * `read` - The stream of the decompressed data
+    pub fn from_read(object: Object, read: Box<dyn Read>) -> Self {
+        Self {
+            object,
+            read,
+            chunks: None,
+        }
+    }
+
+    /// Constructs a reader for already decompressed object data
+    /// # Arguments
+    /// * `object` - The object the data belongs to
+    /// * `data` - The decompressed data of the object
+    pub fn from_data(object: Object, data: Arc<[u8]>) -> Self {
+        Self::from_read(object, Box::new(Cursor::new(data)))
+    }
+
+    /// Returns the chunks of the object, if it is chunked
+    pub fn chunks(&self) -> Option<&[ObjectChunk]> {
+        self.chunks.as_deref()
     }
 }
 
@@ -1,19 +1,25 @@
 use std::io::{Read, Seek};
 
-use tooling_codegen::IntoU16;
+use infer::MatcherType;
 
 use crate::{
     error::{Error, ErrorExt},
     util::{Packable, ReprU16, Unpackable},
 };
 
-/// The types of objects supported
+/// The types of objects supported.
+///
+/// Types this version does not know of are kept as [ObjectType::Unknown] and written back
+/// unchanged, so objects of types added by newer versions can still be pulled, copied and served
 #[repr(u16)]
-#[derive(Clone, Copy, Debug, IntoU16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ObjectType {
     /// Any other object
     Other = 0,
 
+    /// An Acacia specific repository index object
+    AcaciaRepositoryIndex = 0x0110,
+
     /// An Acacia specific formula object
     AcaciaFormula = 0x0120,
 
@@ -22,16 +28,142 @@ pub enum ObjectType {
 
     /// An Acacia specific tree object
     AcaciaTree = 0x0150,
+
+    /// A chunk of the data of a chunked object
+    AcaciaChunk = 0x0160,
+
+    /// Key/value annotations attached to another object
+    AcaciaAnnotation = 0x0170,
+
+    /// The receipt of how a package has been built
+    AcaciaBuildReceipt = 0x0180,
+
+    /// A detached signature of another object
+    AcaciaSignature = 0x0190,
+
+    /// An index of the files of a set of packages
+    AcaciaFileIndex = 0x01A0,
+
+    /// An ELF file (executable, shared object, object file)
+    ELF = 0x0210,
+
+    /// A script with an interpreter line (`#!`)
+    Script = 0x0220,
+
+    /// An archive or compressed file
+    Archive = 0x0230,
+
+    /// A UTF-8 text file
+    Text = 0x0240,
+
+    /// A type that is not known to this version, holding its raw value
+    Unknown(u16),
 }
 
+/// The amount of bytes to inspect when inferring the type of an object
+static INFER_SIZE: u64 = 8192;
+
 impl ObjectType {
     /// Infers the object type from the supplied seekable stream
     /// # Arguments
     /// * `path` - The path to the file to infer the object type of
     ///
     /// This will seek `input` and leave it in a possibly random position
-    pub fn infer<R: Read + Seek>(_input: &mut R) -> Result<Self, Error> {
-        Ok(Self::Other)
+    pub fn infer<R: Read + Seek>(input: &mut R) -> Result<Self, Error> {
+        let mut buf = Vec::new();
+        input
+            .take(INFER_SIZE)
+            .read_to_end(&mut buf)
+            .e_context(|| "Reading data to infer the object type")?;
+
+        let ty = if buf.is_empty() {
+            Self::Other
+        } else if infer::app::is_elf(&buf) {
+            Self::ELF
+        } else if infer::text::is_shellscript(&buf) {
+            Self::Script
+        } else if infer::get(&buf).is_some_and(|t| t.matcher_type() == MatcherType::Archive) {
+            Self::Archive
+        } else if is_text(&buf) {
+            Self::Text
+        } else {
+            Self::Other
+        };
+
+        Ok(ty)
+    }
+}
+
+/// Checks if `buf` looks like the start of a UTF-8 text: It has to be valid UTF-8,
+/// except for a character cut off at the end, and must not contain `NUL` bytes
+/// # Arguments
+/// * `buf` - The data to check
+fn is_text(buf: &[u8]) -> bool {
+    if buf.contains(&0) {
+        return false;
+    }
+
+    match std::str::from_utf8(buf) {
+        Ok(_) => true,
+        // `error_len()` is `None` if the input ended within a character
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+impl ObjectType {
+    /// Returns whether this type is known to this version
+    pub fn is_known(&self) -> bool {
+        !matches!(self, Self::Unknown(_))
+    }
+
+    /// Returns the class (namespace) of this type, the upper byte of its value
+    /// (`0x01` for AcaciaLinux specific objects, `0x02` for files).
+    /// This is known for unknown types, too
+    pub fn get_class(&self) -> u8 {
+        (self.into_u16() >> 8) as u8
+    }
+}
+
+impl ReprU16 for ObjectType {
+    fn into_u16(&self) -> u16 {
+        match self {
+            Self::Other => 0,
+            Self::AcaciaRepositoryIndex => 0x0110,
+            Self::AcaciaFormula => 0x0120,
+            Self::AcaciaIndex => 0x0140,
+            Self::AcaciaTree => 0x0150,
+            Self::AcaciaChunk => 0x0160,
+            Self::AcaciaAnnotation => 0x0170,
+            Self::AcaciaBuildReceipt => 0x0180,
+            Self::AcaciaSignature => 0x0190,
+            Self::AcaciaFileIndex => 0x01A0,
+            Self::ELF => 0x0210,
+            Self::Script => 0x0220,
+            Self::Archive => 0x0230,
+            Self::Text => 0x0240,
+            Self::Unknown(ty) => *ty,
+        }
+    }
+
+    /// Never returns `None`, unknown types are returned as [ObjectType::Unknown]
+    fn from_u16(num: u16) -> Option<Self> {
+        Some(match num {
+            0 => Self::Other,
+            0x0110 => Self::AcaciaRepositoryIndex,
+            0x0120 => Self::AcaciaFormula,
+            0x0140 => Self::AcaciaIndex,
+            0x0150 => Self::AcaciaTree,
+            0x0160 => Self::AcaciaChunk,
+            0x0170 => Self::AcaciaAnnotation,
+            0x0180 => Self::AcaciaBuildReceipt,
+            0x0190 => Self::AcaciaSignature,
+            0x01A0 => Self::AcaciaFileIndex,
+            0x0210 => Self::ELF,
+            0x0220 => Self::Script,
+            0x0230 => Self::Archive,
+            0x0240 => Self::Text,
+            ty => Self::Unknown(ty),
+        })
     }
 }
 
@@ -0,0 +1,487 @@
+//! Data structures for indexing the formulae and packages available in a repository
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    package::info::PackageInfo,
+    util::{
+        architecture::Architecture,
+        parse::{spdx::SpdxExpression, versionstring::compare_versions},
+    },
+};
+
+use super::{Formula, Object, ObjectCompression, ObjectDB, ObjectDBError, ObjectID, ObjectType};
+
+/// An index of the formulae and packages available in a repository,
+/// keyed by their name, version and architecture
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct RepositoryIndex {
+    /// The entries in the index, sorted by name, version and architecture
+    pub entries: Vec<RepositoryIndexEntry>,
+}
+
+/// An entry in a [RepositoryIndex]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RepositoryIndexEntry {
+    /// The name of the package
+    pub name: String,
+    /// The version of the package
+    pub version: String,
+    /// The architecture of the package, `None` if architecture independent
+    pub arch: Option<Architecture>,
+    /// The virtual capabilities the package provides in addition to its name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provides: Vec<String>,
+    /// The license of the package as an SPDX expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<SpdxExpression>,
+
+    /// The object id of the formula for the package
+    pub formula: Option<ObjectID>,
+    /// The object id of the built package
+    pub package: Option<ObjectID>,
+}
+
+impl RepositoryIndex {
+    /// Creates a new, empty repository index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a repository index from a set of objects.
+    ///
+    /// Currently, only [formula](ObjectType::AcaciaFormula) objects are supported
+    /// # Arguments
+    /// * `oids` - The object ids of the objects to index
+    /// * `odb` - The object database to read the objects from
+    pub fn from_objects(oids: &[ObjectID], odb: &ObjectDB) -> Result<Self, Error> {
+        let mut index = Self::new();
+
+        for oid in oids {
+            let object = odb.get_object(oid)?;
+
+            match object.ty {
+                ObjectType::AcaciaFormula => {
+                    let formula = Formula::from_odb(odb, oid)?;
+                    index.insert(RepositoryIndexEntry {
+                        name: formula.name,
+                        version: formula.version,
+                        arch: formula.arch,
+                        provides: formula.provides,
+                        license: formula.license,
+                        formula: Some(oid.clone()),
+                        package: None,
+                    });
+                }
+                ty => {
+                    return Err(Error::new(ErrorType::ObjectDB(
+                        ObjectDBError::UnexpectedObjectType {
+                            oid: oid.clone(),
+                            expected: ObjectType::AcaciaFormula,
+                            received: ty,
+                        },
+                    )))
+                    .ctx(|| "Indexing repository objects");
+                }
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Reads a repository index from `odb`
+    /// # Arguments
+    /// * `odb` - The object database to read from
+    /// * `oid` - The object id of the repository index object
+    pub fn from_odb(odb: &ObjectDB, oid: &ObjectID) -> Result<Self, Error> {
+        let reader = odb.read(oid)?;
+
+        if reader.object.ty != ObjectType::AcaciaRepositoryIndex {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::UnexpectedObjectType {
+                    oid: oid.clone(),
+                    expected: ObjectType::AcaciaRepositoryIndex,
+                    received: reader.object.ty,
+                },
+            )));
+        }
+
+        serde_json::from_reader(reader).ctx(|| format!("Parsing repository index {oid}"))
+    }
+
+    /// Finds a package that has been built from `formula` in the
+    /// repository indices depending on the formula
+    /// # Arguments
+    /// * `odb` - The object database to read the indices from
+    /// * `formula` - The object id of the formula
+    pub fn find_built_package(
+        odb: &ObjectDB,
+        formula: &ObjectID,
+    ) -> Result<Option<ObjectID>, Error> {
+        for dependent in odb.reverse_dependencies(formula)? {
+            if odb.get_object(&dependent)?.ty != ObjectType::AcaciaRepositoryIndex {
+                continue;
+            }
+
+            let index = Self::from_odb(odb, &dependent)
+                .ctx(|| format!("Reading repository index {dependent}"))?;
+
+            if let Some(package) = index
+                .entries
+                .iter()
+                .filter(|e| e.formula.as_ref() == Some(formula))
+                .find_map(|e| e.package.clone())
+            {
+                return Ok(Some(package));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Inserts an entry into the index.
+    ///
+    /// If an entry with the same name, version and architecture exists,
+    /// the object ids and provided capabilities of `entry` replace the ones
+    /// of the existing entry if they are available
+    /// # Arguments
+    /// * `entry` - The entry to insert
+    pub fn insert(&mut self, entry: RepositoryIndexEntry) {
+        match self.get_mut(&entry.name, &entry.version, entry.arch.as_ref()) {
+            Some(existing) => {
+                if !entry.provides.is_empty() {
+                    existing.provides = entry.provides;
+                }
+                if entry.formula.is_some() {
+                    existing.formula = entry.formula;
+                }
+                if entry.package.is_some() {
+                    existing.package = entry.package;
+                }
+            }
+            None => {
+                self.entries.push(entry);
+                self.entries.sort_by_key(|e| e.sort_key());
+            }
+        }
+    }
+
+    /// Merges another index into this index, entries of `other` take precedence
+    /// over the ones of this index (refer to [RepositoryIndex::insert()])
+    /// # Arguments
+    /// * `other` - The other index to merge
+    pub fn merge(&mut self, other: RepositoryIndex) {
+        for entry in other.entries {
+            self.insert(entry);
+        }
+    }
+
+    /// Returns the entry matching the name, version and architecture
+    /// # Arguments
+    /// * `name` - The name of the package
+    /// * `version` - The version of the package
+    /// * `arch` - The architecture of the package
+    pub fn get(
+        &self,
+        name: &str,
+        version: &str,
+        arch: Option<&Architecture>,
+    ) -> Option<&RepositoryIndexEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.name == name && e.version == version && e.arch.as_ref() == arch)
+    }
+
+    /// Returns a mutable reference to the entry matching the name, version and architecture
+    /// # Arguments
+    /// * `name` - The name of the package
+    /// * `version` - The version of the package
+    /// * `arch` - The architecture of the package
+    pub fn get_mut(
+        &mut self,
+        name: &str,
+        version: &str,
+        arch: Option<&Architecture>,
+    ) -> Option<&mut RepositoryIndexEntry> {
+        self.entries
+            .iter_mut()
+            .find(|e| e.name == name && e.version == version && e.arch.as_ref() == arch)
+    }
+
+    /// Returns all entries for the package `name`
+    /// # Arguments
+    /// * `name` - The name of the package
+    pub fn find(&self, name: &str) -> Vec<&RepositoryIndexEntry> {
+        self.entries.iter().filter(|e| e.name == name).collect()
+    }
+
+    /// Returns the entry describing `package`.
+    ///
+    /// If the object id of the package is known, the entry of exactly that build is returned.
+    /// Otherwise, the first entry with the name and version of the package is returned,
+    /// which is ambiguous if the package has been rebuilt or exists for multiple architectures
+    /// # Arguments
+    /// * `package` - The package to search for
+    pub fn find_package(&self, package: &PackageInfo) -> Option<&RepositoryIndexEntry> {
+        match &package.oid {
+            Some(oid) => self
+                .entries
+                .iter()
+                .find(|e| e.package.as_ref() == Some(oid)),
+            None => self
+                .entries
+                .iter()
+                .find(|e| e.name == package.name && e.version == package.version),
+        }
+    }
+
+    /// Returns all entries that satisfy `requirement`, either by their name
+    /// or by providing it as a capability. Entries matching by name come first
+    /// # Arguments
+    /// * `requirement` - The package name or capability to search for
+    pub fn find_providers(&self, requirement: &str) -> Vec<&RepositoryIndexEntry> {
+        let mut providers = self.find(requirement);
+
+        providers.extend(
+            self.entries
+                .iter()
+                .filter(|e| e.name != requirement && e.satisfies(requirement)),
+        );
+
+        providers
+    }
+
+    /// Resolves `requirement` to the newest entry with a built package that can run on `arch`.
+    ///
+    /// Entries matching by name are preferred over ones providing `requirement`
+    /// # Arguments
+    /// * `requirement` - The package name or capability to resolve
+    /// * `arch` - The architecture the package has to run on
+    pub fn resolve(&self, requirement: &str, arch: &Architecture) -> Option<&RepositoryIndexEntry> {
+        let candidates: Vec<&RepositoryIndexEntry> = self
+            .find_providers(requirement)
+            .into_iter()
+            .filter(|e| e.package.is_some())
+            .filter(|e| e.arch.as_ref().is_none_or(|a| a.can_run_on(arch)))
+            .collect();
+
+        let by_name = candidates.iter().any(|e| e.name == requirement);
+
+        candidates
+            .into_iter()
+            .filter(|e| !by_name || e.name == requirement)
+            .max_by(|a, b| compare_versions(&a.version, &b.version))
+    }
+
+    /// Computes the entries that need to be rebuilt if the package built by `formula` changes.
+    ///
+    /// Follows the reverse dependencies (see [ObjectDB::reverse_dependencies()]) of the packages
+    /// of the entries with the name and architecture of `formula`: An entry is affected if its
+    /// package depends on the package of an affected entry. Packages only depend on their
+    /// target and extra dependencies, so host dependencies do not affect entries
+    /// # Arguments
+    /// * `odb` - The object database to read the reverse dependencies from
+    /// * `formula` - The object id of the changed formula
+    /// # Returns
+    /// The affected entries (excluding the changed one) in build order
+    pub fn rebuild_impact(
+        &self,
+        odb: &ObjectDB,
+        formula: &ObjectID,
+    ) -> Result<Vec<&RepositoryIndexEntry>, Error> {
+        if !odb.has_reverse_dependencies() {
+            return Err(Error::new(ErrorType::Other(
+                "The object database has no reverse dependency index, \
+                 rebuild it using 'twig odb rdeps --rebuild'"
+                    .to_owned(),
+            )));
+        }
+
+        let changed = Formula::from_odb(odb, formula).ctx(|| "Reading changed formula")?;
+        let is_changed =
+            |entry: &RepositoryIndexEntry| entry.name == changed.name && entry.arch == changed.arch;
+
+        // The entries by the object id of their package
+        let packages: HashMap<&ObjectID, usize> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| entry.package.as_ref().map(|oid| (oid, i)))
+            .collect();
+
+        let mut queue: Vec<&ObjectID> = self
+            .entries
+            .iter()
+            .filter(|e| is_changed(e))
+            .filter_map(|e| e.package.as_ref())
+            .collect();
+        let mut seen: HashSet<&ObjectID> = queue.iter().copied().collect();
+        let mut affected: HashSet<usize> = HashSet::new();
+
+        while let Some(oid) = queue.pop() {
+            for dependent in odb.reverse_dependencies(oid)? {
+                // Only packages of this index can be affected
+                let Some((oid, i)) = packages.get_key_value(&dependent) else {
+                    continue;
+                };
+
+                if seen.insert(oid) {
+                    if !is_changed(&self.entries[*i]) {
+                        affected.insert(*i);
+                    }
+                    queue.push(oid);
+                }
+            }
+        }
+
+        // The dependencies of the affected packages
+        let mut dependencies: HashMap<usize, HashSet<ObjectID>> = HashMap::new();
+        for i in &affected {
+            if let Some(oid) = &self.entries[*i].package {
+                let object = odb.get_object(oid)?;
+                dependencies.insert(*i, object.dependencies.into_iter().collect());
+            }
+        }
+
+        // Order the affected entries so that dependencies get built first
+        let mut order: Vec<usize> = Vec::new();
+        let mut remaining = affected;
+
+        while !remaining.is_empty() {
+            let pending: HashSet<&ObjectID> = remaining
+                .iter()
+                .filter_map(|i| self.entries[*i].package.as_ref())
+                .collect();
+
+            let mut ready: Vec<usize> = remaining
+                .iter()
+                .copied()
+                .filter(|i| !dependencies[i].iter().any(|d| pending.contains(d)))
+                .collect();
+            ready.sort_unstable();
+
+            if ready.is_empty() {
+                warn!("Dependency cycle between affected entries, build order is undefined");
+                let mut rest: Vec<usize> = remaining.into_iter().collect();
+                rest.sort_unstable();
+                order.extend(rest);
+                break;
+            }
+
+            for i in &ready {
+                remaining.remove(i);
+            }
+            order.extend(ready);
+        }
+
+        Ok(order.into_iter().map(|i| &self.entries[i]).collect())
+    }
+
+    /// Returns the object ids referenced by this index
+    pub fn get_dependencies(&self) -> Vec<ObjectID> {
+        let mut dependencies = Vec::new();
+
+        for entry in &self.entries {
+            dependencies.extend(entry.get_oids());
+        }
+
+        dependencies
+    }
+
+    /// Returns the `JSON` string for this index
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).expect("Serialize repository index should never fail")
+    }
+
+    /// Inserts this index into `odb`, depending on all referenced objects
+    /// # Arguments
+    /// * `odb` - The object database to insert into
+    /// * `compression` - The compression to apply for inserting
+    pub fn insert_into_odb(
+        &self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+    ) -> Result<Object, Error> {
+        let mut cursor = Cursor::new(self.json());
+
+        let object = odb.insert_stream(
+            &mut cursor,
+            ObjectType::AcaciaRepositoryIndex,
+            compression,
+            self.get_dependencies(),
+        )?;
+
+        debug!(
+            "Inserted repository index with {} entries as {}",
+            self.entries.len(),
+            object.oid
+        );
+
+        Ok(object)
+    }
+}
+
+impl RepositoryIndexEntry {
+    /// Returns the object ids of the formula and package of this entry
+    pub fn get_oids(&self) -> Vec<ObjectID> {
+        self.formula
+            .iter()
+            .chain(self.package.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns whether this entry satisfies `requirement` by its name or a provided capability
+    /// # Arguments
+    /// * `requirement` - The package name or capability to check
+    pub fn satisfies(&self, requirement: &str) -> bool {
+        self.name == requirement || self.provides.iter().any(|p| p == requirement)
+    }
+
+    /// Returns the key to sort entries by
+    fn sort_key(&self) -> (String, String, String) {
+        (
+            self.name.clone(),
+            self.version.clone(),
+            self.arch
+                .as_ref()
+                .map(|a| a.to_string())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+impl std::fmt::Display for RepositoryIndexEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arch = match &self.arch {
+            Some(arch) => arch.to_string(),
+            None => crate::ANY_ARCH.to_string(),
+        };
+
+        write!(f, "{arch}/{}@{}", self.name, self.version)?;
+
+        if !self.provides.is_empty() {
+            write!(f, " PROVIDES [{}]", self.provides.join(", "))?;
+        }
+
+        if let Some(license) = &self.license {
+            write!(f, " LICENSE [{license}]")?;
+        }
+
+        if let Some(formula) = &self.formula {
+            write!(f, " FORMULA [{formula}]")?;
+        }
+        if let Some(package) = &self.package {
+            write!(f, " PACKAGE [{package}]")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+//! Detached signatures of objects to verify their origin
+
+use std::io::Cursor;
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+use crate::error::{Error, ErrorExt, ErrorType};
+
+use super::{
+    ODBDriver, Object, ObjectCompression, ObjectDB, ObjectDBError, ObjectID, ObjectReader,
+    ObjectType,
+};
+
+/// The prefix of the signed message, so signatures of objects
+/// cannot be mistaken for signatures of something else
+static SIGNATURE_CONTEXT: &[u8] = b"acacia-object-signature\0";
+
+/// An ed25519 signature of the object id of an object.
+///
+/// Like [super::Annotation]s, signatures are stored as separate objects depending on
+/// the signed object and are found using the reverse dependency index.
+/// As the object id of an object covers the object ids of its dependencies,
+/// a signature vouches for the whole closure of the signed object
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// The object id of the signed object
+    pub object: ObjectID,
+    /// The public key that created the signature (hex encoded)
+    pub key: String,
+    /// The signature (hex encoded)
+    pub signature: String,
+}
+
+impl Signature {
+    /// Signs `object` using `key`
+    /// # Arguments
+    /// * `object` - The object id of the object to sign
+    /// * `key` - The key to sign with
+    pub fn new(object: ObjectID, key: &SigningKey) -> Self {
+        let signature = key.sign(&Self::message(&object));
+
+        Self {
+            key: hex::encode(key.verifying_key().as_bytes()),
+            signature: hex::encode(signature.to_bytes()),
+            object,
+        }
+    }
+
+    /// Returns whether this signature has been created by one of `trusted_keys`
+    /// and is valid for the signed object
+    /// # Arguments
+    /// * `trusted_keys` - The public keys to accept signatures of
+    pub fn verify(&self, trusted_keys: &[VerifyingKey]) -> bool {
+        let Ok(key) = self.get_key() else {
+            return false;
+        };
+
+        if !trusted_keys
+            .iter()
+            .any(|trusted| bool::from(trusted.as_bytes().ct_eq(key.as_bytes())))
+        {
+            return false;
+        }
+
+        let signature = match hex::decode(&self.signature)
+            .ok()
+            .and_then(|s| <[u8; 64]>::try_from(s).ok())
+        {
+            Some(bytes) => ed25519_dalek::Signature::from_bytes(&bytes),
+            None => return false,
+        };
+
+        key.verify_strict(&Self::message(&self.object), &signature)
+            .is_ok()
+    }
+
+    /// Returns the public key that claims to have created this signature
+    pub fn get_key(&self) -> Result<VerifyingKey, Error> {
+        parse_public_key(&self.key).ctx(|| format!("Parsing key of signature for {}", self.object))
+    }
+
+    /// Reads a signature from `odb`
+    /// # Arguments
+    /// * `odb` - The object database to read from
+    /// * `oid` - The object id of the signature object
+    pub fn from_odb(odb: &ObjectDB, oid: &ObjectID) -> Result<Self, Error> {
+        Self::from_reader(odb.read(oid)?)
+    }
+
+    /// Parses a signature from the object read by `reader`
+    /// # Arguments
+    /// * `reader` - The reader of the signature object
+    pub fn from_reader(reader: ObjectReader) -> Result<Self, Error> {
+        let oid = reader.object.oid.clone();
+
+        if reader.object.ty != ObjectType::AcaciaSignature {
+            return Err(Error::new(ErrorType::ObjectDB(
+                ObjectDBError::UnexpectedObjectType {
+                    oid,
+                    expected: ObjectType::AcaciaSignature,
+                    received: reader.object.ty,
+                },
+            )));
+        }
+
+        serde_json::from_reader(reader).ctx(|| format!("Parsing signature {oid}"))
+    }
+
+    /// Returns all signatures of `oid` in the order they were inserted, valid or not
+    /// # Arguments
+    /// * `odb` - The object database to search in
+    /// * `oid` - The object id of the signed object
+    /// # Returns
+    /// The object ids of the signature objects and the signatures
+    pub fn find(odb: &ObjectDB, oid: &ObjectID) -> Result<Vec<(ObjectID, Self)>, Error> {
+        let mut signatures = Vec::new();
+
+        for dependent in odb.reverse_dependencies(oid)? {
+            if odb.get_object(&dependent)?.ty != ObjectType::AcaciaSignature {
+                continue;
+            }
+
+            let signature = Self::from_odb(odb, &dependent)?;
+            if signature.object.ct_eq(oid) {
+                signatures.push((dependent, signature));
+            }
+        }
+
+        Ok(signatures)
+    }
+
+    /// Finds the first signature of `oid` in `driver` created by one of `trusted_keys`
+    /// # Arguments
+    /// * `driver` - The object database driver to search in
+    /// * `oid` - The object id of the signed object
+    /// * `trusted_keys` - The public keys to accept signatures of
+    /// # Returns
+    /// The object id of the signature object and the signature
+    pub fn find_trusted(
+        driver: &dyn ODBDriver,
+        oid: &ObjectID,
+        trusted_keys: &[VerifyingKey],
+    ) -> Result<Option<(ObjectID, Self)>, Error> {
+        for dependent in driver.reverse_dependencies(oid)? {
+            // A single broken dependent must not hide the valid signatures
+            let reader = match driver.retrieve(&dependent) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    warn!("Skipping dependent {dependent} of {oid}: {}", e.oneline());
+                    continue;
+                }
+            };
+            if reader.object.ty != ObjectType::AcaciaSignature {
+                continue;
+            }
+
+            let signature = match Self::from_reader(reader) {
+                Ok(signature) => signature,
+                Err(e) => {
+                    warn!("Skipping signature {dependent} of {oid}: {}", e.oneline());
+                    continue;
+                }
+            };
+            if signature.object.ct_eq(oid) && signature.verify(trusted_keys) {
+                return Ok(Some((dependent, signature)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the `JSON` string for this signature
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).expect("Serialize signature should never fail")
+    }
+
+    /// Inserts this signature into `odb`, depending on the signed object
+    /// # Arguments
+    /// * `odb` - The object database to insert into
+    /// * `compression` - The compression to apply for inserting
+    pub fn insert_into_odb(
+        &self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+    ) -> Result<Object, Error> {
+        let mut cursor = Cursor::new(self.json());
+
+        let object = odb.insert_stream(
+            &mut cursor,
+            ObjectType::AcaciaSignature,
+            compression,
+            vec![self.object.clone()],
+        )?;
+
+        debug!("Signed {} as {}", self.object, object.oid);
+
+        Ok(object)
+    }
+
+    /// Returns the message that gets signed for `oid`
+    /// # Arguments
+    /// * `oid` - The object id of the signed object
+    fn message(oid: &ObjectID) -> Vec<u8> {
+        let mut message = SIGNATURE_CONTEXT.to_vec();
+        message.extend_from_slice(oid.bytes());
+        message
+    }
+}
+
+/// Parses a hex encoded ed25519 public key
+/// # Arguments
+/// * `key` - The hex string to parse
+pub fn parse_public_key(key: &str) -> Result<VerifyingKey, Error> {
+    let bytes = hex::decode(key.trim())
+        .ok()
+        .and_then(|k| <[u8; 32]>::try_from(k).ok())
+        .ok_or_else(|| {
+            Error::new(ErrorType::Other(format!(
+                "Invalid public key '{}', expected 64 hex digits",
+                key.trim()
+            )))
+        })?;
+
+    VerifyingKey::from_bytes(&bytes).map_err(|e| {
+        Error::new(ErrorType::Other(format!(
+            "Invalid public key '{}': {e}",
+            key.trim()
+        )))
+    })
+}
@@ -3,15 +3,49 @@
 mod treecommand;
 pub use treecommand::*;
 
+mod treedeploy;
+pub use treedeploy::*;
+
+mod treediff;
+pub use treediff::*;
+
+mod treefilter;
+
+mod treehardening;
+pub use treehardening::*;
+
+mod treelicense;
+pub use treelicense::*;
+
+mod treemerge;
+pub use treemerge::*;
+
+mod treeneeds;
+pub use treeneeds::*;
+
+mod treepython;
+pub use treepython::*;
+
+mod treerunpath;
+pub use treerunpath::*;
+
+mod treesymlinks;
+pub use treesymlinks::*;
+
+mod treetar;
+
+mod treeverify;
+
 use core::panic;
-use log::{debug, trace};
+use indexmap::IndexMap;
+use log::{debug, trace, warn};
 use std::{
     io::{Cursor, ErrorKind, Read, Write},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use crate::{
-    error::{Error, ErrorExt},
+    error::{Error, ErrorExt, ErrorType},
     model::ObjectDB,
     util::{
         self,
@@ -26,7 +60,7 @@ use super::{Object, ObjectCompression, ObjectID, ObjectType};
 pub static CURRENT_VERSION: u8 = 0;
 
 /// The representing structure for the index file
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Tree {
     /// The entries listed in the tree
     pub entries: Vec<TreeEntry>,
@@ -70,6 +104,9 @@ impl Tree {
                         .to_string_lossy()
                         .to_string(),
                 })
+            } else if let Some(special) = TreeEntry::from_special_file(&entry, &unix_info, &name)? {
+                // Device nodes and FIFOs have no contents to insert
+                entries.push(special)
             } else if path.is_dir() {
                 // Directories get linked to as subtrees
                 let tree = Tree::index(&path, db, compression)?;
@@ -102,33 +139,12 @@ impl Tree {
     /// - A non-existing (by name) entry gets added
     /// - Existing entries (by name) keep the name and UNIX info of the existing entry
     /// - Subtrees get merged in the same way
+    ///
+    /// Use [Tree::merge_with_strategy()] to get notified about conflicting entries
     /// # Arguments
     /// * `other` - The other tree to merge
     pub fn merge(&mut self, other: Tree) {
-        for entry in other.entries {
-            match self.get_entry_by_name_mut(entry.name()) {
-                None => self.entries.push(entry),
-                Some(my_entry) => {
-                    if let TreeEntry::Subtree {
-                        info: _,
-                        name: _,
-                        tree: my_tree,
-                    } = my_entry
-                    {
-                        if let TreeEntry::Subtree {
-                            info: _,
-                            name: _,
-                            tree,
-                        } = entry
-                        {
-                            my_tree.merge(tree);
-                        }
-                    }
-                }
-            }
-        }
-
-        self.entries.sort();
+        self.merge_into(other, false, Path::new(""), &mut Vec::new());
     }
 
     /// Walks the index file and yields the entries
@@ -159,6 +175,63 @@ impl Tree {
         Ok(())
     }
 
+    /// Returns the paths of all files and symlinks in this tree, relative to its root
+    pub fn get_file_paths(&self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        self.collect_file_paths(Path::new(""), &mut paths);
+        paths
+    }
+
+    /// Recursively collects the paths of all files and symlinks into `paths`
+    /// # Arguments
+    /// * `prefix` - The path of this tree relative to the root tree
+    /// * `paths` - The vector to push the paths to
+    fn collect_file_paths(&self, prefix: &Path, paths: &mut Vec<PathBuf>) {
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+
+            match entry {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => tree.collect_file_paths(&path, paths),
+                _ => paths.push(path),
+            }
+        }
+    }
+
+    /// Finds all files in this tree that are claimed by more than one claimant
+    /// # Arguments
+    /// * `claims` - The claimants (e.g. packages) and the paths they claim,
+    ///   a path claims itself and everything below it
+    /// # Returns
+    /// The conflicting files and the names of all claimants that claim them
+    pub fn find_conflicts(
+        &self,
+        claims: &IndexMap<String, Vec<String>>,
+    ) -> IndexMap<PathBuf, Vec<String>> {
+        let mut conflicts = IndexMap::new();
+
+        for path in self.get_file_paths() {
+            let claimants: Vec<String> = claims
+                .iter()
+                .filter(|(_, paths)| {
+                    paths
+                        .iter()
+                        .any(|claim| path.starts_with(claim.trim_start_matches('/')))
+                })
+                .map(|(name, _)| name.clone())
+                .collect();
+
+            if claimants.len() > 1 {
+                conflicts.insert(path, claimants);
+            }
+        }
+
+        conflicts
+    }
+
     /// Returns the dependencies this tree uses with no recursion
     pub fn get_dependencies(&self) -> Vec<ObjectID> {
         let mut dependencies = Vec::new();
@@ -174,7 +247,15 @@ impl Tree {
                     info: _,
                     name: _,
                     destination: _,
-                } => {}
+                }
+                | TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind: _,
+                    major: _,
+                    minor: _,
+                }
+                | TreeEntry::Fifo { info: _, name: _ } => {}
                 TreeEntry::Subtree {
                     info: _,
                     name: _,
@@ -196,6 +277,24 @@ impl Tree {
         &self,
         db: &mut ObjectDB,
         compression: ObjectCompression,
+    ) -> Result<Object, Error> {
+        self.insert_into_odb_with_dependencies(db, compression, Vec::new())
+    }
+
+    /// Inserts `self` into the object database, depending on `dependencies`
+    /// in addition to its entries, e.g. for a package that needs other packages
+    /// at runtime. The extra dependencies are part of the object id of the tree
+    /// # Arguments
+    /// * `db` - The object database to insert into
+    /// * `compression` - The form of compression to use when inserting
+    /// * `dependencies` - The object ids of the objects to depend on additionally
+    /// # Returns
+    /// The inserted [Object]
+    pub fn insert_into_odb_with_dependencies(
+        &self,
+        db: &mut ObjectDB,
+        compression: ObjectCompression,
+        dependencies: Vec<ObjectID>,
     ) -> Result<Object, Error> {
         // Before inserting self, we must insert all subtrees
         for entry in &self.entries {
@@ -213,11 +312,14 @@ impl Tree {
         self.pack(&mut buf)?;
         let mut buf = Cursor::new(buf);
 
+        let mut all_dependencies = self.get_dependencies();
+        all_dependencies.extend(dependencies);
+
         let object = db.insert_stream(
             &mut buf,
             ObjectType::AcaciaTree,
             compression,
-            self.get_dependencies(),
+            all_dependencies,
         )?;
 
         debug!(
@@ -234,16 +336,143 @@ impl Tree {
     /// * `root` - The root directory to deploy to
     /// * `db` - The object database to use for getting objects
     pub fn deploy(&self, root: &Path, db: &ObjectDB) -> Result<(), Error> {
+        self.deploy_with_progress(root, db, &mut |_| {})
+    }
+
+    /// Deploys this index to `root`, reporting the progress after every entry
+    /// # Arguments
+    /// * `root` - The root directory to deploy to
+    /// * `db` - The object database to use for getting objects
+    /// * `callback` - The callback to report the progress to
+    pub fn deploy_with_progress(
+        &self,
+        root: &Path,
+        db: &ObjectDB,
+        callback: &mut dyn FnMut(&DeployProgress),
+    ) -> Result<(), Error> {
+        let mut progress = DeployProgress {
+            entries_total: self.entry_count(),
+            ..Default::default()
+        };
+
+        self.deploy_entries(root, db, &mut progress, callback)
+    }
+
+    /// Deploys the entries of this index to `root`, accumulating into `progress`
+    /// # Arguments
+    /// * `root` - The root directory to deploy to
+    /// * `db` - The object database to use for getting objects
+    /// * `progress` - The progress of the whole deployment
+    /// * `callback` - The callback to report the progress to
+    fn deploy_entries(
+        &self,
+        root: &Path,
+        db: &ObjectDB,
+        progress: &mut DeployProgress,
+        callback: &mut dyn FnMut(&DeployProgress),
+    ) -> Result<(), Error> {
         util::fs::create_dir_all(root).ctx(|| "Creating parent directory")?;
 
         for command in &self.entries {
             debug!("Executing {command} @ {}", root.str_lossy());
-            command.execute(root, db)?;
+            command.execute_with_progress(root, db, progress, callback)?;
         }
 
         Ok(())
     }
 
+    /// Deploys this index to `root` in a transactional manner:
+    /// The tree gets deployed to a staging directory next to `root` first,
+    /// then every top-level entry gets swapped into `root`.
+    ///
+    /// Existing top-level entries of `root` that are part of this tree are
+    /// replaced as a whole. If anything fails, `root` is rolled back to its previous state.
+    /// # Arguments
+    /// * `root` - The root directory to deploy to
+    /// * `db` - The object database to use for getting objects
+    pub fn deploy_atomic(&self, root: &Path, db: &ObjectDB) -> Result<(), Error> {
+        self.deploy_atomic_with_progress(root, db, &mut |_| {})
+    }
+
+    /// Deploys this index to `root` in a transactional manner (see [Tree::deploy_atomic()]),
+    /// reporting the progress of staging the deployment after every entry
+    /// # Arguments
+    /// * `root` - The root directory to deploy to
+    /// * `db` - The object database to use for getting objects
+    /// * `callback` - The callback to report the progress to
+    pub fn deploy_atomic_with_progress(
+        &self,
+        root: &Path,
+        db: &ObjectDB,
+        callback: &mut dyn FnMut(&DeployProgress),
+    ) -> Result<(), Error> {
+        util::fs::create_dir_all(root).ctx(|| "Creating root directory")?;
+
+        // Staging and backup directories must live on the same filesystem to be able to rename
+        let id = uuid::Uuid::new_v4();
+        let name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let staging = root.with_file_name(format!(".{name}.staging-{id}"));
+        let backup = root.with_file_name(format!(".{name}.backup-{id}"));
+
+        if let Err(e) = self.deploy_with_progress(&staging, db, callback) {
+            util::fs::remove_dir_all(&staging)?;
+            return Err(e).ctx(|| format!("Staging deployment at {}", staging.str_lossy()));
+        }
+        util::fs::create_dir(&backup).ctx(|| "Creating backup directory")?;
+
+        let mut swapped: Vec<(&str, bool)> = Vec::new();
+        let mut res = Ok(());
+
+        for entry in &self.entries {
+            let target = root.join(entry.name());
+            let existed = target.symlink_metadata().is_ok();
+
+            if existed {
+                res = util::fs::rename(&target, &backup.join(entry.name()));
+                if res.is_err() {
+                    break;
+                }
+            }
+
+            res = util::fs::rename(&staging.join(entry.name()), &target);
+            swapped.push((entry.name(), existed));
+            if res.is_err() {
+                break;
+            }
+        }
+
+        if let Err(e) = res {
+            warn!("Deployment to {} failed, rolling back", root.str_lossy());
+
+            for (name, existed) in swapped.into_iter().rev() {
+                let target = root.join(name);
+
+                if target.is_dir() && !target.is_symlink() {
+                    util::fs::remove_dir_all(&target)?;
+                } else if target.symlink_metadata().is_ok() {
+                    util::fs::remove_file(&target)?;
+                }
+
+                if existed {
+                    util::fs::rename(&backup.join(name), &target)?;
+                }
+            }
+
+            util::fs::remove_dir_all(&staging)?;
+            util::fs::remove_dir_all(&backup)?;
+
+            return Err(e).ctx(|| format!("Deploying to {}", root.str_lossy()));
+        }
+
+        util::fs::remove_dir_all(&staging)?;
+        util::fs::remove_dir_all(&backup)?;
+
+        Ok(())
+    }
+
     /// Returns the object id derived from this tree
     pub fn oid(&self) -> ObjectID {
         let mut buf = Vec::new();
@@ -255,6 +484,68 @@ impl Tree {
             .expect("Hashing should never fail")
     }
 
+    /// Resolves the object id of the subtree at `subpath` below the tree `oid`.
+    ///
+    /// Only the trees along `subpath` are read, all other subtrees are never unpacked.
+    /// # Arguments
+    /// * `oid` - The object id of the tree to start at
+    /// * `subpath` - The relative path of the subtree to resolve
+    /// * `db` - The object database to read the trees from
+    pub fn resolve_subtree(
+        oid: &ObjectID,
+        subpath: &Path,
+        db: &ObjectDB,
+    ) -> Result<ObjectID, Error> {
+        let context = || format!("Resolving {} in tree {}", subpath.str_lossy(), oid);
+
+        let mut cur_oid = oid.clone();
+
+        for component in subpath.components() {
+            let name = match component {
+                Component::Normal(name) => name.to_string_lossy(),
+                Component::CurDir => continue,
+                _ => {
+                    return Err(Error::new_context(
+                        ErrorType::Other(format!(
+                            "Subpath {} has to be relative and normalized",
+                            subpath.str_lossy()
+                        )),
+                        context(),
+                    ))
+                }
+            };
+
+            let mut object = db.read(&cur_oid).ctx(context)?;
+            unpack_header(&mut object).ctx(context)?;
+
+            let mut subtree = None;
+            while let Some((entry_name, entry_oid)) = TreeEntry::skim(&mut object).ctx(context)? {
+                if entry_name == name {
+                    subtree = Some(entry_oid);
+                    break;
+                }
+            }
+
+            cur_oid = match subtree {
+                Some(Some(oid)) => oid,
+                Some(None) => {
+                    return Err(Error::new_context(
+                        ErrorType::Other(format!("'{name}' is not a subtree")),
+                        context(),
+                    ))
+                }
+                None => {
+                    return Err(Error::new_context(
+                        ErrorType::Other(format!("'{name}' does not exist")),
+                        context(),
+                    ))
+                }
+            };
+        }
+
+        Ok(cur_oid)
+    }
+
     /// Returns a reference to an entry by name, if available
     /// # Arguments
     /// * `name` - The name of the entry
@@ -262,12 +553,59 @@ impl Tree {
         self.entries.iter().find(|entry| entry.name() == name)
     }
 
+    /// Returns the entry at `path`, if available
+    /// # Arguments
+    /// * `path` - The relative, normalized path of the entry
+    pub fn get_entry(&self, path: &Path) -> Option<&TreeEntry> {
+        let name = path.file_name()?.to_string_lossy();
+        let mut cur = self;
+
+        for component in path.parent()?.components() {
+            cur = match cur.get_entry_by_name(&component.as_os_str().to_string_lossy())? {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => tree,
+                _ => return None,
+            };
+        }
+
+        cur.get_entry_by_name(&name)
+    }
+
     /// Returns a mutable reference to an entry by name, if available
     /// # Arguments
     /// * `name` - The name of the entry
     pub fn get_entry_by_name_mut(&mut self, name: &str) -> Option<&mut TreeEntry> {
         self.entries.iter_mut().find(|entry| entry.name() == name)
     }
+
+    /// Returns a mutable reference to the subtree at `subpath`, if available
+    /// # Arguments
+    /// * `subpath` - The relative path of the subtree
+    pub fn get_subtree_mut(&mut self, subpath: &Path) -> Option<&mut Tree> {
+        let mut cur = self;
+
+        for component in subpath.components() {
+            let name = match component {
+                Component::Normal(name) => name.to_string_lossy(),
+                Component::CurDir => continue,
+                _ => return None,
+            };
+
+            cur = match cur.get_entry_by_name_mut(&name)? {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => tree,
+                _ => return None,
+            };
+        }
+
+        Some(cur)
+    }
 }
 
 impl Packable for Tree {
@@ -294,30 +632,7 @@ impl ODBUnpackable for Tree {
     fn try_unpack_from_odb<R: Read>(input: &mut R, odb: &ObjectDB) -> Result<Option<Self>, Error> {
         let context = || "Parsing index entry";
 
-        let mut buf = [0u8; 4];
-        input.read_exact(&mut buf).e_context(context)?;
-
-        if buf != ['A', 'L', 'T', 'R'].map(|p| p as u8) {
-            Err(std::io::Error::new(
-                ErrorKind::InvalidInput,
-                "Expected file magic",
-            ))
-            .e_context(context)?;
-        }
-
-        let mut buf = [0u8];
-
-        input.read_exact(&mut buf).e_context(context)?;
-        if buf[0] != CURRENT_VERSION {
-            Err(std::io::Error::new(
-                ErrorKind::InvalidInput,
-                format!(
-                    "Expected version to be {:x}, got {:x}",
-                    CURRENT_VERSION, buf[0]
-                ),
-            ))
-            .e_context(context)?;
-        }
+        unpack_header(input).ctx(context)?;
 
         let mut entries: Vec<TreeEntry> = Vec::new();
 
@@ -329,3 +644,37 @@ impl ODBUnpackable for Tree {
         Ok(Some(Tree { entries }))
     }
 }
+
+/// Reads and verifies the magic and version of a tree file
+/// # Arguments
+/// * `input` - The input to read from
+fn unpack_header<R: Read>(input: &mut R) -> Result<(), Error> {
+    let context = || "Reading tree header";
+
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf).e_context(context)?;
+
+    if buf != ['A', 'L', 'T', 'R'].map(|p| p as u8) {
+        Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "Expected file magic",
+        ))
+        .e_context(context)?;
+    }
+
+    let mut buf = [0u8];
+
+    input.read_exact(&mut buf).e_context(context)?;
+    if buf[0] != CURRENT_VERSION {
+        Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Expected version to be {:x}, got {:x}",
+                CURRENT_VERSION, buf[0]
+            ),
+        ))
+        .e_context(context)?;
+    }
+
+    Ok(())
+}
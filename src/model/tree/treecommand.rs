@@ -1,6 +1,8 @@
 use std::{
     fmt::Display,
+    fs::DirEntry,
     io::{self, ErrorKind, Read},
+    os::unix::fs::{FileTypeExt, MetadataExt},
     path::{Path, PathBuf},
 };
 
@@ -10,14 +12,14 @@ use crate::{
     error::{Error, ErrorExt},
     model::{ObjectDB, ObjectID},
     util::{
-        fs::{self, PathUtil, UNIXInfo},
+        fs::{self, DeviceKind, PathUtil, UNIXInfo},
         ODBUnpackable, Packable, Unpackable,
     },
 };
 
-use super::Tree;
+use super::{DeployProgress, Tree};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TreeEntry {
     File {
         /// UNIX information about the file
@@ -43,6 +45,24 @@ pub enum TreeEntry {
         /// The object ID of the tree to place
         tree: Tree,
     },
+    Device {
+        /// UNIX information about the device node
+        info: UNIXInfo,
+        /// The name of the device node
+        name: String,
+        /// The kind of device (character or block)
+        kind: DeviceKind,
+        /// The major device number
+        major: u32,
+        /// The minor device number
+        minor: u32,
+    },
+    Fifo {
+        /// UNIX information about the FIFO
+        info: UNIXInfo,
+        /// The name of the FIFO
+        name: String,
+    },
 }
 impl TreeEntry {
     /// Executes this index command in `path`
@@ -50,6 +70,24 @@ impl TreeEntry {
     /// * `path` - The working directory to execute the command in
     /// * `db` - The object database to use for retrieving objects
     pub fn execute(&self, path: &Path, db: &ObjectDB) -> Result<(), Error> {
+        let mut progress = DeployProgress::default();
+        self.execute_with_progress(path, db, &mut progress, &mut |_| {})
+    }
+
+    /// Executes this index command in `path`, accumulating into `progress`
+    /// and reporting it to `callback` once the command is done
+    /// # Arguments
+    /// * `path` - The working directory to execute the command in
+    /// * `db` - The object database to use for retrieving objects
+    /// * `progress` - The progress of the deployment this command is part of
+    /// * `callback` - The callback to report the progress to
+    pub fn execute_with_progress(
+        &self,
+        path: &Path,
+        db: &ObjectDB,
+        progress: &mut DeployProgress,
+        callback: &mut dyn FnMut(&DeployProgress),
+    ) -> Result<(), Error> {
         match self {
             Self::File { info, name, oid } => {
                 let path = path.join(name);
@@ -62,7 +100,8 @@ impl TreeEntry {
                 info.apply_file(&mut file)
                     .ctx(|| format!("Applying UNIX info to {}", path.str_lossy()))?;
 
-                io::copy(&mut object, &mut file).ctx(|| "Copying data")?;
+                progress.bytes_written +=
+                    io::copy(&mut object, &mut file).ctx(|| "Copying data")?;
             }
 
             Self::Symlink {
@@ -90,13 +129,112 @@ impl TreeEntry {
                 info.apply_path(&path)
                     .e_context(|| format!("Applying UNIX info to {}", path.str_lossy()))?;
 
-                tree.deploy(&path, db)?;
+                // Report the subtree itself before its contents
+                progress.entries_done += 1;
+                callback(progress);
+
+                return tree.deploy_entries(&path, db, progress, callback);
+            }
+
+            Self::Device {
+                info,
+                name,
+                kind,
+                major,
+                minor,
+            } => {
+                let path = path.join(name);
+                trace!(
+                    "Placing device node {kind} {major}:{minor} @ {}",
+                    path.str_lossy()
+                );
+                fs::create_device_node(&path, *kind, *major, *minor, info.mode)?;
+
+                info.apply_path(&path)
+                    .e_context(|| format!("Applying UNIX info to {}", path.str_lossy()))?;
+            }
+
+            Self::Fifo { info, name } => {
+                let path = path.join(name);
+                trace!("Placing FIFO @ {}", path.str_lossy());
+                fs::create_fifo(&path, info.mode)?;
+
+                info.apply_path(&path)
+                    .e_context(|| format!("Applying UNIX info to {}", path.str_lossy()))?;
             }
         }
 
+        progress.entries_done += 1;
+        callback(progress);
+
         Ok(())
     }
 
+    /// Creates a device node or FIFO entry for a special file on the filesystem
+    /// # Arguments
+    /// * `entry` - The directory entry of the file
+    /// * `info` - The UNIX information of the file
+    /// * `name` - The name of the file
+    /// # Returns
+    /// `None` if the file is no device node or FIFO
+    pub fn from_special_file(
+        entry: &DirEntry,
+        info: &UNIXInfo,
+        name: &str,
+    ) -> Result<Option<Self>, Error> {
+        let context = || format!("Inspecting {}", entry.path().str_lossy());
+        let file_type = entry.file_type().e_context(context)?;
+
+        if file_type.is_fifo() {
+            return Ok(Some(Self::Fifo {
+                info: info.clone(),
+                name: name.to_owned(),
+            }));
+        }
+
+        let Some(kind) = DeviceKind::from_mode(info.mode) else {
+            return Ok(None);
+        };
+        let rdev = entry.metadata().e_context(context)?.rdev();
+
+        Ok(Some(Self::Device {
+            info: info.clone(),
+            name: name.to_owned(),
+            kind,
+            major: nix::sys::stat::major(rdev) as u32,
+            minor: nix::sys::stat::minor(rdev) as u32,
+        }))
+    }
+
+    /// Returns the UNIX information of this entry
+    pub fn info(&self) -> &UNIXInfo {
+        match self {
+            TreeEntry::File {
+                info,
+                name: _,
+                oid: _,
+            } => info,
+            TreeEntry::Symlink {
+                info,
+                name: _,
+                destination: _,
+            } => info,
+            TreeEntry::Subtree {
+                info,
+                name: _,
+                tree: _,
+            } => info,
+            TreeEntry::Device {
+                info,
+                name: _,
+                kind: _,
+                major: _,
+                minor: _,
+            } => info,
+            TreeEntry::Fifo { info, name: _ } => info,
+        }
+    }
+
     pub fn name(&self) -> &str {
         match self {
             TreeEntry::File {
@@ -114,8 +252,85 @@ impl TreeEntry {
                 name,
                 tree: _,
             } => name,
+            TreeEntry::Device {
+                info: _,
+                name,
+                kind: _,
+                major: _,
+                minor: _,
+            } => name,
+            TreeEntry::Fifo { info: _, name } => name,
         }
     }
+
+    /// Reads the next entry from a tree file without unpacking subtrees
+    /// # Arguments
+    /// * `input` - The input to read from, positioned after the tree header
+    /// # Returns
+    /// The name of the entry and the object id of the subtree, if the entry is one,
+    /// or `None` if the end of the tree has been reached
+    pub fn skim<R: Read>(input: &mut R) -> Result<Option<(String, Option<ObjectID>)>, Error> {
+        let context = || "Skimming tree command";
+        let ty = match u8::unpack(input).e_context(context)? {
+            Some(ty) => ty,
+            None => return Ok(None),
+        };
+
+        let context = || format!("Skimming index command '{}'", ty);
+
+        let read_string = |input: &mut R, len: u32| -> Result<String, Error> {
+            let mut buf = vec![0u8; len as usize];
+            input.read_exact(&mut buf).e_context(context)?;
+            String::from_utf8(buf).e_context(context)
+        };
+
+        Ok(Some(match ty {
+            0x1 | 0x5 => {
+                let mut oid = [0u8; 32];
+                input.read_exact(&mut oid).e_context(context)?;
+                let oid = ObjectID::new(oid);
+
+                UNIXInfo::try_unpack(input).e_context(context)?;
+                let name_len = u32::try_unpack(input).e_context(context)?;
+                let name = read_string(input, name_len)?;
+
+                (name, (ty == 0x5).then_some(oid))
+            }
+            0x2 => {
+                UNIXInfo::try_unpack(input).e_context(context)?;
+                let name_len = u32::try_unpack(input).e_context(context)?;
+                let dest_len = u32::try_unpack(input).e_context(context)?;
+                let name = read_string(input, name_len)?;
+                read_string(input, dest_len)?;
+
+                (name, None)
+            }
+            0x3 => {
+                UNIXInfo::try_unpack(input).e_context(context)?;
+                u8::try_unpack(input).e_context(context)?;
+                u32::try_unpack(input).e_context(context)?;
+                u32::try_unpack(input).e_context(context)?;
+                let name_len = u32::try_unpack(input).e_context(context)?;
+                let name = read_string(input, name_len)?;
+
+                (name, None)
+            }
+            0x4 => {
+                UNIXInfo::try_unpack(input).e_context(context)?;
+                let name_len = u32::try_unpack(input).e_context(context)?;
+                let name = read_string(input, name_len)?;
+
+                (name, None)
+            }
+            _ => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Got unknown tree command {:x}", ty),
+                ))
+                .ctx(context);
+            }
+        }))
+    }
 }
 
 impl PartialOrd for TreeEntry {
@@ -192,6 +407,48 @@ impl ODBUnpackable for TreeEntry {
                     destination,
                 }
             }
+
+            0x3 => {
+                let info = UNIXInfo::try_unpack(input).e_context(context)?;
+
+                let kind = match u8::try_unpack(input).e_context(context)? {
+                    0x0 => DeviceKind::Character,
+                    0x1 => DeviceKind::Block,
+                    kind => {
+                        return Err(std::io::Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("Got unknown device kind {:x}", kind),
+                        ))
+                        .ctx(context);
+                    }
+                };
+                let major = u32::try_unpack(input).e_context(context)?;
+                let minor = u32::try_unpack(input).e_context(context)?;
+
+                let name_len = u32::try_unpack(input).e_context(context)?;
+                let mut buf = vec![0u8; name_len as usize];
+                input.read_exact(&mut buf).e_context(context)?;
+                let name = String::from_utf8(buf).e_context(context)?;
+
+                TreeEntry::Device {
+                    info,
+                    name,
+                    kind,
+                    major,
+                    minor,
+                }
+            }
+
+            0x4 => {
+                let info = UNIXInfo::try_unpack(input).e_context(context)?;
+
+                let name_len = u32::try_unpack(input).e_context(context)?;
+                let mut buf = vec![0u8; name_len as usize];
+                input.read_exact(&mut buf).e_context(context)?;
+                let name = String::from_utf8(buf).e_context(context)?;
+
+                TreeEntry::Fifo { info, name }
+            }
             _ => {
                 return Err(std::io::Error::new(
                     ErrorKind::InvalidInput,
@@ -217,6 +474,14 @@ impl Packable for TreeEntry {
                 name: _,
                 destination: _,
             } => 0x2u8,
+            Self::Device {
+                info: _,
+                name: _,
+                kind: _,
+                major: _,
+                minor: _,
+            } => 0x3u8,
+            Self::Fifo { info: _, name: _ } => 0x4u8,
             Self::Subtree {
                 info: _,
                 name: _,
@@ -251,6 +516,30 @@ impl Packable for TreeEntry {
                 (name.len() as u32).pack(output).e_context(context)?;
                 output.write(name.as_bytes()).e_context(context)?;
             }
+            Self::Device {
+                info,
+                name,
+                kind,
+                major,
+                minor,
+            } => {
+                info.pack(output).e_context(context)?;
+                match kind {
+                    DeviceKind::Character => 0x0u8,
+                    DeviceKind::Block => 0x1u8,
+                }
+                .pack(output)
+                .e_context(context)?;
+                major.pack(output).e_context(context)?;
+                minor.pack(output).e_context(context)?;
+                (name.len() as u32).pack(output).e_context(context)?;
+                output.write(name.as_bytes()).e_context(context)?;
+            }
+            Self::Fifo { info, name } => {
+                info.pack(output).e_context(context)?;
+                (name.len() as u32).pack(output).e_context(context)?;
+                output.write(name.as_bytes()).e_context(context)?;
+            }
         }
 
         Ok(())
@@ -271,6 +560,14 @@ impl Display for TreeEntry {
                 name,
                 tree,
             } => write!(f, "TREE [{}] => {name}", tree.oid()),
+            Self::Device {
+                info: _,
+                name,
+                kind,
+                major,
+                minor,
+            } => write!(f, "DEV  [{kind} {major}:{minor}] => {name}"),
+            Self::Fifo { info: _, name } => write!(f, "FIFO => {name}"),
         }
     }
 }
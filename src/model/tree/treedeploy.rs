@@ -0,0 +1,223 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    model::ObjectDB,
+    util::{self, fs::PathUtil},
+};
+
+use super::{Tree, TreeEntry};
+
+/// A change to a root directory made by [Tree::deploy_replacing()], recorded for rolling it back
+enum ReplaceStep {
+    /// A directory has been created
+    CreatedDir(PathBuf),
+    /// A file has been put in place, `backed_up` if an existing one has been moved to the backup
+    Placed { path: PathBuf, backed_up: bool },
+    /// A file has been moved to the backup
+    Removed(PathBuf),
+}
+
+/// The progress of deploying a tree, reported after every deployed entry
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeployProgress {
+    /// The number of entries (files, symlinks and subtrees) deployed so far
+    pub entries_done: usize,
+    /// The number of entries of the tree, including the ones of all subtrees
+    pub entries_total: usize,
+    /// The number of bytes written to files so far
+    pub bytes_written: u64,
+}
+
+impl Tree {
+    /// Returns the number of entries in this tree, including the ones of all subtrees
+    pub fn entry_count(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| match entry {
+                TreeEntry::Subtree { tree, .. } => 1 + tree.entry_count(),
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// Replaces the files of `previous`, which is deployed to `root`, by the files of this tree,
+    /// leaving all other files of `root` untouched (e.g. to upgrade an installed package).
+    ///
+    /// This tree gets deployed to a staging directory next to `root` first, then every file
+    /// gets renamed over its old version and the files of `previous` that are not part of this
+    /// tree get removed. Replaced and removed files are moved to a backup directory until all
+    /// files are in place, so `root` is rolled back to `previous` if anything fails.
+    /// Directories that are empty after removing files get removed, too
+    /// # Arguments
+    /// * `previous` - The tree deployed to `root` that gets replaced
+    /// * `root` - The root directory `previous` has been deployed to
+    /// * `db` - The object database to use for getting objects
+    pub fn deploy_replacing(
+        &self,
+        previous: &Tree,
+        root: &Path,
+        db: &ObjectDB,
+    ) -> Result<(), Error> {
+        util::fs::create_dir_all(root).ctx(|| "Creating root directory")?;
+
+        // Staging and backup directories must live on the same filesystem to be able to rename
+        let id = uuid::Uuid::new_v4();
+        let name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let staging = root.with_file_name(format!(".{name}.staging-{id}"));
+        let backup = root.with_file_name(format!(".{name}.backup-{id}"));
+
+        if let Err(e) = self.deploy(&staging, db) {
+            util::fs::remove_dir_all(&staging)?;
+            return Err(e).ctx(|| format!("Staging deployment at {}", staging.str_lossy()));
+        }
+        util::fs::create_dir(&backup).ctx(|| "Creating backup directory")?;
+
+        let mut steps = Vec::new();
+        let res = self.replace_files(previous, root, &staging, &backup, &mut steps);
+
+        if let Err(e) = res {
+            warn!("Deployment to {} failed, rolling back", root.str_lossy());
+
+            for step in steps.into_iter().rev() {
+                match step {
+                    ReplaceStep::CreatedDir(path) => util::fs::remove_dir(&root.join(path))?,
+                    ReplaceStep::Placed { path, backed_up } => {
+                        util::fs::remove_file(&root.join(&path))?;
+                        if backed_up {
+                            util::fs::rename(&backup.join(&path), &root.join(&path))?;
+                        }
+                    }
+                    ReplaceStep::Removed(path) => {
+                        util::fs::rename(&backup.join(&path), &root.join(&path))?
+                    }
+                }
+            }
+
+            util::fs::remove_dir_all(&staging)?;
+            util::fs::remove_dir_all(&backup)?;
+
+            return Err(e).ctx(|| format!("Deploying to {}", root.str_lossy()));
+        }
+
+        util::fs::remove_dir_all(&staging)?;
+        util::fs::remove_dir_all(&backup)?;
+
+        // Remove the directories that are empty now, deepest first
+        let mut dirs: Vec<PathBuf> = steps
+            .iter()
+            .filter_map(|step| match step {
+                ReplaceStep::Removed(path) => Some(path),
+                _ => None,
+            })
+            .flat_map(|f| f.ancestors().skip(1))
+            .filter(|d| !d.as_os_str().is_empty())
+            .map(|d| d.to_owned())
+            .collect();
+        dirs.sort_by(|a, b| {
+            b.components()
+                .count()
+                .cmp(&a.components().count())
+                .then_with(|| a.cmp(b))
+        });
+        dirs.dedup();
+
+        for dir in dirs {
+            // Directories shared with other packages are not empty and stay
+            if std::fs::remove_dir(root.join(&dir)).is_ok() {
+                debug!("Removed empty directory {}", dir.str_lossy());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the staged files of this tree into `root` and the files of `previous`
+    /// that are not part of this tree to `backup`, recording every change to `steps`
+    /// # Arguments
+    /// * `previous` - The tree deployed to `root` that gets replaced
+    /// * `root` - The root directory to replace the files in
+    /// * `staging` - The directory this tree has been deployed to
+    /// * `backup` - The directory to move replaced and removed files to
+    /// * `steps` - The changes made so far
+    fn replace_files(
+        &self,
+        previous: &Tree,
+        root: &Path,
+        staging: &Path,
+        backup: &Path,
+        steps: &mut Vec<ReplaceStep>,
+    ) -> Result<(), Error> {
+        let paths = self.get_file_paths();
+
+        for path in &paths {
+            let target = root.join(path);
+
+            if target.is_dir() && !target.is_symlink() {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "{} is a directory",
+                    target.str_lossy()
+                ))));
+            }
+
+            // Create the missing parents with the modes of their staged counterparts
+            let mut missing: Vec<&Path> = path
+                .ancestors()
+                .skip(1)
+                .filter(|d| !d.as_os_str().is_empty() && !root.join(d).exists())
+                .collect();
+            missing.reverse();
+            for dir in missing {
+                util::fs::create_dir(&root.join(dir))?;
+                steps.push(ReplaceStep::CreatedDir(dir.to_owned()));
+
+                let permissions = std::fs::symlink_metadata(staging.join(dir))
+                    .e_context(|| format!("Reading metadata of staged {}", dir.str_lossy()))?
+                    .permissions();
+                std::fs::set_permissions(root.join(dir), permissions)
+                    .e_context(|| format!("Setting permissions of {}", dir.str_lossy()))?;
+            }
+
+            let backed_up = target.symlink_metadata().is_ok();
+            if backed_up {
+                let dest = backup.join(path);
+                util::fs::create_parent_dir_all(&dest)?;
+                util::fs::rename(&target, &dest)?;
+            }
+
+            if let Err(e) = util::fs::rename(&staging.join(path), &target) {
+                if backed_up {
+                    util::fs::rename(&backup.join(path), &target)?;
+                }
+                return Err(e);
+            }
+            steps.push(ReplaceStep::Placed {
+                path: path.clone(),
+                backed_up,
+            });
+        }
+
+        let paths: HashSet<PathBuf> = paths.into_iter().collect();
+        for path in previous.get_file_paths() {
+            let target = root.join(&path);
+            if paths.contains(&path) || target.symlink_metadata().is_err() {
+                continue;
+            }
+
+            let dest = backup.join(&path);
+            util::fs::create_parent_dir_all(&dest)?;
+            util::fs::rename(&target, &dest)?;
+            steps.push(ReplaceStep::Removed(path));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::util::fs::{PathUtil, UNIXInfo};
+
+use super::{Tree, TreeEntry};
+
+/// A difference between two trees
+#[derive(Debug, PartialEq, Eq)]
+pub enum TreeDifference {
+    /// The entry only exists in the new tree
+    Added(PathBuf),
+    /// The entry only exists in the old tree
+    Removed(PathBuf),
+    /// The entry changed its type (file, symlink, subtree)
+    TypeChanged(PathBuf),
+    /// The contents (object id or symlink destination) of the entry differ
+    ContentChanged(PathBuf),
+    /// The UNIX information of the entry differs
+    InfoChanged {
+        path: PathBuf,
+        old: UNIXInfo,
+        new: UNIXInfo,
+    },
+}
+
+impl Tree {
+    /// Computes the differences between `self` (old) and `other` (new), recursing into subtrees
+    /// # Arguments
+    /// * `other` - The tree to compare against
+    pub fn diff(&self, other: &Tree) -> Vec<TreeDifference> {
+        let mut differences = Vec::new();
+        self.diff_into(other, Path::new(""), &mut differences);
+        differences
+    }
+
+    /// Computes the differences between `self` and `other` and pushes them to `differences`
+    /// # Arguments
+    /// * `other` - The tree to compare against
+    /// * `prefix` - The path of the trees relative to the root tree
+    /// * `differences` - The vector to push the differences to
+    fn diff_into(&self, other: &Tree, prefix: &Path, differences: &mut Vec<TreeDifference>) {
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+
+            let other_entry = match other.get_entry_by_name(entry.name()) {
+                Some(other_entry) => other_entry,
+                None => {
+                    differences.push(TreeDifference::Removed(path));
+                    continue;
+                }
+            };
+
+            let (old_info, new_info) = (entry.info(), other_entry.info());
+            if old_info != new_info {
+                differences.push(TreeDifference::InfoChanged {
+                    path: path.clone(),
+                    old: old_info.clone(),
+                    new: new_info.clone(),
+                });
+            }
+
+            match (entry, other_entry) {
+                (
+                    TreeEntry::File {
+                        info: _,
+                        name: _,
+                        oid,
+                    },
+                    TreeEntry::File {
+                        info: _,
+                        name: _,
+                        oid: other_oid,
+                    },
+                ) => {
+                    if oid != other_oid {
+                        differences.push(TreeDifference::ContentChanged(path));
+                    }
+                }
+                (
+                    TreeEntry::Symlink {
+                        info: _,
+                        name: _,
+                        destination,
+                    },
+                    TreeEntry::Symlink {
+                        info: _,
+                        name: _,
+                        destination: other_destination,
+                    },
+                ) => {
+                    if destination != other_destination {
+                        differences.push(TreeDifference::ContentChanged(path));
+                    }
+                }
+                (
+                    TreeEntry::Subtree {
+                        info: _,
+                        name: _,
+                        tree,
+                    },
+                    TreeEntry::Subtree {
+                        info: _,
+                        name: _,
+                        tree: other_tree,
+                    },
+                ) => tree.diff_into(other_tree, &path, differences),
+                (
+                    TreeEntry::Device {
+                        info: _,
+                        name: _,
+                        kind,
+                        major,
+                        minor,
+                    },
+                    TreeEntry::Device {
+                        info: _,
+                        name: _,
+                        kind: other_kind,
+                        major: other_major,
+                        minor: other_minor,
+                    },
+                ) => {
+                    if (kind, major, minor) != (other_kind, other_major, other_minor) {
+                        differences.push(TreeDifference::ContentChanged(path));
+                    }
+                }
+                (TreeEntry::Fifo { info: _, name: _ }, TreeEntry::Fifo { info: _, name: _ }) => {}
+                _ => differences.push(TreeDifference::TypeChanged(path)),
+            }
+        }
+
+        for entry in &other.entries {
+            if self.get_entry_by_name(entry.name()).is_none() {
+                differences.push(TreeDifference::Added(prefix.join(entry.name())));
+            }
+        }
+    }
+}
+
+impl TreeDifference {
+    /// Returns the path of the entry that differs, relative to the root tree
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Added(path)
+            | Self::Removed(path)
+            | Self::TypeChanged(path)
+            | Self::ContentChanged(path) => path,
+            Self::InfoChanged {
+                path,
+                old: _,
+                new: _,
+            } => path,
+        }
+    }
+}
+
+impl Display for TreeDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added(path) => write!(f, "+ {}", path.str_lossy()),
+            Self::Removed(path) => write!(f, "- {}", path.str_lossy()),
+            Self::TypeChanged(path) => write!(f, "~ {} (type)", path.str_lossy()),
+            Self::ContentChanged(path) => write!(f, "~ {} (contents)", path.str_lossy()),
+            Self::InfoChanged { path, old, new } => write!(
+                f,
+                "~ {} ({}:{} {:o} => {}:{} {:o})",
+                path.str_lossy(),
+                old.uid,
+                old.gid,
+                old.mode,
+                new.uid,
+                new.gid,
+                new.mode
+            ),
+        }
+    }
+}
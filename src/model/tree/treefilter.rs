@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use crate::util::{fs::PathUtil, string::glob_match};
+
+use super::{Tree, TreeEntry};
+
+impl Tree {
+    /// Creates a new tree containing only the entries `predicate` matches.
+    ///
+    /// The predicate gets called with the path of every entry relative to the root
+    /// of this tree. A matching subtree is kept including all of its contents,
+    /// other subtrees are filtered recursively and dropped if nothing in them matches.
+    ///
+    /// The filtered tree and its changed subtrees are not in the object database
+    /// yet, use [Tree::insert_into_odb()] to insert them
+    /// # Arguments
+    /// * `predicate` - The function deciding whether to keep an entry
+    pub fn filter<F: FnMut(&Path, &TreeEntry) -> bool>(&self, mut predicate: F) -> Tree {
+        self.filter_in(Path::new(""), &mut predicate)
+    }
+
+    /// Creates a new tree containing only the entries whose path matches
+    /// one of `patterns` (see [glob_match()]), refer to [Tree::filter()]
+    /// # Arguments
+    /// * `patterns` - The glob patterns to match the paths against, leading `/` are ignored
+    pub fn filter_glob<S: AsRef<str>>(&self, patterns: &[S]) -> Tree {
+        self.filter(|path, _| matches_any(patterns, path))
+    }
+
+    /// Creates a new tree without the entries whose path matches one of `patterns`
+    /// (see [glob_match()]), the inverse of [Tree::filter_glob()].
+    /// Matching subtrees are dropped including all of their contents,
+    /// subtrees that end up empty are dropped, too
+    /// # Arguments
+    /// * `patterns` - The glob patterns to match the paths against, leading `/` are ignored
+    pub fn exclude_glob<S: AsRef<str>>(&self, patterns: &[S]) -> Tree {
+        self.filter(|path, entry| {
+            !matches!(
+                entry,
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree: _
+                }
+            ) && !path.ancestors().any(|path| matches_any(patterns, path))
+        })
+    }
+
+    /// Creates a new tree for a sparse checkout: Only the entries whose path
+    /// or one of its parent directories matches one of `include` are kept,
+    /// everything if `include` is empty. Entries matching one of `exclude`
+    /// are dropped including all of their contents.
+    ///
+    /// The patterns are evaluated while walking the tree, so excluded subtrees
+    /// are never descended into. Included directories are kept even if their
+    /// contents got excluded, other directories are dropped if nothing in them is kept
+    /// # Arguments
+    /// * `include` - The glob patterns of the paths to keep, leading `/` are ignored
+    /// * `exclude` - The glob patterns of the paths to drop, leading `/` are ignored
+    pub fn sparse<S: AsRef<str>>(&self, include: &[S], exclude: &[S]) -> Tree {
+        self.sparse_in(Path::new(""), include, exclude, include.is_empty())
+    }
+
+    /// Recursively selects the entries of this tree for a sparse checkout
+    /// # Arguments
+    /// * `prefix` - The path of this tree relative to the root tree
+    /// * `include` - The glob patterns of the paths to keep
+    /// * `exclude` - The glob patterns of the paths to drop
+    /// * `included` - Whether this tree is included already
+    fn sparse_in<S: AsRef<str>>(
+        &self,
+        prefix: &Path,
+        include: &[S],
+        exclude: &[S],
+        included: bool,
+    ) -> Tree {
+        let mut entries = Vec::new();
+
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+
+            if matches_any(exclude, &path) {
+                continue;
+            }
+
+            let included = included || matches_any(include, &path);
+
+            match entry {
+                TreeEntry::Subtree { info, name, tree } => {
+                    let tree = tree.sparse_in(&path, include, exclude, included);
+
+                    if included || !tree.entries.is_empty() {
+                        entries.push(TreeEntry::Subtree {
+                            info: info.clone(),
+                            name: name.clone(),
+                            tree,
+                        });
+                    }
+                }
+                _ if included => entries.push(entry.clone()),
+                _ => {}
+            }
+        }
+
+        Tree { entries }
+    }
+
+    /// Recursively filters the entries of this tree
+    /// # Arguments
+    /// * `prefix` - The path of this tree relative to the root tree
+    /// * `predicate` - The function deciding whether to keep an entry
+    fn filter_in<F: FnMut(&Path, &TreeEntry) -> bool>(
+        &self,
+        prefix: &Path,
+        predicate: &mut F,
+    ) -> Tree {
+        let mut entries = Vec::new();
+
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+
+            if predicate(&path, entry) {
+                entries.push(entry.clone());
+                continue;
+            }
+
+            if let TreeEntry::Subtree { info, name, tree } = entry {
+                let tree = tree.filter_in(&path, predicate);
+
+                if !tree.entries.is_empty() {
+                    entries.push(TreeEntry::Subtree {
+                        info: info.clone(),
+                        name: name.clone(),
+                        tree,
+                    });
+                }
+            }
+        }
+
+        Tree { entries }
+    }
+}
+
+/// Returns whether `path` matches any of the glob `patterns`
+/// # Arguments
+/// * `patterns` - The glob patterns to match against, leading `/` are ignored
+/// * `path` - The path to match
+pub(super) fn matches_any<S: AsRef<str>>(patterns: &[S], path: &Path) -> bool {
+    let path = path.str_lossy();
+
+    patterns
+        .iter()
+        .any(|pattern| glob_match(pattern.as_ref().trim_start_matches('/'), &path))
+}
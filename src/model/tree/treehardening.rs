@@ -0,0 +1,235 @@
+use std::{
+    fmt::Display,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use elf::{abi, endian::AnyEndian, ElfBytes};
+
+use crate::{
+    error::{Error, ErrorExt},
+    model::{ObjectDB, ObjectType},
+    package::{is_within_dirs, should_descend},
+    util::fs::PathUtil,
+};
+
+use super::{Tree, TreeEntry};
+
+/// The symbols referenced by code compiled with stack protection
+static STACK_CANARY_SYMBOLS: &[&str] = &["__stack_chk_fail", "__stack_chk_guard"];
+
+/// A missing hardening feature of an ELF file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HardeningIssueKind {
+    /// There is no `PT_GNU_RELRO` segment, relocations stay writable
+    NoRelro,
+    /// Relocations are read-only, but symbols are bound lazily (missing `BIND_NOW`),
+    /// so the GOT stays writable
+    PartialRelro,
+    /// The executable is not position independent (`ET_EXEC`)
+    NoPie,
+    /// The stack is executable (`PT_GNU_STACK` with `PF_X` or missing)
+    ExecutableStack,
+    /// The file does not reference the stack protector (`__stack_chk_fail`)
+    NoStackCanary,
+}
+
+/// An ELF file in a tree that lacks a hardening feature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HardeningIssue {
+    /// The path to the ELF file
+    pub path: PathBuf,
+    /// The missing hardening feature
+    pub kind: HardeningIssueKind,
+}
+
+impl Tree {
+    /// Checks the executables and shared libraries in this tree for missing
+    /// hardening features (RELRO, PIE, non-executable stack and stack canaries).
+    ///
+    /// Only files inserted as [ObjectType::ELF] are checked. Stack canaries
+    /// can't be detected in files without symbol tables, these are skipped
+    /// # Arguments
+    /// * `odb` - The object database to read the ELF files from
+    pub fn check_hardening(&self, odb: &ObjectDB) -> Result<Vec<HardeningIssue>, Error> {
+        let mut issues = Vec::new();
+        self.check_hardening_in(odb, Path::new(""), None, &mut issues)?;
+        Ok(issues)
+    }
+
+    /// Checks the ELF files within `dirs` for missing hardening features,
+    /// refer to [Tree::check_hardening()]. Subtrees outside of `dirs` are not descended into
+    /// # Arguments
+    /// * `odb` - The object database to read the ELF files from
+    /// * `dirs` - The directories to check, relative to this tree (e.g. [crate::package::PackageLayout::get_scan_dirs()])
+    pub fn check_hardening_in_dirs(
+        &self,
+        odb: &ObjectDB,
+        dirs: &[PathBuf],
+    ) -> Result<Vec<HardeningIssue>, Error> {
+        let mut issues = Vec::new();
+        self.check_hardening_in(odb, Path::new(""), Some(dirs), &mut issues)?;
+        Ok(issues)
+    }
+
+    /// Recursively checks the ELF files of this tree
+    /// # Arguments
+    /// * `odb` - The object database to read the ELF files from
+    /// * `prefix` - The path of this tree relative to the root tree
+    /// * `dirs` - The directories to check, `None` to check all of them
+    /// * `issues` - The vector to push the found issues to
+    fn check_hardening_in(
+        &self,
+        odb: &ObjectDB,
+        prefix: &Path,
+        dirs: Option<&[PathBuf]>,
+        issues: &mut Vec<HardeningIssue>,
+    ) -> Result<(), Error> {
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+
+            match entry {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => {
+                    if should_descend(&path, dirs) {
+                        tree.check_hardening_in(odb, &path, dirs, issues)?
+                    }
+                }
+                TreeEntry::File {
+                    info: _,
+                    name: _,
+                    oid,
+                } => {
+                    if !is_within_dirs(&path, dirs) || odb.get_object(oid)?.ty != ObjectType::ELF {
+                        continue;
+                    }
+
+                    let mut data = Vec::new();
+                    odb.read(oid)?
+                        .read_to_end(&mut data)
+                        .e_context(|| format!("Reading ELF file {}", path.str_lossy()))?;
+
+                    let kinds = check_elf_hardening(&data)
+                        .ctx(|| format!("Checking ELF file {}", path.str_lossy()))?;
+
+                    for kind in kinds {
+                        issues.push(HardeningIssue {
+                            path: path.clone(),
+                            kind,
+                        });
+                    }
+                }
+                TreeEntry::Symlink {
+                    info: _,
+                    name: _,
+                    destination: _,
+                }
+                | TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind: _,
+                    major: _,
+                    minor: _,
+                }
+                | TreeEntry::Fifo { info: _, name: _ } => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the ELF file in `data` for missing hardening features,
+/// object files and core dumps are not checked
+/// # Arguments
+/// * `data` - The contents of the ELF file
+pub fn check_elf_hardening(data: &[u8]) -> Result<Vec<HardeningIssueKind>, Error> {
+    let file = ElfBytes::<AnyEndian>::minimal_parse(data).e_context(|| "Parsing ELF header")?;
+
+    let e_type = file.ehdr.e_type;
+    if e_type != abi::ET_EXEC && e_type != abi::ET_DYN {
+        return Ok(Vec::new());
+    }
+
+    let mut kinds = Vec::new();
+
+    let segments: Vec<_> = file.segments().into_iter().flatten().collect();
+    let has_segment = |p_type| segments.iter().any(|s| s.p_type == p_type);
+
+    // Shared libraries are always position independent, only executables have an interpreter
+    if e_type == abi::ET_EXEC {
+        kinds.push(HardeningIssueKind::NoPie);
+    }
+
+    let bind_now = match file.dynamic().e_context(|| "Parsing dynamic section")? {
+        Some(dynamic) => dynamic.iter().any(|d| match d.d_tag {
+            abi::DT_BIND_NOW => true,
+            abi::DT_FLAGS => d.d_val() as i64 & abi::DF_BIND_NOW != 0,
+            abi::DT_FLAGS_1 => d.d_val() as i64 & abi::DF_1_NOW != 0,
+            _ => false,
+        }),
+        // Statically linked files have nothing to bind
+        None => true,
+    };
+
+    if !has_segment(abi::PT_GNU_RELRO) {
+        kinds.push(HardeningIssueKind::NoRelro);
+    } else if !bind_now {
+        kinds.push(HardeningIssueKind::PartialRelro);
+    }
+
+    let executable_stack = segments
+        .iter()
+        .find(|s| s.p_type == abi::PT_GNU_STACK)
+        .is_none_or(|s| s.p_flags & abi::PF_X != 0);
+    if executable_stack {
+        kinds.push(HardeningIssueKind::ExecutableStack);
+    }
+
+    let mut has_symbols = false;
+    let mut has_canary = false;
+    for table in [
+        file.dynamic_symbol_table()
+            .e_context(|| "Parsing dynamic symbol table")?,
+        file.symbol_table().e_context(|| "Parsing symbol table")?,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let (symbols, strings) = table;
+        has_symbols = true;
+
+        has_canary |= symbols.iter().any(|symbol| {
+            strings
+                .get(symbol.st_name as usize)
+                .is_ok_and(|name| STACK_CANARY_SYMBOLS.contains(&name))
+        });
+    }
+
+    if has_symbols && !has_canary {
+        kinds.push(HardeningIssueKind::NoStackCanary);
+    }
+
+    Ok(kinds)
+}
+
+impl Display for HardeningIssueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoRelro => write!(f, "No RELRO"),
+            Self::PartialRelro => write!(f, "Partial RELRO (missing BIND_NOW)"),
+            Self::NoPie => write!(f, "Not position independent (no PIE)"),
+            Self::ExecutableStack => write!(f, "Executable stack"),
+            Self::NoStackCanary => write!(f, "No stack canary"),
+        }
+    }
+}
+
+impl Display for HardeningIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.str_lossy(), self.kind)
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fmt::Display,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{Error, ErrorExt},
+    model::ObjectDB,
+    util::{fs::PathUtil, parse::spdx::SpdxExpression},
+};
+
+use super::{Tree, TreeEntry};
+
+/// The amount of bytes to read from license files to detect their license
+static LICENSE_DETECT_SIZE: u64 = 64 * 1024;
+
+/// Phrases identifying license texts, the first license whose phrases
+/// are all contained in a license file is detected.
+/// More specific licenses need to come before the ones they contain
+static LICENSE_SIGNATURES: &[(&str, &[&str])] = &[
+    (
+        "AGPL-3.0",
+        &["GNU AFFERO GENERAL PUBLIC LICENSE", "Version 3"],
+    ),
+    (
+        "LGPL-3.0",
+        &["GNU LESSER GENERAL PUBLIC LICENSE", "Version 3"],
+    ),
+    (
+        "LGPL-2.1",
+        &["GNU LESSER GENERAL PUBLIC LICENSE", "Version 2.1"],
+    ),
+    (
+        "LGPL-2.0",
+        &["GNU LIBRARY GENERAL PUBLIC LICENSE", "Version 2"],
+    ),
+    ("GPL-3.0", &["GNU GENERAL PUBLIC LICENSE", "Version 3"]),
+    ("GPL-2.0", &["GNU GENERAL PUBLIC LICENSE", "Version 2"]),
+    ("Apache-2.0", &["Apache License", "Version 2.0"]),
+    ("MPL-2.0", &["Mozilla Public License Version 2.0"]),
+    (
+        "BSD-3-Clause",
+        &[
+            "Redistribution and use in source and binary forms",
+            "Neither the name",
+        ],
+    ),
+    (
+        "BSD-2-Clause",
+        &["Redistribution and use in source and binary forms"],
+    ),
+    ("MIT", &["Permission is hereby granted, free of charge"]),
+    (
+        "ISC",
+        &["Permission to use, copy, modify, and/or distribute this software"],
+    ),
+    (
+        "Zlib",
+        &["This software is provided 'as-is', without any express or implied"],
+    ),
+];
+
+/// A license file whose license is not part of the declared license
+#[derive(Debug, PartialEq, Eq)]
+pub struct LicenseMismatch {
+    /// The path to the license file
+    pub path: PathBuf,
+    /// The license detected in the file
+    pub detected: &'static str,
+}
+
+impl Tree {
+    /// Checks the license files (`LICENSE*`, `LICENCE*`, `COPYING*`) in this tree against `license`.
+    ///
+    /// The license of a file is detected using well-known phrases of common licenses,
+    /// files with unknown licenses are ignored
+    /// # Arguments
+    /// * `license` - The declared license of the tree
+    /// * `odb` - The object database to read the license files from
+    pub fn check_licenses(
+        &self,
+        license: &SpdxExpression,
+        odb: &ObjectDB,
+    ) -> Result<Vec<LicenseMismatch>, Error> {
+        let mut mismatches = Vec::new();
+        self.check_licenses_in(license, odb, Path::new(""), &mut mismatches)?;
+        Ok(mismatches)
+    }
+
+    /// Recursively checks the license files of this tree
+    /// # Arguments
+    /// * `license` - The declared license of the tree
+    /// * `odb` - The object database to read the license files from
+    /// * `prefix` - The path of this tree relative to the root tree
+    /// * `mismatches` - The vector to push the found mismatches to
+    fn check_licenses_in(
+        &self,
+        license: &SpdxExpression,
+        odb: &ObjectDB,
+        prefix: &Path,
+        mismatches: &mut Vec<LicenseMismatch>,
+    ) -> Result<(), Error> {
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+
+            match entry {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => tree.check_licenses_in(license, odb, &path, mismatches)?,
+                TreeEntry::File { info: _, name, oid } => {
+                    if !is_license_file(name) {
+                        continue;
+                    }
+
+                    let mut text = String::new();
+                    odb.read(oid)?
+                        .take(LICENSE_DETECT_SIZE)
+                        .read_to_string(&mut text)
+                        .e_context(|| format!("Reading license file {}", path.str_lossy()))?;
+
+                    if let Some(detected) = detect_license(&text) {
+                        if !license.mentions(detected) {
+                            mismatches.push(LicenseMismatch { path, detected });
+                        }
+                    }
+                }
+                TreeEntry::Symlink {
+                    info: _,
+                    name: _,
+                    destination: _,
+                }
+                | TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind: _,
+                    major: _,
+                    minor: _,
+                }
+                | TreeEntry::Fifo { info: _, name: _ } => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks if `name` is the name of a license file
+/// # Arguments
+/// * `name` - The file name to check
+fn is_license_file(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+
+    ["LICENSE", "LICENCE", "COPYING"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Detects the license of a license text using [LICENSE_SIGNATURES]
+/// # Arguments
+/// * `text` - The license text
+fn detect_license(text: &str) -> Option<&'static str> {
+    // Normalize line breaks and indentation within phrases
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    LICENSE_SIGNATURES
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| text.contains(phrase)))
+        .map(|(id, _)| *id)
+}
+
+impl Display for LicenseMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} looks like {}, which is not part of the declared license",
+            self.path.str_lossy(),
+            self.detected
+        )
+    }
+}
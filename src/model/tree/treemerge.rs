@@ -0,0 +1,266 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    error::{Error, ErrorType},
+    model::ObjectID,
+    util::fs::{DeviceKind, PathUtil},
+};
+
+use super::{Tree, TreeEntry};
+
+/// How to resolve entries that exist in both trees with different contents when merging
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep the entry of the tree that is merged into
+    #[default]
+    KeepOurs,
+    /// Replace the entry with the one of the tree that gets merged in
+    KeepTheirs,
+    /// Fail the merge without changing the tree
+    Error,
+}
+
+/// The contents of one side of a [MergeConflict]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeSide {
+    /// A file with the object id of its contents
+    File(ObjectID),
+    /// A symlink with its destination
+    Symlink(String),
+    /// A subtree with its object id
+    Subtree(ObjectID),
+    /// A device node with its kind and major and minor numbers
+    Device(DeviceKind, u32, u32),
+    /// A FIFO
+    Fifo,
+}
+
+/// An entry that exists in both merged trees with different contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    /// The path of the entry relative to the root of the trees
+    pub path: PathBuf,
+    /// The entry of the tree that is merged into
+    pub ours: MergeSide,
+    /// The entry of the tree that gets merged in
+    pub theirs: MergeSide,
+}
+
+impl Tree {
+    /// Merges another tree into this tree like [Tree::merge()], resolving
+    /// entries with different contents in both trees using `strategy`.
+    ///
+    /// Subtrees existing in both trees are merged recursively and are no conflict.
+    /// Entries only differing in their UNIX info are no conflict and keep the info of this tree.
+    /// # Arguments
+    /// * `other` - The other tree to merge
+    /// * `strategy` - The strategy to resolve conflicts with
+    /// # Returns
+    /// The conflicts that have been resolved, [MergeStrategy::Error] fails
+    /// listing the conflicts and leaves this tree unchanged
+    pub fn merge_with_strategy(
+        &mut self,
+        other: Tree,
+        strategy: MergeStrategy,
+    ) -> Result<Vec<MergeConflict>, Error> {
+        let mut conflicts = Vec::new();
+
+        if strategy == MergeStrategy::Error {
+            self.find_merge_conflicts(&other, Path::new(""), &mut conflicts);
+
+            if !conflicts.is_empty() {
+                let mut msg = "Trees conflict:".to_owned();
+                for conflict in &conflicts {
+                    msg.push_str(&format!("\n  {conflict}"));
+                }
+
+                return Err(Error::new(ErrorType::Other(msg)));
+            }
+        }
+
+        self.merge_into(
+            other,
+            strategy == MergeStrategy::KeepTheirs,
+            Path::new(""),
+            &mut conflicts,
+        );
+
+        Ok(conflicts)
+    }
+
+    /// Recursively merges `other` into this tree
+    /// # Arguments
+    /// * `other` - The other tree to merge
+    /// * `keep_theirs` - Whether to replace conflicting entries with the ones of `other`
+    /// * `prefix` - The path of the trees relative to the root tree
+    /// * `conflicts` - The vector to push the conflicts to
+    pub(super) fn merge_into(
+        &mut self,
+        other: Tree,
+        keep_theirs: bool,
+        prefix: &Path,
+        conflicts: &mut Vec<MergeConflict>,
+    ) {
+        for entry in other.entries {
+            let path = prefix.join(entry.name());
+
+            let my_entry = match self.get_entry_by_name_mut(entry.name()) {
+                None => {
+                    self.entries.push(entry);
+                    continue;
+                }
+                Some(my_entry) => my_entry,
+            };
+
+            match (my_entry, entry) {
+                (
+                    TreeEntry::Subtree {
+                        info: _,
+                        name: _,
+                        tree: my_tree,
+                    },
+                    TreeEntry::Subtree {
+                        info: _,
+                        name: _,
+                        tree,
+                    },
+                ) => my_tree.merge_into(tree, keep_theirs, &path, conflicts),
+                (my_entry, entry) => {
+                    let (ours, theirs) = (MergeSide::from(&*my_entry), MergeSide::from(&entry));
+
+                    if ours != theirs {
+                        conflicts.push(MergeConflict { path, ours, theirs });
+
+                        if keep_theirs {
+                            *my_entry = entry;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.entries.sort();
+    }
+
+    /// Recursively finds the conflicts of merging `other` into this tree without merging
+    /// # Arguments
+    /// * `other` - The other tree to check
+    /// * `prefix` - The path of the trees relative to the root tree
+    /// * `conflicts` - The vector to push the conflicts to
+    fn find_merge_conflicts(
+        &self,
+        other: &Tree,
+        prefix: &Path,
+        conflicts: &mut Vec<MergeConflict>,
+    ) {
+        for entry in &other.entries {
+            let path = prefix.join(entry.name());
+
+            match (self.get_entry_by_name(entry.name()), entry) {
+                (None, _) => {}
+                (
+                    Some(TreeEntry::Subtree {
+                        info: _,
+                        name: _,
+                        tree: my_tree,
+                    }),
+                    TreeEntry::Subtree {
+                        info: _,
+                        name: _,
+                        tree,
+                    },
+                ) => my_tree.find_merge_conflicts(tree, &path, conflicts),
+                (Some(my_entry), entry) => {
+                    let (ours, theirs) = (MergeSide::from(my_entry), MergeSide::from(entry));
+
+                    if ours != theirs {
+                        conflicts.push(MergeConflict { path, ours, theirs });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl From<&TreeEntry> for MergeSide {
+    fn from(value: &TreeEntry) -> Self {
+        match value {
+            TreeEntry::File {
+                info: _,
+                name: _,
+                oid,
+            } => Self::File(oid.clone()),
+            TreeEntry::Symlink {
+                info: _,
+                name: _,
+                destination,
+            } => Self::Symlink(destination.clone()),
+            TreeEntry::Subtree {
+                info: _,
+                name: _,
+                tree,
+            } => Self::Subtree(tree.oid()),
+            TreeEntry::Device {
+                info: _,
+                name: _,
+                kind,
+                major,
+                minor,
+            } => Self::Device(*kind, *major, *minor),
+            TreeEntry::Fifo { info: _, name: _ } => Self::Fifo,
+        }
+    }
+}
+
+impl FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep-ours" => Ok(Self::KeepOurs),
+            "keep-theirs" => Ok(Self::KeepTheirs),
+            "error" => Ok(Self::Error),
+            _ => Err(format!(
+                "Unknown merge strategy '{s}', expected 'keep-ours', 'keep-theirs' or 'error'"
+            )),
+        }
+    }
+}
+
+impl Display for MergeStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::KeepOurs => write!(f, "keep-ours"),
+            Self::KeepTheirs => write!(f, "keep-theirs"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl Display for MergeSide {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(oid) => write!(f, "FILE [{oid}]"),
+            Self::Symlink(destination) => write!(f, "LINK => {destination}"),
+            Self::Subtree(oid) => write!(f, "TREE [{oid}]"),
+            Self::Device(kind, major, minor) => write!(f, "DEV  [{kind} {major}:{minor}]"),
+            Self::Fifo => write!(f, "FIFO"),
+        }
+    }
+}
+
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} <=> {}",
+            self.path.str_lossy(),
+            self.ours,
+            self.theirs
+        )
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use elf::{endian::AnyEndian, ElfBytes};
+
+use crate::{
+    error::{Error, ErrorExt},
+    model::{ObjectDB, ObjectID, ObjectType},
+    package::{is_within_dirs, should_descend},
+    util::{
+        elf::ELFExt,
+        fs::{PathUtil, ScriptFile},
+        ODBUnpackable,
+    },
+};
+
+use super::{Tree, TreeEntry};
+
+/// What a file needs to be provided at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeNeedKind {
+    /// A shared library (`DT_NEEDED`) of an ELF file
+    Library,
+    /// The program interpreter (`PT_INTERP`) of an ELF file, e.g. the dynamic linker
+    Interpreter,
+    /// The interpreter of a script (`#!`), the program looked up by `env` for `env` shbangs
+    ScriptInterpreter,
+}
+
+/// A file that is needed at runtime by a file in a tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeNeed {
+    /// The path to the file having the need
+    pub path: PathBuf,
+    /// What kind of file is needed
+    pub kind: RuntimeNeedKind,
+    /// The name of the needed file
+    pub name: String,
+}
+
+impl Tree {
+    /// Finds the files the ELF files and scripts in this tree need at runtime:
+    /// The shared libraries and interpreters of ELF files and the interpreters of scripts.
+    ///
+    /// Only files inserted as [ObjectType::ELF] or [ObjectType::Script] are inspected
+    /// # Arguments
+    /// * `odb` - The object database to read the files from
+    pub fn find_runtime_needs(&self, odb: &ObjectDB) -> Result<Vec<RuntimeNeed>, Error> {
+        let mut needs = Vec::new();
+        self.find_runtime_needs_in(odb, Path::new(""), None, &mut needs)?;
+        Ok(needs)
+    }
+
+    /// Finds the runtime needs of the files within `dirs`,
+    /// refer to [Tree::find_runtime_needs()]. Subtrees outside of `dirs` are not descended into
+    /// # Arguments
+    /// * `odb` - The object database to read the files from
+    /// * `dirs` - The directories to inspect, relative to this tree (e.g. [crate::package::PackageLayout::get_scan_dirs()])
+    pub fn find_runtime_needs_in_dirs(
+        &self,
+        odb: &ObjectDB,
+        dirs: &[PathBuf],
+    ) -> Result<Vec<RuntimeNeed>, Error> {
+        let mut needs = Vec::new();
+        self.find_runtime_needs_in(odb, Path::new(""), Some(dirs), &mut needs)?;
+        Ok(needs)
+    }
+
+    /// Returns whether this tree provides a file named `name`,
+    /// which is the case if any file or symlink in it carries that name
+    /// # Arguments
+    /// * `name` - The name of the file to search for
+    pub fn provides(&self, name: &str) -> bool {
+        self.entries.iter().any(|entry| match entry {
+            TreeEntry::File {
+                info: _,
+                name: entry_name,
+                oid: _,
+            }
+            | TreeEntry::Symlink {
+                info: _,
+                name: entry_name,
+                destination: _,
+            } => entry_name == name,
+            TreeEntry::Subtree {
+                info: _,
+                name: _,
+                tree,
+            } => tree.provides(name),
+            TreeEntry::Device {
+                info: _,
+                name: _,
+                kind: _,
+                major: _,
+                minor: _,
+            }
+            | TreeEntry::Fifo { info: _, name: _ } => false,
+        })
+    }
+
+    /// Finds the declared `dependencies` that provide none of the runtime `needs`
+    /// of this tree that this tree does not provide by itself.
+    ///
+    /// Dependencies that are not trees can't be inspected and are never reported
+    /// # Arguments
+    /// * `odb` - The object database to read the dependencies from
+    /// * `needs` - The runtime needs of this tree (e.g. [Tree::find_runtime_needs()])
+    /// * `dependencies` - The object ids of the declared dependencies
+    /// # Returns
+    /// The object ids of the unused dependencies in the order of `dependencies`
+    pub fn find_unused_dependencies(
+        &self,
+        odb: &ObjectDB,
+        needs: &[RuntimeNeed],
+        dependencies: &[ObjectID],
+    ) -> Result<Vec<ObjectID>, Error> {
+        let external: Vec<&RuntimeNeed> =
+            needs.iter().filter(|n| !self.provides(&n.name)).collect();
+
+        let mut unused = Vec::new();
+        for oid in dependencies {
+            if odb.get_object(oid)?.ty != ObjectType::AcaciaTree {
+                continue;
+            }
+
+            let mut object = odb.read(oid)?;
+            let tree = Tree::unpack_from_odb(&mut object, odb)
+                .ctx(|| format!("Reading dependency tree {oid}"))?;
+
+            if !external.iter().any(|n| tree.provides(&n.name)) {
+                unused.push(oid.clone());
+            }
+        }
+
+        Ok(unused)
+    }
+
+    /// Recursively finds the runtime needs of the files of this tree
+    /// # Arguments
+    /// * `odb` - The object database to read the files from
+    /// * `prefix` - The path of this tree relative to the root tree
+    /// * `dirs` - The directories to inspect, `None` to inspect all of them
+    /// * `needs` - The vector to push the found needs to
+    fn find_runtime_needs_in(
+        &self,
+        odb: &ObjectDB,
+        prefix: &Path,
+        dirs: Option<&[PathBuf]>,
+        needs: &mut Vec<RuntimeNeed>,
+    ) -> Result<(), Error> {
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+
+            match entry {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => {
+                    if should_descend(&path, dirs) {
+                        tree.find_runtime_needs_in(odb, &path, dirs, needs)?
+                    }
+                }
+                TreeEntry::File { info: _, name, oid } => {
+                    if !is_within_dirs(&path, dirs) {
+                        continue;
+                    }
+
+                    let found = match odb.get_object(oid)?.ty {
+                        ObjectType::ELF => {
+                            let mut data = Vec::new();
+                            odb.read(oid)?
+                                .read_to_end(&mut data)
+                                .e_context(|| format!("Reading ELF file {}", path.str_lossy()))?;
+
+                            find_elf_needs(&data)
+                                .ctx(|| format!("Inspecting ELF file {}", path.str_lossy()))?
+                        }
+                        ObjectType::Script => {
+                            let mut first_line = Vec::new();
+                            BufReader::new(odb.read(oid)?)
+                                .read_until(b'\n', &mut first_line)
+                                .e_context(|| format!("Reading script {}", path.str_lossy()))?;
+
+                            let script = ScriptFile::from_shbang(
+                                &String::from_utf8_lossy(&first_line),
+                                OsString::from(name),
+                            );
+
+                            script
+                                .get_program()
+                                .map(|(program, _)| {
+                                    (
+                                        RuntimeNeedKind::ScriptInterpreter,
+                                        program.to_string_lossy().to_string(),
+                                    )
+                                })
+                                .into_iter()
+                                .collect()
+                        }
+                        _ => continue,
+                    };
+
+                    needs.extend(found.into_iter().map(|(kind, name)| RuntimeNeed {
+                        path: path.clone(),
+                        kind,
+                        name,
+                    }));
+                }
+                TreeEntry::Symlink {
+                    info: _,
+                    name: _,
+                    destination: _,
+                }
+                | TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind: _,
+                    major: _,
+                    minor: _,
+                }
+                | TreeEntry::Fifo { info: _, name: _ } => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds the file names of the interpreter and the shared libraries the ELF file in `data` needs
+/// # Arguments
+/// * `data` - The contents of the ELF file
+pub fn find_elf_needs(data: &[u8]) -> Result<Vec<(RuntimeNeedKind, String)>, Error> {
+    let file = ElfBytes::<AnyEndian>::minimal_parse(data).e_context(|| "Parsing ELF header")?;
+
+    let mut needs = Vec::new();
+
+    let interpreter = file.get_interpreter().e_context(|| "Reading interpreter")?;
+    if let Some(name) = interpreter.as_ref().and_then(|i| i.file_name()) {
+        needs.push((
+            RuntimeNeedKind::Interpreter,
+            name.to_string_lossy().to_string(),
+        ));
+    }
+
+    let libraries = file
+        .get_shared_needed()
+        .e_context(|| "Reading shared libraries")?;
+    for library in libraries.unwrap_or_default() {
+        needs.push((
+            RuntimeNeedKind::Library,
+            library.to_string_lossy().to_string(),
+        ));
+    }
+
+    Ok(needs)
+}
+
+impl Display for RuntimeNeedKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Library => write!(f, "library"),
+            Self::Interpreter => write!(f, "interpreter"),
+            Self::ScriptInterpreter => write!(f, "script interpreter"),
+        }
+    }
+}
+
+impl Display for RuntimeNeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: Needs {} '{}'",
+            self.path.str_lossy(),
+            self.kind,
+            self.name
+        )
+    }
+}
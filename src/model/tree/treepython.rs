@@ -0,0 +1,626 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::{Error, ErrorExt},
+    model::{ObjectCompression, ObjectDB, ObjectID},
+    util::fs::PathUtil,
+};
+
+use super::{treesymlinks::normalize, Tree, TreeEntry};
+
+/// The directory CPython places the bytecode of the modules of a directory in
+static PYCACHE_DIR: &str = "__pycache__";
+
+/// The maximum length of an embedded source path that is searched for in bytecode files
+static MAX_EMBEDDED_PATH_LEN: usize = 4096;
+
+/// The `marshal` type codes of strings with a 1 byte length (short ASCII, interned short ASCII)
+static MARSHAL_SHORT_STRINGS: &[u8] = b"zZ";
+
+/// The `marshal` type codes of strings with a 4 byte length (ASCII, interned ASCII,
+/// unicode, interned unicode)
+static MARSHAL_LONG_STRINGS: &[u8] = b"aAut";
+
+/// The `marshal` flag marking objects that get referenced later on
+const MARSHAL_FLAG_REF: u8 = 0x80;
+
+/// What to do with bytecode files that have been compiled against the wrong path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PycAction {
+    /// Rewrite the embedded source paths to the path the source gets deployed to,
+    /// which is what recompiling the file with that path (`compileall -d`) would embed
+    Rewrite,
+    /// Delete the bytecode files, the interpreter compiles the sources on demand
+    Delete,
+}
+
+/// A path pointing outside of the tree in a Python package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PythonIssueKind {
+    /// The bytecode file names its source at `embedded` (e.g. within the build root),
+    /// while the source gets deployed to `expected`
+    Bytecode { embedded: String, expected: String },
+    /// The entry of a `RECORD` or `.pth` file points outside of the tree,
+    /// the path it has most likely been meant to be is `fixed`
+    Entry { entry: String, fixed: String },
+}
+
+/// A file of a Python package within a tree referencing a path outside of the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonIssue {
+    /// The path to the file
+    pub path: PathBuf,
+    /// The wrong reference
+    pub kind: PythonIssueKind,
+}
+
+impl Tree {
+    /// Checks the Python bytecode files (`.pyc`) of this tree for being compiled against
+    /// paths other than the ones their sources get deployed to (e.g. the build root the
+    /// package got installed to) and the `RECORD` files of installed distributions and `.pth`
+    /// files for absolute entries pointing outside of the tree. The root of this tree is
+    /// treated as `/`.
+    ///
+    /// The source path embedded in bytecode files is found by the path of its source
+    /// (`<dir>/__pycache__/<module>.<tag>.pyc` and `<dir>/<module>.pyc` belong to
+    /// `<dir>/<module>.py`), files embedding no path ending in it are skipped.
+    /// Entries are taken to point into the build root if they do not exist in this tree,
+    /// but dropping leading directories yields a path that does
+    /// # Arguments
+    /// * `odb` - The object database to read the files from
+    pub fn check_python_paths(&self, odb: &ObjectDB) -> Result<Vec<PythonIssue>, Error> {
+        let mut issues = Vec::new();
+
+        for path in self.get_file_paths() {
+            let Some((data, _)) = self.read_python_file(odb, &path)? else {
+                continue;
+            };
+
+            if is_pyc(&path) {
+                if let Some(kind) = check_pyc(&path, &data) {
+                    issues.push(PythonIssue { path, kind });
+                }
+                continue;
+            }
+
+            for (entry, fixed) in self.fix_text_entries(&path, &data, &HashMap::new()).1 {
+                issues.push(PythonIssue {
+                    path: path.clone(),
+                    kind: PythonIssueKind::Entry { entry, fixed },
+                });
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Fixes the issues found by [Tree::check_python_paths()]: Bytecode files get handled
+    /// according to `action`, `RECORD` and `.pth` entries pointing into the build root get
+    /// rewritten. The `RECORD` entries of rewritten bytecode files get their hashes and sizes
+    /// updated, the ones of deleted files get removed.
+    ///
+    /// The modified files are inserted into `odb` and replace the original ones in this tree,
+    /// so the tree has to be inserted again afterwards
+    /// # Arguments
+    /// * `odb` - The object database to read the files from and insert the modified ones into
+    /// * `compression` - The compression to apply to the modified files
+    /// * `action` - What to do with the bytecode files compiled against the wrong path
+    /// # Returns
+    /// The fixed issues
+    pub fn fix_python_paths(
+        &mut self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+        action: PycAction,
+    ) -> Result<Vec<PythonIssue>, Error> {
+        let mut issues = Vec::new();
+        // The new hashes and sizes of the modified bytecode files, `None` for deleted ones
+        let mut changed: HashMap<PathBuf, Option<(String, usize)>> = HashMap::new();
+
+        let paths = self.get_file_paths();
+
+        // Bytecode files come first, their changes have to be reflected in the `RECORD` files
+        for path in paths.iter().filter(|p| is_pyc(p)) {
+            let Some((data, oid)) = self.read_python_file(odb, path)? else {
+                continue;
+            };
+            let Some(kind) = check_pyc(path, &data) else {
+                continue;
+            };
+
+            match action {
+                PycAction::Rewrite => {
+                    let PythonIssueKind::Bytecode { embedded, expected } = &kind else {
+                        unreachable!("Bytecode files only have bytecode issues")
+                    };
+                    let data = rewrite_pyc(&data, embedded, expected);
+                    changed.insert(path.clone(), Some((record_hash(&data), data.len())));
+                    self.replace_file(odb, compression, path, &oid, data)?;
+                }
+                PycAction::Delete => {
+                    changed.insert(path.clone(), None);
+                    self.remove_file(path);
+                }
+            }
+
+            issues.push(PythonIssue {
+                path: path.clone(),
+                kind,
+            });
+        }
+
+        for path in paths.iter().filter(|p| !is_pyc(p)) {
+            let Some((data, oid)) = self.read_python_file(odb, path)? else {
+                continue;
+            };
+
+            let (fixed_data, fixed) = self.fix_text_entries(path, &data, &changed);
+            if fixed_data != data {
+                self.replace_file(odb, compression, path, &oid, fixed_data)?;
+            }
+
+            for (entry, fixed) in fixed {
+                issues.push(PythonIssue {
+                    path: path.clone(),
+                    kind: PythonIssueKind::Entry { entry, fixed },
+                });
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Reads the file at `path` if it is a bytecode, `RECORD` or `.pth` file
+    /// # Arguments
+    /// * `odb` - The object database to read the file from
+    /// * `path` - The path of the file within this tree
+    /// # Returns
+    /// The contents and object id of the file, `None` if it is of no interest
+    fn read_python_file(
+        &self,
+        odb: &ObjectDB,
+        path: &Path,
+    ) -> Result<Option<(Vec<u8>, ObjectID)>, Error> {
+        if !is_pyc(path) && !is_record(path) && !is_pth(path) {
+            return Ok(None);
+        }
+
+        let Some(TreeEntry::File {
+            info: _,
+            name: _,
+            oid,
+        }) = self.get_entry(path)
+        else {
+            return Ok(None);
+        };
+
+        let mut data = Vec::new();
+        odb.read(oid)?
+            .read_to_end(&mut data)
+            .e_context(|| format!("Reading {}", path.str_lossy()))?;
+
+        Ok(Some((data, oid.clone())))
+    }
+
+    /// Rewrites the entries of the `RECORD` or `.pth` file at `path` pointing into the
+    /// build root and, for `RECORD` files, applies the changes of the bytecode files
+    /// # Arguments
+    /// * `path` - The path of the file within this tree
+    /// * `data` - The contents of the file
+    /// * `changed` - The new hashes and sizes of changed bytecode files, `None` for deleted ones
+    /// # Returns
+    /// The new contents of the file and the rewritten entries along with their fixed paths
+    fn fix_text_entries(
+        &self,
+        path: &Path,
+        data: &[u8],
+        changed: &HashMap<PathBuf, Option<(String, usize)>>,
+    ) -> (Vec<u8>, Vec<(String, String)>) {
+        let content = String::from_utf8_lossy(data);
+        let mut fixed = Vec::new();
+        let mut lines: Vec<String> = Vec::new();
+
+        for line in content.split_inclusive('\n') {
+            let (text, ending) = match line.strip_suffix("\r\n") {
+                Some(text) => (text, "\r\n"),
+                None => line
+                    .strip_suffix('\n')
+                    .map_or((line, ""), |text| (text, "\n")),
+            };
+
+            if is_pth(path) {
+                let is_entry =
+                    !text.is_empty() && !text.starts_with('#') && !text.starts_with("import");
+                match self.fix_entry(text).filter(|_| is_entry) {
+                    Some(entry) => {
+                        fixed.push((text.to_owned(), entry.clone()));
+                        lines.push(format!("{entry}{ending}"));
+                    }
+                    None => lines.push(line.to_owned()),
+                }
+                continue;
+            }
+
+            let Some((entry, rest)) = split_record_line(text) else {
+                lines.push(line.to_owned());
+                continue;
+            };
+
+            let entry_fix = self.fix_entry(&entry);
+            let target = entry_fix.as_deref().unwrap_or(&entry);
+
+            // Entries are relative to the directory containing the `.dist-info` directory
+            let site_dir = path
+                .parent()
+                .and_then(Path::parent)
+                .unwrap_or(Path::new(""));
+            let resolved: Option<PathBuf> =
+                normalize(&site_dir.join(target)).map(|c| c.iter().collect());
+
+            let rest = match resolved.as_ref().and_then(|r| changed.get(r)) {
+                Some(None) => continue,
+                // Only hashed entries get updated, bytecode files are often recorded without
+                Some(Some((hash, size))) if !rest.is_empty() && !rest.starts_with(",,") => {
+                    format!(",sha256={hash},{size}")
+                }
+                _ => rest.to_owned(),
+            };
+
+            if let Some(entry_fix) = &entry_fix {
+                fixed.push((entry.clone(), entry_fix.clone()));
+            }
+
+            lines.push(format!("{}{rest}{ending}", quote_record_path(target)));
+        }
+
+        (lines.concat().into_bytes(), fixed)
+    }
+
+    /// Returns the path within this tree an absolute entry pointing outside of it has most likely
+    /// been meant to be: The shortest suffix of the entry that exists in this tree
+    /// # Arguments
+    /// * `entry` - The entry to fix
+    /// # Returns
+    /// The fixed absolute entry, `None` if the entry is relative, exists or can't be fixed
+    fn fix_entry(&self, entry: &str) -> Option<String> {
+        let path = Path::new(entry);
+        if !path.is_absolute() {
+            return None;
+        }
+
+        let components = normalize(path)?;
+        let exists = |c: &[String]| {
+            !c.is_empty() && self.get_entry(&c.iter().collect::<PathBuf>()).is_some()
+        };
+
+        if exists(&components) {
+            return None;
+        }
+
+        (1..components.len())
+            .map(|i| &components[i..])
+            .find(|c| exists(c))
+            .map(|c| format!("/{}", c.join("/")))
+    }
+
+    /// Inserts `data` as the new contents of the file at `path`
+    /// # Arguments
+    /// * `odb` - The object database to insert the file into
+    /// * `compression` - The compression to apply to the file
+    /// * `path` - The path of the file within this tree
+    /// * `oid` - The object id of the current contents, to take the type and dependencies from
+    /// * `data` - The new contents
+    fn replace_file(
+        &mut self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+        path: &Path,
+        oid: &ObjectID,
+        data: Vec<u8>,
+    ) -> Result<(), Error> {
+        let object = odb.get_object(oid)?;
+        let new_oid = odb
+            .insert_stream(
+                &mut Cursor::new(data),
+                object.ty,
+                compression,
+                object.dependencies,
+            )
+            .ctx(|| format!("Inserting fixed {}", path.str_lossy()))?
+            .oid;
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(TreeEntry::File {
+            info: _,
+            name: _,
+            oid,
+        }) = self
+            .get_subtree_mut(path.parent().unwrap_or(Path::new("")))
+            .and_then(|t| t.get_entry_by_name_mut(&name))
+        {
+            *oid = new_oid;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the file at `path` from this tree
+    /// # Arguments
+    /// * `path` - The path of the file within this tree
+    fn remove_file(&mut self, path: &Path) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(tree) = self.get_subtree_mut(path.parent().unwrap_or(Path::new(""))) {
+            tree.entries.retain(|e| e.name() != name);
+        }
+    }
+}
+
+/// Returns whether `path` is a bytecode file
+/// # Arguments
+/// * `path` - The path to check
+fn is_pyc(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "pyc")
+}
+
+/// Returns whether `path` is the `RECORD` file of an installed distribution (`*.dist-info/RECORD`)
+/// # Arguments
+/// * `path` - The path to check
+fn is_record(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == "RECORD")
+        && path
+            .parent()
+            .and_then(Path::extension)
+            .is_some_and(|e| e == "dist-info")
+}
+
+/// Returns whether `path` is a `.pth` file
+/// # Arguments
+/// * `path` - The path to check
+fn is_pth(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "pth")
+}
+
+/// Returns the path of the source of the bytecode file at `path`
+/// # Arguments
+/// * `path` - The path to the bytecode file
+fn pyc_source(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    let stem = path.file_stem()?.to_string_lossy();
+
+    if dir.file_name().is_some_and(|n| n == PYCACHE_DIR) {
+        // `<module>.<tag>[.opt-<level>].pyc`
+        let module = stem.split('.').next()?;
+        Some(dir.parent()?.join(format!("{module}.py")))
+    } else {
+        Some(dir.join(format!("{stem}.py")))
+    }
+}
+
+/// Checks whether the bytecode file at `path` has been compiled against the path of its source
+/// # Arguments
+/// * `path` - The path to the file, the root of the tree being `/`
+/// * `data` - The contents of the file
+/// # Returns
+/// The issue if the file has been compiled against a different path, `None` otherwise
+fn check_pyc(path: &Path, data: &[u8]) -> Option<PythonIssueKind> {
+    if !is_pyc(path) {
+        return None;
+    }
+
+    let source = pyc_source(path)?;
+    let expected = format!("/{}", source.str_lossy());
+
+    let strings = find_marshal_strings(data, expected.as_bytes());
+    if strings
+        .iter()
+        .any(|(start, end, _)| &data[*start..*end] == expected.as_bytes())
+    {
+        return None;
+    }
+
+    let (start, end, _) = strings.first()?;
+    let embedded = std::str::from_utf8(&data[*start..*end]).ok()?;
+
+    Some(PythonIssueKind::Bytecode {
+        embedded: embedded.to_owned(),
+        expected,
+    })
+}
+
+/// Finds the `marshal` strings in `data` that end in `suffix`
+/// # Arguments
+/// * `data` - The bytecode to search
+/// * `suffix` - The suffix to search for
+/// # Returns
+/// The ranges of the contents of the strings and the lengths of their headers
+fn find_marshal_strings(data: &[u8], suffix: &[u8]) -> Vec<(usize, usize, usize)> {
+    let mut strings = Vec::new();
+    if suffix.is_empty() || suffix.len() > data.len() {
+        return strings;
+    }
+
+    for end in suffix.len()..=data.len() {
+        if &data[end - suffix.len()..end] != suffix {
+            continue;
+        }
+
+        let min_start = end.saturating_sub(MAX_EMBEDDED_PATH_LEN);
+        if let Some((start, header_len)) = (min_start..=end - suffix.len())
+            .rev()
+            .find_map(|start| marshal_header_len(data, start, end - start).map(|h| (start, h)))
+        {
+            strings.push((start, end, header_len));
+        }
+    }
+
+    strings
+}
+
+/// Returns the length of the header of the `marshal` string of `len` bytes
+/// whose contents start at `start`, `None` if there is no such header
+/// # Arguments
+/// * `data` - The bytecode
+/// * `start` - The offset of the contents of the string
+/// * `len` - The length of the contents of the string
+fn marshal_header_len(data: &[u8], start: usize, len: usize) -> Option<usize> {
+    let is_type = |offset: usize, types: &[u8]| types.contains(&(data[offset] & !MARSHAL_FLAG_REF));
+
+    if start >= 5
+        && is_type(start - 5, MARSHAL_LONG_STRINGS)
+        && data[start - 4..start] == (len as u32).to_le_bytes()
+    {
+        Some(5)
+    } else if start >= 2
+        && is_type(start - 2, MARSHAL_SHORT_STRINGS)
+        && data[start - 1] as usize == len
+    {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Replaces all `marshal` strings `from` in the bytecode `data` with `to`,
+/// adjusting the string headers to the new length
+/// # Arguments
+/// * `data` - The bytecode
+/// * `from` - The string to replace
+/// * `to` - The string to replace `from` with
+fn rewrite_pyc(data: &[u8], from: &str, to: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut pos = 0;
+
+    for (start, end, header_len) in find_marshal_strings(data, from.as_bytes()) {
+        let header = start - header_len;
+        if end - start != from.len() {
+            continue;
+        }
+        if header < pos {
+            continue;
+        }
+
+        let ty = data[header];
+        let flags = ty & MARSHAL_FLAG_REF;
+        let interned = matches!(ty & !MARSHAL_FLAG_REF, b'Z' | b'A' | b't');
+
+        out.extend_from_slice(&data[pos..header]);
+        match (to.is_ascii(), to.len() <= u8::MAX as usize, interned) {
+            (true, true, false) => out.extend_from_slice(&[b'z' | flags, to.len() as u8]),
+            (true, true, true) => out.extend_from_slice(&[b'Z' | flags, to.len() as u8]),
+            (ascii, _, interned) => {
+                let ty = match (ascii, interned) {
+                    (true, false) => b'a',
+                    (true, true) => b'A',
+                    (false, false) => b'u',
+                    (false, true) => b't',
+                };
+                out.push(ty | flags);
+                out.extend_from_slice(&(to.len() as u32).to_le_bytes());
+            }
+        }
+        out.extend_from_slice(to.as_bytes());
+        pos = end;
+    }
+
+    out.extend_from_slice(&data[pos..]);
+    out
+}
+
+/// Returns the hash of `data` in the format of `RECORD` files
+/// (URL-safe base64 of the SHA-256 digest without padding)
+/// # Arguments
+/// * `data` - The data to hash
+fn record_hash(data: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(data))
+}
+
+/// Splits a line of a `RECORD` file (CSV) into its unquoted path and the remaining fields
+/// # Arguments
+/// * `line` - The line to split, without the line ending
+/// # Returns
+/// The path and the remaining fields, starting with the separating `,`
+fn split_record_line(line: &str) -> Option<(String, &str)> {
+    if line.is_empty() {
+        return None;
+    }
+
+    let Some(quoted) = line.strip_prefix('"') else {
+        let end = line.find(',').unwrap_or(line.len());
+        return Some((line[..end].to_owned(), &line[end..]));
+    };
+
+    let mut path = String::new();
+    let mut chars = quoted.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if chars.peek().is_some_and(|(_, c)| *c == '"') => {
+                chars.next();
+                path.push('"');
+            }
+            '"' => return Some((path, &quoted[i + 1..])),
+            c => path.push(c),
+        }
+    }
+
+    None
+}
+
+/// Quotes `path` for a `RECORD` file if it contains separators or quotes
+/// # Arguments
+/// * `path` - The path to quote
+fn quote_record_path(path: &str) -> String {
+    if path.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", path.replace('"', "\"\""))
+    } else {
+        path.to_owned()
+    }
+}
+
+impl FromStr for PycAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rewrite" => Ok(Self::Rewrite),
+            "delete" => Ok(Self::Delete),
+            _ => Err(format!(
+                "Unknown bytecode action '{s}', expected 'rewrite' or 'delete'"
+            )),
+        }
+    }
+}
+
+impl Display for PycAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rewrite => write!(f, "rewrite"),
+            Self::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+impl Display for PythonIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            PythonIssueKind::Bytecode { embedded, expected } => write!(
+                f,
+                "{}: Compiled against '{embedded}' instead of '{expected}'",
+                self.path.str_lossy()
+            ),
+            PythonIssueKind::Entry { entry, fixed } => write!(
+                f,
+                "{}: Entry '{entry}' points outside of the tree, meant to be '{fixed}'",
+                self.path.str_lossy()
+            ),
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    io::{Cursor, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use elf::{
+    abi,
+    endian::AnyEndian,
+    file::Class,
+    gnu_symver::{VerDefIterator, VerNeedIterator},
+    section::SectionHeaderTable,
+    ElfBytes,
+};
+use log::warn;
+
+use crate::{
+    abs_dist_dir,
+    error::{Error, ErrorExt},
+    model::{ObjectCompression, ObjectDB, ObjectType},
+    package::{is_within_dirs, should_descend},
+    util::fs::PathUtil,
+};
+
+use super::{Tree, TreeEntry};
+
+/// Dynamic section tag of the names of auxiliary filtees (not in [elf::abi])
+const DT_AUXILIARY: i64 = 0x7ffffffd;
+/// Dynamic section tag of the names of filtees (not in [elf::abi])
+const DT_FILTER: i64 = 0x7fffffff;
+
+/// The dynamic section tags whose values are offsets into the dynamic string table
+static STRING_TAGS: &[i64] = &[
+    abi::DT_NEEDED,
+    abi::DT_SONAME,
+    abi::DT_RPATH,
+    abi::DT_RUNPATH,
+    abi::DT_CONFIG,
+    abi::DT_DEPAUDIT,
+    abi::DT_AUDIT,
+    DT_AUXILIARY,
+    DT_FILTER,
+];
+
+/// Why an entry has been removed from the `RPATH` or `RUNPATH` of an ELF file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunpathRemovalReason {
+    /// The entry does not point into the dist directory, e.g. into the build directory,
+    /// and is not relative to the file (`$ORIGIN`)
+    OutsideDistDir,
+    /// The entry appears earlier in the same search path
+    Duplicate,
+}
+
+/// An entry that has been removed from the `RPATH` or `RUNPATH` of an ELF file in a tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunpathRemoval {
+    /// The path to the ELF file
+    pub path: PathBuf,
+    /// The removed search path entry
+    pub entry: String,
+    /// Why the entry has been removed
+    pub reason: RunpathRemovalReason,
+}
+
+impl Tree {
+    /// Removes the entries of the `RPATH` and `RUNPATH` search paths of the ELF files in this
+    /// tree that point outside of the [dist directory](crate::abs_dist_dir()) (e.g. into the
+    /// build directory) or appear twice, so the files only search the dist directory for libraries.
+    /// Entries relative to the file (`$ORIGIN`) are kept.
+    ///
+    /// Only files inserted as [ObjectType::ELF] are sanitized. The search paths get shortened in
+    /// place, search paths without entries left are removed from the dynamic section. The modified
+    /// files are inserted into `odb` and replace the original ones in this tree, so the tree has
+    /// to be inserted again afterwards
+    /// # Arguments
+    /// * `odb` - The object database to read the ELF files from and insert the modified ones into
+    /// * `compression` - The compression to apply to the modified files
+    /// # Returns
+    /// The removed entries
+    pub fn sanitize_runpaths(
+        &mut self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+    ) -> Result<Vec<RunpathRemoval>, Error> {
+        let mut removals = Vec::new();
+        self.sanitize_runpaths_in(odb, compression, Path::new(""), None, &mut removals)?;
+        Ok(removals)
+    }
+
+    /// Sanitizes the search paths of the ELF files within `dirs`,
+    /// refer to [Tree::sanitize_runpaths()]. Subtrees outside of `dirs` are not descended into
+    /// # Arguments
+    /// * `odb` - The object database to read the ELF files from and insert the modified ones into
+    /// * `compression` - The compression to apply to the modified files
+    /// * `dirs` - The directories to sanitize, relative to this tree (e.g. [crate::package::PackageLayout::get_scan_dirs()])
+    /// # Returns
+    /// The removed entries
+    pub fn sanitize_runpaths_in_dirs(
+        &mut self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+        dirs: &[PathBuf],
+    ) -> Result<Vec<RunpathRemoval>, Error> {
+        let mut removals = Vec::new();
+        self.sanitize_runpaths_in(odb, compression, Path::new(""), Some(dirs), &mut removals)?;
+        Ok(removals)
+    }
+
+    /// Recursively sanitizes the search paths of the ELF files of this tree
+    /// # Arguments
+    /// * `odb` - The object database to read the ELF files from and insert the modified ones into
+    /// * `compression` - The compression to apply to the modified files
+    /// * `prefix` - The path of this tree relative to the root tree
+    /// * `dirs` - The directories to sanitize, `None` to sanitize all of them
+    /// * `removals` - The vector to push the removed entries to
+    fn sanitize_runpaths_in(
+        &mut self,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+        prefix: &Path,
+        dirs: Option<&[PathBuf]>,
+        removals: &mut Vec<RunpathRemoval>,
+    ) -> Result<(), Error> {
+        for entry in &mut self.entries {
+            let path = prefix.join(entry.name());
+
+            match entry {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => {
+                    if should_descend(&path, dirs) {
+                        tree.sanitize_runpaths_in(odb, compression, &path, dirs, removals)?
+                    }
+                }
+                TreeEntry::File {
+                    info: _,
+                    name: _,
+                    oid,
+                } => {
+                    if !is_within_dirs(&path, dirs) {
+                        continue;
+                    }
+
+                    let object = odb.get_object(oid)?;
+                    if object.ty != ObjectType::ELF {
+                        continue;
+                    }
+
+                    let mut data = Vec::new();
+                    odb.read(oid)?
+                        .read_to_end(&mut data)
+                        .e_context(|| format!("Reading ELF file {}", path.str_lossy()))?;
+
+                    let removed = sanitize_elf_runpaths(&mut data)
+                        .ctx(|| format!("Sanitizing search paths of {}", path.str_lossy()))?;
+                    if removed.is_empty() {
+                        continue;
+                    }
+
+                    *oid = odb
+                        .insert_stream(
+                            &mut Cursor::new(data),
+                            ObjectType::ELF,
+                            compression,
+                            object.dependencies,
+                        )
+                        .ctx(|| format!("Inserting sanitized {}", path.str_lossy()))?
+                        .oid;
+
+                    removals.extend(removed.into_iter().map(|(entry, reason)| RunpathRemoval {
+                        path: path.clone(),
+                        entry,
+                        reason,
+                    }));
+                }
+                TreeEntry::Symlink {
+                    info: _,
+                    name: _,
+                    destination: _,
+                }
+                | TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind: _,
+                    major: _,
+                    minor: _,
+                }
+                | TreeEntry::Fifo { info: _, name: _ } => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Removes the entries of the `RPATH` and `RUNPATH` search paths of the ELF file in `data`
+/// that point outside of the dist directory or appear twice, refer to [Tree::sanitize_runpaths()].
+///
+/// Search paths sharing their storage in the string table with other strings
+/// (linkers merge common suffixes) are left untouched with a warning
+/// # Arguments
+/// * `data` - The contents of the ELF file, modified in place
+/// # Returns
+/// The removed entries and why they have been removed
+pub fn sanitize_elf_runpaths(
+    data: &mut [u8],
+) -> Result<Vec<(String, RunpathRemovalReason)>, Error> {
+    let mut removals = Vec::new();
+
+    // The string table ranges to overwrite with the sanitized search paths
+    let mut rewrites: Vec<(usize, usize, String)> = Vec::new();
+    // The indices of the dynamic section entries without search path entries left
+    let mut emptied: Vec<usize> = Vec::new();
+
+    let (dynamic_range, entry_size) = {
+        let file = ElfBytes::<AnyEndian>::minimal_parse(data).e_context(|| "Parsing ELF header")?;
+
+        let Some(shdrs) = file.section_headers() else {
+            return Ok(removals);
+        };
+        let Some(dynamic_shdr) = shdrs.iter().find(|s| s.sh_type == abi::SHT_DYNAMIC) else {
+            return Ok(removals);
+        };
+        let strtab_shdr = shdrs
+            .get(dynamic_shdr.sh_link as usize)
+            .e_context(|| "Reading dynamic string table header")?;
+        let strtab = file
+            .section_data_as_strtab(&strtab_shdr)
+            .e_context(|| "Reading dynamic string table")?;
+
+        // The tags and values of the dynamic section entries
+        let dynamic: Vec<(i64, usize)> = file
+            .dynamic()
+            .e_context(|| "Parsing dynamic section")?
+            .into_iter()
+            .flatten()
+            .map(|d| (d.d_tag, d.d_val() as usize))
+            .collect();
+
+        // The search paths by their offsets in the string table, with the entries referencing them
+        let mut search_paths: Vec<(usize, Vec<usize>)> = Vec::new();
+        for (i, (tag, offset)) in dynamic.iter().copied().enumerate() {
+            if tag == abi::DT_RPATH || tag == abi::DT_RUNPATH {
+                match search_paths.iter_mut().find(|(o, _)| *o == offset) {
+                    Some((_, entries)) => entries.push(i),
+                    None => search_paths.push((offset, vec![i])),
+                }
+            }
+        }
+
+        if search_paths.is_empty() {
+            return Ok(removals);
+        }
+
+        let references = collect_string_references(&file, &dynamic, &shdrs)?;
+
+        for (offset, entries) in search_paths {
+            let value = strtab
+                .get(offset)
+                .e_context(|| format!("Reading search path at {offset:#x}"))?;
+            let len = value.len();
+
+            // Other strings stored within this one or this one being the suffix
+            // of another string would change along with it
+            let shared = references.iter().any(|r| {
+                (offset + 1..offset + len).contains(r)
+                    || (*r < offset && strtab.get(*r).is_ok_and(|s| r + s.len() > offset))
+            });
+            if shared {
+                warn!("Not sanitizing search path '{value}', it shares its storage with other strings");
+                continue;
+            }
+
+            let (kept, removed) = sanitize_search_path(value);
+            if removed.is_empty() {
+                continue;
+            }
+
+            if kept.is_empty() {
+                emptied.extend(entries);
+            }
+
+            removals.extend(removed);
+            rewrites.push((strtab_shdr.sh_offset as usize + offset, len, kept));
+        }
+
+        let entry_size = match (dynamic_shdr.sh_entsize, file.ehdr.class) {
+            (0, Class::ELF32) => 8,
+            (0, Class::ELF64) => 16,
+            (size, _) => size as usize,
+        };
+        let start = dynamic_shdr.sh_offset as usize;
+
+        (start..start + dynamic.len() * entry_size, entry_size)
+    };
+
+    for (start, len, kept) in rewrites {
+        data[start..start + len].fill(0);
+        data[start..start + kept.len()].copy_from_slice(kept.as_bytes());
+    }
+
+    // Move the following entries up, the freed entries at the end become `DT_NULL`
+    if !emptied.is_empty() {
+        let table = &mut data[dynamic_range];
+        let mut entries: Vec<u8> = table
+            .chunks(entry_size)
+            .enumerate()
+            .filter(|(i, _)| !emptied.contains(i))
+            .flat_map(|(_, entry)| entry.to_vec())
+            .collect();
+        entries.resize(table.len(), 0);
+        table.copy_from_slice(&entries);
+    }
+
+    Ok(removals)
+}
+
+/// Splits a search path into its entries and drops the ones
+/// outside of the dist directory and duplicates
+/// # Arguments
+/// * `search_path` - The `:` separated search path
+/// # Returns
+/// The sanitized search path and the removed entries
+fn sanitize_search_path(search_path: &str) -> (String, Vec<(String, RunpathRemovalReason)>) {
+    let mut kept: Vec<&str> = Vec::new();
+    let mut removed = Vec::new();
+    let mut seen = HashSet::new();
+
+    for entry in search_path.split(':') {
+        if !is_dist_entry(entry) {
+            removed.push((entry.to_owned(), RunpathRemovalReason::OutsideDistDir));
+        } else if !seen.insert(entry) {
+            removed.push((entry.to_owned(), RunpathRemovalReason::Duplicate));
+        } else {
+            kept.push(entry);
+        }
+    }
+
+    (kept.join(":"), removed)
+}
+
+/// Returns whether a search path entry is relative to the file (`$ORIGIN`)
+/// or an absolute path within the dist directory
+/// # Arguments
+/// * `entry` - The search path entry to check
+fn is_dist_entry(entry: &str) -> bool {
+    if entry.starts_with("$ORIGIN") || entry.starts_with("${ORIGIN}") {
+        return true;
+    }
+
+    let path = Path::new(entry);
+    path.is_absolute()
+        && path.starts_with(abs_dist_dir())
+        && !path.components().any(|c| c == Component::ParentDir)
+}
+
+/// Collects the offsets of all strings in the dynamic string table that are referenced
+/// by the dynamic section, the dynamic symbols and the symbol versions
+/// # Arguments
+/// * `file` - The ELF file
+/// * `dynamic` - The tags and values of the dynamic section entries
+/// * `shdrs` - The section headers of the file
+fn collect_string_references(
+    file: &ElfBytes<AnyEndian>,
+    dynamic: &[(i64, usize)],
+    shdrs: &SectionHeaderTable<AnyEndian>,
+) -> Result<Vec<usize>, Error> {
+    let mut references: Vec<usize> = dynamic
+        .iter()
+        .filter(|(tag, _)| STRING_TAGS.contains(tag))
+        .map(|(_, value)| *value)
+        .collect();
+
+    if let Some((symbols, _)) = file
+        .dynamic_symbol_table()
+        .e_context(|| "Parsing dynamic symbol table")?
+    {
+        references.extend(symbols.iter().map(|s| s.st_name as usize));
+    }
+
+    for shdr in shdrs.iter() {
+        if shdr.sh_type != abi::SHT_GNU_VERNEED && shdr.sh_type != abi::SHT_GNU_VERDEF {
+            continue;
+        }
+
+        let (section, _) = file
+            .section_data(&shdr)
+            .e_context(|| "Reading symbol version section")?;
+
+        match shdr.sh_type {
+            abi::SHT_GNU_VERNEED => {
+                let needs = VerNeedIterator::new(
+                    file.ehdr.endianness,
+                    file.ehdr.class,
+                    shdr.sh_info as u64,
+                    0,
+                    section,
+                );
+                for (need, auxs) in needs {
+                    references.push(need.vn_file as usize);
+                    references.extend(auxs.map(|aux| aux.vna_name as usize));
+                }
+            }
+            abi::SHT_GNU_VERDEF => {
+                let defs = VerDefIterator::new(
+                    file.ehdr.endianness,
+                    file.ehdr.class,
+                    shdr.sh_info as u64,
+                    0,
+                    section,
+                );
+                for (_, auxs) in defs {
+                    references.extend(auxs.map(|aux| aux.vda_name as usize));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(references)
+}
+
+impl Display for RunpathRemovalReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutsideDistDir => write!(f, "outside of {}", abs_dist_dir().str_lossy()),
+            Self::Duplicate => write!(f, "duplicate"),
+        }
+    }
+}
+
+impl Display for RunpathRemoval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: Removed search path entry '{}' ({})",
+            self.path.str_lossy(),
+            self.entry,
+            self.reason
+        )
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    fmt::Display,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{abs_dist_dir, util::fs::PathUtil};
+
+use super::{Tree, TreeEntry};
+
+/// The maximum amount of symlinks to follow when resolving a path
+static MAX_SYMLINK_DEPTH: u32 = 40;
+
+/// A problem with a symlink in a tree
+#[derive(Debug, PartialEq, Eq)]
+pub enum SymlinkIssue {
+    /// The destination of the symlink does not exist within the tree
+    Dangling { path: PathBuf, destination: String },
+    /// The symlink points to an absolute path outside of the dist directory,
+    /// which will most likely not exist once the tree is deployed somewhere else
+    Absolute { path: PathBuf, destination: String },
+}
+
+impl Tree {
+    /// Checks all symlinks in this tree for dangling and absolute destinations.
+    ///
+    /// Destinations within the [dist directory](crate::abs_dist_dir()) point to other
+    /// packages and are neither flagged as absolute nor checked for existence
+    pub fn check_symlinks(&self) -> Vec<SymlinkIssue> {
+        let mut issues = Vec::new();
+        self.check_symlinks_in(self, Path::new(""), &mut issues);
+        issues
+    }
+
+    /// Rewrites all absolute symlink destinations outside of the dist directory
+    /// into destinations relative to the symlink, treating the root of this tree as `/`
+    /// # Returns
+    /// The number of rewritten symlinks
+    pub fn relativize_symlinks(&mut self) -> usize {
+        self.relativize_symlinks_in(Path::new(""))
+    }
+
+    /// Recursively checks the symlinks of this tree, resolving destinations in `root`
+    /// # Arguments
+    /// * `root` - The root tree to resolve destinations in
+    /// * `prefix` - The path of this tree relative to `root`
+    /// * `issues` - The vector to push the found issues to
+    fn check_symlinks_in(&self, root: &Tree, prefix: &Path, issues: &mut Vec<SymlinkIssue>) {
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+
+            match entry {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => tree.check_symlinks_in(root, &path, issues),
+                TreeEntry::Symlink {
+                    info: _,
+                    name: _,
+                    destination,
+                } => {
+                    let dest = Path::new(destination);
+
+                    if dest.is_absolute() && !dest.starts_with(abs_dist_dir()) {
+                        issues.push(SymlinkIssue::Absolute {
+                            path: path.clone(),
+                            destination: destination.clone(),
+                        });
+                    }
+
+                    if root.resolve(&prefix.join(dest), MAX_SYMLINK_DEPTH) == Some(false) {
+                        issues.push(SymlinkIssue::Dangling {
+                            path,
+                            destination: destination.clone(),
+                        });
+                    }
+                }
+                TreeEntry::File {
+                    info: _,
+                    name: _,
+                    oid: _,
+                }
+                | TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind: _,
+                    major: _,
+                    minor: _,
+                }
+                | TreeEntry::Fifo { info: _, name: _ } => {}
+            }
+        }
+    }
+
+    /// Recursively rewrites the absolute symlinks of this tree
+    /// # Arguments
+    /// * `prefix` - The path of this tree relative to the root tree
+    fn relativize_symlinks_in(&mut self, prefix: &Path) -> usize {
+        let mut count = 0;
+
+        for entry in &mut self.entries {
+            let path = prefix.join(entry.name());
+
+            match entry {
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => count += tree.relativize_symlinks_in(&path),
+                TreeEntry::Symlink {
+                    info: _,
+                    name: _,
+                    destination,
+                } => {
+                    let dest = Path::new(destination.as_str());
+
+                    if dest.is_absolute() && !dest.starts_with(abs_dist_dir()) {
+                        *destination = relative_destination(prefix, dest).str_lossy();
+                        count += 1;
+                    }
+                }
+                TreeEntry::File {
+                    info: _,
+                    name: _,
+                    oid: _,
+                }
+                | TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind: _,
+                    major: _,
+                    minor: _,
+                }
+                | TreeEntry::Fifo { info: _, name: _ } => {}
+            }
+        }
+
+        count
+    }
+
+    /// Resolves `path` within this tree, following symlinks
+    /// # Arguments
+    /// * `path` - The path to resolve, absolute paths are resolved from the root of this tree
+    /// * `depth` - The amount of symlinks that may still be followed
+    /// # Returns
+    /// Whether the path exists or `None` if this cannot be determined,
+    /// because the path leads into the dist directory
+    fn resolve(&self, path: &Path, depth: u32) -> Option<bool> {
+        if path.starts_with(abs_dist_dir()) {
+            return None;
+        }
+
+        let components = match normalize(path) {
+            Some(components) => components,
+            None => return Some(false),
+        };
+
+        let mut tree = self;
+        for (i, name) in components.iter().enumerate() {
+            let is_last = i + 1 == components.len();
+
+            match tree.get_entry_by_name(name) {
+                None => return Some(false),
+                Some(TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree: subtree,
+                }) => tree = subtree,
+                Some(TreeEntry::File {
+                    info: _,
+                    name: _,
+                    oid: _,
+                })
+                | Some(TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind: _,
+                    major: _,
+                    minor: _,
+                })
+                | Some(TreeEntry::Fifo { info: _, name: _ }) => return Some(is_last),
+                Some(TreeEntry::Symlink {
+                    info: _,
+                    name: _,
+                    destination,
+                }) => {
+                    if depth == 0 {
+                        return Some(false);
+                    }
+
+                    // Continue resolving at the destination of the symlink
+                    let mut next: PathBuf = components[..i].iter().collect();
+                    next.push(destination);
+                    next.extend(&components[i + 1..]);
+
+                    return self.resolve(&next, depth - 1);
+                }
+            }
+        }
+
+        Some(true)
+    }
+}
+
+/// Normalizes `path` lexically, treating it as relative to the root
+/// # Arguments
+/// * `path` - The path to normalize
+/// # Returns
+/// The components of the path or `None` if it escapes the root
+pub(super) fn normalize(path: &Path) -> Option<Vec<String>> {
+    let mut components = Vec::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy().to_string()),
+            Component::ParentDir => {
+                components.pop()?;
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+
+    Some(components)
+}
+
+/// Computes the destination relative to `dir` that points to the absolute `destination`
+/// # Arguments
+/// * `dir` - The directory containing the symlink, relative to the root
+/// * `destination` - The absolute destination of the symlink
+fn relative_destination(dir: &Path, destination: &Path) -> PathBuf {
+    let dir = normalize(dir).unwrap_or_default();
+    let destination = normalize(destination).unwrap_or_default();
+
+    let common = dir
+        .iter()
+        .zip(&destination)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut res = PathBuf::new();
+    for _ in common..dir.len() {
+        res.push("..");
+    }
+    res.extend(&destination[common..]);
+
+    if res.as_os_str().is_empty() {
+        res.push(".");
+    }
+
+    res
+}
+
+impl Display for SymlinkIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dangling { path, destination } => {
+                write!(f, "{} -> {} (dangling)", path.str_lossy(), destination)
+            }
+            Self::Absolute { path, destination } => {
+                write!(f, "{} -> {} (absolute)", path.str_lossy(), destination)
+            }
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    io::{Cursor, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use log::{trace, warn};
+use tar::{EntryType, Header};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{ObjectCompression, ObjectDB, ObjectID, ObjectType},
+    util::fs::{DeviceKind, PathUtil, UNIXInfo},
+};
+
+use super::{Tree, TreeEntry};
+
+/// The file type bits of a directory in a UNIX mode
+const S_IFDIR: u32 = 0o040000;
+/// The file type bits of a regular file in a UNIX mode
+const S_IFREG: u32 = 0o100000;
+/// The file type bits of a symlink in a UNIX mode
+const S_IFLNK: u32 = 0o120000;
+/// The file type bits of a character device in a UNIX mode
+const S_IFCHR: u32 = 0o020000;
+/// The file type bits of a block device in a UNIX mode
+const S_IFBLK: u32 = 0o060000;
+/// The file type bits of a FIFO in a UNIX mode
+const S_IFIFO: u32 = 0o010000;
+
+/// The UNIX mode of directories that are not part of the archive
+/// but contain entries of it
+const IMPLICIT_DIR_MODE: u32 = S_IFDIR | 0o755;
+
+impl Tree {
+    /// Creates a new tree from the entries of a `tar` archive, inserting the
+    /// contents of the files while streaming the archive instead of extracting it.
+    ///
+    /// Every file is held in memory while it gets inserted. Directories that contain
+    /// entries but are missing from the archive are created owned by `root` with mode `0755`.
+    /// Later entries replace earlier ones with the same path, like when extracting.
+    /// Entries of other types (e.g. GNU long names that failed to resolve) are skipped with a warning
+    /// # Arguments
+    /// * `archive` - The archive to index
+    /// * `db` - The object database to insert into
+    /// * `compression` - The form of compression to use when inserting
+    /// # Returns
+    /// The indexed tree
+    pub fn index_tar<R: Read>(
+        archive: &mut tar::Archive<R>,
+        db: &mut ObjectDB,
+        compression: ObjectCompression,
+    ) -> Result<Tree, Error> {
+        let context = || "Reading tar archive entry";
+        let mut root = Tree {
+            entries: Vec::new(),
+        };
+
+        for entry in archive.entries().e_context(context)? {
+            let mut entry = entry.e_context(context)?;
+
+            let raw_path = entry.path().e_context(context)?.to_path_buf();
+            let path = normalize(&raw_path)?;
+            let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+                // The root directory of the archive (`./`)
+                continue;
+            };
+
+            let header = entry.header();
+            let mode = header.mode().e_context(context)? & 0o7777;
+            let uid = header.uid().e_context(context)? as u32;
+            let gid = header.gid().e_context(context)? as u32;
+            let ty = header.entry_type();
+            let parent_path = path.parent().unwrap_or(Path::new(""));
+
+            let new_entry = match ty {
+                EntryType::Directory => {
+                    let info = UNIXInfo::new(uid, gid, S_IFDIR | mode);
+
+                    // Keep the contents of a directory that has been seen before
+                    if let Some(TreeEntry::Subtree {
+                        info: existing,
+                        name: _,
+                        tree: _,
+                    }) = root
+                        .get_or_create_subtree(parent_path)?
+                        .get_entry_by_name_mut(&name)
+                    {
+                        *existing = info;
+                        continue;
+                    }
+
+                    TreeEntry::Subtree {
+                        info,
+                        name,
+                        tree: Tree {
+                            entries: Vec::new(),
+                        },
+                    }
+                }
+                EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                    let mut data = Vec::new();
+                    entry
+                        .read_to_end(&mut data)
+                        .e_context(|| format!("Reading {} from archive", path.str_lossy()))?;
+
+                    let oid = insert_data(db, data, compression)
+                        .ctx(|| format!("Inserting {}", path.str_lossy()))?;
+
+                    TreeEntry::File {
+                        info: UNIXInfo::new(uid, gid, S_IFREG | mode),
+                        name,
+                        oid,
+                    }
+                }
+                EntryType::Symlink => {
+                    let destination = entry
+                        .link_name()
+                        .e_context(context)?
+                        .map(|d| d.to_string_lossy().to_string())
+                        .unwrap_or_default();
+
+                    TreeEntry::Symlink {
+                        info: UNIXInfo::new(uid, gid, S_IFLNK | mode),
+                        name,
+                        destination,
+                    }
+                }
+                EntryType::Link => {
+                    let target = entry.link_name().e_context(context)?.ok_or_else(|| {
+                        Error::new(ErrorType::Other(format!(
+                            "Hardlink {} has no target",
+                            path.str_lossy()
+                        )))
+                    })?;
+                    let target = normalize(&target)?;
+
+                    // Hardlinks become copies of the file they link to
+                    let oid = match root.get_entry(&target) {
+                        Some(TreeEntry::File {
+                            info: _,
+                            name: _,
+                            oid,
+                        }) => oid.clone(),
+                        _ => {
+                            return Err(Error::new(ErrorType::Other(format!(
+                                "Hardlink {} points to {}, which is no preceding file",
+                                path.str_lossy(),
+                                target.str_lossy()
+                            ))))
+                        }
+                    };
+
+                    TreeEntry::File {
+                        info: UNIXInfo::new(uid, gid, S_IFREG | mode),
+                        name,
+                        oid,
+                    }
+                }
+                EntryType::Char | EntryType::Block => {
+                    let (kind, ty_bits) = match ty {
+                        EntryType::Char => (DeviceKind::Character, S_IFCHR),
+                        _ => (DeviceKind::Block, S_IFBLK),
+                    };
+
+                    TreeEntry::Device {
+                        info: UNIXInfo::new(uid, gid, ty_bits | mode),
+                        name,
+                        kind,
+                        major: header.device_major().e_context(context)?.unwrap_or(0),
+                        minor: header.device_minor().e_context(context)?.unwrap_or(0),
+                    }
+                }
+                EntryType::Fifo => TreeEntry::Fifo {
+                    info: UNIXInfo::new(uid, gid, S_IFIFO | mode),
+                    name,
+                },
+                ty => {
+                    warn!("Skipping {} of type {ty:?}", path.str_lossy());
+                    continue;
+                }
+            };
+
+            trace!("Indexed {} from archive", path.str_lossy());
+
+            let parent = root.get_or_create_subtree(parent_path)?;
+            parent.entries.retain(|e| e.name() != new_entry.name());
+            parent.entries.push(new_entry);
+        }
+
+        root.sort_recursive();
+
+        Ok(root)
+    }
+
+    /// Appends the entries of this tree to a `tar` archive, the counterpart to [Tree::index_tar()].
+    ///
+    /// The entries keep their ownership and modes, their modification times are
+    /// set to `0` so the same tree always yields the same archive.
+    /// Every file is held in memory while it gets appended
+    /// # Arguments
+    /// * `builder` - The archive to append to
+    /// * `prefix` - The path within the archive to put the entries at
+    /// * `db` - The object database to read the files from
+    pub fn write_tar<W: Write>(
+        &self,
+        builder: &mut tar::Builder<W>,
+        prefix: &Path,
+        db: &ObjectDB,
+    ) -> Result<(), Error> {
+        for entry in &self.entries {
+            let path = prefix.join(entry.name());
+            let context = || format!("Appending {} to archive", path.str_lossy());
+
+            let info = entry.info();
+
+            let mut header = Header::new_gnu();
+            header.set_uid(info.uid as u64);
+            header.set_gid(info.gid as u64);
+            header.set_mode(info.mode & 0o7777);
+            header.set_mtime(0);
+
+            match entry {
+                TreeEntry::File {
+                    info: _,
+                    name: _,
+                    oid,
+                } => {
+                    let mut data = Vec::new();
+                    db.read(oid)
+                        .ctx(context)?
+                        .read_to_end(&mut data)
+                        .e_context(context)?;
+
+                    header.set_entry_type(EntryType::Regular);
+                    header.set_size(data.len() as u64);
+                    builder
+                        .append_data(&mut header, &path, data.as_slice())
+                        .e_context(context)?;
+                }
+                TreeEntry::Symlink {
+                    info: _,
+                    name: _,
+                    destination,
+                } => {
+                    header.set_entry_type(EntryType::Symlink);
+                    header.set_size(0);
+                    builder
+                        .append_link(&mut header, &path, destination)
+                        .e_context(context)?;
+                }
+                TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                } => {
+                    header.set_entry_type(EntryType::Directory);
+                    header.set_size(0);
+                    builder
+                        .append_data(&mut header, &path, std::io::empty())
+                        .e_context(context)?;
+
+                    tree.write_tar(builder, &path, db)?;
+                }
+                TreeEntry::Device {
+                    info: _,
+                    name: _,
+                    kind,
+                    major,
+                    minor,
+                } => {
+                    header.set_entry_type(match kind {
+                        DeviceKind::Character => EntryType::Char,
+                        DeviceKind::Block => EntryType::Block,
+                    });
+                    header.set_size(0);
+                    header.set_device_major(*major).e_context(context)?;
+                    header.set_device_minor(*minor).e_context(context)?;
+                    builder
+                        .append_data(&mut header, &path, std::io::empty())
+                        .e_context(context)?;
+                }
+                TreeEntry::Fifo { info: _, name: _ } => {
+                    header.set_entry_type(EntryType::Fifo);
+                    header.set_size(0);
+                    builder
+                        .append_data(&mut header, &path, std::io::empty())
+                        .e_context(context)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the subtree at `subpath`, creating the missing subtrees along the way
+    /// # Arguments
+    /// * `subpath` - The relative, normalized path of the subtree
+    fn get_or_create_subtree(&mut self, subpath: &Path) -> Result<&mut Tree, Error> {
+        let mut cur = self;
+
+        for component in subpath.components() {
+            let name = component.as_os_str().to_string_lossy().to_string();
+
+            if cur.get_entry_by_name(&name).is_none() {
+                cur.entries.push(TreeEntry::Subtree {
+                    info: UNIXInfo::new(0, 0, IMPLICIT_DIR_MODE),
+                    name: name.clone(),
+                    tree: Tree {
+                        entries: Vec::new(),
+                    },
+                });
+            }
+
+            cur = match cur.get_entry_by_name_mut(&name) {
+                Some(TreeEntry::Subtree {
+                    info: _,
+                    name: _,
+                    tree,
+                }) => tree,
+                _ => {
+                    return Err(Error::new(ErrorType::Other(format!(
+                        "{} is not a directory",
+                        subpath.str_lossy()
+                    ))))
+                }
+            };
+        }
+
+        Ok(cur)
+    }
+
+    /// Sorts the entries of this tree and all subtrees alphabetically
+    fn sort_recursive(&mut self) {
+        self.entries.sort();
+
+        for entry in &mut self.entries {
+            if let TreeEntry::Subtree {
+                info: _,
+                name: _,
+                tree,
+            } = entry
+            {
+                tree.sort_recursive();
+            }
+        }
+    }
+}
+
+/// Inserts `data` into `db` as an object of the inferred type
+/// # Arguments
+/// * `db` - The object database to insert into
+/// * `data` - The data to insert
+/// * `compression` - The form of compression to use when inserting
+fn insert_data(
+    db: &mut ObjectDB,
+    data: Vec<u8>,
+    compression: ObjectCompression,
+) -> Result<ObjectID, Error> {
+    let mut cursor = Cursor::new(data);
+
+    let ty = ObjectType::infer(&mut cursor)?;
+    cursor.rewind().e_context(|| "Rewinding data")?;
+
+    Ok(db
+        .insert_stream(&mut cursor, ty, compression, Vec::new())?
+        .oid)
+}
+
+/// Normalizes the path of an archive entry to be relative to the root of the tree,
+/// dropping leading `/` and `.` components
+/// # Arguments
+/// * `path` - The path to normalize
+/// # Errors
+/// If the path leaves the root using `..`
+fn normalize(path: &Path) -> Result<PathBuf, Error> {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Archive entry {} leaves the root",
+                    path.str_lossy()
+                ))))
+            }
+        }
+    }
+
+    Ok(normalized)
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use crate::{
+    error::{Error, ErrorExt},
+    model::ObjectID,
+    util::fs::{self, PathUtil, UNIXInfo},
+};
+
+use super::{treefilter::matches_any, Tree, TreeDifference, TreeEntry};
+
+impl Tree {
+    /// Verifies that the filesystem at `root` matches this tree by recomputing
+    /// the object ids of all files and comparing them and the UNIX information.
+    ///
+    /// The returned differences treat this tree as the old one, so
+    /// [TreeDifference::Added] are extra entries in `root` and
+    /// [TreeDifference::Removed] are entries missing from `root`
+    /// # Arguments
+    /// * `root` - The directory this tree has been deployed to
+    /// * `ignore` - Glob patterns of paths to skip (see [crate::util::string::glob_match()]),
+    ///   matching directories are not walked
+    pub fn verify<S: AsRef<str>>(
+        &self,
+        root: &Path,
+        ignore: &[S],
+    ) -> Result<Vec<TreeDifference>, Error> {
+        let deployed = Tree::hash_dir(root, Path::new(""), ignore)
+            .ctx(|| format!("Verifying {}", root.str_lossy()))?;
+
+        let differences = self
+            .diff(&deployed)
+            .into_iter()
+            .filter(|d| !d.path().ancestors().any(|p| matches_any(ignore, p)))
+            .collect();
+
+        Ok(differences)
+    }
+
+    /// Creates a tree from the directory `dir` like [Tree::index()],
+    /// but only computes the object ids instead of inserting the files
+    /// # Arguments
+    /// * `dir` - The directory to walk
+    /// * `prefix` - The path of `dir` relative to the root directory
+    /// * `ignore` - Glob patterns of paths to skip
+    fn hash_dir<S: AsRef<str>>(dir: &Path, prefix: &Path, ignore: &[S]) -> Result<Tree, Error> {
+        let mut entries: Vec<TreeEntry> = Vec::new();
+
+        for entry in std::fs::read_dir(dir).e_context(|| format!("Walking {}", dir.str_lossy()))? {
+            let entry = entry.e_context(|| "Reading filesystem entry")?;
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            let rel_path = prefix.join(&name);
+            if matches_any(ignore, &rel_path) {
+                continue;
+            }
+
+            let path = dir.join(&name);
+            let info = UNIXInfo::from_entry(&entry)
+                .e_context(|| format!("Getting UNIX info of {}", path.str_lossy()))?;
+
+            if path.is_symlink() {
+                entries.push(TreeEntry::Symlink {
+                    info,
+                    name,
+                    destination: path
+                        .read_link()
+                        .e_context(|| format!("Reading link target of {}", path.str_lossy()))?
+                        .to_string_lossy()
+                        .to_string(),
+                })
+            } else if let Some(special) = TreeEntry::from_special_file(&entry, &info, &name)? {
+                entries.push(special)
+            } else if path.is_dir() {
+                let tree = Tree::hash_dir(&path, &rel_path, ignore)?;
+                entries.push(TreeEntry::Subtree { info, name, tree });
+            } else {
+                let mut file = fs::file_open(&path)?;
+                let oid = ObjectID::new_from_stream(&mut file, &Vec::new())
+                    .ctx(|| format!("Hashing {}", path.str_lossy()))?;
+                entries.push(TreeEntry::File { info, name, oid });
+            }
+        }
+
+        entries.sort();
+
+        Ok(Tree { entries })
+    }
+}
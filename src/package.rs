@@ -5,10 +5,19 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::util::fs::{Directory, SearchType};
+use crate::{
+    error::{Error, ErrorType},
+    util::{
+        architecture::Architecture,
+        fs::{self, Directory, PathUtil, SearchType},
+    },
+};
 
 use self::info::PackageInfo;
 
+mod archive;
+pub use archive::*;
+
 #[cfg(feature = "builder")]
 mod buildable;
 #[cfg(feature = "builder")]
@@ -16,6 +25,12 @@ pub use buildable::*;
 
 pub mod info;
 
+mod layout;
+pub use layout::*;
+
+mod scriptlet;
+pub use scriptlet::*;
+
 /// A package that has a name
 pub trait NamedPackage {
     /// Returns the `name` of the package
@@ -48,8 +63,12 @@ pub trait NameVersionPackage: NamedPackage + VersionedPackage {
 /// The minimal trait to be considered a package
 pub trait CorePackage: NamedPackage + VersionedPackage + NameVersionPackage {
     /// Returns the path to the package when it is installed: `<DIST_DIR>/<arch>/<name>/<version>/<pkgver>`
-    fn get_path(&self, dist_dir: &Path) -> PathBuf {
+    /// # Arguments
+    /// * `dist_dir` - The DIST directory
+    /// * `arch` - The architecture the package is built for
+    fn get_path(&self, dist_dir: &Path, arch: &Architecture) -> PathBuf {
         dist_dir
+            .join(&arch.arch)
             .join(self.get_name())
             .join(self.get_version())
             .join(self.get_pkgver().to_string())
@@ -62,31 +81,74 @@ pub trait CorePackage: NamedPackage + VersionedPackage + NameVersionPackage {
             version: self.get_version().to_owned(),
             pkgver: self.get_pkgver(),
             id: self.get_id().to_owned(),
+            oid: None,
         }
     }
 
-    /// Returns the directory this package lives at relative to `dist_dir`
+    /// Returns the directory this package lives at relative to `dist_dir`,
+    /// refer to [CorePackage::get_path()]
     /// # Arguments
     /// * `dist_dir` - The DIST directory
-    fn get_install_dir(&self, dist_dir: &Path) -> PathBuf {
-        dist_dir
-            .join("pkg")
-            .join(format!("{}_{}", self.get_name_version(), self.get_id()))
+    /// * `arch` - The architecture the package is built for
+    fn get_install_dir(&self, dist_dir: &Path, arch: &Architecture) -> PathBuf {
+        self.get_path(dist_dir, arch)
     }
 
     /// Returns the directory that contains the package files relative to `dist_dir`
     /// # Arguments
     /// * `dist_dir` - The DIST directory
-    fn get_root_dir(&self, dist_dir: &Path) -> PathBuf {
-        self.get_install_dir(dist_dir).join("root")
+    /// * `arch` - The architecture the package is built for
+    fn get_root_dir(&self, dist_dir: &Path, arch: &Architecture) -> PathBuf {
+        self.get_install_dir(dist_dir, arch).join("root")
     }
 
     /// Returns the directory that contains the directory for linking
     /// to other objects relative to `dist_dir`
     /// # Arguments
     /// * `dist_dir` - The DIST directory
-    fn get_link_dir(&self, dist_dir: &Path) -> PathBuf {
-        self.get_install_dir(dist_dir).join("link")
+    /// * `arch` - The architecture the package is built for
+    fn get_link_dir(&self, dist_dir: &Path, arch: &Architecture) -> PathBuf {
+        self.get_install_dir(dist_dir, arch).join("link")
+    }
+
+    /// Returns the directory this package lived at before the DIST directory
+    /// included the architecture: `<DIST_DIR>/pkg/<name>-<version>-<pkgver>_<id>`
+    /// # Arguments
+    /// * `dist_dir` - The DIST directory
+    fn get_legacy_install_dir(&self, dist_dir: &Path) -> PathBuf {
+        dist_dir
+            .join("pkg")
+            .join(format!("{}_{}", self.get_name_version(), self.get_id()))
+    }
+
+    /// Moves this package from its [legacy install directory](CorePackage::get_legacy_install_dir())
+    /// to its [install directory](CorePackage::get_install_dir()), if it exists.
+    ///
+    /// The legacy layout does not record the architecture, so it has to be provided
+    /// # Arguments
+    /// * `dist_dir` - The DIST directory
+    /// * `arch` - The architecture the package has been built for
+    /// # Returns
+    /// Whether the package has been moved
+    fn migrate_install_dir(&self, dist_dir: &Path, arch: &Architecture) -> Result<bool, Error> {
+        let legacy = self.get_legacy_install_dir(dist_dir);
+        if !legacy.is_dir() {
+            return Ok(false);
+        }
+
+        let target = self.get_install_dir(dist_dir, arch);
+        if target.exists() {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Can't migrate {}, {} exists already",
+                legacy.str_lossy(),
+                target.str_lossy()
+            ))));
+        }
+
+        fs::create_parent_dir_all(&target)?;
+        fs::rename(&legacy, &target)?;
+
+        Ok(true)
     }
 }
 
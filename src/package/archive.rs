@@ -0,0 +1,271 @@
+use std::{
+    fmt::Display,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tar::{EntryType, Header};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    model::{Formula, ObjectDB, ObjectID, RepositoryIndexEntry, Signature, Tree},
+    util::{architecture::Architecture, parse::spdx::SpdxExpression},
+};
+
+/// The name of the metadata file at the root of package archives
+pub static PACKAGE_METADATA_FILE: &str = "package.toml";
+
+/// The version of the package metadata format written by [PackageMetadata::toml()]:
+/// Version `2` adds the object ids of the dependencies, the build receipt and the signature
+pub static PACKAGE_METADATA_FORMAT: u32 = 2;
+
+/// The directory in package archives holding the files of the package,
+/// relative to the root they get installed to
+pub static PACKAGE_ROOT_DIR: &str = "root";
+
+/// The metadata embedded into package archives (`package.toml`), so
+/// the package can be installed without access to the object database
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackageMetadata {
+    /// The version of the metadata format, metadata without it is of version `1`
+    #[serde(default = "default_metadata_format")]
+    pub format: u32,
+    /// The name of the package
+    pub name: String,
+    /// The version of the package
+    pub version: String,
+    /// The architecture of the package, `None` if architecture independent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<Architecture>,
+    /// The description of the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The license of the package as an SPDX expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<SpdxExpression>,
+    /// The virtual capabilities the package provides in addition to its name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provides: Vec<String>,
+    /// The packages this package needs at runtime
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends: Vec<PackageDependency>,
+    /// The script to run in the target root after installing the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_install: Option<String>,
+    /// The script to run in the target root before removing the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_remove: Option<String>,
+
+    /// The directories containing executables, relative to the package root
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub executable_dirs: Vec<PathBuf>,
+    /// The directories containing libraries, relative to the package root
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub library_dirs: Vec<PathBuf>,
+
+    /// The object id of the package the archive has been created from
+    pub oid: ObjectID,
+    /// The object id of the formula the package has been built from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formula: Option<ObjectID>,
+    /// The object id of the build receipt of the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<ObjectID>,
+    /// A detached signature vouching for the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<PackageSignatureRef>,
+}
+
+/// A runtime dependency listed in [PackageMetadata]. Version `1` of the
+/// metadata format lists the names only, which get parsed with no object id
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "PackageDependencyRepr")]
+pub struct PackageDependency {
+    /// The name or a provided capability of the package
+    pub name: String,
+    /// The object id of the package the dependency has been resolved to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oid: Option<ObjectID>,
+}
+
+/// The representations of a [PackageDependency] in the metadata formats
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PackageDependencyRepr {
+    /// Version `1`: The name only
+    Name(String),
+    /// Version `2`: A table with the name and object id
+    Table {
+        name: String,
+        #[serde(default)]
+        oid: Option<ObjectID>,
+    },
+}
+
+/// A reference to a detached [crate::model::Signature] of a package
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackageSignatureRef {
+    /// The object id of the signature object
+    pub oid: ObjectID,
+    /// The public key that created the signature (hex encoded)
+    pub key: String,
+}
+
+impl PackageMetadata {
+    /// Collects the metadata of the package of a repository index entry
+    /// # Arguments
+    /// * `entry` - The entry of the package, has to have a package
+    /// * `formula` - The formula of the package, if available
+    pub fn new(entry: &RepositoryIndexEntry, formula: Option<&Formula>) -> Self {
+        let layout = formula.map(|f| f.get_layout());
+
+        Self {
+            format: PACKAGE_METADATA_FORMAT,
+            name: entry.name.clone(),
+            version: entry.version.clone(),
+            arch: entry.arch.clone(),
+            description: formula.map(|f| f.description.clone()),
+            license: entry.license.clone(),
+            provides: entry.provides.clone(),
+            depends: Vec::new(),
+            post_install: formula.and_then(|f| f.post_install.clone()),
+            pre_remove: formula.and_then(|f| f.pre_remove.clone()),
+            executable_dirs: layout
+                .as_ref()
+                .map(|l| l.executable_dirs.clone())
+                .unwrap_or_default(),
+            library_dirs: layout.map(|l| l.library_dirs).unwrap_or_default(),
+            oid: entry
+                .package
+                .clone()
+                .expect("Package metadata needs an entry with a package"),
+            formula: entry.formula.clone(),
+            receipt: None,
+            signature: None,
+        }
+    }
+
+    /// Sets the packages this package needs at runtime
+    /// # Arguments
+    /// * `depends` - The dependencies of the package
+    pub fn with_depends(mut self, depends: Vec<PackageDependency>) -> Self {
+        self.depends = depends;
+        self
+    }
+
+    /// Sets the build receipt of the package
+    /// # Arguments
+    /// * `receipt` - The object id of the receipt object
+    pub fn with_receipt(mut self, receipt: Option<ObjectID>) -> Self {
+        self.receipt = receipt;
+        self
+    }
+
+    /// Sets the signature vouching for the package
+    /// # Arguments
+    /// * `signature` - The object id of the signature object and the signature
+    pub fn with_signature(mut self, signature: Option<(ObjectID, Signature)>) -> Self {
+        self.signature = signature.map(|(oid, signature)| PackageSignatureRef {
+            oid,
+            key: signature.key,
+        });
+        self
+    }
+
+    /// Parses metadata from its `TOML` representation, accepting
+    /// all formats up to [PACKAGE_METADATA_FORMAT]
+    /// # Arguments
+    /// * `toml` - The `TOML` string to parse
+    pub fn parse(toml: &str) -> Result<Self, Error> {
+        let metadata: Self = toml::from_str(toml).ctx(|| "Parsing package metadata")?;
+
+        if metadata.format > PACKAGE_METADATA_FORMAT {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Package metadata format {} is not supported, supported up to {}",
+                metadata.format, PACKAGE_METADATA_FORMAT
+            ))));
+        }
+
+        Ok(metadata)
+    }
+
+    /// Returns the `TOML` string for this metadata
+    pub fn toml(&self) -> String {
+        toml::to_string(self).expect("Serialize package metadata should never fail")
+    }
+}
+
+impl From<PackageDependencyRepr> for PackageDependency {
+    fn from(value: PackageDependencyRepr) -> Self {
+        match value {
+            PackageDependencyRepr::Name(name) => Self { name, oid: None },
+            PackageDependencyRepr::Table { name, oid } => Self { name, oid },
+        }
+    }
+}
+
+impl Display for PackageDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.oid {
+            Some(oid) => write!(f, "{} ({oid})", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+fn default_metadata_format() -> u32 {
+    1
+}
+
+/// Writes a package to a `tar.xz` archive that can be used without the object database:
+/// The metadata is stored as [PACKAGE_METADATA_FILE], the files of the package below [PACKAGE_ROOT_DIR]
+/// # Arguments
+/// * `output` - The stream to write the archive to
+/// * `metadata` - The metadata to embed
+/// * `tree` - The tree of the package's files
+/// * `odb` - The object database to read the files from
+/// # Returns
+/// The `output` stream after the archive has been written
+pub fn write_package_archive<W: Write>(
+    output: W,
+    metadata: &PackageMetadata,
+    tree: &Tree,
+    odb: &ObjectDB,
+) -> Result<W, Error> {
+    let context = || format!("Writing package archive of {}", metadata.oid);
+
+    let mut builder = tar::Builder::new(xz::write::XzEncoder::new(output, 6));
+
+    let toml = metadata.toml();
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_size(toml.len() as u64);
+    builder
+        .append_data(&mut header, PACKAGE_METADATA_FILE, toml.as_bytes())
+        .e_context(context)?;
+
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Directory);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mode(0o755);
+    header.set_mtime(0);
+    header.set_size(0);
+    builder
+        .append_data(&mut header, PACKAGE_ROOT_DIR, std::io::empty())
+        .e_context(context)?;
+
+    tree.write_tar(&mut builder, Path::new(PACKAGE_ROOT_DIR), odb)
+        .ctx(context)?;
+
+    builder
+        .into_inner()
+        .e_context(context)?
+        .finish()
+        .e_context(context)
+}
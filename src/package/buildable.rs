@@ -4,11 +4,19 @@ use log::info;
 
 use crate::{
     cache::download::DownloadCache,
-    env::executable::BuildStep,
-    error::{Error, ErrorExt},
-    files::formula::FormulaFile,
+    env::{executable::BuildStep, HermeticSettings},
+    error::{Error, ErrorExt, ErrorType},
+    files::{
+        formulafile::{is_within_formula_dir, FormulaFile},
+        formulavars::FormulaVariables,
+    },
+    model::{ObjectDB, Tree},
     tools::builder::{BuilderError, BuilderWorkdir},
-    util::{self, architecture::Architecture},
+    util::{
+        self,
+        architecture::Architecture,
+        fs::{self, PathUtil},
+    },
 };
 
 use super::{CorePackage, DescribedPackage, NameVersionPackage, NamedPackage, VersionedPackage};
@@ -22,6 +30,12 @@ pub struct BuildablePackage<'a> {
     architecture: Architecture,
     /// The working directory to use for building
     workdir: &'a BuilderWorkdir,
+    /// The variables of the formula
+    variables: FormulaVariables,
+    /// The fixed clock and locale settings for the build steps
+    hermetic: Option<HermeticSettings>,
+    /// Whether to retain the changes of every build step as a snapshot
+    step_snapshots: bool,
 }
 
 impl<'a> BuildablePackage<'a> {
@@ -41,11 +55,20 @@ impl<'a> BuildablePackage<'a> {
         // First, make sure we can even build the formula for the architecture
         Self::ensure_buildable(&formula, &architecture)?;
 
+        // Refuse to build on top of mounts a crashed build left behind,
+        // callers can recover them using `workdir.prepare(true)` beforehand
+        workdir.prepare(false)?;
+
+        let variables = formula.get_variables(&architecture)?;
+
         // Create the package
         let pkg = Self {
             formula: formula.clone(),
             architecture,
             workdir,
+            variables,
+            hermetic: None,
+            step_snapshots: false,
         };
 
         // Ensure sources are present
@@ -54,6 +77,81 @@ impl<'a> BuildablePackage<'a> {
         Ok(pkg)
     }
 
+    /// Constructs a buildable package from a formula file and the tree of its resolved
+    /// formula (refer to [crate::model::Formula::tree]), which contains the formula
+    /// directory and the fetched sources: The tree gets deployed to the formula directory
+    /// of `workdir` and the sources get extracted, so nothing has to be fetched again
+    ///
+    /// This function will not succeed unless the resulting package can be built
+    /// # Arguments
+    /// * `formula` - The formula to wrap in this package
+    /// * `architecture` - The architecture the package should be buildable for
+    /// * `workdir` - The working directory to use for building
+    /// * `tree` - The tree of the resolved formula
+    /// * `odb` - The object database to read the files of `tree` from
+    pub fn from_tree(
+        formula: FormulaFile,
+        architecture: Architecture,
+        workdir: &'a BuilderWorkdir,
+        tree: &Tree,
+        odb: &ObjectDB,
+    ) -> Result<Self, Error> {
+        Self::ensure_buildable(&formula, &architecture)?;
+
+        workdir.prepare(false)?;
+
+        let variables = formula.get_variables(&architecture)?;
+
+        let pkg = Self {
+            formula,
+            architecture,
+            workdir,
+            variables,
+            hermetic: None,
+            step_snapshots: false,
+        };
+
+        let formula_dir = workdir.get_formula_dir();
+        tree.deploy(&formula_dir, odb)
+            .ctx(|| format!("Deploying formula tree to {}", formula_dir.str_lossy()))?;
+
+        pkg.extract_sources()?;
+        pkg.copy_patches()?;
+
+        Ok(pkg)
+    }
+
+    /// Runs all build steps with the fixed clock and locale of `settings`,
+    /// unless the formula opted out using `hermetic = false`
+    /// # Arguments
+    /// * `settings` - The settings to use
+    pub fn with_hermetic(mut self, settings: HermeticSettings) -> Self {
+        if self.formula.package.hermetic {
+            self.hermetic = Some(settings);
+        }
+        self
+    }
+
+    /// Returns the fixed clock and locale settings the build steps run with, if any
+    pub fn get_hermetic(&self) -> Option<&HermeticSettings> {
+        self.hermetic.as_ref()
+    }
+
+    /// Retains the files every build step changed in the build root as a tree object
+    /// linked to the build receipt (refer to [crate::tools::builder::BuildReport::snapshot_step()]),
+    /// so failed builds can be inspected without running them again
+    /// # Arguments
+    /// * `step_snapshots` - Whether to retain the snapshots
+    pub fn with_step_snapshots(mut self, step_snapshots: bool) -> Self {
+        self.step_snapshots = step_snapshots;
+        self
+    }
+
+    /// Returns whether the changes of every build step are retained as snapshots
+    pub fn get_step_snapshots(&self) -> bool {
+        self.step_snapshots
+    }
+
     /// Returns the working directory for this buildable package
     pub fn get_workdir(&self) -> &BuilderWorkdir {
         self.workdir
@@ -75,7 +173,10 @@ impl<'a> BuildablePackage<'a> {
     }
 
     /// Returns the build steps for this package to be executed
-    /// in the order they are returned from this function
+    /// in the order they are returned from this function.
+    ///
+    /// Every patch gets applied in its own step between `prepare` and `build`,
+    /// running in the patch directory (refer to [FormulaFile::get_patch_dir()])
     pub fn get_buildsteps(&self) -> Vec<BuildStep> {
         let mut res = Vec::new();
 
@@ -83,6 +184,20 @@ impl<'a> BuildablePackage<'a> {
             res.push(self.create_buildstep("Prepare".to_owned(), step.to_owned()))
         }
 
+        let patches_dir = self.workdir.get_patches_dir_inner();
+        let patch_dir = self.formula.get_patch_dir(&self.variables);
+        for (i, patch) in self.formula.package.patches.iter().flatten().enumerate() {
+            let mut step = self.create_buildstep(
+                format!("Patch ({patch})"),
+                format!("patch -p{} -N -i \"$1\"", self.formula.package.patch_strip),
+            );
+            step.arguments = vec![patches_dir.join(patch_file_name(i)).str_lossy()];
+            if let Some(dir) = &patch_dir {
+                step.workdir = step.workdir.join(dir);
+            }
+            res.push(step)
+        }
+
         if let Some(step) = &self.formula.package.build {
             res.push(self.create_buildstep("Build".to_owned(), step.to_owned()))
         }
@@ -103,13 +218,29 @@ impl<'a> BuildablePackage<'a> {
     /// * `name` - The name for the build step
     /// * `command` - The command to execute for this buildstep
     fn create_buildstep(&self, name: String, command: String) -> BuildStep {
+        let policy = self.formula.package.get_step_policy(&name.to_lowercase());
+
         BuildStep {
             name,
             pkg_info: self.get_info(),
             arch: self.architecture.clone(),
             command,
+            arguments: Vec::new(),
             workdir: PathBuf::from("/"),
             install_dir: self.workdir.get_install_dir_inner(),
+            timeout: policy.get_timeout(),
+            retries: policy.retries.unwrap_or(0),
+            variables: self.variables.get_all(),
+            environment: self
+                .formula
+                .get_env()
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = self.variables.replace(&value);
+                    (name, value)
+                })
+                .collect(),
+            hermetic: self.hermetic.clone(),
         }
     }
 }
@@ -154,8 +285,9 @@ impl<'a> BuildablePackage<'a> {
         // Fetch and extract sources
         if let Some(sources) = &self.formula.package.sources {
             for src in sources {
-                let url = src.get_url(self);
-                let dest = src.get_dest(self);
+                let url = src.get_url(&self.variables);
+                let urls = src.get_urls(&self.variables);
+                let dest = src.get_dest(&self.variables);
 
                 let context = || format!("Fetching source '{url}' to '{dest}'",);
 
@@ -164,14 +296,23 @@ impl<'a> BuildablePackage<'a> {
 
                 util::fs::create_dir_all(&formula_dir).e_context(context)?;
 
-                cache
-                    .download(
-                        &url,
+                if let Some(reference) = src.get_git_reference(&self.variables) {
+                    let commit = util::git::clone_shallow(&url, &reference, &full_dest_dir)
+                        .e_context(context)?;
+                    info!("Checked out '{dest}' at {commit}");
+                    continue;
+                }
+
+                let mirror = cache
+                    .download_mirrored(
+                        &urls,
                         &full_dest_dir,
                         &format!("Fetching '{url}' to '{dest}'"),
-                        true,
                     )
                     .e_context(context)?;
+                if let Some(mirror) = mirror.filter(|m| *m != url) {
+                    info!("Fetched '{dest}' from mirror '{mirror}'");
+                }
 
                 if src.extract {
                     info!("Extracting {}...", dest);
@@ -182,6 +323,52 @@ impl<'a> BuildablePackage<'a> {
 
         Ok(())
     }
+
+    /// Copies the patches from the formula directory to the patches directory of
+    /// the workdir, so the patch steps only read the files validated by the formula
+    /// (refer to [BuildablePackage::get_buildsteps()])
+    fn copy_patches(&self) -> Result<(), Error> {
+        let formula_dir = self.workdir.get_formula_dir();
+        let patches_dir = self.workdir.get_patches_dir();
+
+        for (i, patch) in self.formula.package.patches.iter().flatten().enumerate() {
+            if !is_within_formula_dir(patch) {
+                return Err(Error::new(ErrorType::Other(format!(
+                    "Patch '{patch}' has to be relative to the formula directory without '..'"
+                ))));
+            }
+
+            fs::create_dir_all(&patches_dir)?;
+            fs::copy(
+                &formula_dir.join(patch),
+                &patches_dir.join(patch_file_name(i)),
+            )
+            .ctx(|| format!("Copying patch '{patch}'"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Extracts the downloaded sources that are present in the formula directory already,
+    /// sources that are directories (git checkouts and overridden sources) are left as they are
+    fn extract_sources(&self) -> Result<(), Error> {
+        let formula_dir = self.workdir.get_formula_dir();
+
+        for src in self.formula.package.sources.iter().flatten() {
+            let dest = src.get_dest(&self.variables);
+            let full_dest_dir = formula_dir.join(&dest);
+
+            if !src.extract || full_dest_dir.is_dir() {
+                continue;
+            }
+
+            info!("Extracting {}...", dest);
+            util::archive::extract_infer(&full_dest_dir, &formula_dir)
+                .e_context(|| format!("Extracting source '{dest}'"))?
+        }
+
+        Ok(())
+    }
 }
 
 impl<'a> NamedPackage for BuildablePackage<'a> {
@@ -196,7 +383,7 @@ impl<'a> VersionedPackage for BuildablePackage<'a> {
     }
 
     fn get_pkgver(&self) -> u32 {
-        self.formula.package.pkgver
+        self.formula.package.get_pkgver()
     }
 
     fn get_id(&self) -> &str {
@@ -213,3 +400,10 @@ impl<'a> DescribedPackage for BuildablePackage<'a> {
 }
 
 impl<'a> CorePackage for BuildablePackage<'a> {}
+
+/// Returns the file name the patch at `index` gets copied to within the patches directory
+/// # Arguments
+/// * `index` - The index of the patch in the formula
+fn patch_file_name(index: usize) -> String {
+    format!("{index:03}.patch")
+}
@@ -1,3 +1,8 @@
+use crate::{
+    error::Error,
+    model::{ObjectDB, ObjectID, RepositoryIndex},
+};
+
 use super::{CorePackage, NameVersionPackage, NamedPackage, VersionedPackage};
 
 /// Describes a package, just the neccessary stuff
@@ -11,6 +16,9 @@ pub struct PackageInfo {
     pub pkgver: u32,
     /// The unique package id
     pub id: String,
+    /// The object id of the built package, if it exists in the object database.
+    /// Unlike the other fields, this identifies a specific build of the package
+    pub oid: Option<ObjectID>,
 }
 
 impl PackageInfo {
@@ -21,7 +29,32 @@ impl PackageInfo {
             version: package.get_version().to_owned(),
             pkgver: package.get_pkgver(),
             id: package.get_id().to_owned(),
+            oid: None,
+        }
+    }
+
+    /// Populates the object id from the built package of the entry in `index`
+    /// matching this package (refer to [RepositoryIndex::find_package()]),
+    /// if the package exists in `odb`
+    /// # Arguments
+    /// * `index` - The repository index to search the package in
+    /// * `odb` - The object database the package has to exist in
+    /// # Returns
+    /// The object id of the package, if it has been found
+    pub fn resolve_oid(
+        &mut self,
+        index: &RepositoryIndex,
+        odb: &ObjectDB,
+    ) -> Result<Option<&ObjectID>, Error> {
+        let package = index.find_package(self).and_then(|e| e.package.clone());
+
+        if let Some(oid) = package {
+            if odb.try_get_object(&oid)?.is_some() {
+                self.oid = Some(oid);
+            }
         }
+
+        Ok(self.oid.as_ref())
     }
 }
 
@@ -32,6 +65,7 @@ impl From<&dyn CorePackage> for PackageInfo {
             version: value.get_version().to_owned(),
             pkgver: value.get_pkgver(),
             id: value.get_id().to_owned(),
+            oid: None,
         }
     }
 }
@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+
+/// The layout purpose listing the directories containing executables
+pub static LAYOUT_EXECUTABLES: &str = "executables";
+
+/// The layout purpose listing the directories containing libraries
+pub static LAYOUT_LIBRARIES: &str = "libraries";
+
+/// The directories containing executables if the layout does not declare them
+pub static DEFAULT_EXECUTABLE_DIRS: &[&str] = &["bin", "sbin", "usr/bin", "usr/sbin"];
+
+/// The directories containing libraries if the layout does not declare them
+pub static DEFAULT_LIBRARY_DIRS: &[&str] = &["lib", "lib64", "usr/lib", "usr/lib64"];
+
+/// The special directories of a package derived from the `layout` of its formula.
+///
+/// The directories are relative to the package root. The
+/// validators only descend into these directories when scanning for
+/// ELF files and scripts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageLayout {
+    /// The directories containing executable files
+    pub executable_dirs: Vec<PathBuf>,
+    /// The directories containing library files
+    pub library_dirs: Vec<PathBuf>,
+}
+
+impl PackageLayout {
+    /// Derives the package layout from the `layout` section of a formula,
+    /// using the [LAYOUT_EXECUTABLES] and [LAYOUT_LIBRARIES] purposes.
+    /// Purposes that are not declared fall back to [DEFAULT_EXECUTABLE_DIRS]
+    /// and [DEFAULT_LIBRARY_DIRS], respectively
+    /// # Arguments
+    /// * `layout` - The purposes and the directories that serve them
+    pub fn from_layout(layout: &IndexMap<String, Vec<String>>) -> Self {
+        let dirs = |purpose: &str, defaults: &[&str]| -> Vec<PathBuf> {
+            match layout.get(purpose) {
+                Some(dirs) => dirs
+                    .iter()
+                    .map(|d| PathBuf::from(d.trim_start_matches('/')))
+                    .collect(),
+                None => defaults.iter().map(PathBuf::from).collect(),
+            }
+        };
+
+        Self {
+            executable_dirs: dirs(LAYOUT_EXECUTABLES, DEFAULT_EXECUTABLE_DIRS),
+            library_dirs: dirs(LAYOUT_LIBRARIES, DEFAULT_LIBRARY_DIRS),
+        }
+    }
+
+    /// Returns the directories the validators scan: The executable and library directories
+    pub fn get_scan_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = self.executable_dirs.clone();
+        for dir in &self.library_dirs {
+            if !dirs.contains(dir) {
+                dirs.push(dir.clone());
+            }
+        }
+        dirs
+    }
+}
+
+impl Default for PackageLayout {
+    fn default() -> Self {
+        Self::from_layout(&IndexMap::new())
+    }
+}
+
+/// Returns whether `path` lies within one of `dirs`
+/// # Arguments
+/// * `path` - The path to check, relative to the package root
+/// * `dirs` - The directories to check against, `None` to allow every path
+pub fn is_within_dirs(path: &Path, dirs: Option<&[PathBuf]>) -> bool {
+    dirs.map(|dirs| dirs.iter().any(|dir| path.starts_with(dir)))
+        .unwrap_or(true)
+}
+
+/// Returns whether a validator scanning `dirs` has to descend into the directory at `path`:
+/// If it lies within one of `dirs` or one of `dirs` lies within it
+/// # Arguments
+/// * `path` - The path to the directory, relative to the package root
+/// * `dirs` - The directories to scan, `None` to scan every directory
+pub fn should_descend(path: &Path, dirs: Option<&[PathBuf]>) -> bool {
+    dirs.map(|dirs| {
+        dirs.iter()
+            .any(|dir| path.starts_with(dir) || dir.starts_with(path))
+    })
+    .unwrap_or(true)
+}
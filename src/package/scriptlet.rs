@@ -0,0 +1,93 @@
+use std::{fmt::Display, path::Path, process::Command};
+
+use log::{debug, info};
+
+use crate::error::{Error, ErrorExt, ErrorType};
+
+/// The `PATH` for scriptlets, relative to the target root
+static SCRIPTLET_PATH: &str = "/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// The points in the lifecycle of a package a scriptlet runs at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptletKind {
+    /// After the files of the package have been installed (e.g. `ldconfig`, user creation)
+    PostInstall,
+    /// Before the files of the package get removed
+    PreRemove,
+}
+
+/// A script shipped with a package that runs in the target root at a point of its lifecycle
+#[derive(Debug, Clone)]
+pub struct Scriptlet {
+    /// When the scriptlet runs
+    pub kind: ScriptletKind,
+    /// The name of the package the scriptlet belongs to
+    pub package: String,
+    /// The version of the package the scriptlet belongs to
+    pub version: String,
+    /// The script to run using `sh -e`
+    pub script: String,
+}
+
+impl Scriptlet {
+    /// Runs the scriptlet in `root` using `chroot`, a `root` of `/` runs it directly.
+    ///
+    /// The host environment is cleared, the scriptlet gets `PKG_NAME`
+    /// and `PKG_VERSION` to identify the package it belongs to
+    /// # Arguments
+    /// * `root` - The target root the package is installed in
+    pub fn run(&self, root: &Path) -> Result<(), Error> {
+        let context = || format!("Running {self}");
+
+        let mut command = if root == Path::new("/") {
+            Command::new("sh")
+        } else {
+            let mut command = Command::new("chroot");
+            command.arg(root).arg("sh");
+            command
+        };
+
+        command
+            .env_clear()
+            .arg("-e")
+            .arg("-c")
+            .arg(&self.script)
+            .current_dir(root)
+            .env("PATH", SCRIPTLET_PATH)
+            .env("PKG_NAME", &self.package)
+            .env("PKG_VERSION", &self.version);
+
+        info!("Running {self} in {}", root.to_string_lossy());
+        debug!("Scriptlet: {}", self.script);
+
+        let status = command.status().e_context(context)?;
+
+        if !status.success() {
+            return Err(Error::new_context(
+                ErrorType::Other(format!("Scriptlet exited with {status}")),
+                context(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ScriptletKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PostInstall => write!(f, "post_install"),
+            Self::PreRemove => write!(f, "pre_remove"),
+        }
+    }
+}
+
+impl Display for Scriptlet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} scriptlet of {}@{}",
+            self.kind, self.package, self.version
+        )
+    }
+}
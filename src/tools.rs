@@ -1,4 +1,9 @@
 //! Concrete tools that can be invoked to provide common functionality
 
+pub mod auditor;
+
 #[cfg(feature = "builder")]
 pub mod builder;
+
+pub mod publisher;
+pub mod server;
@@ -0,0 +1,280 @@
+//! Auditing packages against a local dump of security advisories
+//!
+//! The advisories are read in the [OSV format](https://ossf.github.io/osv-schema/):
+//! The dump is either a JSON file holding one record or an array of records, or a
+//! directory of such files (e.g. an extracted OSV ecosystem archive). Packages are
+//! matched by their name, the ecosystem of the advisories is not considered
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, ErrorExt},
+    util::{
+        download::download_to_file,
+        fs::{self, PathUtil},
+        parse::versionstring::compare_versions,
+    },
+};
+
+/// The version of `introduced` events that affects all versions
+static OSV_VERSION_ZERO: &str = "0";
+
+/// The type of ranges listing commit hashes, which can't be matched against versions
+static OSV_RANGE_GIT: &str = "GIT";
+
+/// Matches packages against a set of security advisories
+pub struct Auditor {
+    /// The known advisories
+    advisories: Vec<Advisory>,
+}
+
+/// A security advisory (an OSV record)
+#[derive(Debug, Clone, Deserialize)]
+pub struct Advisory {
+    /// The identifier of the advisory (e.g. `GHSA-...`, `CVE-...`)
+    pub id: String,
+    /// A short description of the vulnerability
+    #[serde(default)]
+    pub summary: String,
+    /// Other identifiers of the same vulnerability
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// The packages and versions affected by the vulnerability
+    #[serde(default)]
+    pub affected: Vec<AffectedPackage>,
+}
+
+/// A package affected by an [Advisory]
+#[derive(Debug, Clone, Deserialize)]
+pub struct AffectedPackage {
+    /// The affected package
+    pub package: AdvisoryPackage,
+    /// The ranges of affected versions
+    #[serde(default)]
+    pub ranges: Vec<AffectedRange>,
+    /// The affected versions in addition to the ones within `ranges`
+    #[serde(default)]
+    pub versions: Vec<String>,
+}
+
+/// The identification of a package within an [Advisory]
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdvisoryPackage {
+    /// The ecosystem the package belongs to (e.g. `Debian`)
+    #[serde(default)]
+    pub ecosystem: String,
+    /// The name of the package
+    pub name: String,
+}
+
+/// A range of affected versions, described by the versions the affection starts and ends at
+#[derive(Debug, Clone, Deserialize)]
+pub struct AffectedRange {
+    /// The type of versions used by the events (`ECOSYSTEM`, `SEMVER` or `GIT`)
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The events changing whether versions are affected
+    #[serde(default)]
+    pub events: Vec<RangeEvent>,
+}
+
+/// An event within an [AffectedRange], exactly one of the fields is set
+#[derive(Debug, Clone, Deserialize)]
+pub struct RangeEvent {
+    /// Versions starting at this one are affected, `0` for all versions
+    pub introduced: Option<String>,
+    /// Versions starting at this one are not affected anymore
+    pub fixed: Option<String>,
+    /// Versions after this one are not affected anymore
+    pub last_affected: Option<String>,
+}
+
+/// The contents of an advisory file: One record or an array of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AdvisoryFile {
+    Many(Vec<Advisory>),
+    One(Box<Advisory>),
+}
+
+impl Auditor {
+    /// Loads the advisories from a JSON file or a directory of JSON files
+    /// # Arguments
+    /// * `path` - The path to the advisory dump
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let context = || format!("Loading advisories from {}", path.str_lossy());
+
+        let files = if path.is_dir() {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+                .e_context(context)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == "json"))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![path.to_owned()]
+        };
+
+        let mut advisories = Vec::new();
+        for file in files {
+            let content = fs::file_read_to_string(&file).ctx(context)?;
+            match serde_json::from_str(&content)
+                .ctx(|| format!("Parsing advisories in {}", file.str_lossy()))
+                .ctx(context)?
+            {
+                AdvisoryFile::Many(many) => advisories.extend(many),
+                AdvisoryFile::One(one) => advisories.push(*one),
+            }
+        }
+
+        debug!(
+            "Loaded {} advisories from {}",
+            advisories.len(),
+            path.str_lossy()
+        );
+
+        Ok(Self { advisories })
+    }
+
+    /// Downloads an advisory dump to `path`, replacing the existing one
+    /// only after the download has succeeded
+    /// # Arguments
+    /// * `url` - The URL to download the dump from
+    /// * `path` - The path to store the dump at
+    pub fn fetch(url: &str, path: &Path) -> Result<(), Error> {
+        let context = || format!("Fetching advisories from {url}");
+        info!("Fetching advisories from {url}");
+
+        let partial = path.with_extension("part");
+        fs::create_parent_dir_all(path).ctx(context)?;
+        if let Err(e) = download_to_file(url, &partial, "Fetching advisories", true) {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e).ctx(context);
+        }
+
+        std::fs::rename(&partial, path)
+            .e_context(|| format!("Moving {} to {}", partial.str_lossy(), path.str_lossy()))
+            .ctx(context)
+    }
+
+    /// Returns the number of known advisories
+    pub fn len(&self) -> usize {
+        self.advisories.len()
+    }
+
+    /// Returns whether there are no known advisories
+    pub fn is_empty(&self) -> bool {
+        self.advisories.is_empty()
+    }
+
+    /// Returns the advisories affecting the version `version` of the package `name`.
+    /// Names are compared case-insensitively
+    /// # Arguments
+    /// * `name` - The name of the package
+    /// * `version` - The version of the package
+    pub fn audit(&self, name: &str, version: &str) -> Vec<&Advisory> {
+        self.advisories
+            .iter()
+            .filter(|advisory| {
+                advisory.affected.iter().any(|affected| {
+                    affected.package.name.eq_ignore_ascii_case(name) && affected.affects(version)
+                })
+            })
+            .collect()
+    }
+}
+
+impl AffectedPackage {
+    /// Returns whether `version` is listed or lies within one of the ranges
+    /// # Arguments
+    /// * `version` - The version to check
+    pub fn affects(&self, version: &str) -> bool {
+        self.versions
+            .iter()
+            .any(|v| compare_versions(v, version) == Ordering::Equal)
+            || self.ranges.iter().any(|r| r.affects(version))
+    }
+}
+
+impl AffectedRange {
+    /// Returns whether `version` lies within this range by replaying the events
+    /// that happened at or before `version` in version order. Ranges of commit
+    /// hashes (`GIT`) never match
+    /// # Arguments
+    /// * `version` - The version to check
+    pub fn affects(&self, version: &str) -> bool {
+        if self.ty == OSV_RANGE_GIT {
+            return false;
+        }
+
+        let mut events: Vec<(&str, &RangeEvent)> = self
+            .events
+            .iter()
+            .filter_map(|e| e.get_version().map(|v| (v, e)))
+            .collect();
+        events.sort_by(|(a, _), (b, _)| compare_event_versions(a, b));
+
+        let mut affected = false;
+        for (event_version, event) in events {
+            let ordering = compare_event_versions(event_version, version);
+
+            // The `last_affected` version itself is still affected
+            let reached = match event.last_affected {
+                Some(_) => ordering == Ordering::Less,
+                None => ordering != Ordering::Greater,
+            };
+
+            if reached {
+                affected = event.introduced.is_some();
+            }
+        }
+
+        affected
+    }
+}
+
+impl RangeEvent {
+    /// Returns the version this event happens at
+    fn get_version(&self) -> Option<&str> {
+        self.introduced
+            .as_deref()
+            .or(self.fixed.as_deref())
+            .or(self.last_affected.as_deref())
+    }
+}
+
+/// Compares two versions of range events, sorting [OSV_VERSION_ZERO] before all other versions
+/// # Arguments
+/// * `a` - The first version
+/// * `b` - The version to compare `a` against
+fn compare_event_versions(a: &str, b: &str) -> Ordering {
+    match (a == OSV_VERSION_ZERO, b == OSV_VERSION_ZERO) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => compare_versions(a, b),
+    }
+}
+
+impl Display for Advisory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)?;
+
+        if !self.aliases.is_empty() {
+            write!(f, " ({})", self.aliases.join(", "))?;
+        }
+
+        if !self.summary.is_empty() {
+            write!(f, ": {}", self.summary)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,11 +1,909 @@
-use std::process::ExitStatus;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use indexmap::IndexMap;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+mod report;
+pub use report::*;
 
 mod workdir;
 pub use workdir::*;
 
-use crate::error::{Error, ErrorExt, ErrorType, Throwable};
+use crate::{
+    env::{BuildEnvironment, EmulatedEnvironment, FailureAction, HermeticSettings},
+    error::{architecture::ArchitectureError, Error, ErrorExt, ErrorType, Throwable},
+    files::{
+        formulafile::{FormulaFile, FormulaStepPolicy, StepArtifacts},
+        homeconfig::HomeConfig,
+    },
+    model::{
+        Formula, Home, MergeStrategy, ObjectCompression, ObjectDB, ObjectID, RepositoryIndex,
+        RepositoryIndexEntry, RuntimeNeed, RuntimeNeedKind, Tree, TreeEntry,
+    },
+    package::{BuildablePackage, PackageLayout},
+    util::{
+        architecture::Architecture,
+        fs::{self, MountEntry, PathUtil, ScriptFile},
+        mount::{mount_overlay, BindMount, MountOptions},
+        signal::SignalDispatcher,
+        userns::UserNamespace,
+        ODBUnpackable,
+    },
+};
+
+/// Builds packages from resolved formulae in build environments, refer to [Builder::build()]
+pub struct Builder<'a> {
+    /// The home the builds happen in
+    home: &'a Home,
+    /// The configuration of the home
+    config: HomeConfig,
+    /// The architecture of the host
+    host: Architecture,
+    /// The directory of the host's toolchain, bind mounted into the build roots
+    toolchain: Option<PathBuf>,
+    /// The directory containing the `qemu-<arch>-static` binaries, `None` to disable emulation
+    qemu_dir: Option<PathBuf>,
+    /// The compression to use for inserting the objects
+    compression: ObjectCompression,
+    /// Whether to only warn about files claimed by multiple layouts of a formula
+    allow_overlap: bool,
+    /// The user namespace the process has entered to build unprivileged, if any
+    user_namespace: Option<UserNamespace>,
+    /// The action to take if a build step fails
+    failure_action: FailureAction,
+    /// The host environment variables to pass into the build environments in
+    /// addition to the ones of the configuration, refer to [Builder::with_passthrough_env()]
+    passthrough_env: Vec<String>,
+    /// Whether to retain the changes of every build step as a snapshot
+    step_snapshots: bool,
+    /// Whether to drop the extra dependencies the packages don't use
+    prune_dependencies: bool,
+    /// The path to write the reports of all builds to, refer to [Builder::with_report()]
+    report_path: Option<PathBuf>,
+    /// The reports of the builds so far
+    reports: Mutex<Vec<BuildReport>>,
+    /// The dispatcher for signals to the build processes
+    signal_dispatcher: SignalDispatcher,
+}
+
+/// How a formula gets built for an architecture
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildStrategy {
+    /// The architecture runs on the host
+    Native,
+    /// The host dependencies of the formula provide a cross toolchain running on the host
+    Cross,
+    /// The binaries of the architecture get emulated using `qemu-user`
+    Emulated,
+}
 
-pub struct Builder {}
+/// The result of a successful build, refer to [Builder::build()]
+pub struct BuildOutput {
+    /// The object id of the built package
+    pub package: ObjectID,
+    /// The object id of the build receipt, `None` if a previously built package has been reused
+    pub receipt: Option<ObjectID>,
+    /// The report of the build
+    pub report: BuildReport,
+}
+
+/// The fingerprint of the inputs a package has been built from, refer to [Builder::find_up_to_date_package()]
+#[derive(Debug, Serialize, Deserialize)]
+struct PackageStamp {
+    /// The fingerprint of the artifacts and inputs
+    fingerprint: String,
+    /// The object id of the resulting package
+    package: ObjectID,
+}
+
+impl<'a> Builder<'a> {
+    /// Creates a new builder building in `home`, using the configuration of the home
+    /// # Arguments
+    /// * `home` - The home to build in
+    /// * `compression` - The compression to use for inserting the objects
+    pub fn new(home: &'a Home, compression: ObjectCompression) -> Result<Self, Error> {
+        Ok(Self {
+            home,
+            config: home.load_config()?,
+            host: Architecture::new_uname()?,
+            toolchain: None,
+            qemu_dir: None,
+            compression,
+            allow_overlap: false,
+            user_namespace: None,
+            failure_action: FailureAction::Abort,
+            passthrough_env: Vec::new(),
+            step_snapshots: false,
+            prune_dependencies: false,
+            report_path: None,
+            reports: Mutex::new(Vec::new()),
+            signal_dispatcher: SignalDispatcher::default(),
+        })
+    }
+
+    /// Bind mounts the toolchain directory of the host read-only to the same path in
+    /// the build roots, its `bin` and `sbin` directories are added to the `PATH`
+    /// # Arguments
+    /// * `toolchain` - The toolchain directory, `None` to use the toolchain of the dist directory
+    pub fn with_toolchain(mut self, toolchain: Option<PathBuf>) -> Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Enables emulating architectures that can't run on the host and can't be cross
+    /// compiled for, refer to [EmulatedEnvironment]
+    /// # Arguments
+    /// * `qemu_dir` - The directory containing the `qemu-<arch>-static` binaries, `None` to disable emulation
+    pub fn with_emulation(mut self, qemu_dir: Option<PathBuf>) -> Self {
+        self.qemu_dir = qemu_dir;
+        self
+    }
+
+    /// Only warns about files of the built packages that are claimed by multiple
+    /// layouts instead of failing the build, refer to [Builder::check_package_conflicts()]
+    /// # Arguments
+    /// * `allow_overlap` - Whether to allow overlapping layouts
+    pub fn with_allow_overlap(mut self, allow_overlap: bool) -> Self {
+        self.allow_overlap = allow_overlap;
+        self
+    }
+
+    /// Builds in unprivileged build environments (refer to [BuildEnvironment::new_unprivileged()]).
+    /// The installed files get indexed within the namespace, so the ownership the build
+    /// intended gets recorded: `root` for the files of the user that entered it
+    /// # Arguments
+    /// * `user_namespace` - The user namespace the process has entered, `None` to build privileged
+    pub fn with_user_namespace(mut self, user_namespace: Option<UserNamespace>) -> Self {
+        self.user_namespace = user_namespace;
+        self
+    }
+
+    /// Sets the action to take if a build step fails, e.g. spawning a shell in the
+    /// build environment to inspect it before it gets torn down
+    /// # Arguments
+    /// * `failure_action` - The action to take, refer to [FailureAction]
+    pub fn with_failure_action(mut self, failure_action: FailureAction) -> Self {
+        self.failure_action = failure_action;
+        self
+    }
+
+    /// Passes the host environment variables in `allowlist` into the build environments
+    /// in addition to the ones of the `[builder]` section of the home's configuration,
+    /// refer to [BuildEnvironment::set_passthrough_env()]
+    /// # Arguments
+    /// * `allowlist` - The names of the host variables to pass through
+    pub fn with_passthrough_env(mut self, allowlist: Vec<String>) -> Self {
+        self.passthrough_env = allowlist;
+        self
+    }
+
+    /// Retains the files every build step changed in the build root as a snapshot
+    /// linked to the step in the report and the receipt, including the failing step,
+    /// refer to [BuildablePackage::with_step_snapshots()]
+    /// # Arguments
+    /// * `step_snapshots` - Whether to retain the snapshots
+    pub fn with_step_snapshots(mut self, step_snapshots: bool) -> Self {
+        self.step_snapshots = step_snapshots;
+        self
+    }
+
+    /// Drops the extra dependencies of the formulae that provide nothing the built
+    /// packages need (refer to [Tree::find_unused_dependencies()]) from the dependencies
+    /// of the packages instead of recording them as validation findings
+    /// # Arguments
+    /// * `prune_dependencies` - Whether to drop the unused dependencies
+    pub fn with_prune_dependencies(mut self, prune_dependencies: bool) -> Self {
+        self.prune_dependencies = prune_dependencies;
+        self
+    }
+
+    /// Writes the reports of all builds of this builder to `path` as a JSON array,
+    /// rewriting it after every build, whether it succeeded or not
+    /// # Arguments
+    /// * `path` - The path to write the reports to, `None` to not write them
+    pub fn with_report(mut self, path: Option<PathBuf>) -> Self {
+        self.report_path = path;
+        self
+    }
+
+    /// Returns how `formula` gets built for `arch` on this host: Natively if `arch`
+    /// runs on the host, else cross compiled if the formula has host dependencies
+    /// providing a cross toolchain, else emulated if emulation is enabled
+    /// # Arguments
+    /// * `formula` - The resolved formula to build
+    /// * `arch` - The architecture to build for
+    /// # Returns
+    /// `None` if the formula can't be built for `arch` on this host
+    pub fn get_strategy(&self, formula: &Formula, arch: &Architecture) -> Option<BuildStrategy> {
+        if arch.can_run_on(&self.host) {
+            Some(BuildStrategy::Native)
+        } else if !formula.host_dependencies.is_empty() {
+            Some(BuildStrategy::Cross)
+        } else if self.qemu_dir.is_some() {
+            Some(BuildStrategy::Emulated)
+        } else {
+            None
+        }
+    }
+
+    /// Builds `formula` for `arch` in a new workdir and inserts the package and its
+    /// build receipt into `odb`:
+    /// 1. Deploys the formula tree and the host and target dependencies to the workdir
+    /// 2. Mounts the build root, the formula directory on top of the dependencies, and runs
+    ///    the build steps in it using the [BuildStrategy] of [Builder::get_strategy()]
+    /// 3. Indexes and validates the installed files and inserts them as the package,
+    ///    depending on the target and extra dependencies
+    ///
+    /// If the artifacts of the `build` step are unchanged since the last build of the
+    /// package, the `package` step is skipped and the previous package is reused.
+    ///
+    /// The workdir gets removed once the build succeeded, failed builds
+    /// leave it and their report (`out/report.json`) behind for inspection.
+    /// The report is recorded to the reports path, refer to [Builder::with_report()]
+    /// # Arguments
+    /// * `file` - The formula file `formula` has been resolved from
+    /// * `formula` - The resolved formula (refer to [FormulaFile::parse_and_resolve()])
+    /// * `arch` - The architecture to build for
+    /// * `odb` - The object database to read the formula from and insert the package into
+    pub fn build(
+        &self,
+        file: FormulaFile,
+        formula: &Formula,
+        arch: &Architecture,
+        odb: &mut ObjectDB,
+    ) -> Result<BuildOutput, Error> {
+        let context = || format!("Building {}@{} for {arch}", formula.name, formula.version);
+
+        let strategy = self.get_strategy(formula, arch).ok_or_else(|| {
+            Error::new_context(
+                ErrorType::Architecture(ArchitectureError::NotSupported {
+                    arch: arch.clone(),
+                    supported: vec![self.host.clone()],
+                }),
+                "The formula has no host dependencies to cross compile with, \
+                 building it for a foreign architecture requires emulation"
+                    .to_owned(),
+            )
+        });
+        let strategy = strategy.ctx(context)?;
+
+        let workdir = BuilderWorkdir::new(self.home).ctx(context)?;
+        info!(
+            "Building {}@{} for {arch} ({strategy}) in {}",
+            formula.name,
+            formula.version,
+            workdir.get_root().str_lossy()
+        );
+
+        let mut report = BuildReport::new(workdir.get_id());
+        report.set_validation_policy(self.config.builder.validation.merged(&formula.validation));
+
+        let res = self.build_in(&workdir, file, formula, arch, strategy, odb, &mut report);
+        report.finish(&res);
+        if let Ok((package, _)) = &res {
+            report.add_package(formula.name.clone(), package.clone());
+        }
+        let recorded = self.record_report(&report);
+
+        match res {
+            Ok((package, receipt)) => {
+                recorded.ctx(context)?;
+                fs::remove_dir_all(workdir.get_root())
+                    .ctx(|| format!("Removing workdir {}", workdir.get_root().str_lossy()))?;
+
+                Ok(BuildOutput {
+                    package,
+                    receipt,
+                    report,
+                })
+            }
+            Err(e) => {
+                let path = workdir.get_output_dir().join("report.json");
+                match fs::create_dir_all(&workdir.get_output_dir())
+                    .and_then(|_| report.write(&path))
+                {
+                    Ok(()) => info!("Wrote build report to {}", path.str_lossy()),
+                    Err(e) => warn!("Failed to write build report: {}", e.oneline()),
+                }
+                if let Err(e) = recorded {
+                    warn!("Failed to record build report: {}", e.oneline());
+                }
+
+                Err(e).ctx(context)
+            }
+        }
+    }
+
+    /// Records `report` to the reports of this builder and writes
+    /// them to the reports path, if there is one
+    /// # Arguments
+    /// * `report` - The report of the finished build
+    fn record_report(&self, report: &BuildReport) -> Result<(), Error> {
+        let Some(path) = &self.report_path else {
+            return Ok(());
+        };
+
+        let mut reports = self.reports.lock().expect("Lock build reports");
+        reports.push(report.clone());
+        BuildReport::write_all(&reports, path)
+    }
+
+    /// Resolves the formula file at `path` for `arch` and builds it (refer to [Builder::build()]),
+    /// unless a package has been built from the resolved formula already
+    /// (refer to [RepositoryIndex::find_built_package()])
+    /// # Arguments
+    /// * `path` - The path to the formula file
+    /// * `arch` - The architecture to build for
+    /// * `dependencies` - The repository index to resolve the dependencies from
+    /// * `odb` - The object database to insert the objects into
+    /// # Returns
+    /// The repository index entry of the formula and its package
+    /// and whether the package has been built
+    pub fn resolve_and_build(
+        &self,
+        path: &Path,
+        arch: &Architecture,
+        dependencies: &RepositoryIndex,
+        odb: &mut ObjectDB,
+    ) -> Result<(RepositoryIndexEntry, bool), Error> {
+        let file = FormulaFile::parse(path)?;
+        let (formula, object) = FormulaFile::parse_and_resolve(
+            path,
+            self.home,
+            odb,
+            arch.clone(),
+            self.compression,
+            &IndexMap::new(),
+            dependencies,
+        )?;
+
+        let (package, built) = match RepositoryIndex::find_built_package(odb, &object.oid)? {
+            Some(package) => (package, false),
+            None => (self.build(file, &formula, arch, odb)?.package, true),
+        };
+
+        let entry = RepositoryIndexEntry {
+            name: formula.name,
+            version: formula.version,
+            arch: formula.arch,
+            provides: formula.provides,
+            license: formula.license,
+            formula: Some(object.oid),
+            package: Some(package),
+        };
+
+        Ok((entry, built))
+    }
+
+    /// Runs the build of `formula` in `workdir`, refer to [Builder::build()]
+    /// # Arguments
+    /// * `workdir` - The workdir to build in
+    /// * `file` - The formula file `formula` has been resolved from
+    /// * `formula` - The resolved formula
+    /// * `arch` - The architecture to build for
+    /// * `strategy` - How to build the formula
+    /// * `odb` - The object database to read the formula from and insert the package into
+    /// * `report` - The report to record the build to
+    /// # Returns
+    /// The object ids of the package and of the build receipt, if the package has been built
+    #[allow(clippy::too_many_arguments)]
+    fn build_in(
+        &self,
+        workdir: &BuilderWorkdir,
+        file: FormulaFile,
+        formula: &Formula,
+        arch: &Architecture,
+        strategy: BuildStrategy,
+        odb: &mut ObjectDB,
+        report: &mut BuildReport,
+    ) -> Result<(ObjectID, Option<ObjectID>), Error> {
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
+        let tree = read_tree(odb, &formula.tree)?;
+        let package = BuildablePackage::from_tree(file, arch.clone(), workdir, &tree, odb)?
+            .with_hermetic(HermeticSettings::from_host_or(start)?)
+            .with_step_snapshots(self.step_snapshots);
+
+        let dependencies_dir = workdir.get_dependencies_dir();
+        let mut dependencies = Tree {
+            entries: Vec::new(),
+        };
+        for oid in formula
+            .host_dependencies
+            .iter()
+            .chain(&formula.target_dependencies)
+        {
+            let conflicts = dependencies
+                .merge_with_strategy(read_tree(odb, oid)?, MergeStrategy::KeepTheirs)
+                .ctx(|| format!("Merging dependency {oid}"))?;
+            for conflict in conflicts {
+                warn!("Dependency {oid} conflicts with another dependency at {conflict}");
+            }
+        }
+        dependencies
+            .deploy(&dependencies_dir, odb)
+            .ctx(|| format!("Deploying dependencies to {}", dependencies_dir.str_lossy()))?;
+
+        let root_mount = mount_overlay(
+            self.config.builder.overlay,
+            vec![dependencies_dir, workdir.get_formula_dir()],
+            workdir.get_overlay_dir_work(),
+            workdir.get_overlay_dir_upper(),
+            workdir.get_overlay_dir_merged(),
+        )?;
+        let root = root_mount.get_target_path().to_owned();
+
+        let toolchain_dir = match &self.toolchain {
+            Some(dir) => dir.clone(),
+            None => crate::abs_dist_dir(),
+        };
+        let mut env = match self.user_namespace {
+            Some(_) => BuildEnvironment::new_unprivileged(root_mount, toolchain_dir)?,
+            None => BuildEnvironment::new(root_mount, toolchain_dir)?,
+        };
+
+        if let Some(dir) = &self.toolchain {
+            let target = root.join(dir.strip_prefix("/").unwrap_or(dir));
+            env.add_mount(Box::new(BindMount::new(
+                dir,
+                &target,
+                MountOptions::new().readonly(),
+            )?));
+        }
+        if let Some(config) = self.config.get_remote_compile() {
+            env.enable_remote_compile(config)?;
+        }
+        env.set_scheduling(self.config.builder.cpuset.clone(), self.config.builder.nice);
+        let mut passthrough_env = self.config.builder.passthrough_env.clone();
+        passthrough_env.extend(self.passthrough_env.iter().cloned());
+        env.set_passthrough_env(&passthrough_env);
+
+        // Cross builds run the cross toolchain of the host dependencies on the host
+        let env_arch = match strategy {
+            BuildStrategy::Cross => &self.host,
+            BuildStrategy::Native | BuildStrategy::Emulated => arch,
+        };
+        let env =
+            EmulatedEnvironment::select(env, env_arch, self.qemu_dir.as_deref(), workdir.get_id())?;
+
+        let upper = workdir.get_overlay_dir_upper();
+        let mut fingerprint: Option<String> = None;
+        for step in package.get_buildsteps() {
+            let policy = package
+                .get_formula()
+                .package
+                .get_step_policy(&step.name.to_lowercase());
+
+            if step.name == "Package" {
+                if let Some(fingerprint) = &fingerprint {
+                    if let Some(oid) = Self::find_up_to_date_package(
+                        self.home,
+                        &formula.name,
+                        arch,
+                        fingerprint,
+                        odb,
+                    )? {
+                        info!("Artifacts are unchanged, reusing package {oid}");
+                        return Ok((oid, None));
+                    }
+                }
+            }
+
+            let status = report.execute_step(
+                env.as_ref(),
+                &step,
+                &self.signal_dispatcher,
+                step.retries,
+                self.failure_action,
+            )?;
+
+            if package.get_step_snapshots() {
+                let snapshot = report.snapshot_step(&upper, odb, self.compression);
+                match snapshot {
+                    Ok(oid) => info!("Snapshotted step '{}': {oid}", step.name),
+                    // Don't let a failed snapshot hide why the step failed
+                    Err(e) if !status.success() => {
+                        warn!("Failed to snapshot step '{}': {}", step.name, e.oneline())
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if !status.success() {
+                return Err(BuilderError::CommandFailed { status })
+                    .e_context(|| format!("Running step '{}'", step.name));
+            }
+
+            if !policy.artifacts.is_empty() {
+                Self::check_step_artifacts(&step.name, &policy, &upper)?;
+
+                if step.name == "Build" {
+                    let inputs = package
+                        .get_formula()
+                        .package
+                        .package
+                        .clone()
+                        .unwrap_or_default();
+                    fingerprint = Some(policy.fingerprint_artifacts(&upper, &inputs)?);
+                }
+            }
+        }
+
+        let install_dir = workdir.get_install_dir_outer();
+        fs::create_dir_all(&install_dir)?;
+
+        // Within a user namespace, this records the ownership within it instead of the one on disk
+        let mut files = Tree::index(&install_dir, odb, self.compression)
+            .ctx(|| format!("Indexing installed files in {}", install_dir.str_lossy()))?;
+        Self::check_package_conflicts(&files, &formula.layout, self.allow_overlap)?;
+
+        let dependencies = self.validate(&mut files, &install_dir, formula, odb, report)?;
+        if let Some(settings) = package.get_hermetic() {
+            report.add_timestamp_issues(&settings.find_newer_files(&install_dir)?, settings);
+        }
+        Self::enforce_validation(&formula.name, report)?;
+
+        let package_oid = files
+            .insert_into_odb_with_dependencies(odb, self.compression, dependencies)
+            .ctx(|| "Inserting package")?
+            .oid;
+        let receipt = report
+            .receipt(package_oid.clone(), formula.tainted)
+            .insert_into_odb(odb, self.compression)
+            .ctx(|| "Inserting build receipt")?
+            .oid;
+
+        if let Some(fingerprint) = &fingerprint {
+            Self::record_package_stamp(
+                self.home,
+                &formula.name,
+                arch,
+                fingerprint,
+                package_oid.clone(),
+            )?;
+        }
+
+        info!(
+            "Built {}@{} for {arch}: {package_oid}",
+            formula.name, formula.version
+        );
+
+        Ok((package_oid, Some(receipt)))
+    }
+
+    /// Validates the installed `files` of the package built from `formula` and
+    /// records the findings to `report`, refer to the validation policy
+    /// # Arguments
+    /// * `files` - The installed files, search paths and shbangs get sanitized in place
+    /// * `install_dir` - The directory the files have been indexed from
+    /// * `formula` - The formula the files have been built from
+    /// * `odb` - The object database to read the files and dependencies from
+    /// * `report` - The report to record the findings to
+    /// # Returns
+    /// The dependencies of the package: The target and extra dependencies, without the
+    /// unused ones if pruning (refer to [Builder::with_prune_dependencies()]), and the
+    /// dependencies providing the interpreters of the scripts
+    fn validate(
+        &self,
+        files: &mut Tree,
+        install_dir: &Path,
+        formula: &Formula,
+        odb: &mut ObjectDB,
+        report: &mut BuildReport,
+    ) -> Result<Vec<ObjectID>, Error> {
+        let context = || format!("Validating package {}", formula.name);
+        let dirs = formula.get_layout().get_scan_dirs();
+
+        let removals = files
+            .sanitize_runpaths_in_dirs(odb, self.compression, &dirs)
+            .ctx(context)?;
+        report.add_runpath_removals(&removals);
+
+        let needs = files.find_runtime_needs_in_dirs(odb, &dirs).ctx(context)?;
+
+        let mut dependencies = Vec::new();
+        for oid in formula
+            .target_dependencies
+            .iter()
+            .chain(&formula.extra_dependencies)
+        {
+            dependencies.push((oid.clone(), read_tree(odb, oid).ctx(context)?));
+        }
+        let unresolved: Vec<RuntimeNeed> = needs
+            .iter()
+            .filter(|n| {
+                !files.provides(&n.name) && !dependencies.iter().any(|(_, d)| d.provides(&n.name))
+            })
+            .cloned()
+            .collect();
+        report.add_unresolved_dependencies(&unresolved);
+
+        let unused = files
+            .find_unused_dependencies(odb, &needs, &formula.extra_dependencies)
+            .ctx(context)?;
+        if self.prune_dependencies {
+            for oid in &unused {
+                report.add_validation(format!("fixed: Dropped unused dependency {oid}"));
+            }
+        } else {
+            report.add_unused_dependencies(&unused);
+        }
+
+        let issues = files.check_hardening_in_dirs(odb, &dirs).ctx(context)?;
+        report.add_hardening_issues(&issues);
+
+        let issues = files.check_python_paths(odb).ctx(context)?;
+        report.add_python_issues(&issues);
+
+        report.add_symlink_issues(&files.check_symlinks());
+
+        if let Some(license) = &formula.license {
+            let mismatches = files.check_licenses(license, odb).ctx(context)?;
+            report.add_license_mismatches(&mismatches);
+        }
+
+        let interpreters = self
+            .resolve_script_interpreters(files, install_dir, &needs, &dependencies, odb, report)
+            .ctx(context)?;
+
+        let mut dependencies = formula.target_dependencies.clone();
+        for oid in formula.extra_dependencies.iter().chain(&interpreters) {
+            let pruned = self.prune_dependencies && unused.contains(oid);
+            if !pruned && !dependencies.contains(oid) {
+                dependencies.push(oid.clone());
+            }
+        }
+
+        Ok(dependencies)
+    }
+
+    /// Resolves the interpreters of the scripts in `files` the package does not provide by
+    /// itself using [ScriptFile::resolve_interpreter()]. `env` shbangs get rewritten to the
+    /// resolved interpreter using [ScriptFile::rewrite_interpreter()] and reinserted
+    /// # Arguments
+    /// * `files` - The installed files, rewritten scripts get replaced in place
+    /// * `install_dir` - The directory the files have been indexed from
+    /// * `needs` - The runtime needs of `files`
+    /// * `dependencies` - The object ids and trees of the dependencies to search
+    /// * `odb` - The object database to insert the rewritten scripts into
+    /// * `report` - The report to record the rewritten shbangs to
+    /// # Returns
+    /// The dependencies providing the interpreters
+    fn resolve_script_interpreters(
+        &self,
+        files: &mut Tree,
+        install_dir: &Path,
+        needs: &[RuntimeNeed],
+        dependencies: &[(ObjectID, Tree)],
+        odb: &mut ObjectDB,
+        report: &mut BuildReport,
+    ) -> Result<Vec<ObjectID>, Error> {
+        let executable_dirs = PackageLayout::default().executable_dirs;
+        let mut interpreters: Vec<ObjectID> = Vec::new();
+
+        for need in needs {
+            if need.kind != RuntimeNeedKind::ScriptInterpreter || files.provides(&need.name) {
+                continue;
+            }
+
+            let path = install_dir.join(&need.path);
+            let name = need.path.file_name().unwrap_or_default().to_owned();
+            let script = ScriptFile::parse(&path, name.clone())?;
+
+            let Some((interpreter, oid)) =
+                script.resolve_interpreter(dependencies, &executable_dirs)
+            else {
+                continue;
+            };
+
+            if !interpreters.contains(oid) {
+                interpreters.push(oid.clone());
+            }
+
+            if !script.is_env_shbang() {
+                continue;
+            }
+
+            let interpreter = Path::new("/").join(interpreter);
+            script.rewrite_interpreter(&path, &interpreter)?;
+            let new_oid = odb.insert_file_infer(&path, self.compression)?.oid;
+
+            let entry = need
+                .path
+                .parent()
+                .and_then(|parent| files.get_subtree_mut(parent))
+                .and_then(|tree| tree.get_entry_by_name_mut(&name.to_string_lossy()));
+            if let Some(TreeEntry::File {
+                info: _,
+                name: _,
+                oid,
+            }) = entry
+            {
+                *oid = new_oid;
+            }
+
+            report.add_validation(format!(
+                "fixed: {}: Rewrote shbang to use {}",
+                need.path.str_lossy(),
+                interpreter.str_lossy()
+            ));
+        }
+
+        Ok(interpreters)
+    }
+
+    /// Checks that no file of the built `tree` is claimed by more than one package
+    /// # Arguments
+    /// * `tree` - The tree of built files to split into the packages
+    /// * `packages` - The packages and the paths their layouts claim
+    /// * `allow_overlap` - Only warn about conflicts instead of erroring
+    pub fn check_package_conflicts(
+        tree: &Tree,
+        packages: &IndexMap<String, Vec<String>>,
+        allow_overlap: bool,
+    ) -> Result<(), Error> {
+        let conflicts = tree.find_conflicts(packages);
+
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+
+        if allow_overlap {
+            for (path, packages) in &conflicts {
+                warn!(
+                    "File {} is contained in multiple packages: {}",
+                    path.str_lossy(),
+                    packages.join(", ")
+                );
+            }
+
+            Ok(())
+        } else {
+            Err(BuilderError::PackageConflict { conflicts }
+                .throw("Checking for package conflicts".to_owned()))
+        }
+    }
+
+    /// Fails the build if the validation policy classified any of the findings
+    /// recorded in `report` as errors, to be called before inserting the package
+    /// # Arguments
+    /// * `package` - The name of the validated package
+    /// * `report` - The report the validation findings have been recorded to
+    pub fn enforce_validation(package: &str, report: &BuildReport) -> Result<(), Error> {
+        if report.validation_errors.is_empty() {
+            return Ok(());
+        }
+
+        Err(BuilderError::ValidationFailed {
+            package: package.to_owned(),
+            errors: report.validation_errors.clone(),
+        }
+        .throw(format!("Enforcing validation policy of package {package}")))
+    }
+
+    /// Ensures the artifacts declared by the policy of `step` exist after running it
+    /// # Arguments
+    /// * `step` - The name of the step
+    /// * `policy` - The policy of the step declaring the artifacts
+    /// * `dir` - The directory the step ran in
+    pub fn check_step_artifacts(
+        step: &str,
+        policy: &FormulaStepPolicy,
+        dir: &Path,
+    ) -> Result<StepArtifacts, Error> {
+        let context = || format!("Checking artifacts of step '{step}'");
+
+        let artifacts = policy.find_artifacts(dir).ctx(context)?;
+
+        if !artifacts.missing.is_empty() {
+            return Err(BuilderError::MissingArtifacts {
+                step: step.to_owned(),
+                patterns: artifacts.missing,
+            }
+            .throw(context()));
+        }
+
+        Ok(artifacts)
+    }
+
+    /// Returns the package that has previously been built from the same inputs,
+    /// so the `package` step can be skipped, if it still exists in `odb`
+    /// # Arguments
+    /// * `home` - The home the builds happen in
+    /// * `name` - The name of the package
+    /// * `arch` - The architecture the package is built for
+    /// * `fingerprint` - The fingerprint of the inputs, refer to [FormulaStepPolicy::fingerprint_artifacts()]
+    /// * `odb` - The object database to look for the package in
+    pub fn find_up_to_date_package(
+        home: &Home,
+        name: &str,
+        arch: &Architecture,
+        fingerprint: &str,
+        odb: &ObjectDB,
+    ) -> Result<Option<ObjectID>, Error> {
+        let path = Self::get_package_stamp_path(home, name, arch);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let stamp: PackageStamp = toml::from_str(&fs::file_read_to_string(&path)?)
+            .ctx(|| format!("Parsing package stamp {}", path.str_lossy()))?;
+
+        if stamp.fingerprint != fingerprint || odb.get_object(&stamp.package).is_err() {
+            debug!("Package {name} is not up to date");
+            return Ok(None);
+        }
+
+        Ok(Some(stamp.package))
+    }
+
+    /// Records that `package` has been built from the inputs with `fingerprint`
+    /// # Arguments
+    /// * `home` - The home the builds happen in
+    /// * `name` - The name of the package
+    /// * `arch` - The architecture the package is built for
+    /// * `fingerprint` - The fingerprint of the inputs, refer to [FormulaStepPolicy::fingerprint_artifacts()]
+    /// * `package` - The object id of the built package
+    pub fn record_package_stamp(
+        home: &Home,
+        name: &str,
+        arch: &Architecture,
+        fingerprint: &str,
+        package: ObjectID,
+    ) -> Result<(), Error> {
+        let path = Self::get_package_stamp_path(home, name, arch);
+        let context = || format!("Writing package stamp {}", path.str_lossy());
+
+        let stamp = PackageStamp {
+            fingerprint: fingerprint.to_owned(),
+            package,
+        };
+
+        fs::create_parent_dir_all(&path).ctx(context)?;
+        std::fs::write(&path, toml::to_string(&stamp).ctx(context)?).e_context(context)
+    }
+
+    /// Returns the path to the stamp of the last build of a package
+    /// # Arguments
+    /// * `home` - The home the builds happen in
+    /// * `name` - The name of the package
+    /// * `arch` - The architecture the package is built for
+    fn get_package_stamp_path(home: &Home, name: &str, arch: &Architecture) -> PathBuf {
+        home.get_builds_dir()
+            .join("stamps")
+            .join(format!("{}-{name}.toml", arch.arch))
+    }
+}
+
+/// Reads the tree `oid` from `odb`
+/// # Arguments
+/// * `odb` - The object database to read the tree from
+/// * `oid` - The object id of the tree
+fn read_tree(odb: &ObjectDB, oid: &ObjectID) -> Result<Tree, Error> {
+    let mut object = odb.read(oid).ctx(|| format!("Reading tree {oid}"))?;
+    Tree::unpack_from_odb(&mut object, odb).ctx(|| format!("Reading tree {oid}"))
+}
+
+impl Display for BuildStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Native => write!(f, "native"),
+            Self::Cross => write!(f, "cross"),
+            Self::Emulated => write!(f, "emulated"),
+        }
+    }
+}
 
 /// An error that originated from the `Builder` struct
 #[derive(Debug)]
@@ -19,13 +917,34 @@ pub enum BuilderError {
     DependencyNotFound { name: String },
     /// A subcommand failed and the builder cannot continue working
     CommandFailed { status: ExitStatus },
+    /// Files are claimed by multiple packages of the same formula
+    PackageConflict {
+        conflicts: IndexMap<PathBuf, Vec<String>>,
+    },
+    /// A step did not produce the artifacts it declares
+    MissingArtifacts { step: String, patterns: Vec<String> },
+    /// Validation found errors the validation policy does not tolerate
+    ValidationFailed {
+        package: String,
+        errors: Vec<String>,
+    },
+    /// The workdir of a build is locked by another build
+    WorkdirInUse { id: String },
+    /// Mounts have been left below the workdir of a build by a build that crashed
+    StaleMounts {
+        root: PathBuf,
+        mounts: Vec<MountEntry>,
+    },
 }
 
 impl<T> ErrorExt<T> for Result<T, BuilderError> {
-    fn e_context<F: Fn() -> String>(self, context: F) -> Result<T, Error> {
+    fn e_context<S: ToString, F: Fn() -> S>(self, context: F) -> Result<T, Error> {
         match self {
             Ok(v) => Ok(v),
-            Err(e) => Err(Error::new_context(ErrorType::Builder(e), context())),
+            Err(e) => Err(Error::new_context(
+                ErrorType::Builder(e),
+                context().to_string(),
+            )),
         }
     }
 }
@@ -54,6 +973,41 @@ impl std::fmt::Display for BuilderError {
             Self::CommandFailed { status } => {
                 write!(f, "Command failed with the following code: {}", status)
             }
+            Self::PackageConflict { conflicts } => {
+                write!(f, "Files are claimed by multiple packages:")?;
+                for (path, packages) in conflicts {
+                    write!(f, "\n  {} ({})", path.str_lossy(), packages.join(", "))?;
+                }
+                Ok(())
+            }
+            Self::MissingArtifacts { step, patterns } => write!(
+                f,
+                "Step '{step}' did not produce the artifacts {}",
+                patterns.join(", ")
+            ),
+            Self::ValidationFailed { package, errors } => {
+                write!(f, "Validation of package {package} failed:")?;
+                for error in errors {
+                    write!(f, "\n  {error}")?;
+                }
+                Ok(())
+            }
+            Self::WorkdirInUse { id } => {
+                write!(f, "The workdir of build '{id}' is in use by another build")
+            }
+            Self::StaleMounts { root, mounts } => {
+                write!(
+                    f,
+                    "Mounts have been left below the workdir {} by a previous build, unmount them first:",
+                    root.str_lossy()
+                )?;
+                for mount in mounts {
+                    write!(f, "\n  {mount}")?;
+                }
+                Ok(())
+            }
         }
     }
 }
+
+impl std::error::Error for BuilderError {}
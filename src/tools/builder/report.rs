@@ -0,0 +1,358 @@
+//! Machine-readable reports about builds for CI systems to archive and gate on
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::{
+    env::{Environment, EnvironmentExecutable, FailureAction, HermeticSettings},
+    error::{Error, ErrorExt},
+    files::validationpolicy::{ValidationCategory, ValidationPolicy, ValidationSeverity},
+    model::{
+        BuildReceipt, HardeningIssue, LicenseMismatch, ObjectCompression, ObjectDB, ObjectID,
+        PythonIssue, RunpathRemoval, RuntimeNeed, StepRecord, SymlinkIssue, Tree,
+    },
+    util::{
+        fs::{self, PathUtil},
+        signal::SignalDispatcher,
+    },
+    GIT_COMMIT_HASH,
+};
+
+/// A report about a build, serialized as JSON
+#[derive(Serialize, Debug, Clone)]
+pub struct BuildReport {
+    /// The unique id of the build (see [BuilderWorkdir::get_id()](super::BuilderWorkdir::get_id()))
+    pub build_id: String,
+    /// The commit hash of the tooling that executed the build
+    pub tooling_commit: String,
+    /// Whether the build succeeded
+    pub success: bool,
+    /// The total duration of the build in milliseconds
+    pub duration_ms: u64,
+    /// The executed build steps in the order of their execution
+    pub steps: Vec<BuildStepReport>,
+    /// The object ids of the produced packages, indexed by their names
+    pub packages: IndexMap<String, ObjectID>,
+    /// The errors validation found and the actions taken to fix them
+    pub validation: Vec<String>,
+    /// The validation findings that fail the build according to the validation policy
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub validation_errors: Vec<String>,
+    /// The error that caused the build to fail
+    pub error: Option<String>,
+    #[serde(skip)]
+    start: Instant,
+    #[serde(skip)]
+    validation_policy: ValidationPolicy,
+}
+
+/// The report about a single build step
+#[derive(Serialize, Debug, Clone)]
+pub struct BuildStepReport {
+    /// The name of the build step
+    pub name: String,
+    /// The duration of the build step in milliseconds, including retries
+    pub duration_ms: u64,
+    /// The exit code of the build step or `None` if it was killed by a signal
+    /// or could not be executed
+    pub exit_code: Option<i32>,
+    /// The error that prevented the build step from executing
+    pub error: Option<String>,
+    /// How the build step has been executed, `None` if it has not been executed in an environment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<StepRecord>,
+}
+
+impl BuildReport {
+    /// Creates a new report, starting the timer for the build duration
+    /// # Arguments
+    /// * `build_id` - The unique id of the build
+    pub fn new(build_id: &str) -> Self {
+        Self {
+            build_id: build_id.to_owned(),
+            tooling_commit: GIT_COMMIT_HASH.to_owned(),
+            success: false,
+            duration_ms: 0,
+            steps: Vec::new(),
+            packages: IndexMap::new(),
+            validation: Vec::new(),
+            validation_errors: Vec::new(),
+            error: None,
+            start: Instant::now(),
+            validation_policy: ValidationPolicy::default(),
+        }
+    }
+
+    /// Executes `executable` in `env` using [Environment::execute_or_fail_with()] and records the result
+    /// # Arguments
+    /// * `env` - The environment to execute the executable in
+    /// * `executable` - The executable to execute
+    /// * `signal_dispatcher` - The `SignalDispatcher` to register signals for the executed process
+    /// * `retries` - How often to retry the executable before giving up
+    /// * `on_failure` - The action to take if the executable fails
+    /// # Returns
+    /// The result of the execution
+    pub fn execute_step(
+        &mut self,
+        env: &dyn Environment,
+        executable: &dyn EnvironmentExecutable,
+        signal_dispatcher: &SignalDispatcher,
+        retries: u32,
+        on_failure: FailureAction,
+    ) -> Result<ExitStatus, Error> {
+        let start = Instant::now();
+        let res = env.execute_or_fail_with(executable, signal_dispatcher, retries, on_failure);
+
+        self.record_step(executable.get_name(), start.elapsed(), &res);
+        if let Some(step) = self.steps.last_mut() {
+            step.environment = Some(env.record(executable));
+        }
+
+        res
+    }
+
+    /// Records the result of a build step
+    /// # Arguments
+    /// * `name` - The name of the build step
+    /// * `duration` - The time the build step took
+    /// * `result` - The result of the build step
+    pub fn record_step(
+        &mut self,
+        name: String,
+        duration: Duration,
+        result: &Result<ExitStatus, Error>,
+    ) {
+        let (exit_code, error) = match result {
+            Ok(status) => (status.code(), None),
+            Err(e) => (None, Some(e.oneline())),
+        };
+
+        self.steps.push(BuildStepReport {
+            name,
+            duration_ms: duration.as_millis() as u64,
+            exit_code,
+            error,
+            environment: None,
+        });
+    }
+
+    /// Retains the files the last executed build step changed as a snapshot:
+    /// Indexes `upper` (the upper directory of the build root's overlay) into
+    /// `odb` and links the tree to the step, so it ends up in the report and receipt
+    /// # Arguments
+    /// * `upper` - The upper directory of the overlay after the step
+    /// * `odb` - The object database to insert the snapshot into
+    /// * `compression` - The compression to apply for inserting
+    /// # Returns
+    /// The object id of the snapshot tree
+    pub fn snapshot_step(
+        &mut self,
+        upper: &Path,
+        odb: &mut ObjectDB,
+        compression: ObjectCompression,
+    ) -> Result<ObjectID, Error> {
+        let context = || format!("Snapshotting build step from {}", upper.str_lossy());
+
+        let tree = Tree::index(upper, odb, compression).ctx(context)?;
+        let oid = tree.insert_into_odb(odb, compression).ctx(context)?.oid;
+
+        if let Some(environment) = self
+            .steps
+            .last_mut()
+            .and_then(|step| step.environment.as_mut())
+        {
+            environment.snapshot = Some(oid.clone());
+        }
+
+        Ok(oid)
+    }
+
+    /// Records a produced package
+    /// # Arguments
+    /// * `name` - The name of the package
+    /// * `oid` - The object id of the package's tree
+    pub fn add_package(&mut self, name: String, oid: ObjectID) {
+        self.packages.insert(name, oid);
+    }
+
+    /// Records a validation error or the action taken to fix it
+    /// # Arguments
+    /// * `message` - The message describing the error or action
+    pub fn add_validation(&mut self, message: String) {
+        self.validation.push(message);
+    }
+
+    /// Sets the policy deciding how the validation findings recorded
+    /// from now on are treated (refer to [BuildReport::add_finding()])
+    /// # Arguments
+    /// * `policy` - The policy to apply, usually the home's one merged with the formula's one
+    pub fn set_validation_policy(&mut self, policy: ValidationPolicy) {
+        self.validation_policy = policy;
+    }
+
+    /// Records a validation finding according to the severity the
+    /// validation policy assigns to `category`: Errors are recorded as
+    /// validation errors, failing the build (refer to [Builder::enforce_validation()](super::Builder::enforce_validation())),
+    /// warnings as validation warnings and ignored findings are dropped
+    /// # Arguments
+    /// * `category` - The category of the finding
+    /// * `message` - The message describing the finding
+    pub fn add_finding(&mut self, category: ValidationCategory, message: String) {
+        match self.validation_policy.get(category) {
+            ValidationSeverity::Error => {
+                self.add_validation(format!("error: {message}"));
+                self.validation_errors
+                    .push(format!("{category}: {message}"));
+            }
+            ValidationSeverity::Warn => self.add_validation(format!("warning: {message}")),
+            ValidationSeverity::Ignore => {}
+        }
+    }
+
+    /// Records the runtime needs of a built package no dependency provides
+    /// # Arguments
+    /// * `needs` - The needs that could not be resolved
+    pub fn add_unresolved_dependencies(&mut self, needs: &[RuntimeNeed]) {
+        for need in needs {
+            self.add_finding(
+                ValidationCategory::UnresolvedDependencies,
+                format!("{need}, but no dependency provides it"),
+            );
+        }
+    }
+
+    /// Records the declared dependencies of a built package that provide nothing it needs
+    /// # Arguments
+    /// * `dependencies` - The dependencies found by [crate::model::Tree::find_unused_dependencies()]
+    pub fn add_unused_dependencies(&mut self, dependencies: &[ObjectID]) {
+        for oid in dependencies {
+            self.add_finding(
+                ValidationCategory::UnusedDependencies,
+                format!("Dependency {oid} provides nothing the package needs"),
+            );
+        }
+    }
+
+    /// Records the hardening issues of a built package
+    /// # Arguments
+    /// * `issues` - The issues found by [crate::model::Tree::check_hardening()]
+    pub fn add_hardening_issues(&mut self, issues: &[HardeningIssue]) {
+        for issue in issues {
+            self.add_finding(ValidationCategory::Hardening, issue.to_string());
+        }
+    }
+
+    /// Records the Python files of a built package referencing paths outside of it
+    /// # Arguments
+    /// * `issues` - The issues found by [crate::model::Tree::check_python_paths()]
+    pub fn add_python_issues(&mut self, issues: &[PythonIssue]) {
+        for issue in issues {
+            self.add_finding(ValidationCategory::PythonPaths, issue.to_string());
+        }
+    }
+
+    /// Records the dangling and absolute symlinks of a built package
+    /// # Arguments
+    /// * `issues` - The issues found by [crate::model::Tree::check_symlinks()]
+    pub fn add_symlink_issues(&mut self, issues: &[SymlinkIssue]) {
+        for issue in issues {
+            self.add_finding(ValidationCategory::Symlinks, issue.to_string());
+        }
+    }
+
+    /// Records the license files of a built package whose license is not declared
+    /// # Arguments
+    /// * `mismatches` - The mismatches found by [crate::model::Tree::check_licenses()]
+    pub fn add_license_mismatches(&mut self, mismatches: &[LicenseMismatch]) {
+        for mismatch in mismatches {
+            self.add_finding(ValidationCategory::Licenses, mismatch.to_string());
+        }
+    }
+
+    /// Records the search path entries removed from the ELF files of a built package
+    /// # Arguments
+    /// * `removals` - The entries removed by [crate::model::Tree::sanitize_runpaths()]
+    pub fn add_runpath_removals(&mut self, removals: &[RunpathRemoval]) {
+        for removal in removals {
+            self.add_validation(format!("fixed: {removal}"));
+        }
+    }
+
+    /// Records the files of a built package modified after `SOURCE_DATE_EPOCH`
+    /// # Arguments
+    /// * `paths` - The files found by [HermeticSettings::find_newer_files()]
+    /// * `settings` - The settings the package has been built with
+    pub fn add_timestamp_issues(&mut self, paths: &[PathBuf], settings: &HermeticSettings) {
+        for path in paths {
+            self.add_finding(
+                ValidationCategory::Timestamps,
+                format!(
+                    "{} is newer than SOURCE_DATE_EPOCH ({})",
+                    path.str_lossy(),
+                    settings.source_date_epoch
+                ),
+            );
+        }
+    }
+
+    /// Finishes the report, stopping the timer and recording the outcome of the build
+    /// # Arguments
+    /// * `result` - The result of the build
+    pub fn finish<T>(&mut self, result: &Result<T, Error>) {
+        self.duration_ms = self.start.elapsed().as_millis() as u64;
+        self.success = result.is_ok();
+        self.error = result.as_ref().err().map(|e| e.oneline());
+    }
+
+    /// Creates the receipt of the build of `package` from the build steps
+    /// executed in an environment, to be inserted into the object database
+    /// # Arguments
+    /// * `package` - The object id of the built package
+    /// * `tainted` - Whether the package has been built from a tainted formula
+    pub fn receipt(&self, package: ObjectID, tainted: bool) -> BuildReceipt {
+        BuildReceipt::new(
+            package,
+            self.build_id.clone(),
+            self.tooling_commit.clone(),
+            self.steps
+                .iter()
+                .filter_map(|step| step.environment.clone())
+                .collect(),
+            tainted,
+        )
+    }
+
+    /// Returns the report as JSON
+    pub fn json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serialize build report should never fail")
+    }
+
+    /// Writes the report as JSON to `path`
+    /// # Arguments
+    /// * `path` - The path to write the report to
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::create_parent_dir_all(path)?;
+
+        std::fs::write(path, self.json())
+            .e_context(|| format!("Writing build report to {}", path.to_string_lossy()))
+    }
+
+    /// Writes multiple reports as a JSON array to `path`
+    /// # Arguments
+    /// * `reports` - The reports to write
+    /// * `path` - The path to write the reports to
+    pub fn write_all(reports: &[BuildReport], path: &Path) -> Result<(), Error> {
+        fs::create_parent_dir_all(path)?;
+
+        let json = serde_json::to_string_pretty(reports)
+            .expect("Serialize build report should never fail");
+        std::fs::write(path, json)
+            .e_context(|| format!("Writing build reports to {}", path.to_string_lossy()))
+    }
+}
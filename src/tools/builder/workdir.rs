@@ -1,33 +1,41 @@
 use std::path::{Path, PathBuf};
 
+use log::warn;
 use uuid::Uuid;
 
 use crate::{
-    error::{Error, ErrorExt},
+    error::{Error, ErrorExt, ErrorType},
     model::Home,
-    util,
+    util::{
+        self,
+        fs::{FileLock, MountEntry, PathUtil},
+    },
 };
 
-use lazy_static::lazy_static;
+use super::BuilderError;
 
-lazy_static! {
-    /// The name of the install directory
-    static ref PATH_INSTALL_DIR: PathBuf = PathBuf::from("install");
-}
+/// The name of the install directory
+static PATH_INSTALL_DIR: &str = "install";
+static PATH_PATCHES_DIR: &str = ".patches";
 
-/// A working directory for the builder to work in
+/// A working directory for the builder to work in.
+///
+/// Every build gets its own workdir, which is locked (see [Home::get_build_lock_path()])
+/// as long as this struct lives, so no other build or `trunk clean` touches it
 #[derive(Debug)]
 pub struct BuilderWorkdir {
     /// The root of the working directory
     root: PathBuf,
     /// The build's unique ID
     id: String,
+    /// The lock held on the working directory
+    _lock: FileLock,
 }
 
 impl BuilderWorkdir {
-    /// Creates a new workdir at `<root>/<id>`
+    /// Creates a new workdir at `<builds_dir>/<id>` with a unique `id`
     /// # Arguments
-    /// * `root` - The directory the workdir exists in
+    /// * `home` - The home to create the workdir in
     pub fn new(home: &Home) -> Result<Self, Error> {
         let id = Uuid::new_v4().to_string();
         let root = home.get_builds_dir().join(&id);
@@ -35,7 +43,86 @@ impl BuilderWorkdir {
         util::fs::create_dir_all(&root)
             .e_context(|| format!("Creating workdir root @ {}", root.to_string_lossy()))?;
 
-        Ok(Self { root, id })
+        Self::lock(home, root, id)
+    }
+
+    /// Opens the existing workdir of the build `id` to reuse it
+    /// # Arguments
+    /// * `home` - The home the workdir exists in
+    /// * `id` - The id of the build that created the workdir
+    /// # Errors
+    /// If the workdir does not exist or is in use by another build
+    pub fn open(home: &Home, id: &str) -> Result<Self, Error> {
+        let root = home.get_builds_dir().join(id);
+
+        if !root.is_dir() {
+            return Err(Error::new(ErrorType::Other(format!(
+                "There is no workdir @ {}",
+                root.to_string_lossy()
+            ))));
+        }
+
+        Self::lock(home, root, id.to_owned())
+    }
+
+    /// Locks the workdir at `root`
+    /// # Arguments
+    /// * `home` - The home the workdir exists in
+    /// * `root` - The root of the workdir
+    /// * `id` - The id of the build
+    fn lock(home: &Home, root: PathBuf, id: String) -> Result<Self, Error> {
+        match FileLock::try_lock(&home.get_build_lock_path(&id))? {
+            Some(lock) => Ok(Self {
+                root,
+                id,
+                _lock: lock,
+            }),
+            None => Err(BuilderError::WorkdirInUse { id })
+                .e_context(|| format!("Locking workdir @ {}", root.to_string_lossy())),
+        }
+    }
+
+    /// Returns the mounts below this workdir.
+    ///
+    /// As the workdir is locked, no running build uses them: They have been
+    /// left behind by a build of this workdir that crashed before tearing down its environment
+    pub fn find_stale_mounts(&self) -> Result<Vec<MountEntry>, Error> {
+        let root = std::fs::canonicalize(&self.root)
+            .e_context(|| format!("Canonicalizing workdir @ {}", self.root.str_lossy()))?;
+
+        util::fs::mounts_under(&root)
+    }
+
+    /// Ensures that there are no stale mounts below this workdir (see [Self::find_stale_mounts()])
+    /// before a build starts, as building on top of them fails in confusing ways
+    /// # Arguments
+    /// * `unmount` - Whether to unmount the stale mounts instead of failing
+    /// # Errors
+    /// [BuilderError::StaleMounts] if there are stale mounts and `unmount` is `false`
+    pub fn prepare(&self, unmount: bool) -> Result<(), Error> {
+        let context = || format!("Preparing workdir @ {}", self.root.str_lossy());
+
+        let mounts = self.find_stale_mounts().ctx(context)?;
+        if mounts.is_empty() {
+            return Ok(());
+        }
+
+        if !unmount {
+            return Err(BuilderError::StaleMounts {
+                root: self.root.clone(),
+                mounts,
+            })
+            .e_context(context);
+        }
+
+        let unmounted = util::mount::unmount_stale(&self.root).ctx(context)?;
+        warn!(
+            "Recovered {} stale mounts below workdir {}",
+            unmounted.len(),
+            self.root.str_lossy()
+        );
+
+        Ok(())
     }
 
     /// Returns the build id for this working directory
@@ -83,18 +170,40 @@ impl BuilderWorkdir {
         self.root.join("formula")
     }
 
+    /// The directory for the dependencies of the build to be deployed to
+    ///
+    /// `<root>/dependencies`
+    pub fn get_dependencies_dir(&self) -> PathBuf {
+        self.root.join("dependencies")
+    }
+
+    /// The directory the patches of the formula get copied to, the formula directory
+    /// is the root of the `chroot`
+    ///
+    /// `<formula_dir>/<PATH_PATCHES_DIR>`
+    pub fn get_patches_dir(&self) -> PathBuf {
+        self.get_formula_dir().join(PATH_PATCHES_DIR)
+    }
+
+    /// The path to the patches directory from inside the `chroot`
+    ///
+    /// `/<PATH_PATCHES_DIR>`
+    pub fn get_patches_dir_inner(&self) -> PathBuf {
+        PathBuf::from("/").join(PATH_PATCHES_DIR)
+    }
+
     /// The path to the installation target directory from inside the `chroot`
     ///
     /// `/<PATH_INSTALL_DIR>`
     pub fn get_install_dir_inner(&self) -> PathBuf {
-        PathBuf::from("/").join(&*PATH_INSTALL_DIR)
+        PathBuf::from("/").join(PATH_INSTALL_DIR)
     }
 
     /// The path to the installation target directory from outside the `chroot`
     ///
     /// `<overlay_dir_merged>/<PATH_INSTALL_DIR>`
     pub fn get_install_dir_outer(&self) -> PathBuf {
-        self.get_overlay_dir_merged().join(&*PATH_INSTALL_DIR)
+        self.get_overlay_dir_merged().join(PATH_INSTALL_DIR)
     }
 
     /// The directory to place the finished artifact's output files in
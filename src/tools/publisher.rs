@@ -0,0 +1,274 @@
+//! Publishing packages to a remote object database served by `trunk serve`
+//!
+//! Objects are uploaded after all of their dependencies and chunks, so the remote
+//! never contains an object whose closure is incomplete. This allows skipping
+//! the whole closure of every object that already exists on the remote.
+use std::{io::Read, time::Duration};
+
+use curl::easy::{Easy, List, ReadError};
+use http::StatusCode;
+use log::{debug, info, warn};
+
+use crate::{
+    error::{support::CURLError, Error, ErrorExt, ErrorType},
+    model::{
+        odb_driver::{FilesystemDriver, HTTPDriver},
+        ODBDriver, ObjectCompression, ObjectDB, ObjectID, RepositoryIndex, RepositoryIndexEntry,
+    },
+    tools::server::INDEX_REF,
+    util::fs,
+};
+
+/// How often to retry updating the remote repository index if it changed concurrently
+static MAX_INDEX_ATTEMPTS: usize = 5;
+
+/// Uploads objects from a local object database to a remote one
+/// and updates the repository index of the remote
+pub struct Publisher {
+    /// The driver of the local object database to upload from
+    driver: FilesystemDriver,
+    /// The driver of the remote object database
+    remote: HTTPDriver,
+    /// The token to authenticate uploads with
+    token: String,
+}
+
+impl Publisher {
+    /// Creates a new publisher
+    /// # Arguments
+    /// * `driver` - The driver of the local object database to upload from
+    /// * `remote` - The driver of the remote object database
+    /// * `token` - The upload token of the remote (`trunk serve --upload-token`)
+    pub fn new(driver: FilesystemDriver, remote: HTTPDriver, token: String) -> Self {
+        Self {
+            driver,
+            remote,
+            token,
+        }
+    }
+
+    /// Uploads the package and formula of `entry` and adds `entry` to the
+    /// repository index of the remote.
+    ///
+    /// The `index` ref of the remote is only moved if nobody else updated it in the
+    /// meantime, else the new index gets rebuilt from the changed one and retried
+    /// # Arguments
+    /// * `odb` - The local object database to build the new index in
+    /// * `entry` - The entry to publish
+    /// * `compression` - The compression to apply to the new index
+    /// # Returns
+    /// The object id of the new repository index
+    pub fn publish(
+        &self,
+        odb: &mut ObjectDB,
+        entry: RepositoryIndexEntry,
+        compression: ObjectCompression,
+    ) -> Result<ObjectID, Error> {
+        for oid in entry.get_oids() {
+            let uploaded = self
+                .upload_closure(&oid)
+                .ctx(|| format!("Uploading {oid}"))?;
+            info!("Uploaded {uploaded} objects for {oid}");
+        }
+
+        for attempt in 1..=MAX_INDEX_ATTEMPTS {
+            let old = self.get_ref(INDEX_REF)?;
+
+            let mut index = match &old {
+                Some(oid) => {
+                    odb.pull_from_driver(&self.remote, oid.clone(), compression, false)
+                        .ctx(|| "Fetching remote repository index")?;
+                    RepositoryIndex::from_odb(odb, oid)?
+                }
+                None => RepositoryIndex::new(),
+            };
+
+            index.insert(entry.clone());
+            let new = index.insert_into_odb(odb, compression)?.oid;
+
+            self.upload_closure(&new)
+                .ctx(|| "Uploading repository index")?;
+
+            if self.update_ref(INDEX_REF, old.as_ref(), &new)? {
+                info!("Published {entry} in repository index {new}");
+                return Ok(new);
+            }
+
+            warn!(
+                "Remote repository index changed concurrently, retrying ({attempt}/{MAX_INDEX_ATTEMPTS})"
+            );
+        }
+
+        Err(Error::new(ErrorType::Other(format!(
+            "Remote repository index kept changing, gave up after {MAX_INDEX_ATTEMPTS} attempts"
+        ))))
+    }
+
+    /// Uploads `oid`, its dependencies and chunks recursively,
+    /// skipping objects that already exist on the remote
+    /// # Arguments
+    /// * `oid` - The object id of the object to upload
+    /// # Returns
+    /// The amount of uploaded objects
+    pub fn upload_closure(&self, oid: &ObjectID) -> Result<usize, Error> {
+        let mut uploaded = Vec::new();
+        self.upload_closure_inner(oid, &mut uploaded)?;
+        Ok(uploaded.len())
+    }
+
+    /// Uploads the closure of `oid` (see [Publisher::upload_closure()])
+    /// # Arguments
+    /// * `oid` - The object id of the object to upload
+    /// * `uploaded` - The objects that have been uploaded already
+    fn upload_closure_inner(
+        &self,
+        oid: &ObjectID,
+        uploaded: &mut Vec<ObjectID>,
+    ) -> Result<(), Error> {
+        if uploaded.contains(oid) || self.remote.exists(oid) {
+            debug!("[SKIP] Uploading {oid}");
+            return Ok(());
+        }
+
+        let reader = self.driver.retrieve(oid)?;
+
+        for chunk in reader.chunks().unwrap_or_default() {
+            self.upload_closure_inner(&chunk.oid, uploaded)?;
+        }
+
+        for dependency in &reader.object.dependencies {
+            self.upload_closure_inner(dependency, uploaded)?;
+        }
+
+        self.upload_object(oid)?;
+        uploaded.push(oid.clone());
+
+        Ok(())
+    }
+
+    /// Uploads the object file of `oid` without its dependencies
+    /// # Arguments
+    /// * `oid` - The object id of the object to upload
+    pub fn upload_object(&self, oid: &ObjectID) -> Result<(), Error> {
+        debug!("Uploading {oid}");
+
+        let path = self.driver.get_oid_path(oid);
+        let mut file = fs::file_open(&path)?;
+        let len = file
+            .metadata()
+            .ctx(|| format!("Reading metadata of {}", path.to_string_lossy()))?
+            .len();
+
+        let status = self
+            .put(&self.remote.get_oid_url(oid), &mut file, len, &[])
+            .ctx(|| format!("Uploading object {oid}"))?;
+
+        match status {
+            StatusCode::OK | StatusCode::CREATED => Ok(()),
+            status => Err(Error::new_context(
+                ErrorType::CURL(CURLError::ErrorStatus(status)),
+                format!("Uploading object {oid}"),
+            )),
+        }
+    }
+
+    /// Returns the object id the ref `name` of the remote points to
+    /// # Arguments
+    /// * `name` - The name of the ref
+    pub fn get_ref(&self, name: &str) -> Result<Option<ObjectID>, Error> {
+        self.remote.get_ref(name)
+    }
+
+    /// Points the ref `name` of the remote to `new` if it still points to `old`
+    /// # Arguments
+    /// * `name` - The name of the ref to update
+    /// * `old` - The object id the ref is expected to point to, `None` if it should not exist
+    /// * `new` - The object id to point the ref to, has to exist on the remote
+    /// # Returns
+    /// `false` if the ref has been changed by someone else
+    pub fn update_ref(
+        &self,
+        name: &str,
+        old: Option<&ObjectID>,
+        new: &ObjectID,
+    ) -> Result<bool, Error> {
+        let precondition = match old {
+            Some(old) => format!("If-Match: {old}"),
+            None => "If-None-Match: *".to_owned(),
+        };
+
+        let body = new.to_string();
+        let status = self
+            .put(
+                &self.remote.get_ref_url(name),
+                &mut body.as_bytes(),
+                body.len() as u64,
+                &[precondition],
+            )
+            .ctx(|| format!("Updating ref {name}"))?;
+
+        match status {
+            StatusCode::OK => Ok(true),
+            StatusCode::PRECONDITION_FAILED => Ok(false),
+            status => Err(Error::new_context(
+                ErrorType::CURL(CURLError::ErrorStatus(status)),
+                format!("Updating ref {name} to {new}"),
+            )),
+        }
+    }
+
+    /// Sends a `PUT` request authenticated with the upload token
+    /// # Arguments
+    /// * `url` - The URL to send the request to
+    /// * `body` - The stream providing the request body
+    /// * `len` - The length of the request body
+    /// * `headers` - Additional headers in the form `<name>: <value>`
+    /// # Returns
+    /// The response status
+    fn put<R: Read>(
+        &self,
+        url: &str,
+        body: &mut R,
+        len: u64,
+        headers: &[String],
+    ) -> Result<StatusCode, Error> {
+        let context = || format!("Sending PUT request to {url}");
+
+        let mut list = List::new();
+        list.append(&format!("Authorization: Bearer {}", self.token))
+            .e_context(context)?;
+        for header in headers {
+            list.append(header).e_context(context)?;
+        }
+
+        let mut easy = Easy::new();
+        easy.url(url).e_context(context)?;
+        easy.upload(true).e_context(context)?;
+        easy.in_filesize(len).e_context(context)?;
+        easy.http_headers(list).e_context(context)?;
+
+        //Setup the low speed bounds (less that 1000bytes in 30 seconds)
+        easy.low_speed_limit(1000).e_context(context)?;
+        easy.low_speed_time(Duration::from_secs(30))
+            .e_context(context)?;
+
+        {
+            let mut transfer = easy.transfer();
+            transfer
+                .read_function(|buf| body.read(buf).map_err(|_| ReadError::Abort))
+                .e_context(context)?;
+            // The response body is a short message that only matters for debugging
+            transfer
+                .write_function(|data| {
+                    debug!("{url}: {}", String::from_utf8_lossy(data).trim());
+                    Ok(data.len())
+                })
+                .e_context(context)?;
+            transfer.perform().e_context(context)?;
+        }
+
+        let code = easy.response_code().e_context(context)?;
+        StatusCode::from_u16(code as u16)
+            .map_err(|_| Error::new(ErrorType::CURL(CURLError::InvalidStatus(code))))
+    }
+}
@@ -0,0 +1,749 @@
+//! A HTTP server exposing an object database to other machines
+//!
+//! The server understands the following requests:
+//! - `GET /objects/<oid>`: Retrieve the object file for `<oid>`, supports `Range: bytes=<start>-[<end>]`
+//! - `HEAD /objects/<oid>`: Check for the existence of `<oid>`
+//! - `PUT /objects/<oid>`: Upload an object file (only if an upload token is configured)
+//! - `GET /rdeps/<oid>`: List the object ids of the objects depending on `<oid>`, one per line
+//! - `GET /refs`: List all refs in the form `<name> <oid>`, one per line
+//! - `GET /refs/<name>`: Retrieve the object id the ref `<name>` points to
+//! - `PUT /refs/<name>`: Point the ref `<name>` to another object (only if an upload token is configured),
+//!   requires `If-Match: <oid>` with the current object id or `If-None-Match: *` for new refs
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex, RwLock},
+    thread,
+    time::Duration,
+};
+
+use http::StatusCode;
+use log::{debug, info, warn};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    error::{Error, ErrorExt},
+    model::{
+        odb_driver::FilesystemDriver, read_refs, write_refs, ODBDriver, ObjectID, ObjectReader,
+        ObjectTemplate,
+    },
+    util::fs::{self, PathUtil},
+};
+
+/// The name of the ref that points to the repository index
+pub static INDEX_REF: &str = "index";
+
+/// The maximum size of the body of a ref update (an object id with some whitespace)
+static MAX_REF_BODY_SIZE: u64 = 256;
+
+/// The maximum size of the body of an object upload by default, refer to [ODBServer::with_max_object_size()]
+pub const MAX_OBJECT_BODY_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+/// The maximum size of the request line and of every header line
+static MAX_LINE_SIZE: u64 = 8 * 1024;
+
+/// The maximum number of headers of a request
+static MAX_HEADERS: usize = 100;
+
+/// The time after which a connection that does not send any data gets dropped
+static CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The number of connections handled concurrently by default
+pub const DEFAULT_SERVER_WORKERS: usize = 16;
+
+/// A HTTP server that serves the objects of an object database
+pub struct ODBServer {
+    /// The driver of the object database to serve
+    driver: RwLock<FilesystemDriver>,
+    /// The refs (named object ids) to serve
+    refs: RwLock<HashMap<String, ObjectID>>,
+    /// The file the refs get persisted in, `None` to only keep them in memory
+    refs_path: Option<PathBuf>,
+    /// The token to authenticate uploads with, uploads are disabled if `None`
+    upload_token: Option<String>,
+    /// The number of connections to handle concurrently
+    workers: usize,
+    /// The maximum size of the body of an object upload
+    max_object_size: u64,
+}
+
+/// A parsed HTTP request
+struct Request {
+    /// The HTTP method
+    method: String,
+    /// The requested path
+    path: String,
+    /// The request headers with lowercase names
+    headers: HashMap<String, String>,
+}
+
+/// The body of a HTTP response
+enum ResponseBody {
+    /// A body held in memory
+    Bytes(Vec<u8>),
+    /// A section of a file
+    File {
+        /// The file to read from
+        file: File,
+        /// The amount of bytes to send
+        len: u64,
+    },
+}
+
+/// A HTTP response
+struct Response {
+    /// The status code
+    status: StatusCode,
+    /// Additional headers
+    headers: Vec<(String, String)>,
+    /// The body
+    body: ResponseBody,
+}
+
+impl ODBServer {
+    /// Creates a new object database server
+    /// # Arguments
+    /// * `driver` - The driver of the object database to serve
+    /// * `refs` - The refs to serve
+    /// * `upload_token` - The token that authenticates uploads, `None` disables uploads
+    pub fn new(
+        driver: FilesystemDriver,
+        refs: HashMap<String, ObjectID>,
+        upload_token: Option<String>,
+    ) -> Self {
+        Self {
+            driver: RwLock::new(driver),
+            refs: RwLock::new(refs),
+            refs_path: None,
+            upload_token,
+            workers: DEFAULT_SERVER_WORKERS,
+            max_object_size: MAX_OBJECT_BODY_SIZE,
+        }
+    }
+
+    /// Persists the refs in the file at `path` (usually [crate::model::Home::get_refs_path()]),
+    /// so they survive restarts: The refs of the file get loaded, the refs passed to
+    /// [ODBServer::new()] replace the ones of the same name and the file gets updated
+    /// along with every ref
+    /// # Arguments
+    /// * `path` - The path to the refs file
+    pub fn with_refs_file(mut self, path: PathBuf) -> Result<Self, Error> {
+        let mut refs = read_refs(&path).ctx(|| "Loading persisted refs")?;
+        refs.extend(self.refs.into_inner().expect("Poisoned refs lock"));
+
+        write_refs(&path, &refs).ctx(|| "Persisting refs")?;
+        info!("Loaded {} refs from {}", refs.len(), path.str_lossy());
+
+        self.refs = RwLock::new(refs);
+        self.refs_path = Some(path);
+        Ok(self)
+    }
+
+    /// Sets the number of connections to handle concurrently, further
+    /// connections wait until a worker is available (at least `1`)
+    /// # Arguments
+    /// * `workers` - The number of worker threads
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Sets the maximum size of the body of an object upload,
+    /// larger uploads are rejected with `413 Payload Too Large`
+    /// # Arguments
+    /// * `size` - The maximum size in bytes
+    pub fn with_max_object_size(mut self, size: u64) -> Self {
+        self.max_object_size = size;
+        self
+    }
+
+    /// Binds to `address` and serves requests until the process exits,
+    /// the connections get handled by a fixed number of worker threads
+    /// (refer to [ODBServer::with_workers()])
+    /// # Arguments
+    /// * `address` - The address to bind to
+    pub fn serve<A: ToSocketAddrs>(self, address: A) -> Result<(), Error> {
+        let listener = TcpListener::bind(address).ctx(|| "Binding server socket")?;
+        let local_addr = listener.local_addr().ctx(|| "Getting local address")?;
+
+        info!(
+            "Serving object database on {} (uploads {})",
+            local_addr,
+            match self.upload_token {
+                Some(_) => "enabled",
+                None => "disabled",
+            }
+        );
+
+        let server = Arc::new(self);
+
+        // Accepted connections queue up until a worker picks them up
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(server.workers);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..server.workers {
+            let server = server.clone();
+            let receiver = receiver.clone();
+
+            thread::spawn(move || loop {
+                let stream = match receiver.lock().expect("Poisoned connection queue").recv() {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+
+                if let Err(e) = server.handle(stream) {
+                    warn!("Failed to handle request: {}", e.oneline());
+                }
+            });
+        }
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept connection: {e}");
+                    continue;
+                }
+            };
+
+            if sender.send(stream).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles a single connection
+    /// # Arguments
+    /// * `stream` - The stream to handle
+    fn handle(&self, mut stream: TcpStream) -> Result<(), Error> {
+        // Idle connections must not occupy a worker forever
+        stream
+            .set_read_timeout(Some(CONNECTION_TIMEOUT))
+            .ctx(|| "Setting read timeout")?;
+
+        let mut reader = BufReader::new(stream.try_clone().ctx(|| "Cloning TCP stream")?);
+
+        let request = Request::parse(&mut reader).ctx(|| "Parsing request")?;
+        let response = match self.route(&request, &mut reader) {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("{} {}: {}", request.method, request.path, e.oneline());
+                Response::text(StatusCode::INTERNAL_SERVER_ERROR, &e.oneline())
+            }
+        };
+
+        debug!("{} {} => {}", request.method, request.path, response.status);
+
+        response
+            .write(&mut stream, request.method == "HEAD")
+            .ctx(|| "Writing response")
+    }
+
+    /// Routes a request to the according handler
+    /// # Arguments
+    /// * `request` - The request to route
+    /// * `body` - The stream to read the request body from
+    fn route<R: Read>(&self, request: &Request, body: &mut R) -> Result<Response, Error> {
+        let path = request.path.trim_start_matches('/');
+        let (resource, name) = path.split_once('/').unwrap_or((path, ""));
+
+        match (request.method.as_str(), resource, name) {
+            ("GET" | "HEAD", "objects", oid) => match ObjectID::new_from_hex(oid) {
+                Ok(oid) => self.get_object(&oid, request),
+                Err(_) => Ok(Response::text(StatusCode::BAD_REQUEST, "Invalid object id")),
+            },
+            ("PUT", "objects", oid) => match ObjectID::new_from_hex(oid) {
+                Ok(oid) => self.put_object(&oid, request, body),
+                Err(_) => Ok(Response::text(StatusCode::BAD_REQUEST, "Invalid object id")),
+            },
+            ("GET" | "HEAD", "rdeps", oid) => match ObjectID::new_from_hex(oid) {
+                Ok(oid) => {
+                    let dependents = self
+                        .driver
+                        .read()
+                        .expect("Poisoned object database lock")
+                        .reverse_dependencies(&oid)?;
+
+                    let lines: String = dependents.iter().map(|d| format!("{d}\n")).collect();
+                    Ok(Response::text(StatusCode::OK, &lines))
+                }
+                Err(_) => Ok(Response::text(StatusCode::BAD_REQUEST, "Invalid object id")),
+            },
+            ("GET" | "HEAD", "refs", "") => {
+                let mut refs: Vec<String> = self
+                    .refs
+                    .read()
+                    .expect("Poisoned refs lock")
+                    .iter()
+                    .map(|(name, oid)| format!("{name} {oid}\n"))
+                    .collect();
+                refs.sort();
+                Ok(Response::text(StatusCode::OK, &refs.concat()))
+            }
+            ("GET" | "HEAD", "refs", name) => {
+                match self.refs.read().expect("Poisoned refs lock").get(name) {
+                    Some(oid) => Ok(Response::text(StatusCode::OK, &format!("{oid}\n"))),
+                    None => Ok(Response::text(StatusCode::NOT_FOUND, "Ref not found")),
+                }
+            }
+            ("PUT", "refs", name) if !name.is_empty() => self.put_ref(name, request, body),
+            (_, "objects" | "rdeps" | "refs", _) => Ok(Response::text(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Method not allowed",
+            )),
+            _ => Ok(Response::text(StatusCode::NOT_FOUND, "Not found")),
+        }
+    }
+
+    /// Serves the object file for `oid`, respecting the `Range` header
+    /// # Arguments
+    /// * `oid` - The object id of the object to serve
+    /// * `request` - The request to serve
+    fn get_object(&self, oid: &ObjectID, request: &Request) -> Result<Response, Error> {
+        let path = self
+            .driver
+            .read()
+            .expect("Poisoned object database lock")
+            .get_oid_path(oid);
+
+        if !path.exists() {
+            return Ok(Response::text(StatusCode::NOT_FOUND, "Object not found"));
+        }
+
+        let mut file = fs::file_open(&path)?;
+        let total = file
+            .metadata()
+            .ctx(|| format!("Reading metadata of {}", path.str_lossy()))?
+            .len();
+
+        let range = match request.headers.get("range") {
+            None => None,
+            Some(range) => match parse_range(range, total) {
+                Some(range) => Some(range),
+                None => {
+                    return Ok(Response {
+                        status: StatusCode::RANGE_NOT_SATISFIABLE,
+                        headers: vec![("Content-Range".to_owned(), format!("bytes */{total}"))],
+                        body: ResponseBody::Bytes(Vec::new()),
+                    })
+                }
+            },
+        };
+
+        let mut headers = vec![(
+            "Content-Type".to_owned(),
+            "application/octet-stream".to_owned(),
+        )];
+
+        Ok(match range {
+            None => Response {
+                status: StatusCode::OK,
+                headers,
+                body: ResponseBody::File { file, len: total },
+            },
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start))
+                    .ctx(|| "Seeking to start of range")?;
+
+                headers.push((
+                    "Content-Range".to_owned(),
+                    format!("bytes {start}-{end}/{total}"),
+                ));
+
+                Response {
+                    status: StatusCode::PARTIAL_CONTENT,
+                    headers,
+                    body: ResponseBody::File {
+                        file,
+                        len: end - start + 1,
+                    },
+                }
+            }
+        })
+    }
+
+    /// Receives an object file for `oid` and inserts it into the object database
+    /// # Arguments
+    /// * `oid` - The object id the uploaded object is expected to have
+    /// * `request` - The request to serve
+    /// * `body` - The stream to read the request body from
+    fn put_object<R: Read>(
+        &self,
+        oid: &ObjectID,
+        request: &Request,
+        body: &mut R,
+    ) -> Result<Response, Error> {
+        if let Some(response) = self.authorize_upload(request) {
+            return Ok(response);
+        }
+
+        let len = match request.content_length() {
+            Some(len) if len <= self.max_object_size => len,
+            Some(_) => {
+                return Ok(Response::text(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "Object exceeds the maximum size",
+                ))
+            }
+            None => {
+                return Ok(Response::text(
+                    StatusCode::LENGTH_REQUIRED,
+                    "Content-Length required",
+                ))
+            }
+        };
+
+        let temp_path = {
+            let driver = self.driver.read().expect("Poisoned object database lock");
+
+            if driver.exists(oid) {
+                return Ok(Response::text(StatusCode::OK, "Object exists"));
+            }
+
+            driver.get_temp_file_path()
+        };
+
+        // Buffer the upload in a temporary file to parse it
+        fs::create_parent_dir_all(&temp_path)?;
+        let mut temp_file = fs::file_create_rw(&temp_path)?;
+
+        let res = receive_object(oid, &mut body.take(len), &mut temp_file, &self.driver);
+        fs::remove_file(&temp_path)?;
+
+        match res? {
+            true => {
+                info!("Received object {oid}");
+                Ok(Response::text(StatusCode::CREATED, "Object created"))
+            }
+            false => Ok(Response::text(
+                StatusCode::BAD_REQUEST,
+                "Object ID mismatch",
+            )),
+        }
+    }
+
+    /// Points the ref `name` to the object id in the request body if the
+    /// precondition of the request holds, so concurrent updates cannot get lost
+    /// # Arguments
+    /// * `name` - The name of the ref to update
+    /// * `request` - The request to serve
+    /// * `body` - The stream to read the request body from
+    fn put_ref<R: Read>(
+        &self,
+        name: &str,
+        request: &Request,
+        body: &mut R,
+    ) -> Result<Response, Error> {
+        if let Some(response) = self.authorize_upload(request) {
+            return Ok(response);
+        }
+
+        let len = match request.content_length() {
+            Some(len) if len <= MAX_REF_BODY_SIZE => len,
+            Some(_) => {
+                return Ok(Response::text(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "Expected an object id",
+                ))
+            }
+            None => {
+                return Ok(Response::text(
+                    StatusCode::LENGTH_REQUIRED,
+                    "Content-Length required",
+                ))
+            }
+        };
+
+        let mut new = String::new();
+        body.take(len)
+            .read_to_string(&mut new)
+            .ctx(|| "Receiving ref update")?;
+
+        let new = match ObjectID::new_from_hex(new.trim()) {
+            Ok(oid) => oid,
+            Err(_) => return Ok(Response::text(StatusCode::BAD_REQUEST, "Invalid object id")),
+        };
+
+        // A ref must never point to an object that cannot be retrieved
+        if !self
+            .driver
+            .read()
+            .expect("Poisoned object database lock")
+            .exists(&new)
+        {
+            return Ok(Response::text(StatusCode::CONFLICT, "Object not found"));
+        }
+
+        let expected = match (
+            request.headers.get("if-match"),
+            request.headers.get("if-none-match"),
+        ) {
+            (Some(old), _) => match ObjectID::new_from_hex(old.trim_matches('"')) {
+                Ok(oid) => Some(oid),
+                Err(_) => return Ok(Response::text(StatusCode::BAD_REQUEST, "Invalid If-Match")),
+            },
+            (None, Some(any)) if any == "*" => None,
+            _ => {
+                return Ok(Response::text(
+                    StatusCode::PRECONDITION_REQUIRED,
+                    "If-Match or If-None-Match: * required",
+                ))
+            }
+        };
+
+        let mut refs = self.refs.write().expect("Poisoned refs lock");
+
+        if refs.get(name) != expected.as_ref() {
+            return Ok(Response::text(
+                StatusCode::PRECONDITION_FAILED,
+                "Ref has been changed",
+            ));
+        }
+
+        let mut updated = refs.clone();
+        updated.insert(name.to_owned(), new.clone());
+
+        // The ref only changes once it has been persisted
+        if let Some(path) = &self.refs_path {
+            write_refs(path, &updated).ctx(|| format!("Persisting ref {name}"))?;
+        }
+
+        info!("Updated ref {name} to {new}");
+        *refs = updated;
+
+        Ok(Response::text(StatusCode::OK, "Ref updated"))
+    }
+
+    /// Checks whether `request` may upload data
+    /// # Arguments
+    /// * `request` - The request to check
+    /// # Returns
+    /// The response to reject the request with or `None` if the request is authorized
+    fn authorize_upload(&self, request: &Request) -> Option<Response> {
+        let token = match &self.upload_token {
+            Some(token) => token,
+            None => {
+                return Some(Response::text(
+                    StatusCode::FORBIDDEN,
+                    "Uploads are disabled",
+                ))
+            }
+        };
+
+        let authorized = request
+            .headers
+            .get("authorization")
+            .and_then(|a| a.strip_prefix("Bearer "))
+            .is_some_and(|a| bool::from(a.as_bytes().ct_eq(token.as_bytes())));
+
+        match authorized {
+            true => None,
+            false => Some(Response::text(StatusCode::UNAUTHORIZED, "Invalid token")),
+        }
+    }
+}
+
+/// Copies an uploaded object file into `temp_file` and inserts it into `driver`,
+/// which only gets locked for writing once the object has been received
+/// # Arguments
+/// * `oid` - The object id the object is expected to have
+/// * `body` - The stream providing the object file
+/// * `temp_file` - The file to buffer the object file in
+/// * `driver` - The driver to insert the object into
+/// # Returns
+/// `false` if the object does not declare the expected object id
+fn receive_object<R: Read>(
+    oid: &ObjectID,
+    body: &mut R,
+    temp_file: &mut File,
+    driver: &RwLock<FilesystemDriver>,
+) -> Result<bool, Error> {
+    io::copy(body, temp_file).ctx(|| "Receiving object")?;
+    temp_file
+        .seek(SeekFrom::Start(0))
+        .ctx(|| "Seeking to start of received object")?;
+
+    let file = temp_file.try_clone().ctx(|| "Cloning file handle")?;
+    let mut reader = ObjectReader::from_stream(file).ctx(|| "Parsing received object")?;
+
+    if &reader.object.oid != oid {
+        return Ok(false);
+    }
+
+    let mut driver = driver.write().expect("Poisoned object database lock");
+
+    // Another upload may have inserted the object in the meantime
+    if driver.exists(oid) {
+        return Ok(true);
+    }
+
+    // Chunked objects require their chunks to be uploaded first
+    if let Some(chunks) = reader.chunks() {
+        let chunks = chunks.to_vec();
+        driver.insert_chunked(reader.object, chunks)?;
+
+        return Ok(true);
+    }
+
+    let ty = reader.object.ty;
+    let compression = reader.object.compression;
+    let dependencies = reader.object.dependencies.clone();
+
+    // The prehashed template verifies the object id while inserting
+    let template = ObjectTemplate::new_prehashed(&mut reader, oid.clone(), ty, dependencies);
+    driver.insert(template, compression)?;
+
+    Ok(true)
+}
+
+/// Parses a `Range` header of the form `bytes=<start>-[<end>]`
+/// # Arguments
+/// * `range` - The value of the header
+/// * `total` - The total length of the resource
+/// # Returns
+/// The inclusive range or `None` if the range cannot be satisfied
+fn parse_range(range: &str, total: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+
+    let start: u64 = start.parse().ok()?;
+    let end: u64 = match end {
+        "" => total.checked_sub(1)?,
+        end => end.parse::<u64>().ok()?.min(total.checked_sub(1)?),
+    };
+
+    if start > end {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+/// Reads a line of at most [MAX_LINE_SIZE] bytes from `reader`
+/// # Arguments
+/// * `reader` - The stream to read from
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE_SIZE).read_line(&mut line)?;
+
+    if line.len() as u64 == MAX_LINE_SIZE && !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Line exceeds {MAX_LINE_SIZE} bytes"),
+        ));
+    }
+
+    Ok(line)
+}
+
+impl Request {
+    /// Parses the request line and headers from `reader`
+    /// # Arguments
+    /// * `reader` - The stream to read from
+    fn parse<R: BufRead>(reader: &mut R) -> Result<Self, Error> {
+        let line = read_line(reader).ctx(|| "Reading request line")?;
+
+        let mut parts = line.split_whitespace();
+        let (method, path) = match (parts.next(), parts.next()) {
+            (Some(method), Some(path)) => (method.to_owned(), path.to_owned()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Malformed request line '{}'", line.trim()),
+                ))
+                .ctx(|| "Parsing request line")
+            }
+        };
+
+        let mut headers = HashMap::new();
+        loop {
+            let line = read_line(reader).ctx(|| "Reading header")?;
+
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+
+            if headers.len() >= MAX_HEADERS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("More than {MAX_HEADERS} headers"),
+                ))
+                .ctx(|| "Reading headers");
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+            }
+        }
+
+        Ok(Self {
+            method,
+            path,
+            headers,
+        })
+    }
+
+    /// Returns the value of the `Content-Length` header, if present and valid
+    fn content_length(&self) -> Option<u64> {
+        self.headers
+            .get("content-length")
+            .and_then(|l| l.parse().ok())
+    }
+}
+
+impl Response {
+    /// Creates a plain text response
+    /// # Arguments
+    /// * `status` - The status code
+    /// * `text` - The text to respond with
+    fn text(status: StatusCode, text: &str) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_owned(), "text/plain".to_owned())],
+            body: ResponseBody::Bytes(text.as_bytes().to_vec()),
+        }
+    }
+
+    /// Writes the response to `output`
+    /// # Arguments
+    /// * `output` - The stream to write to
+    /// * `head_only` - Whether to omit the body (`HEAD` request)
+    fn write<W: Write>(self, output: &mut W, head_only: bool) -> io::Result<()> {
+        let len = match &self.body {
+            ResponseBody::Bytes(bytes) => bytes.len() as u64,
+            ResponseBody::File { file: _, len } => *len,
+        };
+
+        write!(
+            output,
+            "HTTP/1.1 {} {}\r\n",
+            self.status.as_u16(),
+            self.status.canonical_reason().unwrap_or("")
+        )?;
+
+        for (name, value) in &self.headers {
+            write!(output, "{name}: {value}\r\n")?;
+        }
+
+        write!(
+            output,
+            "Content-Length: {len}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n"
+        )?;
+
+        if !head_only {
+            match self.body {
+                ResponseBody::Bytes(bytes) => output.write_all(&bytes)?,
+                ResponseBody::File { file, len } => {
+                    io::copy(&mut file.take(len), output)?;
+                }
+            }
+        }
+
+        output.flush()
+    }
+}
@@ -8,14 +8,19 @@ use std::io::{self, Read, Write};
 
 pub mod architecture;
 pub mod archive;
+pub mod chunker;
 pub mod download;
 pub mod elf;
 pub mod fs;
+pub mod git;
 pub mod hash;
 pub mod parse;
+pub mod progress;
+pub mod sched;
 pub mod serde;
 pub mod signal;
 pub mod string;
+pub mod userns;
 
 #[cfg(feature = "mount")]
 pub mod mount;
@@ -173,3 +178,24 @@ impl Unpackable for u32 {
         })
     }
 }
+
+impl Packable for u64 {
+    fn pack<W: Write>(&self, output: &mut W) -> Result<(), Error> {
+        output
+            .write(&self.to_le_bytes())
+            .ctx(|| format!("Writing {self}"))?;
+
+        Ok(())
+    }
+}
+
+impl Unpackable for u64 {
+    fn unpack<R: Read>(input: &mut R) -> Result<Option<Self>, Error> {
+        let mut buf = [0u8; 8];
+        let x = input.read(&mut buf).e_context(|| "Read u64".to_owned())?;
+        Ok(match x {
+            8 => Some(Self::from_le_bytes(buf)),
+            _ => None,
+        })
+    }
+}
@@ -1,12 +1,99 @@
 //! Utilities for working with architectures
 
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-use crate::error::Error;
+use crate::error::{architecture::ArchitectureError, Error, ErrorType};
 use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::error::ErrorExt;
 
+/// The architectures known to the tooling, refer to [ArchitectureInfo]
+static ARCHITECTURES: &[ArchitectureInfo] = &[
+    ArchitectureInfo {
+        name: "i686",
+        aliases: &["i386", "i486", "i586"],
+        family: "x86",
+        bits: 32,
+        variant_of: None,
+        runs: &[],
+        qemu: "i386",
+    },
+    ArchitectureInfo {
+        name: "x86_64",
+        aliases: &["amd64"],
+        family: "x86",
+        bits: 64,
+        variant_of: None,
+        runs: &["i686"],
+        qemu: "x86_64",
+    },
+    ArchitectureInfo {
+        name: "x86_64_v2",
+        aliases: &[],
+        family: "x86",
+        bits: 64,
+        variant_of: Some("x86_64"),
+        runs: &["x86_64"],
+        qemu: "x86_64",
+    },
+    ArchitectureInfo {
+        name: "x86_64_v3",
+        aliases: &[],
+        family: "x86",
+        bits: 64,
+        variant_of: Some("x86_64"),
+        runs: &["x86_64_v2"],
+        qemu: "x86_64",
+    },
+    ArchitectureInfo {
+        name: "aarch64",
+        aliases: &["arm64"],
+        family: "arm",
+        bits: 64,
+        variant_of: None,
+        runs: &["armv7"],
+        qemu: "aarch64",
+    },
+    ArchitectureInfo {
+        name: "armv7",
+        aliases: &["armv7l", "armhf"],
+        family: "arm",
+        bits: 32,
+        variant_of: None,
+        runs: &[],
+        qemu: "arm",
+    },
+    ArchitectureInfo {
+        name: "riscv64",
+        aliases: &[],
+        family: "riscv",
+        bits: 64,
+        variant_of: None,
+        runs: &[],
+        qemu: "riscv64",
+    },
+];
+
+/// An entry of the built-in architecture database
+#[derive(Debug, PartialEq, Eq)]
+pub struct ArchitectureInfo {
+    /// The canonical name of the architecture
+    pub name: &'static str,
+    /// Other names of the architecture, e.g. the ones reported by `uname` on some systems
+    pub aliases: &'static [&'static str],
+    /// The family of related instruction sets the architecture belongs to
+    pub family: &'static str,
+    /// The width of pointers in bits
+    pub bits: u8,
+    /// The baseline architecture if this is a variant (microarchitecture level) of it
+    pub variant_of: Option<&'static str>,
+    /// The architectures whose binaries run natively on this one,
+    /// the ones they can run in turn are implied
+    pub runs: &'static [&'static str],
+    /// The name `qemu-user` uses for the architecture (`qemu-<name>-static`)
+    pub qemu: &'static str,
+}
+
 /// An architecture description containing a main architecture and subarchitectures
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct Architecture {
@@ -30,6 +117,60 @@ pub fn is_subset<T: PartialEq>(subset: &[T], of: &[T]) -> bool {
     true
 }
 
+impl ArchitectureInfo {
+    /// Returns all known architectures
+    pub fn all() -> &'static [ArchitectureInfo] {
+        ARCHITECTURES
+    }
+
+    /// Returns the architecture known as `name` (its canonical name or an alias)
+    /// # Arguments
+    /// * `name` - The name to look up
+    pub fn get(name: &str) -> Option<&'static ArchitectureInfo> {
+        ARCHITECTURES
+            .iter()
+            .find(|a| a.name == name || a.aliases.contains(&name))
+    }
+
+    /// Returns the canonical name of the architecture known as `name`,
+    /// `name` itself if it is unknown
+    /// # Arguments
+    /// * `name` - The name to canonicalize
+    pub fn canonical_name(name: &str) -> &str {
+        Self::get(name).map_or(name, |a| a.name)
+    }
+
+    /// Returns whether binaries of the architecture known as `name` run natively on this one,
+    /// following the compatibility edges ([ArchitectureInfo::runs]) transitively
+    /// # Arguments
+    /// * `name` - The name of the other architecture
+    pub fn can_run(&self, name: &str) -> bool {
+        let name = Self::canonical_name(name);
+
+        name == self.name
+            || self
+                .runs
+                .iter()
+                .filter_map(|r| Self::get(r))
+                .any(|r| r.can_run(name))
+    }
+
+    /// Returns the names of all architectures whose binaries run natively
+    /// on this one, starting with this one
+    pub fn get_runnable(&self) -> Vec<&'static str> {
+        ARCHITECTURES
+            .iter()
+            .filter(|a| self.can_run(a.name))
+            .map(|a| a.name)
+            .collect()
+    }
+
+    /// Returns the baseline architecture of this variant, `self` if it is no variant
+    pub fn get_baseline(&self) -> &ArchitectureInfo {
+        self.variant_of.and_then(Self::get).unwrap_or(self)
+    }
+}
+
 impl Architecture {
     /// Creates a new `Architecture` from the two arguments
     /// # Arguments
@@ -49,19 +190,65 @@ impl Architecture {
         }
     }
 
-    /// Creates a new architecture by detecting it using the `uname` crete
+    /// Creates a new architecture by detecting it using the `uname` crete,
+    /// using the canonical name if the architecture is known
     pub fn new_uname() -> Result<Self, Error> {
         let info = uname::uname().e_context(|| "Determining host architecture".to_string())?;
 
-        Ok(Self::new(info.machine, Vec::new()))
+        Ok(Self::new(
+            ArchitectureInfo::canonical_name(&info.machine).to_owned(),
+            Vec::new(),
+        ))
+    }
+
+    /// Parses an architecture in the form `<ARCH>[+<SUBARCH>...]` (e.g. `x86_64_v3+aes`),
+    /// the main architecture has to be known and gets canonicalized (`arm64` => `aarch64`)
+    /// # Arguments
+    /// * `s` - The string to parse
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let mut parts = s.split('+');
+        let arch = parts.next().unwrap_or_default();
+        let subarchs: Vec<String> = parts.map(|p| p.to_owned()).collect();
+
+        let valid_name = |name: &str| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        if !valid_name(arch) || !subarchs.iter().all(|s| valid_name(s)) {
+            return Err(Error::new(ErrorType::Architecture(
+                ArchitectureError::InvalidName(s.to_owned()),
+            )));
+        }
+
+        let info = ArchitectureInfo::get(arch).ok_or_else(|| {
+            Error::new(ErrorType::Architecture(ArchitectureError::Unknown(
+                arch.to_owned(),
+            )))
+        })?;
+
+        Ok(Self::new(info.name.to_owned(), subarchs))
+    }
+
+    /// Returns the entry of the main architecture in the architecture database, if it is known
+    pub fn info(&self) -> Option<&'static ArchitectureInfo> {
+        ArchitectureInfo::get(&self.arch)
     }
 
     /// Checks if this architecture can run on `on`.
     ///
-    /// This will check if `self` is a subset of `on`
+    /// This will check if the main architecture of `self` runs natively on the one
+    /// of `on` (refer to [ArchitectureInfo::can_run()]), unknown main architectures
+    /// have to match by name. The subarchitectures of `self` have to be a subset of
+    /// the ones of `on`
     pub fn can_run_on(&self, on: &Architecture) -> bool {
-        // If the main architectures don't match, we can't run
-        if self.arch != on.arch {
+        let arch = ArchitectureInfo::canonical_name(&self.arch);
+
+        let runs = match on.info() {
+            Some(info) => info.can_run(arch),
+            None => arch == on.arch,
+        };
+
+        // If the main architectures aren't compatible, we can't run
+        if !runs {
             return false;
         }
 
@@ -70,14 +257,9 @@ impl Architecture {
 
     /// Checks if this architecture supports hosting `other`.
     ///
-    /// This will check if `other` is a subset of `self`
+    /// This is the reverse of [Architecture::can_run_on()]
     pub fn can_host(&self, other: &Architecture) -> bool {
-        // If the main architectures don't match, we can't run
-        if self.arch != other.arch {
-            return false;
-        }
-
-        is_subset(&other.subarchs, &self.subarchs)
+        other.can_run_on(self)
     }
 }
 
@@ -98,7 +280,25 @@ where
 
 impl Display for Architecture {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}", self.arch, self.subarchs.join("+"))
+        write!(f, "{}", self.arch)?;
+        for subarch in &self.subarchs {
+            write!(f, "+{subarch}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Architecture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).map_err(|e| e.oneline())
+    }
+}
+
+impl Display for ArchitectureInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
     }
 }
 
@@ -2,7 +2,10 @@
 
 use super::fs::file_open;
 use crate::error::{Error, ErrorExt, Throwable};
-use std::{io::Read, path::Path};
+use std::{
+    io::{Read, Seek},
+    path::Path,
+};
 
 /// Tries to determine the archive type and use the according function to extract it
 /// # Arguments
@@ -35,6 +38,29 @@ pub fn extract_infer(src: &Path, dest: &Path) -> Result<(), Error> {
     }
 }
 
+/// Opens a `tar` archive to read its entries, decompressing it if it is
+/// compressed using `xz` or `gzip` (determined by its contents)
+/// # Arguments
+/// * `src` - The path to the archive file
+pub fn open_tar_infer(src: &Path) -> Result<tar::Archive<Box<dyn Read>>, Error> {
+    let context = || format!("Opening archive '{}'", src.to_string_lossy());
+
+    let mut file = file_open(src).e_context(context)?;
+    let mut buf = [0u8; 6];
+    let len = file.read(&mut buf).e_context(context)?;
+    file.rewind().e_context(context)?;
+
+    let reader: Box<dyn Read> = if infer::archive::is_xz(&buf[..len]) {
+        Box::new(xz::read::XzDecoder::new(file))
+    } else if infer::archive::is_gz(&buf[..len]) {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    Ok(tar::Archive::new(reader))
+}
+
 /// Extracts a `tar` `xz` archive
 /// # Arguments
 /// * `src` - The path to the source archive file
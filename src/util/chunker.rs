@@ -0,0 +1,149 @@
+//! Content-defined chunking of streams using FastCDC
+use std::io::{self, Read};
+
+/// The gear table for the rolling hash, generated using `splitmix64`
+static GEAR: [u64; 256] = gear_table();
+
+/// Generates the gear table for the rolling hash
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+
+    table
+}
+
+/// Returns a mask with the `bits` most significant bits set
+/// # Arguments
+/// * `bits` - The number of bits to set
+const fn high_mask(bits: u32) -> u64 {
+    !0u64 << (64 - bits)
+}
+
+/// Splits streams into content-defined chunks using the FastCDC algorithm.
+///
+/// Chunk boundaries depend on the content only, so inserting or
+/// removing data only changes the chunks around the modification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunker {
+    /// The minimum size of a chunk
+    pub min_size: usize,
+    /// The size chunks are normalized to
+    pub avg_size: usize,
+    /// The maximum size of a chunk
+    pub max_size: usize,
+}
+
+impl Default for Chunker {
+    /// Chunks of 16 KiB to 256 KiB, averaging at 64 KiB
+    fn default() -> Self {
+        Self {
+            min_size: 16 * 1024,
+            avg_size: 64 * 1024,
+            max_size: 256 * 1024,
+        }
+    }
+}
+
+impl Chunker {
+    /// Finds the length of the first chunk in `data`
+    /// # Arguments
+    /// * `data` - The data to find the chunk boundary in
+    pub fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+
+        // Normalized chunking: Cut points are harder to hit before the average size
+        let bits = self.avg_size.max(2).ilog2();
+        let mask_small = high_mask(bits + 1);
+        let mask_large = high_mask(bits - 1);
+
+        let max = data.len().min(self.max_size);
+        let normal = max.min(self.avg_size);
+
+        let mut hash = 0u64;
+        let mut i = self.min_size;
+
+        while i < normal {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & mask_small == 0 {
+                return i + 1;
+            }
+            i += 1;
+        }
+
+        while i < max {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & mask_large == 0 {
+                return i + 1;
+            }
+            i += 1;
+        }
+
+        max
+    }
+
+    /// Returns an iterator over the chunks of `input`
+    /// # Arguments
+    /// * `input` - The stream to split into chunks
+    pub fn chunks<R: Read>(&self, input: R) -> Chunks<R> {
+        Chunks {
+            chunker: *self,
+            input,
+            buf: Vec::with_capacity(self.max_size),
+            eof: false,
+        }
+    }
+}
+
+/// An iterator over the chunks of a stream, see [Chunker::chunks()]
+pub struct Chunks<R: Read> {
+    chunker: Chunker,
+    input: R,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Iterator for Chunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Fill the buffer up to the maximum chunk size
+        while !self.eof && self.buf.len() < self.chunker.max_size {
+            let len = self.buf.len();
+            self.buf.resize(self.chunker.max_size, 0);
+
+            match self.input.read(&mut self.buf[len..]) {
+                Ok(0) => {
+                    self.buf.truncate(len);
+                    self.eof = true;
+                }
+                Ok(n) => self.buf.truncate(len + n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => self.buf.truncate(len),
+                Err(e) => {
+                    self.buf.truncate(len);
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let cut = self.chunker.cut(&self.buf);
+        let rest = self.buf.split_off(cut);
+
+        Some(Ok(std::mem::replace(&mut self.buf, rest)))
+    }
+}
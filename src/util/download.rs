@@ -1,19 +1,29 @@
 //! Utilities for downloading files
 use http::StatusCode;
-use log::info;
-use std::fs::File;
+use log::{debug, info, warn};
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::path::Path;
 use std::time::Duration;
 
 use curl::easy::Easy;
 
+/// The number of attempts for operations failing with transient errors, refer to [with_retries()]
+pub const RETRY_ATTEMPTS: usize = 3;
+
+/// The delay before the first retry, doubled for every further retry
+pub const RETRY_DELAY: Duration = Duration::from_secs(1);
+
 use crate::error::support::CURLError;
 use crate::error::Error;
 use crate::error::ErrorExt;
 use crate::error::ErrorType;
 use crate::error::Throwable;
 
+mod manager;
+pub use manager::*;
+
 /// Downloads the contents of the supplied url to the supplied file
 /// # Arguments
 /// * `url` - The URL to fetch from
@@ -29,17 +39,284 @@ pub fn download_to_file(
     file: &Path,
     message: &str,
     expect_success: bool,
+) -> Result<StatusCode, Error> {
+    download_to_file_with_progress(url, file, message, expect_success, &mut |_| {})
+}
+
+/// Downloads the contents of the supplied url to the supplied file (see [download_to_file()]),
+/// reporting the number of bytes received after every chunk of data
+/// # Arguments
+/// * `url` - The URL to fetch from
+/// * `file` - The file to download to
+/// * `message` - The message to log when downloading
+/// * `expect_success` - If this function should return an error if a non-ok status code is encountered
+/// * `callback` - The callback to report the received bytes to
+pub fn download_to_file_with_progress(
+    url: &str,
+    file: &Path,
+    message: &str,
+    expect_success: bool,
+    callback: &mut dyn FnMut(usize),
 ) -> Result<StatusCode, Error> {
     let context = || format!("Downloading {} to {}", url, file.to_string_lossy());
 
-    let mut file = File::create(file).e_context(context)?;
+    with_retries(message, || {
+        let mut file = File::create(file).e_context(context)?;
 
-    download(url, message, expect_success, move |data| {
-        file.write_all(data).is_ok()
+        download(url, message, expect_success, |data| {
+            callback(data.len());
+            file.write_all(data).is_ok()
+        })
     })
     .e_context(context)
 }
 
+/// Runs `operation` up to [RETRY_ATTEMPTS] times for as long as it fails with
+/// a [transient](Error::is_transient()) error, waiting [RETRY_DELAY] before the
+/// first retry and doubling the delay for every further one
+/// # Arguments
+/// * `message` - The description of the operation to log when retrying
+/// * `operation` - The operation to run
+/// # Errors
+/// The first permanent error or the last transient one
+pub fn with_retries<T, F: FnMut() -> Result<T, Error>>(
+    message: &str,
+    mut operation: F,
+) -> Result<T, Error> {
+    let mut delay = RETRY_DELAY;
+
+    for attempt in 1.. {
+        match operation() {
+            Err(e) if e.is_transient() && attempt < RETRY_ATTEMPTS => {
+                warn!(
+                    "{message} failed ({}), retrying in {}s (attempt {}/{RETRY_ATTEMPTS})",
+                    e.oneline(),
+                    delay.as_secs(),
+                    attempt + 1
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            res => return res,
+        }
+    }
+
+    unreachable!("The attempts are unbounded")
+}
+
+/// Downloads the contents of the first of `urls` that succeeds to the supplied file.
+///
+/// The URLs are tried in order, a URL fails if the download errors or responds
+/// with a non-success status. `file` is truncated before every attempt
+/// # Arguments
+/// * `urls` - The URL and its mirrors to fetch from
+/// * `file` - The file to download to
+/// * `message` - The message to log when downloading
+/// # Returns
+/// The response status and the URL that succeeded
+/// # Errors
+/// The error of the last URL if all of them failed
+pub fn download_to_file_mirrored(
+    urls: &[String],
+    file: &Path,
+    message: &str,
+) -> Result<(StatusCode, String), Error> {
+    download_to_file_mirrored_with_progress(urls, file, message, &mut |_| {})
+}
+
+/// Downloads the contents of the first of `urls` that succeeds to the supplied file
+/// (see [download_to_file_mirrored()]), reporting the number of bytes received after
+/// every chunk of data
+/// # Arguments
+/// * `urls` - The URL and its mirrors to fetch from
+/// * `file` - The file to download to
+/// * `message` - The message to log when downloading
+/// * `callback` - The callback to report the received bytes to
+pub fn download_to_file_mirrored_with_progress(
+    urls: &[String],
+    file: &Path,
+    message: &str,
+    callback: &mut dyn FnMut(usize),
+) -> Result<(StatusCode, String), Error> {
+    let mut last_error = None;
+
+    for (i, url) in urls.iter().enumerate() {
+        if i > 0 {
+            info!("Trying mirror {url} ({i}/{})", urls.len() - 1);
+        }
+
+        match download_to_file_with_progress(url, file, message, true, callback) {
+            Ok(status) => return Ok((status, url.to_owned())),
+            Err(e) => {
+                warn!("Downloading {url} failed: {}", e.oneline());
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        Error::new(ErrorType::Other(format!(
+            "There is no URL to download {} from",
+            file.to_string_lossy()
+        )))
+    }))
+}
+
+/// Downloads the contents of the supplied url to the supplied file,
+/// resuming from the data that is already present in `file`.
+///
+/// If the server does not honor the range request, the file gets truncated
+/// and the download starts from the beginning. Bodies of responses with
+/// a status other than `200` or `206` are not written to `file`.
+///
+/// Interrupted transfers and responses with a transient status (e.g. `503`)
+/// are resumed using [with_retries()]
+/// # Arguments
+/// * `url` - The URL to fetch from
+/// * `file` - The file to download to or resume the download in
+/// * `message` - The message to log when downloading
+/// # Returns
+/// The response status, `416` if the file was already complete
+/// # Errors
+/// - If an unknown HTTP response status is received
+/// - Any CURL error
+pub fn download_to_file_resume(url: &str, file: &Path, message: &str) -> Result<StatusCode, Error> {
+    download_to_file_resume_with_progress(url, file, message, &mut |_| {})
+}
+
+/// Downloads the contents of the supplied url to the supplied file, resuming from the data
+/// that is already present in `file` (see [download_to_file_resume()]), reporting the
+/// number of bytes received after every chunk of data
+/// # Arguments
+/// * `url` - The URL to fetch from
+/// * `file` - The file to download to or resume the download in
+/// * `message` - The message to log when downloading
+/// * `callback` - The callback to report the received bytes to
+pub fn download_to_file_resume_with_progress(
+    url: &str,
+    file: &Path,
+    message: &str,
+    callback: &mut dyn FnMut(usize),
+) -> Result<StatusCode, Error> {
+    let res = with_retries(message, || {
+        let status = download_to_file_resume_once(url, file, message, callback)?;
+
+        match CURLError::ErrorStatus(status) {
+            e if e.is_transient() => Err(Error::new(ErrorType::CURL(e))),
+            _ => Ok(status),
+        }
+    });
+
+    // Hand out the status of the last attempt, like for permanent error responses
+    match res {
+        Err(Error {
+            error: ErrorType::CURL(CURLError::ErrorStatus(status)),
+            ..
+        }) => Ok(status),
+        res => res,
+    }
+}
+
+/// Performs a single attempt of [download_to_file_resume()]
+/// # Arguments
+/// * `url` - The URL to fetch from
+/// * `file` - The file to download to or resume the download in
+/// * `message` - The message to log when downloading
+/// * `callback` - The callback to report the received bytes to
+fn download_to_file_resume_once(
+    url: &str,
+    file: &Path,
+    message: &str,
+    callback: &mut dyn FnMut(usize),
+) -> Result<StatusCode, Error> {
+    let context = || format!("Downloading {} to {}", url, file.to_string_lossy());
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)
+        .e_context(context)?;
+    let offset = file.metadata().e_context(context)?.len();
+
+    //Create the curl context and set the url
+    let mut easy = Easy::new();
+    easy.url(url).e_context(context)?;
+
+    //Allow CURL to follow redirections
+    easy.follow_location(true).e_context(context)?;
+
+    //Setup the low speed bounds (less that 1000bytes in 30 seconds)
+    easy.low_speed_limit(1000).e_context(context)?;
+    easy.low_speed_time(Duration::from_secs(30))
+        .e_context(context)?;
+
+    if offset > 0 {
+        debug!("Resuming download of {} at byte {}", url, offset);
+        easy.resume_from(offset).e_context(context)?;
+    }
+
+    //The status of the latest response and whether the file has been truncated
+    let status: Cell<u32> = Cell::new(0);
+    let truncated = Cell::new(false);
+
+    let transfer_res = {
+        let mut transfer = easy.transfer();
+        transfer
+            .header_function(|header| {
+                if let Some(code) = std::str::from_utf8(header)
+                    .ok()
+                    .filter(|h| h.starts_with("HTTP/"))
+                    .and_then(|h| h.split_whitespace().nth(1))
+                    .and_then(|c| c.parse().ok())
+                {
+                    status.set(code);
+                }
+                true
+            })
+            .e_context(context)?;
+        transfer
+            .write_function(|data| {
+                callback(data.len());
+
+                match status.get() {
+                    206 => {}
+                    // The server sends the whole file, start over
+                    200 if offset > 0 && !truncated.get() => {
+                        if file.set_len(0).is_err() {
+                            return Ok(0);
+                        }
+                        truncated.set(true);
+                    }
+                    200 => {}
+                    _ => return Ok(data.len()),
+                }
+
+                match file.write_all(data) {
+                    Ok(_) => Ok(data.len()),
+                    Err(_) => Ok(0),
+                }
+            })
+            .e_context(context)?;
+
+        info!("{}", message);
+
+        //Perform now
+        transfer.perform()
+    };
+
+    match transfer_res {
+        Ok(_) => {
+            let code = easy.response_code().e_context(context)?;
+
+            match StatusCode::from_u16(code as u16) {
+                Ok(status) => Ok(status),
+                Err(_) => Err(Error::new(ErrorType::CURL(CURLError::InvalidStatus(code)))),
+            }
+        }
+        Err(e) => Err(e.throw(message.to_owned())),
+    }
+}
+
 /// Downloads the contents of the supplied url
 /// # Arguments
 /// * `url` - The URL to fetch from
@@ -57,7 +334,7 @@ pub fn download<'data, F>(
     mut write_function: F,
 ) -> Result<StatusCode, Error>
 where
-    F: FnMut(&[u8]) -> bool + Send + 'data,
+    F: FnMut(&[u8]) -> bool + 'data,
 {
     let context = || message.to_owned();
 
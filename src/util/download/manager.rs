@@ -0,0 +1,172 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{error::Error, util::progress::ProgressBar};
+
+/// The number of downloads to run concurrently if not configured otherwise
+pub const DEFAULT_PARALLEL_DOWNLOADS: usize = 4;
+
+/// Runs multiple downloads concurrently, sharing a bandwidth limit
+/// and reporting their combined progress in a single progress bar
+#[derive(Debug, Clone)]
+pub struct DownloadManager {
+    /// The maximum number of concurrent downloads
+    parallel: usize,
+    /// The maximum number of bytes per second all downloads may receive together
+    bandwidth_limit: Option<u64>,
+}
+
+/// The combined progress of the downloads of a [DownloadManager]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// The number of finished downloads
+    pub downloads_done: usize,
+    /// The number of downloads
+    pub downloads_total: usize,
+    /// The number of bytes received by all downloads so far
+    pub bytes_received: u64,
+}
+
+/// Delays the receiving threads to keep the combined rate below a limit
+struct BandwidthLimiter {
+    /// The maximum number of bytes per second
+    rate: u64,
+    /// The time the limiter has been created at
+    start: Instant,
+    /// The number of bytes received since `start`
+    received: Mutex<u64>,
+}
+
+impl DownloadManager {
+    /// Creates a new download manager
+    /// # Arguments
+    /// * `parallel` - The maximum number of concurrent downloads, at least `1`
+    /// * `bandwidth_limit` - The maximum number of bytes per second all downloads may receive together
+    pub fn new(parallel: usize, bandwidth_limit: Option<u64>) -> Self {
+        Self {
+            parallel: parallel.max(1),
+            bandwidth_limit: bandwidth_limit.filter(|l| *l > 0),
+        }
+    }
+
+    /// Runs `download` for every job using up to `parallel` threads, in the order of `jobs`.
+    ///
+    /// `download` gets called with the job and a callback to report the number of bytes
+    /// received after every chunk of data, which delays the calling thread if the
+    /// bandwidth limit is exceeded. Once a download fails, no further downloads are started
+    /// # Arguments
+    /// * `label` - The label of the progress bar
+    /// * `jobs` - The jobs to run
+    /// * `download` - The function downloading a job
+    /// # Returns
+    /// The results of `download`, in the order of `jobs`
+    /// # Errors
+    /// The error of the first download that failed
+    pub fn run<J, T, F>(&self, label: &str, jobs: &[J], download: F) -> Result<Vec<T>, Error>
+    where
+        J: Sync,
+        T: Send,
+        F: Fn(&J, &mut dyn FnMut(usize)) -> Result<T, Error> + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new((0..jobs.len()).map(|_| None).collect::<Vec<Option<T>>>());
+        let limiter = self.bandwidth_limit.map(BandwidthLimiter::new);
+        let progress = Mutex::new((
+            ProgressBar::new(label),
+            DownloadProgress {
+                downloads_total: jobs.len(),
+                ..Default::default()
+            },
+        ));
+
+        let worker = || -> Result<(), Error> {
+            while !failed.load(Ordering::Relaxed) {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(i) else {
+                    break;
+                };
+
+                let mut callback = |bytes: usize| {
+                    if let Some(limiter) = &limiter {
+                        limiter.consume(bytes as u64);
+                    }
+
+                    let (bar, progress) = &mut *progress.lock().expect("Lock download progress");
+                    progress.bytes_received += bytes as u64;
+                    bar.update_download(progress);
+                };
+
+                let result = download(job, &mut callback).inspect_err(|_| {
+                    failed.store(true, Ordering::Relaxed);
+                })?;
+                results.lock().expect("Lock download results")[i] = Some(result);
+
+                let (bar, progress) = &mut *progress.lock().expect("Lock download progress");
+                progress.downloads_done += 1;
+                bar.update_download(progress);
+            }
+
+            Ok(())
+        };
+
+        let threads = self.parallel.min(jobs.len());
+        let res = thread::scope(|s| {
+            let handles: Vec<_> = (0..threads).map(|_| s.spawn(worker)).collect();
+
+            handles
+                .into_iter()
+                .try_for_each(|h| h.join().expect("Join download thread"))
+        });
+        progress.lock().expect("Lock download progress").0.finish();
+        res?;
+
+        Ok(results
+            .into_inner()
+            .expect("Unlock download results")
+            .into_iter()
+            .map(|result| result.expect("Every job has been downloaded"))
+            .collect())
+    }
+}
+
+impl Default for DownloadManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_PARALLEL_DOWNLOADS, None)
+    }
+}
+
+impl BandwidthLimiter {
+    /// Creates a new limiter
+    /// # Arguments
+    /// * `rate` - The maximum number of bytes per second
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            start: Instant::now(),
+            received: Mutex::new(0),
+        }
+    }
+
+    /// Records `bytes` as received and sleeps until receiving them complies with the rate
+    /// # Arguments
+    /// * `bytes` - The number of bytes received
+    fn consume(&self, bytes: u64) {
+        let due = {
+            let mut received = self.received.lock().expect("Lock received bytes");
+            *received += bytes;
+            Duration::from_secs_f64(*received as f64 / self.rate as f64)
+        };
+
+        let delay = due.saturating_sub(self.start.elapsed());
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+}
@@ -15,6 +15,15 @@ pub use fsentry::*;
 mod pathutil;
 pub use pathutil::*;
 
+mod mounts;
+pub use mounts::*;
+
+mod lock;
+pub use lock::*;
+
+mod device;
+pub use device::*;
+
 use crate::error::{Error, ErrorExt};
 use log::trace;
 use std::{
@@ -141,6 +150,37 @@ pub fn remove_dir_all(path: &Path) -> Result<(), Error> {
         .e_context(|| format!("Removing empty directory '{}'", path.to_string_lossy()))
 }
 
+/// Returns the total size of the files under `path` in bytes, without following symlinks.
+/// A missing `path` has a size of `0`
+/// # Arguments
+/// * `path` - The directory to calculate the size of
+pub fn dir_size(path: &Path) -> Result<u64, Error> {
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    let mut size = 0;
+    let mut error = None;
+    walk_dir(path, true, &mut |entry| match entry.metadata() {
+        Ok(metadata) => {
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+            true
+        }
+        Err(e) => {
+            error = Some(e);
+            false
+        }
+    })
+    .e_context(|| format!("Walking {}", path.str_lossy()))?;
+
+    match error {
+        Some(e) => Err(e).e_context(|| format!("Calculating size of {}", path.str_lossy())),
+        None => Ok(size),
+    }
+}
+
 /// Opens a file using the [std::fs::File::open()] function
 /// # Arguments
 /// * `path` - The path to the file to open
@@ -0,0 +1,142 @@
+use std::{fmt::Display, io, path::Path};
+
+use log::trace;
+use nix::sys::stat::{makedev, mknod, Mode, SFlag};
+
+use crate::error::{Error, ErrorExt, ErrorType};
+
+use super::PathUtil;
+
+/// The capability needed for creating device nodes (`CAP_MKNOD`)
+const CAP_MKNOD: u32 = 27;
+
+/// The type of a device node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    /// A character device (`c`), e.g. `/dev/null`
+    Character,
+    /// A block device (`b`), e.g. `/dev/sda`
+    Block,
+}
+
+impl DeviceKind {
+    /// Returns the kind of device node for the file type bits of a UNIX mode, if any
+    /// # Arguments
+    /// * `mode` - The UNIX mode including the file type bits (`st_mode`)
+    pub fn from_mode(mode: u32) -> Option<Self> {
+        match SFlag::from_bits_truncate(mode & SFlag::S_IFMT.bits()) {
+            SFlag::S_IFCHR => Some(Self::Character),
+            SFlag::S_IFBLK => Some(Self::Block),
+            _ => None,
+        }
+    }
+
+    /// Returns the `mknod` file type flag for this kind of device
+    fn sflag(&self) -> SFlag {
+        match self {
+            Self::Character => SFlag::S_IFCHR,
+            Self::Block => SFlag::S_IFBLK,
+        }
+    }
+}
+
+impl Display for DeviceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Character => write!(f, "c"),
+            Self::Block => write!(f, "b"),
+        }
+    }
+}
+
+/// Returns whether this process has the capability to create device nodes (`CAP_MKNOD`)
+/// by inspecting the effective capabilities in `/proc/self/status`
+pub fn can_create_device_nodes() -> bool {
+    let status = match std::fs::read_to_string("/proc/self/status") {
+        Ok(status) => status,
+        Err(_) => return false,
+    };
+
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .map(|caps| caps & (1 << CAP_MKNOD) != 0)
+        .unwrap_or(false)
+}
+
+/// Creates a device node, replacing an existing file at `path`
+///
+/// Uses the `mknod(2)` system call
+/// # Arguments
+/// * `path` - The path to create the device node at
+/// * `kind` - The kind of device
+/// * `major` - The major device number
+/// * `minor` - The minor device number
+/// * `mode` - The permission bits of the device node
+/// # Errors
+/// If this process lacks the `CAP_MKNOD` capability (see [can_create_device_nodes()])
+pub fn create_device_node(
+    path: &Path,
+    kind: DeviceKind,
+    major: u32,
+    minor: u32,
+    mode: u32,
+) -> Result<(), Error> {
+    let context = || {
+        format!(
+            "Creating device node '{}' ({kind} {major}:{minor})",
+            path.str_lossy()
+        )
+    };
+    trace!("{}", context());
+
+    if !can_create_device_nodes() {
+        return Err(Error::new_context(
+            ErrorType::Other(
+                "Creating device nodes requires the CAP_MKNOD capability, run as root".to_owned(),
+            ),
+            context(),
+        ));
+    }
+
+    remove_existing(path).ctx(context)?;
+
+    mknod(
+        path,
+        kind.sflag(),
+        Mode::from_bits_truncate(mode),
+        makedev(major.into(), minor.into()),
+    )
+    .map_err(io::Error::from)
+    .e_context(context)
+}
+
+/// Creates a FIFO (named pipe), replacing an existing file at `path`
+///
+/// Uses the `mkfifo(3)` function
+/// # Arguments
+/// * `path` - The path to create the FIFO at
+/// * `mode` - The permission bits of the FIFO
+pub fn create_fifo(path: &Path, mode: u32) -> Result<(), Error> {
+    let context = || format!("Creating FIFO '{}'", path.str_lossy());
+    trace!("{}", context());
+
+    remove_existing(path).ctx(context)?;
+
+    nix::unistd::mkfifo(path, Mode::from_bits_truncate(mode))
+        .map_err(io::Error::from)
+        .e_context(context)
+}
+
+/// Removes the file at `path` if there is one
+/// # Arguments
+/// * `path` - The path to clear
+fn remove_existing(path: &Path) -> Result<(), Error> {
+    if path.symlink_metadata().is_ok() {
+        std::fs::remove_file(path)
+            .e_context(|| format!("Removing existing file {}", path.str_lossy()))?;
+    }
+
+    Ok(())
+}
@@ -62,7 +62,13 @@ impl FSEntry {
             trace!("[infer] DIR : {}", path.to_string_lossy());
             Ok(Self::Directory(Directory::new(name)))
         } else {
-            if let Ok(mut file) = File::open(&path) {
+            // Only inspect regular files, opening a FIFO would block
+            let file = match path.is_file() {
+                true => File::open(&path).ok(),
+                false => None,
+            };
+
+            if let Some(mut file) = file {
                 let mut buf = vec![0; 53];
                 if file.read(&mut buf).is_ok() {
                     if infer::app::is_elf(&buf) {
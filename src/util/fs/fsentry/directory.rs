@@ -1,6 +1,48 @@
 use super::{FSEntry, SearchType};
-use crate::error::{Error, ErrorExt};
-use std::{collections::LinkedList, ffi::OsString, path::Path};
+use crate::{
+    error::{Error, ErrorExt},
+    util::string::glob_match,
+};
+use log::{trace, warn};
+use std::{
+    collections::LinkedList,
+    ffi::OsString,
+    os::unix::fs::MetadataExt,
+    path::{Component, Path, PathBuf},
+};
+
+/// Options to control the indexing of a directory using [Directory::index_with()]
+#[derive(Clone, Debug, Default)]
+pub struct IndexOptions {
+    /// The maximum depth of subdirectories to descend into, `Some(0)`
+    /// only indexes the direct children of the indexed directory
+    pub max_depth: Option<usize>,
+    /// Glob patterns (see [glob_match()]) of entries to skip. Patterns containing a `/`
+    /// match the path relative to the indexed directory, all others the name of the entry
+    pub exclude: Vec<String>,
+    /// Whether to descend into symlinks pointing to directories, loops are detected and skipped
+    pub follow_symlinks: bool,
+    /// Whether to unwind symlinks to their real destination when inferring the entry type
+    pub unwind_symlinks: bool,
+}
+
+impl IndexOptions {
+    /// Returns whether the entry at `path` (relative to the indexed directory) is excluded
+    /// # Arguments
+    /// * `path` - The relative path of the entry to check
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let path_str = path.to_string_lossy();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        self.exclude.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_match(pattern.trim_start_matches('/'), &path_str)
+            } else {
+                glob_match(pattern, &name)
+            }
+        })
+    }
+}
 
 /// Represents a directory in a filesystem index
 #[derive(Clone)]
@@ -31,32 +73,98 @@ impl Directory {
     /// - Permission is denied
     /// - The `path` is not a directory
     pub fn index(path: &Path, recursive: bool, do_unwind_symlinks: bool) -> Result<Self, Error> {
+        let options = IndexOptions {
+            max_depth: if recursive { None } else { Some(0) },
+            unwind_symlinks: do_unwind_symlinks,
+            ..Default::default()
+        };
+
+        Self::index_with(path, &options)
+    }
+
+    /// Creates a new `Directory` and indexes the contents of `path` into it, respecting `options`
+    /// # Arguments
+    /// * `path` - The path to walk
+    /// * `options` - The options to control which entries get indexed
+    /// # Errors
+    /// Uses the std::fs::read_dir() function which will error on:
+    /// - The `path` does not exist
+    /// - Permission is denied
+    /// - The `path` is not a directory
+    pub fn index_with(path: &Path, options: &IndexOptions) -> Result<Self, Error> {
+        let mut ancestors = Vec::new();
+        Self::index_inner(path, Path::new(""), 0, options, &mut ancestors)
+    }
+
+    /// Indexes `path` recursively
+    /// # Arguments
+    /// * `path` - The path to walk
+    /// * `rel_path` - The path of `path` relative to the indexed directory
+    /// * `depth` - The depth of `path` below the indexed directory
+    /// * `options` - The options to control which entries get indexed
+    /// * `ancestors` - The device and inode numbers of the directories above `path` to detect loops
+    fn index_inner(
+        path: &Path,
+        rel_path: &Path,
+        depth: usize,
+        options: &IndexOptions,
+        ancestors: &mut Vec<(u64, u64)>,
+    ) -> Result<Self, Error> {
         let mut index = Self {
             name: path.file_name().unwrap_or_default().to_owned(),
             children: Vec::new(),
         };
 
+        let metadata = std::fs::metadata(path)
+            .e_context(|| format!("Reading metadata of {}", path.to_string_lossy()))?;
+        ancestors.push((metadata.dev(), metadata.ino()));
+
+        let may_descend = options.max_depth.is_none_or(|max| depth < max);
+
         for entry in std::fs::read_dir(path)
             .e_context(|| format!("Reading directory contents of {}", &path.to_string_lossy()))?
         {
             let entry =
                 entry.e_context(|| format!("Reading entry of {}", path.to_string_lossy()))?;
             let path = entry.path();
+            let rel_path: PathBuf = rel_path.join(entry.file_name());
+
+            if options.is_excluded(&rel_path) {
+                trace!("Excluding {}", rel_path.to_string_lossy());
+                continue;
+            }
+
+            // Do only walk a symlinked subdirectory if requested
+            let descend =
+                may_descend && path.is_dir() && (!path.is_symlink() || options.follow_symlinks);
 
-            // Do only walk a subdirectory if it is not a symlink
-            if !path.is_symlink() && path.is_dir() && recursive {
+            let descend = descend && {
+                let metadata = std::fs::metadata(&path)
+                    .e_context(|| format!("Reading metadata of {}", path.to_string_lossy()))?;
+
+                let is_loop = ancestors.contains(&(metadata.dev(), metadata.ino()));
+                if is_loop {
+                    warn!("Not following symlink loop at {}", path.to_string_lossy());
+                }
+
+                !is_loop
+            };
+
+            if descend {
                 index.children.push(FSEntry::Directory(
-                    Directory::index(&path, recursive, do_unwind_symlinks)
+                    Self::index_inner(&path, &rel_path, depth + 1, options, ancestors)
                         .e_context(|| format!("Indexing {}", &path.to_string_lossy()))?,
                 ));
             } else {
                 index.children.push(
-                    FSEntry::infer(&path, do_unwind_symlinks)
+                    FSEntry::infer(&path, options.unwind_symlinks)
                         .e_context(|| format!("Inferring type of {}", &path.to_string_lossy()))?,
                 );
             }
         }
 
+        ancestors.pop();
+
         Ok(index)
     }
 
@@ -89,6 +197,35 @@ impl Directory {
         None
     }
 
+    /// Returns the entry at `path` relative to this directory without searching
+    /// # Arguments
+    /// * `path` - The path of the entry to get, leading `/` are ignored
+    pub fn get_entry(&self, path: &Path) -> Option<&FSEntry> {
+        let mut components = path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .peekable();
+
+        let mut dir = self;
+        while let Some(component) = components.next() {
+            let child = dir
+                .children
+                .iter()
+                .find(|child| child.name() == component.as_os_str())?;
+
+            if components.peek().is_none() {
+                return Some(child);
+            }
+
+            match child {
+                FSEntry::Directory(child) => dir = child,
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
     /// Returns the amount of children in this directory
     /// # Arguments
     /// * `recursive` - If this function should include the count of entries of subdirectories
@@ -111,7 +248,7 @@ impl Directory {
     /// * `stack` - A mutable linked list to store the path to the current file, should be empty on begin
     /// * `recursive` - If this function should operate recursively
     /// * `callback` - The callback for every file. Args: (stack_to_parent_dir, filesystem_entry) -> bool. If the
-    ///    callback returns with `false`, iterating will stop immediately
+    ///   callback returns with `false`, iterating will stop immediately
     /// # Returns
     /// If the iteration was aborted or not
     pub fn iterate<'a, F: FnMut(&LinkedList<&OsString>, &FSEntry) -> bool>(
@@ -1,12 +1,22 @@
 use std::{
     collections::LinkedList,
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     fs::File,
     io::{BufRead, BufReader},
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
 };
 
-use crate::error::{Error, ErrorExt};
+use crate::{
+    error::{Error, ErrorExt},
+    model::{ObjectID, Tree, TreeEntry},
+};
+
+/// The name of the `env` program that looks up interpreters in `PATH`
+static ENV_PROGRAM: &str = "env";
+
+/// The options of `env` that consume the following argument
+static ENV_OPTIONS_WITH_ARGUMENT: &[&str] = &["-u", "--unset", "-C", "--chdir"];
 
 /// A fs entry that is a script (has a shbang at its start)
 #[derive(Clone)]
@@ -34,14 +44,21 @@ impl ScriptFile {
             shbang
         };
 
-        // Remove the shbang from the start ('#!')
+        Ok(Self::from_shbang(&first_line, name))
+    }
+
+    /// Creates a `ScriptFile` from the first line of a script
+    /// # Arguments
+    /// * `first_line` - The first line of the script, including the shbang (`#!`)
+    /// * `name` - The name of the script
+    pub fn from_shbang(first_line: &str, name: OsString) -> Self {
+        // Remove the shbang from the start ('#!'), allowing whitespace after
+        // it and scripts with Windows line endings ('\r\n')
         let first_line = first_line.trim_start_matches("#!");
 
-        // Split the line into its pieces
-        let mut split: LinkedList<OsString> = first_line
-            .split(' ')
-            .map(|s| OsString::from(s.trim()))
-            .collect();
+        // Split the line into its pieces, separated by any amount of whitespace
+        let mut split: LinkedList<OsString> =
+            first_line.split_whitespace().map(OsString::from).collect();
 
         // Split the interpreter path from its arguments
         let interpreter = split.pop_front().map(|i| {
@@ -51,6 +68,107 @@ impl ScriptFile {
             )
         });
 
-        Ok(Self { name, interpreter })
+        Self { name, interpreter }
+    }
+
+    /// Returns whether the interpreter gets looked up using `env`
+    /// (`#!/usr/bin/env python3` or `#!/usr/bin/env -S python3 -u`)
+    pub fn is_env_shbang(&self) -> bool {
+        match &self.interpreter {
+            Some((path, _)) => path.file_name() == Some(OsStr::new(ENV_PROGRAM)),
+            None => false,
+        }
+    }
+
+    /// Returns the program that actually interprets the script and its arguments.
+    /// For `env` shbangs, this is the program `env` looks up, skipping the options and
+    /// variable assignments of `env`, else the name of the interpreter
+    pub fn get_program(&self) -> Option<(&OsStr, &[OsString])> {
+        let (path, args) = self.interpreter.as_ref()?;
+
+        if !self.is_env_shbang() {
+            return path.file_name().map(|name| (name, args.as_slice()));
+        }
+
+        let mut i = 0;
+        while let Some(arg) = args.get(i) {
+            let bytes = arg.as_bytes();
+
+            if bytes == b"--" {
+                i += 1;
+                break;
+            } else if ENV_OPTIONS_WITH_ARGUMENT.contains(&arg.to_string_lossy().as_ref()) {
+                i += 2;
+            } else if bytes.starts_with(b"-") || bytes.contains(&b'=') {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+
+        args.get(i)
+            .map(|program| (program.as_os_str(), &args[i + 1..]))
+    }
+
+    /// Finds the dependency providing the interpreter of this script by
+    /// searching the `executable_dirs` of `dependencies` in order
+    /// # Arguments
+    /// * `dependencies` - The object ids and trees of the dependencies to search
+    /// * `executable_dirs` - The directories to search, relative to the dependency trees
+    /// # Returns
+    /// The path to the interpreter relative to the dependency tree and the object id
+    /// of the providing dependency, which the script depends on, or `None` if no dependency provides it
+    pub fn resolve_interpreter<'a>(
+        &self,
+        dependencies: &'a [(ObjectID, Tree)],
+        executable_dirs: &[PathBuf],
+    ) -> Option<(PathBuf, &'a ObjectID)> {
+        let (program, _) = self.get_program()?;
+
+        dependencies.iter().find_map(|(oid, tree)| {
+            executable_dirs.iter().find_map(|dir| {
+                let path = dir.join(program);
+                match tree.get_entry(&path) {
+                    Some(TreeEntry::Subtree {
+                        info: _,
+                        name: _,
+                        tree: _,
+                    })
+                    | None => None,
+                    Some(_) => Some((path, oid)),
+                }
+            })
+        })
+    }
+
+    /// Rewrites the shbang of the script at `path` to call `interpreter` directly,
+    /// passing the arguments of the program (see [ScriptFile::get_program()]).
+    /// This replaces `#!/usr/bin/env python3` with `#!<interpreter>` so the
+    /// script does not depend on the `PATH` at runtime
+    /// # Arguments
+    /// * `path` - The path to the script to rewrite, its permissions are kept
+    /// * `interpreter` - The absolute path to the resolved interpreter
+    pub fn rewrite_interpreter(&self, path: &Path, interpreter: &Path) -> Result<(), Error> {
+        let context = || format!("Rewriting shbang of {}", path.to_string_lossy());
+
+        let Some((_, args)) = self.get_program() else {
+            return Ok(());
+        };
+
+        let mut shbang = b"#!".to_vec();
+        shbang.extend_from_slice(interpreter.as_os_str().as_bytes());
+        for arg in args {
+            shbang.push(b' ');
+            shbang.extend_from_slice(arg.as_bytes());
+        }
+
+        let content = std::fs::read(path).e_context(context)?;
+        let rest = match content.iter().position(|b| *b == b'\n') {
+            Some(pos) => &content[pos..],
+            None => &[],
+        };
+        shbang.extend_from_slice(rest);
+
+        std::fs::write(path, shbang).e_context(context)
     }
 }
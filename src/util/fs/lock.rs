@@ -0,0 +1,100 @@
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use log::trace;
+use nix::{
+    errno::Errno,
+    fcntl::{Flock, FlockArg},
+};
+
+use crate::error::{Error, ErrorExt};
+
+use super::PathUtil;
+
+/// How a [FileLock] is held
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    /// Any number of shared locks can be held at the same time
+    Shared,
+    /// An exclusive lock excludes all other locks
+    Exclusive,
+}
+
+/// An advisory lock on a lock file (`flock(2)`), released when dropped.
+///
+/// The lock is bound to the open file, so it is released by the kernel if the
+/// holding process dies, and lock files left behind never block anyone
+#[derive(Debug)]
+pub struct FileLock {
+    /// The locked file
+    _lock: Flock<File>,
+    /// The path to the lock file
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Tries to lock the lock file at `path` exclusively, creating it if needed
+    /// # Arguments
+    /// * `path` - The path to the lock file
+    /// # Returns
+    /// The lock or `None` if another process or lock holds it
+    pub fn try_lock(path: &Path) -> Result<Option<Self>, Error> {
+        Self::lock_with(path, LockMode::Exclusive, false)
+    }
+
+    /// Locks the lock file at `path`, creating it if needed
+    /// # Arguments
+    /// * `path` - The path to the lock file
+    /// * `mode` - How to hold the lock
+    /// * `wait` - Whether to block until conflicting locks are released
+    /// # Returns
+    /// The lock or `None` if `wait` is `false` and a conflicting lock is held
+    pub fn lock_with(path: &Path, mode: LockMode, wait: bool) -> Result<Option<Self>, Error> {
+        let context = || format!("Locking {}", path.str_lossy());
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .e_context(context)?;
+
+        let arg = match (mode, wait) {
+            (LockMode::Shared, false) => FlockArg::LockSharedNonblock,
+            (LockMode::Shared, true) => FlockArg::LockShared,
+            (LockMode::Exclusive, false) => FlockArg::LockExclusiveNonblock,
+            (LockMode::Exclusive, true) => FlockArg::LockExclusive,
+        };
+
+        match Flock::lock(file, arg) {
+            Ok(lock) => {
+                trace!("Locked {} ({mode:?})", path.str_lossy());
+                Ok(Some(Self {
+                    _lock: lock,
+                    path: path.to_owned(),
+                }))
+            }
+            Err((_, Errno::EWOULDBLOCK)) => Ok(None),
+            Err((_, errno)) => Err(std::io::Error::from(errno)).e_context(context),
+        }
+    }
+
+    /// Returns whether the lock file at `path` is held by someone,
+    /// a missing lock file is never held
+    /// # Arguments
+    /// * `path` - The path to the lock file
+    pub fn is_locked(path: &Path) -> Result<bool, Error> {
+        if !path.exists() {
+            return Ok(false);
+        }
+
+        Ok(Self::try_lock(path)?.is_none())
+    }
+
+    /// Returns the path to the lock file
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+}
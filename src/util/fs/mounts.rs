@@ -0,0 +1,116 @@
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Error, ErrorExt};
+
+use super::PathUtil;
+
+/// The file listing the mounts visible to this process
+static MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// A mount listed in `/proc/self/mountinfo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountEntry {
+    /// The path the filesystem is mounted at
+    pub mount_point: PathBuf,
+    /// The type of the filesystem (`overlay`, `proc`...)
+    pub fs_type: String,
+    /// The source of the mount (a device, `overlay`, `proc`...)
+    pub source: String,
+}
+
+/// Returns the mount points at or below `path`, e.g. to detect mounts
+/// that have been left behind by a build that did not tear down its environment
+/// # Arguments
+/// * `path` - The path to search mount points under, has to be absolute
+/// # Returns
+/// The mount points, the innermost ones first so they can be unmounted in order
+pub fn mount_points_under(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut mount_points: Vec<PathBuf> = mounts_under(path)?
+        .into_iter()
+        .map(|mount| mount.mount_point)
+        .collect();
+    mount_points.dedup();
+
+    Ok(mount_points)
+}
+
+/// Returns the mounts at or below `path` like [mount_points_under()],
+/// including the type and source of every mount
+/// # Arguments
+/// * `path` - The path to search mounts under, has to be absolute
+/// # Returns
+/// The mounts, the innermost ones first so they can be unmounted in order.
+/// Mounts stacked on the same mount point are listed topmost first
+pub fn mounts_under(path: &Path) -> Result<Vec<MountEntry>, Error> {
+    let mountinfo = std::fs::read(MOUNTINFO_PATH)
+        .e_context(|| format!("Reading mounts from {MOUNTINFO_PATH}"))?;
+
+    // `<id> <parent id> <major:minor> <root> <mount point> <options> [<optional>...] - <fs type> <source> <super options>`
+    let mut mounts: Vec<MountEntry> = mountinfo
+        .split(|b| *b == b'\n')
+        .filter_map(|line| {
+            let fields: Vec<&[u8]> = line.split(|b| *b == b' ').collect();
+            let separator = fields.iter().position(|f| *f == b"-")?;
+
+            Some(MountEntry {
+                mount_point: unescape_mount_point(fields.get(4)?),
+                fs_type: String::from_utf8_lossy(fields.get(separator + 1)?).to_string(),
+                source: unescape_mount_point(fields.get(separator + 2)?).str_lossy(),
+            })
+        })
+        .filter(|mount| mount.mount_point.starts_with(path))
+        .collect();
+
+    // Later mounts are stacked on top of earlier ones, sorting is stable
+    mounts.reverse();
+    mounts.sort_by(|a, b| b.mount_point.cmp(&a.mount_point));
+
+    Ok(mounts)
+}
+
+/// Decodes the octal escapes (`\040` for a space) the kernel uses for
+/// whitespace and backslashes in the mount points and sources of `mountinfo`
+/// # Arguments
+/// * `raw` - The escaped mount point
+fn unescape_mount_point(raw: &[u8]) -> PathBuf {
+    let mut path = Vec::with_capacity(raw.len());
+
+    let mut i = 0;
+    while i < raw.len() {
+        let escaped = raw
+            .get(i + 1..i + 4)
+            .filter(|_| raw[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+
+        match escaped {
+            Some(byte) => {
+                path.push(byte);
+                i += 4;
+            }
+            None => {
+                path.push(raw[i]);
+                i += 1;
+            }
+        }
+    }
+
+    PathBuf::from(OsString::from_vec(path))
+}
+
+impl Display for MountEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}) => {}",
+            self.source,
+            self.fs_type,
+            self.mount_point.str_lossy()
+        )
+    }
+}
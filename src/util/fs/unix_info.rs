@@ -19,7 +19,7 @@ use crate::{
 };
 
 /// A structure to wrap UNIX file attributes
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UNIXInfo {
     /// The UNIX user id for the entry
     pub uid: u32,
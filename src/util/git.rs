@@ -0,0 +1,116 @@
+//! Utilities for fetching sources from git repositories using the `git` command
+use std::{path::Path, process::Command};
+
+use log::{debug, info};
+
+use crate::error::{Error, ErrorExt, ErrorType};
+
+use super::fs::{self, PathUtil};
+
+/// The revision of a git repository to check out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitReference {
+    /// The default branch of the repository (`HEAD`)
+    Head,
+    /// A commit hash
+    Rev(String),
+    /// A tag
+    Tag(String),
+    /// The tip of a branch
+    Branch(String),
+}
+
+impl GitReference {
+    /// Returns the refspec to fetch for this reference
+    fn refspec(&self) -> String {
+        match self {
+            Self::Head => "HEAD".to_owned(),
+            Self::Rev(rev) => rev.to_owned(),
+            Self::Tag(tag) => format!("refs/tags/{tag}"),
+            Self::Branch(branch) => format!("refs/heads/{branch}"),
+        }
+    }
+}
+
+/// Clones the revision `reference` of the git repository at `url` into `dest`
+/// without any history (`--depth 1`).
+///
+/// The `.git` directory is removed afterwards, so `dest` only contains the
+/// checked out files and indexing it is reproducible
+/// # Arguments
+/// * `url` - The URL of the repository
+/// * `reference` - The revision to check out
+/// * `dest` - The directory to check out to, must not exist or be empty
+/// # Returns
+/// The hash of the checked out commit
+/// # Errors
+/// - If a `git` command fails
+/// - If `reference` is a commit hash that does not match the checked out commit
+pub fn clone_shallow(url: &str, reference: &GitReference, dest: &Path) -> Result<String, Error> {
+    let context = || format!("Cloning {url} to {}", dest.str_lossy());
+
+    fs::create_dir_all(dest).ctx(context)?;
+
+    info!("Fetching {} of {url}", reference.refspec());
+    git(dest, &["init", "--quiet"]).ctx(context)?;
+    git(
+        dest,
+        &[
+            "fetch",
+            "--quiet",
+            "--depth",
+            "1",
+            "--",
+            url,
+            &reference.refspec(),
+        ],
+    )
+    .ctx(context)?;
+    git(dest, &["checkout", "--quiet", "--detach", "FETCH_HEAD"]).ctx(context)?;
+
+    let commit = git(dest, &["rev-parse", "HEAD"]).ctx(context)?;
+    debug!("Checked out commit {commit} of {url}");
+
+    if let GitReference::Rev(rev) = reference {
+        if !commit.starts_with(&rev.to_lowercase()) {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Checked out commit {commit}, expected {rev}"
+            ))))
+            .ctx(context);
+        }
+    }
+
+    fs::remove_dir_all(&dest.join(".git")).ctx(context)?;
+
+    Ok(commit)
+}
+
+/// Runs `git` with `args` in `dir`
+/// # Arguments
+/// * `dir` - The working directory to run `git` in
+/// * `args` - The arguments to pass to `git`
+/// # Returns
+/// The trimmed standard output
+fn git(dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let context = || format!("Running git {}", args.join(" "));
+
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .e_context(context)?;
+
+    if !output.status.success() {
+        return Err(Error::new_context(
+            ErrorType::Other(format!(
+                "git exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            context(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
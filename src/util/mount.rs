@@ -5,12 +5,21 @@ use std::path::Path;
 mod overlay;
 pub use overlay::*;
 
+mod fuseoverlay;
+pub use fuseoverlay::*;
+
 mod vkfs;
 pub use vkfs::*;
 
 mod bind;
 pub use bind::*;
 
+mod options;
+pub use options::*;
+
+mod stale;
+pub use stale::*;
+
 /// A common trait for all mount types
 pub trait Mount {
     /// Returns a description of the type (`overlayfs`, `vkfs`...)
@@ -21,4 +30,6 @@ pub trait Mount {
     fn get_source_path(&self) -> &Path;
     /// Returns all source paths (overlayfs)
     fn get_source_paths(&self) -> Vec<&Path>;
+    /// Returns the flags the mount has been mounted with (`bind`, `ro`, `nosuid`...)
+    fn get_flags(&self) -> Vec<String>;
 }
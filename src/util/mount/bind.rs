@@ -5,11 +5,12 @@ use sys_mount::{MountFlags, UnmountDrop, UnmountFlags};
 
 use crate::error::{Error, ErrorExt};
 
-use super::Mount;
+use super::{Mount, MountOptions};
 
 /// Represents a bind mount
 pub struct BindMount {
-    readonly: bool,
+    options: MountOptions,
+    flags: MountFlags,
     source: PathBuf,
 
     mount: UnmountDrop<sys_mount::Mount>,
@@ -20,10 +21,77 @@ impl BindMount {
     /// # Arguments
     /// * `source` - The source directory
     /// * `target` - The target directory
-    /// * `readonly` - If the `RDONLY` flag should be appended
+    /// * `options` - The options to remount the bind mount with
     ///
     /// Mount command: `mount --bind <source> <target>`
-    pub fn new(source: &Path, target: &Path, readonly: bool) -> Result<Self, Error> {
+    pub fn new(source: &Path, target: &Path, options: MountOptions) -> Result<Self, Error> {
+        Self::new_with_flags(source, target, options, MountFlags::BIND)
+    }
+
+    /// Creates a bind mount from the source to the target using the `--rbind` flag,
+    /// including all mounts below `source`.
+    ///
+    /// Within a user namespace, mounts with submounts (`/dev`, `/proc`, `/sys`)
+    /// can only be bind mounted recursively.
+    /// # Arguments
+    /// * `source` - The source directory
+    /// * `target` - The target directory
+    /// * `options` - The options to remount the bind mount with, these only apply
+    ///   to the top mount, not to the mounts below `source`
+    ///
+    /// Mount command: `mount --rbind <source> <target>`
+    pub fn new_recursive(
+        source: &Path,
+        target: &Path,
+        options: MountOptions,
+    ) -> Result<Self, Error> {
+        Self::new_with_flags(source, target, options, MountFlags::BIND | MountFlags::REC)
+    }
+
+    /// Creates a bind mount of a single file from the source to the target,
+    /// creating an empty target file if it does not exist
+    /// # Arguments
+    /// * `source` - The source file
+    /// * `target` - The target file
+    /// * `options` - The options to remount the bind mount with
+    ///
+    /// Mount command: `mount --bind <source> <target>`
+    pub fn new_file(source: &Path, target: &Path, options: MountOptions) -> Result<Self, Error> {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).e_context(|| {
+                format!(
+                    "Creating bind mount target parent directory {}",
+                    parent.to_string_lossy()
+                )
+            })?;
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(target)
+            .e_context(|| {
+                format!(
+                    "Creating bind mount target file {}",
+                    target.to_string_lossy()
+                )
+            })?;
+
+        Self::mount_with_flags(source, target, options, MountFlags::BIND)
+    }
+
+    /// Creates a bind mount using `flags`, creating the source and target directories
+    /// # Arguments
+    /// * `source` - The source directory
+    /// * `target` - The target directory
+    /// * `options` - The options to remount the bind mount with
+    /// * `flags` - The flags to mount with
+    fn new_with_flags(
+        source: &Path,
+        target: &Path,
+        options: MountOptions,
+        flags: MountFlags,
+    ) -> Result<Self, Error> {
         std::fs::create_dir_all(source).e_context(|| {
             format!(
                 "Creating bind mount source directory {}",
@@ -37,29 +105,58 @@ impl BindMount {
             )
         })?;
 
+        Self::mount_with_flags(source, target, options, flags)
+    }
+
+    /// Bind mounts `source` to `target` using `flags`.
+    ///
+    /// The kernel ignores flags like `RDONLY` when creating a bind mount,
+    /// so the mount gets remounted with `options` if there are any
+    /// # Arguments
+    /// * `source` - The source path
+    /// * `target` - The target path
+    /// * `options` - The options to remount the bind mount with
+    /// * `flags` - The flags to mount with
+    fn mount_with_flags(
+        source: &Path,
+        target: &Path,
+        options: MountOptions,
+        flags: MountFlags,
+    ) -> Result<Self, Error> {
         debug!(
-            "Mounting bind {} ==> {}",
+            "Mounting bind {} ==> {} ({options})",
             source.to_string_lossy(),
             target.to_string_lossy()
         );
 
-        let mount = if readonly {
-            sys_mount::Mount::builder().flags(MountFlags::BIND | MountFlags::RDONLY)
-        } else {
-            sys_mount::Mount::builder().flags(MountFlags::BIND)
+        let mount = sys_mount::Mount::builder()
+            .flags(flags)
+            .mount_autodrop(source, target, UnmountFlags::DETACH)
+            .e_context(|| {
+                format!(
+                    "Bind mounting {} to {}",
+                    source.to_string_lossy(),
+                    target.to_string_lossy()
+                )
+            })?;
+
+        if !options.flags().is_empty() {
+            // Dropping the returned mount does not unmount, `mount` takes care of that
+            sys_mount::Mount::builder()
+                .flags(MountFlags::REMOUNT | MountFlags::BIND | options.flags())
+                .mount(source, target)
+                .e_context(|| {
+                    format!(
+                        "Remounting bind mount {} with {options}",
+                        target.to_string_lossy()
+                    )
+                })?;
         }
-        .mount_autodrop(source, target, UnmountFlags::DETACH)
-        .e_context(|| {
-            format!(
-                "Bind mounting {} to {}",
-                source.to_string_lossy(),
-                target.to_string_lossy()
-            )
-        })?;
 
         Ok(Self {
             mount,
-            readonly,
+            options,
+            flags,
             source: source.to_path_buf(),
         })
     }
@@ -67,7 +164,7 @@ impl BindMount {
 
 impl Mount for BindMount {
     fn get_fs_type(&self) -> String {
-        format!("bind (readonly={})", self.readonly)
+        "bind".to_owned()
     }
 
     fn get_target_path(&self) -> &Path {
@@ -81,6 +178,18 @@ impl Mount for BindMount {
     fn get_source_paths(&self) -> Vec<&Path> {
         vec![&self.source]
     }
+
+    fn get_flags(&self) -> Vec<String> {
+        let bind = if self.flags.contains(MountFlags::REC) {
+            "rbind"
+        } else {
+            "bind"
+        };
+
+        let mut flags = vec![bind.to_owned()];
+        flags.extend(self.options.names());
+        flags
+    }
 }
 
 impl Drop for BindMount {
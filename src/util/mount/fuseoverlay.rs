@@ -0,0 +1,176 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::{debug, warn};
+use sys_mount::UnmountFlags;
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    util::{self, fs::PathUtil},
+};
+
+use super::{overlay_options, Mount};
+
+/// The program mounting overlay filesystems in userspace
+static FUSE_OVERLAYFS: &str = "fuse-overlayfs";
+
+/// The programs to unmount FUSE filesystems with as an unprivileged user, in order of preference
+static FUSERMOUNT: &[&str] = &["fusermount3", "fusermount"];
+
+/// Represents an overlay filesystem mounted in userspace using `fuse-overlayfs`,
+/// for hosts that can't mount the kernel's `overlayfs` (e.g. missing privileges)
+pub struct FuseOverlayMount {
+    lower_dirs: Vec<PathBuf>,
+    work_dir: PathBuf,
+    upper_dir: PathBuf,
+    merged_dir: PathBuf,
+}
+
+impl FuseOverlayMount {
+    /// Mounts an overlay filesystem using `fuse-overlayfs` with the supplied options,
+    /// which has to be available in `PATH`. The filesystem gets unmounted once this is dropped
+    /// # Arguments
+    /// * `lower` -  The `lowerdir` sequence of lower directories
+    /// * `work` - The `workdir`
+    /// * `upper` - The `upperdir` to store the new files in
+    /// * `merged` - The merged directory where to mount the `overlay` filesystem
+    pub fn new(
+        lower: Vec<PathBuf>,
+        work: PathBuf,
+        upper: PathBuf,
+        merged: PathBuf,
+    ) -> Result<FuseOverlayMount, Error> {
+        for d in &lower {
+            util::fs::create_dir_all(d)?;
+        }
+        util::fs::create_dir_all(&work)?;
+        util::fs::create_dir_all(&upper)?;
+        util::fs::create_dir_all(&merged)?;
+
+        let data = overlay_options(&lower, &work, &upper);
+        let context = || {
+            format!(
+                "Mounting {FUSE_OVERLAYFS} ({data}) => {}",
+                merged.str_lossy()
+            )
+        };
+        debug!(
+            "Mounting {FUSE_OVERLAYFS} ({data}) ==> {}",
+            merged.str_lossy()
+        );
+
+        // `fuse-overlayfs` daemonizes once the filesystem is mounted
+        let output = Command::new(FUSE_OVERLAYFS)
+            .arg("-o")
+            .arg(&data)
+            .arg(&merged)
+            .output()
+            .e_context(context)?;
+
+        if !output.status.success() {
+            return Err(Error::new_context(
+                ErrorType::Other(format!(
+                    "{FUSE_OVERLAYFS} exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )),
+                context(),
+            ));
+        }
+
+        Ok(FuseOverlayMount {
+            lower_dirs: lower,
+            work_dir: work,
+            upper_dir: upper,
+            merged_dir: merged,
+        })
+    }
+}
+
+/// Unmounts the FUSE filesystem at `target` lazily: Directly if permitted
+/// (e.g. within the user namespace it has been mounted in), using `fusermount` otherwise
+/// # Arguments
+/// * `target` - The mount point of the filesystem
+fn unmount_fuse(target: &Path) -> Result<(), Error> {
+    let context = || format!("Unmounting {}", target.str_lossy());
+
+    if sys_mount::unmount(target, UnmountFlags::DETACH).is_ok() {
+        return Ok(());
+    }
+
+    for program in FUSERMOUNT {
+        let output = match Command::new(program)
+            .args(["-u", "-z"])
+            .arg(target)
+            .output()
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).e_context(context),
+        };
+
+        if !output.status.success() {
+            return Err(Error::new_context(
+                ErrorType::Other(format!(
+                    "{program} exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )),
+                context(),
+            ));
+        }
+
+        return Ok(());
+    }
+
+    Err(Error::new_context(
+        ErrorType::Other(format!("None of {} is available", FUSERMOUNT.join(", "))),
+        context(),
+    ))
+}
+
+impl Mount for FuseOverlayMount {
+    fn get_fs_type(&self) -> String {
+        "fuse-overlayfs".to_string()
+    }
+
+    fn get_target_path(&self) -> &Path {
+        &self.merged_dir
+    }
+
+    fn get_source_path(&self) -> &Path {
+        self.lower_dirs
+            .first()
+            .expect("Expected at least 1 lowerdir")
+    }
+
+    fn get_source_paths(&self) -> Vec<&Path> {
+        let mut vec: Vec<&Path> = self.lower_dirs.iter().map(|d| d.as_path()).collect();
+        vec.push(&self.work_dir);
+        vec.push(&self.upper_dir);
+
+        vec
+    }
+
+    fn get_flags(&self) -> Vec<String> {
+        // The overlay is always mounted read-write without additional flags
+        vec!["rw".to_owned()]
+    }
+}
+
+impl Drop for FuseOverlayMount {
+    fn drop(&mut self) {
+        debug!(
+            "Unmounting {} at {}",
+            self.get_fs_type(),
+            self.merged_dir.str_lossy()
+        );
+
+        if let Err(e) = unmount_fuse(&self.merged_dir) {
+            warn!("{}", e.oneline());
+        }
+    }
+}
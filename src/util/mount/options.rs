@@ -0,0 +1,108 @@
+use std::fmt::Display;
+
+use sys_mount::MountFlags;
+
+/// Additional options to apply to a mount
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MountOptions {
+    readonly: bool,
+    nosuid: bool,
+    nodev: bool,
+    noexec: bool,
+    size: Option<String>,
+}
+
+impl MountOptions {
+    /// Creates a new set of options with all options disabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mounts the filesystem read-only (`ro`)
+    pub fn readonly(mut self) -> Self {
+        self.readonly = true;
+        self
+    }
+
+    /// Ignores the SUID and SGID bits of files on the filesystem (`nosuid`)
+    pub fn nosuid(mut self) -> Self {
+        self.nosuid = true;
+        self
+    }
+
+    /// Disallows access to device files on the filesystem (`nodev`)
+    pub fn nodev(mut self) -> Self {
+        self.nodev = true;
+        self
+    }
+
+    /// Disallows executing files on the filesystem (`noexec`)
+    pub fn noexec(mut self) -> Self {
+        self.noexec = true;
+        self
+    }
+
+    /// Limits the size of the filesystem (`size=`), only supported by `tmpfs`
+    /// # Arguments
+    /// * `size` - The size in bytes, with a `k`, `m` or `g` suffix or as a percentage of the RAM (`50%`)
+    pub fn size(mut self, size: &str) -> Self {
+        self.size = Some(size.to_owned());
+        self
+    }
+
+    /// Returns whether the mount is read-only
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Returns the mount flags for these options
+    pub fn flags(&self) -> MountFlags {
+        let mut flags = MountFlags::empty();
+
+        if self.readonly {
+            flags |= MountFlags::RDONLY;
+        }
+        if self.nosuid {
+            flags |= MountFlags::NOSUID;
+        }
+        if self.nodev {
+            flags |= MountFlags::NODEV;
+        }
+        if self.noexec {
+            flags |= MountFlags::NOEXEC;
+        }
+
+        flags
+    }
+
+    /// Returns the filesystem specific data string for these options, if any
+    pub fn data(&self) -> Option<String> {
+        self.size.as_ref().map(|size| format!("size={size}"))
+    }
+
+    /// Returns the names of these options as passed to `mount -o` (`ro`, `nosuid`...)
+    pub fn names(&self) -> Vec<String> {
+        let mut options = vec![if self.readonly { "ro" } else { "rw" }.to_owned()];
+
+        if self.nosuid {
+            options.push("nosuid".to_owned());
+        }
+        if self.nodev {
+            options.push("nodev".to_owned());
+        }
+        if self.noexec {
+            options.push("noexec".to_owned());
+        }
+        if let Some(data) = self.data() {
+            options.push(data);
+        }
+
+        options
+    }
+}
+
+impl Display for MountOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.names().join(","))
+    }
+}
@@ -3,15 +3,16 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use log::debug;
+use log::{debug, warn};
 use sys_mount::{UnmountDrop, UnmountFlags};
 
 use crate::{
     error::{Error, ErrorExt},
+    files::homeconfig::OverlayBackend,
     util,
 };
 
-use super::Mount;
+use super::{FuseOverlayMount, Mount};
 
 /// Represents an overlayfs mount
 pub struct OverlayMount {
@@ -43,24 +44,7 @@ impl OverlayMount {
         util::fs::create_dir_all(&upper)?;
         util::fs::create_dir_all(&merged)?;
 
-        let mut done: HashMap<PathBuf, ()> = HashMap::new();
-        let mut lower_s = String::new();
-        for p in lower.iter().rev() {
-            if !done.contains_key(p) {
-                done.insert(p.to_path_buf(), ());
-                lower_s.push_str(&p.to_string_lossy());
-                lower_s.push(':');
-            } else {
-                debug!("Deduplicated '{}'", p.to_string_lossy())
-            }
-        }
-        // Pop the last colon
-        lower_s.pop();
-
-        let work_s = work.to_string_lossy();
-        let upper_s = upper.to_string_lossy();
-
-        let data = format!("lowerdir={lower_s},upperdir={upper_s},workdir={work_s}");
+        let data = overlay_options(&lower, &work, &upper);
         debug!(
             "Mounting overlay ({}) ==> {}",
             &data,
@@ -89,6 +73,67 @@ impl OverlayMount {
     }
 }
 
+/// Mounts an overlay filesystem using `backend`, refer to [OverlayMount::new()]
+/// and [FuseOverlayMount::new()]. [OverlayBackend::Auto] tries the kernel's
+/// `overlayfs` first and falls back to `fuse-overlayfs` with a warning
+/// # Arguments
+/// * `backend` - The implementation to use
+/// * `lower` -  The `lowerdir` sequence of lower directories
+/// * `work` - The `workdir`
+/// * `upper` - The `upperdir` to store the new files in
+/// * `merged` - The merged directory where to mount the `overlay` filesystem
+pub fn mount_overlay(
+    backend: OverlayBackend,
+    lower: Vec<PathBuf>,
+    work: PathBuf,
+    upper: PathBuf,
+    merged: PathBuf,
+) -> Result<Box<dyn Mount>, Error> {
+    match backend {
+        OverlayBackend::Kernel => Ok(Box::new(OverlayMount::new(lower, work, upper, merged)?)),
+        OverlayBackend::Fuse => Ok(Box::new(FuseOverlayMount::new(lower, work, upper, merged)?)),
+        OverlayBackend::Auto => {
+            match OverlayMount::new(lower.clone(), work.clone(), upper.clone(), merged.clone()) {
+                Ok(mount) => Ok(Box::new(mount)),
+                Err(e) => {
+                    warn!(
+                        "Mounting overlayfs failed, falling back to fuse-overlayfs: {}",
+                        e.oneline()
+                    );
+                    Ok(Box::new(FuseOverlayMount::new(lower, work, upper, merged)?))
+                }
+            }
+        }
+    }
+}
+
+/// Assembles the mount options of an overlay filesystem,
+/// dropping duplicate lower directories
+/// # Arguments
+/// * `lower` -  The `lowerdir` sequence of lower directories, the last one being the topmost
+/// * `work` - The `workdir`
+/// * `upper` - The `upperdir`
+pub(super) fn overlay_options(lower: &[PathBuf], work: &Path, upper: &Path) -> String {
+    let mut done: HashMap<PathBuf, ()> = HashMap::new();
+    let mut lower_s = String::new();
+    for p in lower.iter().rev() {
+        if !done.contains_key(p) {
+            done.insert(p.to_path_buf(), ());
+            lower_s.push_str(&p.to_string_lossy());
+            lower_s.push(':');
+        } else {
+            debug!("Deduplicated '{}'", p.to_string_lossy())
+        }
+    }
+    // Pop the last colon
+    lower_s.pop();
+
+    let work_s = work.to_string_lossy();
+    let upper_s = upper.to_string_lossy();
+
+    format!("lowerdir={lower_s},upperdir={upper_s},workdir={work_s}")
+}
+
 impl Mount for OverlayMount {
     fn get_fs_type(&self) -> String {
         "overlayfs".to_string()
@@ -111,6 +156,11 @@ impl Mount for OverlayMount {
 
         vec
     }
+
+    fn get_flags(&self) -> Vec<String> {
+        // The overlay is always mounted read-write without additional flags
+        vec!["rw".to_owned()]
+    }
 }
 
 impl Drop for OverlayMount {
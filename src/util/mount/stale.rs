@@ -0,0 +1,41 @@
+use std::path::Path;
+
+use log::{debug, warn};
+use sys_mount::UnmountFlags;
+
+use crate::{
+    error::{Error, ErrorExt},
+    util::fs::{self, MountEntry, PathUtil},
+};
+
+/// Unmounts all mounts at or below `root`, e.g. the ones left behind by
+/// a build that crashed before tearing down its environment.
+///
+/// The mounts are detached lazily (`umount -l`), innermost first,
+/// so busy mounts vanish from `root` immediately
+/// # Arguments
+/// * `root` - The directory to unmount everything below, has to exist
+/// # Returns
+/// The mounts that have been unmounted
+pub fn unmount_stale(root: &Path) -> Result<Vec<MountEntry>, Error> {
+    let context = || format!("Unmounting stale mounts below {}", root.str_lossy());
+
+    let root = std::fs::canonicalize(root).e_context(context)?;
+    let mounts = fs::mounts_under(&root).ctx(context)?;
+
+    for mount in &mounts {
+        warn!("Unmounting stale mount {mount}");
+
+        sys_mount::unmount(&mount.mount_point, UnmountFlags::DETACH)
+            .e_context(|| format!("Unmounting {mount}"))
+            .ctx(context)?;
+    }
+
+    debug!(
+        "Unmounted {} stale mounts below {}",
+        mounts.len(),
+        root.str_lossy()
+    );
+
+    Ok(mounts)
+}
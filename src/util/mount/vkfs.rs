@@ -5,11 +5,12 @@ use sys_mount::{UnmountDrop, UnmountFlags};
 
 use crate::error::{Error, ErrorExt};
 
-use super::Mount;
+use super::{Mount, MountOptions};
 
 /// Represents a mounted kernel virtual filesystem
 pub struct VKFSMount {
     source: String,
+    options: MountOptions,
 
     mount: UnmountDrop<sys_mount::Mount>,
 }
@@ -19,9 +20,10 @@ impl VKFSMount {
     /// # Arguments
     /// * `filesystem` - The name of the filesystem (e.g. `proc`, `sysfs`)
     /// * `target` - The path where to mount the filesystem
+    /// * `options` - The options to mount the filesystem with
     ///
-    /// Mount command: `mount -t <filesystem> <filesystem> <target>`
-    pub fn new(filesystem: &str, target: &Path) -> Result<Self, Error> {
+    /// Mount command: `mount -t <filesystem> -o <options> <filesystem> <target>`
+    pub fn new(filesystem: &str, target: &Path, options: MountOptions) -> Result<Self, Error> {
         std::fs::create_dir_all(target).e_context(|| {
             format!(
                 "Creating vkfs '{}' target directory {}",
@@ -31,18 +33,25 @@ impl VKFSMount {
         })?;
 
         debug!(
-            "Mounting vkfs '{filesystem}' ==> {}",
+            "Mounting vkfs '{filesystem}' ==> {} ({options})",
             target.to_string_lossy()
         );
 
         let source_path = Path::new(filesystem);
+        let data = options.data();
 
-        let mount = sys_mount::Mount::builder()
+        let mut builder = sys_mount::Mount::builder()
             .fstype(filesystem)
+            .flags(options.flags());
+        if let Some(data) = &data {
+            builder = builder.data(data);
+        }
+
+        let mount = builder
             .mount_autodrop(source_path, target, UnmountFlags::DETACH)
             .e_context(|| {
                 format!(
-                    "Mounting vkfs '{}' => {}",
+                    "Mounting vkfs '{}' => {} ({options})",
                     filesystem,
                     target.to_string_lossy()
                 )
@@ -51,6 +60,7 @@ impl VKFSMount {
         Ok(VKFSMount {
             mount,
             source: filesystem.to_string(),
+            options,
         })
     }
 }
@@ -70,6 +80,10 @@ impl Mount for VKFSMount {
     fn get_source_paths(&self) -> Vec<&Path> {
         vec![Path::new(&self.source)]
     }
+
+    fn get_flags(&self) -> Vec<String> {
+        self.options.names()
+    }
 }
 
 impl Drop for VKFSMount {
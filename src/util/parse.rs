@@ -1,13 +1,36 @@
 //! Utilities for parsing files
 
-use std::{fs::File, io::Write, path::Path};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::error::{Error, ErrorExt};
 
+pub mod spdx;
 pub mod versionstring;
 
+/// Parses a source override in the form `<DEST>=<DIR>`,
+/// refer to [crate::files::formulafile::FormulaFile::parse_and_resolve()]
+/// # Arguments
+/// * `s` - The string to parse
+pub fn parse_source_override(s: &str) -> Result<(String, PathBuf), String> {
+    match s.split_once('=') {
+        Some((dest, dir)) if !dest.is_empty() && !dir.is_empty() => {
+            let dir = PathBuf::from(dir);
+            if !dir.is_dir() {
+                return Err(format!("'{}' is not a directory", dir.to_string_lossy()));
+            }
+
+            Ok((dest.to_owned(), dir))
+        }
+        _ => Err(format!("Expected '<DEST>=<DIR>', got '{s}'")),
+    }
+}
+
 /// Reads the contents of a file to a string
 /// # Arguments
 /// * `path` - The path to the file to read
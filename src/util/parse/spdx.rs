@@ -0,0 +1,313 @@
+//! Parsing utilities for SPDX license expressions, refer to
+//! <https://spdx.github.io/spdx-spec/v2.3/SPDX-license-expressions/> for more information
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A parsed SPDX license expression (e.g. `MIT OR Apache-2.0`, `GPL-2.0-or-later WITH Classpath-exception-2.0`).
+///
+/// License identifiers are validated syntactically only,
+/// identifiers missing from the SPDX license list are accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpdxExpression {
+    /// A single license (`MIT`, `GPL-2.0+`, `LicenseRef-custom`)
+    License {
+        /// The license identifier
+        id: String,
+        /// Whether the license is suffixed by `+` (this version or any later version)
+        or_later: bool,
+    },
+    /// A license with an exception (`<license> WITH <exception>`)
+    With {
+        /// The license the exception applies to
+        license: Box<SpdxExpression>,
+        /// The identifier of the exception
+        exception: String,
+    },
+    /// Both expressions apply (`<a> AND <b>`)
+    And(Box<SpdxExpression>, Box<SpdxExpression>),
+    /// Either expression applies (`<a> OR <b>`)
+    Or(Box<SpdxExpression>, Box<SpdxExpression>),
+}
+
+/// A token of a license expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'a> {
+    Open,
+    Close,
+    And,
+    Or,
+    With,
+    Word(&'a str),
+}
+
+impl SpdxExpression {
+    /// Returns all license identifiers in this expression, without their `+` suffix
+    pub fn license_ids(&self) -> Vec<&str> {
+        match self {
+            Self::License { id, or_later: _ } => vec![id],
+            Self::With {
+                license,
+                exception: _,
+            } => license.license_ids(),
+            Self::And(a, b) | Self::Or(a, b) => {
+                let mut ids = a.license_ids();
+                ids.extend(b.license_ids());
+                ids
+            }
+        }
+    }
+
+    /// Checks if this expression references the license family `family`, ignoring
+    /// version suffixes like `-only`, `-or-later` and `+` (`GPL-2.0-or-later` mentions `GPL-2.0`)
+    /// # Arguments
+    /// * `family` - The license identifier to search for
+    pub fn mentions(&self, family: &str) -> bool {
+        self.license_ids().iter().any(|id| {
+            let id = id
+                .strip_suffix("-only")
+                .or_else(|| id.strip_suffix("-or-later"))
+                .unwrap_or(id);
+
+            id.eq_ignore_ascii_case(family)
+        })
+    }
+
+    /// Parses an `OR` expression, the operator with the lowest precedence
+    /// # Arguments
+    /// * `tokens` - The remaining tokens
+    fn parse_or(tokens: &mut &[Token]) -> Result<Self, String> {
+        let mut expr = Self::parse_and(tokens)?;
+
+        while let Some((Token::Or, rest)) = tokens.split_first() {
+            *tokens = rest;
+            expr = Self::Or(Box::new(expr), Box::new(Self::parse_and(tokens)?));
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses an `AND` expression
+    /// # Arguments
+    /// * `tokens` - The remaining tokens
+    fn parse_and(tokens: &mut &[Token]) -> Result<Self, String> {
+        let mut expr = Self::parse_with(tokens)?;
+
+        while let Some((Token::And, rest)) = tokens.split_first() {
+            *tokens = rest;
+            expr = Self::And(Box::new(expr), Box::new(Self::parse_with(tokens)?));
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a license with an optional `WITH` exception
+    /// # Arguments
+    /// * `tokens` - The remaining tokens
+    fn parse_with(tokens: &mut &[Token]) -> Result<Self, String> {
+        let expr = Self::parse_primary(tokens)?;
+
+        if let Some((Token::With, rest)) = tokens.split_first() {
+            if !matches!(expr, Self::License { id: _, or_later: _ }) {
+                return Err("'WITH' must follow a single license".to_owned());
+            }
+
+            return match rest.split_first() {
+                Some((Token::Word(exception), rest)) if is_idstring(exception) => {
+                    *tokens = rest;
+                    Ok(Self::With {
+                        license: Box::new(expr),
+                        exception: exception.to_string(),
+                    })
+                }
+                _ => Err("Expected an exception identifier after 'WITH'".to_owned()),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a single license or a parenthesized expression
+    /// # Arguments
+    /// * `tokens` - The remaining tokens
+    fn parse_primary(tokens: &mut &[Token]) -> Result<Self, String> {
+        match tokens.split_first() {
+            Some((Token::Open, rest)) => {
+                *tokens = rest;
+                let expr = Self::parse_or(tokens)?;
+
+                match tokens.split_first() {
+                    Some((Token::Close, rest)) => {
+                        *tokens = rest;
+                        Ok(expr)
+                    }
+                    _ => Err("Expected ')'".to_owned()),
+                }
+            }
+            Some((Token::Word(word), rest)) => {
+                *tokens = rest;
+
+                let (id, or_later) = match word.strip_suffix('+') {
+                    Some(id) => (id, true),
+                    None => (*word, false),
+                };
+
+                if !is_license_id(id) {
+                    return Err(format!("Invalid license identifier '{word}'"));
+                }
+
+                Ok(Self::License {
+                    id: id.to_owned(),
+                    or_later,
+                })
+            }
+            Some((token, _)) => Err(format!("Unexpected '{token}', expected a license")),
+            None => Err("Unexpected end of expression, expected a license".to_owned()),
+        }
+    }
+
+    /// Writes `self` to `f`, wrapping it in parentheses if it is an `OR` expression
+    /// # Arguments
+    /// * `f` - The formatter to write to
+    fn fmt_grouped(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Or(_, _) => write!(f, "({self})"),
+            _ => write!(f, "{self}"),
+        }
+    }
+}
+
+/// Checks if `s` is a valid SPDX `idstring` (letters, digits, `-` and `.`)
+/// # Arguments
+/// * `s` - The string to check
+fn is_idstring(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+/// Checks if `s` is a valid license identifier, including
+/// references in the form `[DocumentRef-<id>:]LicenseRef-<id>`
+/// # Arguments
+/// * `s` - The string to check
+fn is_license_id(s: &str) -> bool {
+    match s.split_once(':') {
+        Some((document, license)) => {
+            document.starts_with("DocumentRef-")
+                && is_idstring(document)
+                && license.starts_with("LicenseRef-")
+                && is_idstring(license)
+        }
+        None => is_idstring(s),
+    }
+}
+
+/// Splits a license expression into its tokens
+/// # Arguments
+/// * `s` - The expression to split
+fn tokenize(s: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+
+    for word in s.split_whitespace() {
+        let mut rest = word;
+
+        while !rest.is_empty() {
+            let end = rest.find(['(', ')']).unwrap_or(rest.len());
+
+            let token = match &rest[..end] {
+                "" => {
+                    let paren = if rest.starts_with('(') {
+                        Token::Open
+                    } else {
+                        Token::Close
+                    };
+                    rest = &rest[1..];
+                    tokens.push(paren);
+                    continue;
+                }
+                "AND" | "and" => Token::And,
+                "OR" | "or" => Token::Or,
+                "WITH" | "with" => Token::With,
+                word => Token::Word(word),
+            };
+
+            tokens.push(token);
+            rest = &rest[end..];
+        }
+    }
+
+    tokens
+}
+
+impl FromStr for SpdxExpression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s);
+        let mut rest = tokens.as_slice();
+
+        let expr =
+            Self::parse_or(&mut rest).map_err(|e| format!("Invalid SPDX expression '{s}': {e}"))?;
+
+        match rest.first() {
+            None => Ok(expr),
+            Some(token) => Err(format!(
+                "Invalid SPDX expression '{s}': Unexpected '{token}'"
+            )),
+        }
+    }
+}
+
+impl Display for SpdxExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::License { id, or_later } => {
+                write!(f, "{id}")?;
+                if *or_later {
+                    write!(f, "+")?;
+                }
+                Ok(())
+            }
+            Self::With { license, exception } => write!(f, "{license} WITH {exception}"),
+            Self::And(a, b) => {
+                a.fmt_grouped(f)?;
+                write!(f, " AND ")?;
+                b.fmt_grouped(f)
+            }
+            Self::Or(a, b) => write!(f, "{a} OR {b}"),
+        }
+    }
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open => write!(f, "("),
+            Self::Close => write!(f, ")"),
+            Self::And => write!(f, "AND"),
+            Self::Or => write!(f, "OR"),
+            Self::With => write!(f, "WITH"),
+            Self::Word(word) => write!(f, "{word}"),
+        }
+    }
+}
+
+impl Serialize for SpdxExpression {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for SpdxExpression {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(de::Error::custom)
+    }
+}
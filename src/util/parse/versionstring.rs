@@ -1,8 +1,13 @@
 //! Parsing utilities for version strings
 
+use std::cmp::Ordering;
+
 use serde::{Deserializer, Serializer};
 
 /// A version string that can be deserialized
+///
+/// Version strings are ordered by `name`, then `version` (see [compare_versions()])
+/// and finally by `pkgver`.
 #[derive(Debug, Clone)]
 pub struct VersionString {
     pub name: String,
@@ -10,6 +15,138 @@ pub struct VersionString {
     pub pkgver: u32,
 }
 
+impl VersionString {
+    /// Compares the `version` and `pkgver` of `self` to `other`, ignoring the name
+    /// # Arguments
+    /// * `other` - The version string to compare against
+    pub fn cmp_version(&self, other: &Self) -> Ordering {
+        compare_versions(&self.version, &other.version).then(self.pkgver.cmp(&other.pkgver))
+    }
+}
+
+impl PartialEq for VersionString {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for VersionString {}
+
+impl PartialOrd for VersionString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for VersionString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name
+            .cmp(&other.name)
+            .then_with(|| self.cmp_version(other))
+    }
+}
+
+/// Compares two version strings the way common distribution package managers do:
+/// - An optional numeric epoch (`<epoch>:`) takes precedence, a missing one is `0`
+/// - The rest is split into numeric and alphabetic segments, everything else separates them
+/// - Numeric segments are compared by value (`1.10` > `1.9`, `1.01` == `1.1`)
+/// - Alphabetic segments are compared lexically and are older than numeric ones
+/// - A `~` sorts before anything, even the end of the string (`1.0~rc1` < `1.0`)
+/// - If all segments are equal, the version with more segments is newer (`1.0.1` > `1.0`)
+/// # Arguments
+/// * `a` - The first version
+/// * `b` - The version to compare `a` against
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a) = split_epoch(a);
+    let (b_epoch, b) = split_epoch(b);
+
+    a_epoch.cmp(&b_epoch).then_with(|| compare_segments(a, b))
+}
+
+/// Splits the epoch off a version, returning `0` if there is none
+/// # Arguments
+/// * `version` - The version to split
+fn split_epoch(version: &str) -> (u64, &str) {
+    match version.split_once(':') {
+        Some((epoch, rest)) if !epoch.is_empty() && epoch.bytes().all(|c| c.is_ascii_digit()) => {
+            (epoch.parse().unwrap_or(u64::MAX), rest)
+        }
+        _ => (0, version),
+    }
+}
+
+/// Compares the segments of two versions without their epoch
+/// # Arguments
+/// * `a` - The first version
+/// * `b` - The version to compare `a` against
+fn compare_segments(a: &str, b: &str) -> Ordering {
+    let is_separator = |c: u8| !c.is_ascii_alphanumeric() && c != b'~';
+
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+
+    loop {
+        a = skip_while(a, is_separator);
+        b = skip_while(b, is_separator);
+
+        // A tilde is older than anything, even the end of the version
+        match (a.first(), b.first()) {
+            (Some(b'~'), Some(b'~')) => {
+                a = &a[1..];
+                b = &b[1..];
+                continue;
+            }
+            (Some(b'~'), _) => return Ordering::Less,
+            (_, Some(b'~')) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(_), Some(_)) => {}
+        }
+
+        let a_numeric = a[0].is_ascii_digit();
+        let b_numeric = b[0].is_ascii_digit();
+
+        let ordering = match (a_numeric, b_numeric) {
+            (true, true) => {
+                let (a_seg, a_rest) = split_while(a, |c| c.is_ascii_digit());
+                let (b_seg, b_rest) = split_while(b, |c| c.is_ascii_digit());
+                a = a_rest;
+                b = b_rest;
+
+                let a_seg = skip_while(a_seg, |c| c == b'0');
+                let b_seg = skip_while(b_seg, |c| c == b'0');
+                a_seg.len().cmp(&b_seg.len()).then(a_seg.cmp(b_seg))
+            }
+            (false, false) => {
+                let (a_seg, a_rest) = split_while(a, |c| c.is_ascii_alphabetic());
+                let (b_seg, b_rest) = split_while(b, |c| c.is_ascii_alphabetic());
+                a = a_rest;
+                b = b_rest;
+
+                a_seg.cmp(b_seg)
+            }
+            // Numeric segments are newer than alphabetic ones
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Splits `s` after the longest prefix matching `predicate`
+fn split_while(s: &[u8], predicate: impl Fn(u8) -> bool) -> (&[u8], &[u8]) {
+    let pos = s.iter().position(|c| !predicate(*c)).unwrap_or(s.len());
+    s.split_at(pos)
+}
+
+/// Returns `s` without the longest prefix matching `predicate`
+fn skip_while(s: &[u8], predicate: impl Fn(u8) -> bool) -> &[u8] {
+    split_while(s, predicate).1
+}
+
 impl<'de> serde::Deserialize<'de> for VersionString {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -80,3 +217,64 @@ impl serde::Serialize for VersionString {
         serializer.serialize_str(&string_repr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::compare_versions;
+
+    #[test]
+    fn numeric_segments_compare_by_value() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("2.0", "10.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.2.3", "1.2.3"), Ordering::Equal);
+    }
+
+    #[test]
+    fn alphabetic_segments_compare_lexically() {
+        assert_eq!(compare_versions("1.0a", "1.0b"), Ordering::Less);
+        assert_eq!(compare_versions("1.0beta", "1.0alpha"), Ordering::Greater);
+    }
+
+    #[test]
+    fn numeric_segments_are_newer_than_alphabetic() {
+        assert_eq!(compare_versions("1.0.1", "1.0.a"), Ordering::Greater);
+        assert_eq!(compare_versions("1.a", "1.0"), Ordering::Less);
+    }
+
+    #[test]
+    fn more_segments_are_newer() {
+        assert_eq!(compare_versions("1.0.1", "1.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1", "1.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0", "1.0a"), Ordering::Less);
+    }
+
+    #[test]
+    fn leading_zeros_are_ignored() {
+        assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+        assert_eq!(compare_versions("1.010", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.000", "1.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn separators_only_split_segments() {
+        assert_eq!(compare_versions("1.2", "1-2"), Ordering::Equal);
+        assert_eq!(compare_versions("1_2", "1.2"), Ordering::Equal);
+    }
+
+    #[test]
+    fn pre_releases_are_older() {
+        assert_eq!(compare_versions("1.0~rc1", "1.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0~rc1", "1.0~rc2"), Ordering::Less);
+        assert_eq!(compare_versions("1.0~~", "1.0~"), Ordering::Less);
+        assert_eq!(compare_versions("1.0", "1.0~rc1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn epochs_take_precedence() {
+        assert_eq!(compare_versions("1:1.0", "2.0"), Ordering::Greater);
+        assert_eq!(compare_versions("0:2.0", "2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1:1.0", "2:0.1"), Ordering::Less);
+    }
+}
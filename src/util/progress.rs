@@ -0,0 +1,134 @@
+//! Utilities for reporting the progress of long-running operations on the terminal
+
+use std::{
+    io::{self, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{model::DeployProgress, util::download::DownloadProgress};
+
+/// The minimum time between two redraws of a progress bar
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of characters the bar itself occupies
+const BAR_WIDTH: usize = 30;
+
+/// A single-line progress bar drawn to `stderr`.
+///
+/// The bar only gets drawn if `stderr` is a terminal, making it a no-op
+/// when the output gets redirected to a file or a pipe
+pub struct ProgressBar {
+    /// The label to display in front of the bar
+    label: String,
+    /// Whether `stderr` is a terminal and the bar gets drawn
+    enabled: bool,
+    /// The time the bar was last drawn
+    last_draw: Option<Instant>,
+}
+
+impl ProgressBar {
+    /// Creates a new progress bar
+    /// # Arguments
+    /// * `label` - The label to display in front of the bar
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            enabled: io::stderr().is_terminal(),
+            last_draw: None,
+        }
+    }
+
+    /// Updates the bar, redrawing at most every [REDRAW_INTERVAL]
+    /// # Arguments
+    /// * `done` - The number of units done
+    /// * `total` - The total number of units
+    /// * `detail` - Additional information to display after the counts
+    pub fn update(&mut self, done: usize, total: usize, detail: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        let due = self
+            .last_draw
+            .map(|last| now.duration_since(last) >= REDRAW_INTERVAL)
+            .unwrap_or(true);
+        if !due && done < total {
+            return;
+        }
+        self.last_draw = Some(now);
+
+        let filled = match total {
+            0 => BAR_WIDTH,
+            total => BAR_WIDTH * done.min(total) / total,
+        };
+
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r\x1b[2K{} [{}{}] {done}/{total} {detail}",
+            self.label,
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+        );
+        let _ = stderr.flush();
+    }
+
+    /// Updates the bar from the progress of a tree deployment
+    /// # Arguments
+    /// * `progress` - The progress to display
+    pub fn update_deploy(&mut self, progress: &DeployProgress) {
+        self.update(
+            progress.entries_done,
+            progress.entries_total,
+            &format_bytes(progress.bytes_written),
+        )
+    }
+
+    /// Updates the bar from the combined progress of downloads
+    /// # Arguments
+    /// * `progress` - The progress to display
+    pub fn update_download(&mut self, progress: &DownloadProgress) {
+        self.update(
+            progress.downloads_done,
+            progress.downloads_total,
+            &format_bytes(progress.bytes_received),
+        )
+    }
+
+    /// Clears the bar from the terminal
+    pub fn finish(&mut self) {
+        if self.enabled && self.last_draw.is_some() {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+        self.last_draw = None;
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        self.finish()
+    }
+}
+
+/// Formats a number of bytes using binary units, e.g. `1.5 MiB`
+/// # Arguments
+/// * `bytes` - The number of bytes to format
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{value:.1} {}", UNITS[unit])
+}
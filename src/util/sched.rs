@@ -0,0 +1,146 @@
+//! Utilities for confining child processes to a subset of the CPUs and lowering their priority
+use std::{fmt::Display, io, os::unix::process::CommandExt, process::Command, str::FromStr};
+
+use nix::{
+    libc,
+    sched::{sched_setaffinity, CpuSet},
+    unistd::Pid,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorExt};
+
+/// A list of CPUs in the format of the kernel's `cpuset` lists:
+/// Comma separated CPU numbers and inclusive ranges (`0-3,8,10-11`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CpuList(pub Vec<usize>);
+
+/// Confines the process spawned by `command` to the CPUs in `cpus`
+/// (`sched_setaffinity(2)`) and sets its nice level to `nice` (`setpriority(2)`).
+/// Both are applied in the child right before it executes the program,
+/// so they are inherited by all of its children
+/// # Arguments
+/// * `command` - The command to confine
+/// * `cpus` - The CPUs to confine the process to, `None` to inherit the affinity
+/// * `nice` - The nice level of the process (`-20` to `19`), `None` to inherit it
+/// # Errors
+/// If a CPU in `cpus` exceeds the CPUs supported by the kernel interface
+pub fn confine_command(
+    command: &mut Command,
+    cpus: Option<&CpuList>,
+    nice: Option<i32>,
+) -> Result<(), Error> {
+    // Assemble the CPU mask up front, the child must not allocate between fork and exec
+    let cpuset = match cpus {
+        Some(cpus) => {
+            let mut cpuset = CpuSet::new();
+            for cpu in &cpus.0 {
+                cpuset
+                    .set(*cpu)
+                    .map_err(io::Error::from)
+                    .e_context(|| format!("Adding CPU {cpu} to the CPU set"))?;
+            }
+            Some(cpuset)
+        }
+        None => None,
+    };
+
+    if cpuset.is_none() && nice.is_none() {
+        return Ok(());
+    }
+
+    // SAFETY: The closure only issues the `sched_setaffinity` and `setpriority`
+    // system calls, which are async-signal-safe, and does not allocate
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(cpuset) = &cpuset {
+                sched_setaffinity(Pid::from_raw(0), cpuset)?;
+            }
+
+            if let Some(nice) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(())
+        })
+    };
+
+    Ok(())
+}
+
+impl FromStr for CpuList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |part: &str| {
+            format!("Invalid CPU list '{s}': Expected a CPU number or range, found '{part}'")
+        };
+
+        let mut cpus = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (start, end) = match part.split_once('-') {
+                Some((start, end)) => (start.trim(), end.trim()),
+                None => (part, part),
+            };
+
+            let start: usize = start.parse().map_err(|_| invalid(part))?;
+            let end: usize = end.parse().map_err(|_| invalid(part))?;
+            if start > end {
+                return Err(invalid(part));
+            }
+
+            for cpu in start..=end {
+                if !cpus.contains(&cpu) {
+                    cpus.push(cpu);
+                }
+            }
+        }
+
+        if cpus.is_empty() {
+            return Err(format!("Invalid CPU list '{s}': No CPUs"));
+        }
+
+        cpus.sort();
+        Ok(Self(cpus))
+    }
+}
+
+impl TryFrom<String> for CpuList {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::from_str(&value)
+    }
+}
+
+impl From<CpuList> for String {
+    fn from(value: CpuList) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for CpuList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Collapse consecutive CPUs into ranges
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for cpu in &self.0 {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == *cpu => *end = *cpu,
+                _ => ranges.push((*cpu, *cpu)),
+            }
+        }
+
+        let ranges: Vec<String> = ranges
+            .into_iter()
+            .map(|(start, end)| match start == end {
+                true => start.to_string(),
+                false => format!("{start}-{end}"),
+            })
+            .collect();
+
+        write!(f, "{}", ranges.join(","))
+    }
+}
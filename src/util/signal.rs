@@ -16,7 +16,7 @@ impl SignalDispatcher {
     /// # Returns
     /// A handler guard that automatically pops the handler when the guard is dropped
     #[must_use]
-    pub fn add_handler(&self, function: Box<dyn FnMut() + Send + Sync>) -> HandlerGuard {
+    pub fn add_handler(&self, function: Box<dyn FnMut() + Send + Sync>) -> HandlerGuard<'_> {
         self.handlers
             .write()
             .expect("Poisoned signal handler collection")
@@ -16,3 +16,44 @@ pub fn replace_package_variables(string: &str, package: &dyn CorePackage) -> Str
         .replace("$PKG_NAME", package.get_name())
         .replace("$PKG_VERSION", package.get_version())
 }
+
+/// Matches `text` against the glob `pattern`, supporting the following wildcards:
+/// - `?`: Any single character except `/`
+/// - `*`: Any sequence of characters except `/`
+/// - `**`: Any sequence of characters, including `/`
+/// # Arguments
+/// * `pattern` - The glob pattern to match
+/// * `text` - The text to match against the pattern
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    glob_match_chars(&pattern, &text)
+}
+
+/// Recursively matches `text` against `pattern`, see [glob_match()]
+fn glob_match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            // `**/` also matches no directory at all
+            let rest = &pattern[2..];
+            if rest.first() == Some(&'/') && glob_match_chars(&rest[1..], text) {
+                return true;
+            }
+
+            (0..=text.len()).any(|i| glob_match_chars(rest, &text[i..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            let max = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+
+            (0..=max).any(|i| glob_match_chars(rest, &text[i..]))
+        }
+        Some('?') => {
+            matches!(text.first(), Some(c) if *c != '/')
+                && glob_match_chars(&pattern[1..], &text[1..])
+        }
+        Some(p) => text.first() == Some(p) && glob_match_chars(&pattern[1..], &text[1..]),
+    }
+}
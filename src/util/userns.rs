@@ -0,0 +1,211 @@
+//! Utilities for running privileged operations unprivileged using user namespaces
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use log::{debug, warn};
+use nix::{
+    sched::{unshare, CloneFlags},
+    unistd::{getgid, getpid, getuid, Gid, Uid, User},
+};
+
+use crate::{
+    error::{Error, ErrorExt, ErrorType},
+    util::fs::{self, PathUtil},
+};
+
+/// The file listing the subordinate user ids of the users
+static SUBUID_FILE: &str = "/etc/subuid";
+/// The file listing the subordinate group ids of the groups
+static SUBGID_FILE: &str = "/etc/subgid";
+
+/// The ids of the user that entered a user namespace, as seen from outside of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserNamespace {
+    /// The user id outside of the namespace that got mapped to `0`
+    pub uid: u32,
+    /// The group id outside of the namespace that got mapped to `0`
+    pub gid: u32,
+    /// The subordinate user ids outside of the namespace that got mapped to `1` and above
+    pub subuids: Option<SubordinateRange>,
+    /// The subordinate group ids outside of the namespace that got mapped to `1` and above
+    pub subgids: Option<SubordinateRange>,
+}
+
+/// A range of subordinate ids a user may map into its user namespaces,
+/// refer to `subuid(5)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubordinateRange {
+    /// The first id of the range
+    pub start: u32,
+    /// The number of ids in the range
+    pub count: u32,
+}
+
+impl UserNamespace {
+    /// Moves the current process into a new user and mount namespace,
+    /// mapping the current user and group to `root` (`0`) within the namespace.
+    ///
+    /// The subordinate ids of the user (`/etc/subuid` and `/etc/subgid`) get mapped
+    /// to `1` and above using `newuidmap` and `newgidmap`, so builds can change the
+    /// ownership of files (`chown`, `install -o`). Without subordinate ids, only `root`
+    /// is mapped and changing the ownership fails.
+    ///
+    /// This allows creating mounts and using `chroot` without real `root` privileges.
+    /// Files indexed within the namespace get their ownership within it recorded,
+    /// which is the ownership the build intended instead of the one on disk.
+    ///
+    /// This must be called before any threads are spawned.
+    pub fn enter() -> Result<Self, Error> {
+        let context = || "Entering user namespace";
+
+        let uid = getuid();
+        let gid = getgid();
+
+        // Both files list the ranges by the name or id of the user
+        let user = User::from_uid(uid).ok().flatten().map(|u| u.name);
+        let subuids = find_subordinate_range(Path::new(SUBUID_FILE), user.as_deref(), uid.as_raw())
+            .ctx(context)?;
+        let subgids = find_subordinate_range(Path::new(SUBGID_FILE), user.as_deref(), uid.as_raw())
+            .ctx(context)?;
+
+        let (subuids, subgids) = match (subuids, subgids) {
+            (Some(subuids), Some(subgids)) => (Some(subuids), Some(subgids)),
+            _ => {
+                warn!(
+                    "There are no subordinate ids for user {uid} in {SUBUID_FILE} and {SUBGID_FILE}, \
+                     only root is mapped in the user namespace"
+                );
+                (None, None)
+            }
+        };
+
+        let ns = Self {
+            uid: uid.as_raw(),
+            gid: gid.as_raw(),
+            subuids,
+            subgids,
+        };
+
+        match (subuids, subgids) {
+            (Some(subuids), Some(subgids)) => {
+                Self::unshare_mapped(uid, gid, subuids, subgids).ctx(context)?
+            }
+            _ => {
+                unshare(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS)
+                    .map_err(io::Error::from)
+                    .e_context(context)?;
+
+                // Writing the gid map is not permitted unless 'setgroups' is denied
+                std::fs::write("/proc/self/setgroups", "deny").e_context(context)?;
+                std::fs::write("/proc/self/uid_map", format!("0 {} 1", ns.uid))
+                    .e_context(context)?;
+                std::fs::write("/proc/self/gid_map", format!("0 {} 1", ns.gid))
+                    .e_context(context)?;
+            }
+        }
+
+        debug!("Entered user namespace as {}:{}", ns.uid, ns.gid);
+
+        Ok(ns)
+    }
+
+    /// Unshares the namespaces and maps the user, the group and their subordinate ids
+    /// using `newuidmap` and `newgidmap`. These have to write the maps from outside
+    /// of the namespace, so they run in a helper process spawned before unsharing
+    /// # Arguments
+    /// * `uid` - The user to map to `0`
+    /// * `gid` - The group to map to `0`
+    /// * `subuids` - The subordinate user ids to map to `1` and above
+    /// * `subgids` - The subordinate group ids to map to `1` and above
+    fn unshare_mapped(
+        uid: Uid,
+        gid: Gid,
+        subuids: SubordinateRange,
+        subgids: SubordinateRange,
+    ) -> Result<(), Error> {
+        // The helper waits until the namespace has been unshared before mapping it
+        let script = format!(
+            "read -r _ || exit 1; \
+             newuidmap {pid} 0 {uid} 1 1 {} {} && newgidmap {pid} 0 {gid} 1 1 {} {}",
+            subuids.start,
+            subuids.count,
+            subgids.start,
+            subgids.count,
+            pid = getpid(),
+        );
+        let mut helper = Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .stdin(Stdio::piped())
+            .spawn()
+            .e_context(|| "Spawning id mapping helper")?;
+
+        let unshared = unshare(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS)
+            .map_err(io::Error::from)
+            .e_context(|| "Unsharing namespaces");
+
+        // Closing stdin without a line makes the helper exit without mapping
+        let mut stdin = helper.stdin.take().expect("Helper has stdin");
+        if unshared.is_ok() {
+            stdin
+                .write_all(b"\n")
+                .e_context(|| "Starting id mapping helper")?;
+        }
+        drop(stdin);
+
+        let status = helper
+            .wait()
+            .e_context(|| "Waiting for id mapping helper")?;
+        unshared?;
+
+        if !status.success() {
+            return Err(Error::new(ErrorType::Other(format!(
+                "Mapping the subordinate ids using newuidmap and newgidmap failed ({status})"
+            ))));
+        }
+
+        Ok(())
+    }
+}
+
+/// Searches a `subuid(5)` or `subgid(5)` file for the first range of `name` or `id`
+/// # Arguments
+/// * `path` - The file to search, a missing file contains no ranges
+/// * `name` - The name of the user or group, if it has one
+/// * `id` - The id of the user or group
+pub fn find_subordinate_range(
+    path: &Path,
+    name: Option<&str>,
+    id: u32,
+) -> Result<Option<SubordinateRange>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::file_read_to_string(path)?;
+    let id = id.to_string();
+
+    for line in content.lines() {
+        let mut fields = line.trim().split(':');
+        let (Some(owner), Some(start), Some(count)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+
+        if owner != id && Some(owner) != name {
+            continue;
+        }
+
+        match (start.parse(), count.parse()) {
+            (Ok(start), Ok(count)) if count > 0 => {
+                return Ok(Some(SubordinateRange { start, count }))
+            }
+            _ => warn!("Skipping invalid line '{line}' in {}", path.str_lossy()),
+        }
+    }
+
+    Ok(None)
+}